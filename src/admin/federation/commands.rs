@@ -1,5 +1,6 @@
 use std::fmt::Write;
 
+use conduit::utils::time::rfc2822_from_millis;
 use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, RoomId, ServerName, UserId};
 
 use crate::{escape_html, get_room_info, services, Result};
@@ -126,3 +127,51 @@ pub(super) async fn remote_user_in_rooms(_body: Vec<&str>, user_id: Box<UserId>)
 
 	Ok(RoomMessageEventContent::text_html(output_plain, output_html))
 }
+
+pub(super) async fn list_backoffs(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	let backoffs = services().sending.backoffs()?;
+	if backoffs.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("Not backing off from any server."));
+	}
+
+	let mut msg = format!(
+		"Backing off from {} server(s):\n\n| Server | Failures | Last failure | Next retry | Last error |\n| --- | \
+		 --- | --- | --- | --- |\n",
+		backoffs.len()
+	);
+	for (server_name, backoff) in &backoffs {
+		writeln!(
+			msg,
+			"| {server_name} | {} | {} | {} | {} |",
+			backoff.failures,
+			rfc2822_from_millis(backoff.last_failure),
+			rfc2822_from_millis(backoff.next_retry()),
+			backoff.last_error.replace('|', "\\|").replace('\n', " "),
+		)
+		.expect("should be able to write to string buffer");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+pub(super) async fn retry_server(_body: Vec<&str>, server_name: Box<ServerName>) -> Result<RoomMessageEventContent> {
+	if services().sending.db.get_backoff(&server_name)?.is_none() {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Not backing off from {server_name}."
+		)));
+	}
+
+	services().sending.retry_server(&server_name)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Retrying to send to {server_name}."
+	)))
+}
+
+pub(super) async fn drop_queue(_body: Vec<&str>, server_name: Box<ServerName>) -> Result<RoomMessageEventContent> {
+	services().sending.drop_server_queue(&server_name)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Dropped everything queued for {server_name}."
+	)))
+}
@@ -39,6 +39,24 @@ pub(super) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - Lists the servers we are backing off from sending to, with why and
+	///   until when
+	ListBackoffs,
+
+	/// - Ends the backoff from a server and sends its pending transactions
+	///   right away
+	RetryServer {
+		server_name: Box<ServerName>,
+	},
+
+	/// - Drops all transactions queued for a server and ends the backoff from
+	///   it
+	///
+	/// The dropped events are never sent to the server.
+	DropQueue {
+		server_name: Box<ServerName>,
+	},
 }
 
 pub(super) async fn process(command: FederationCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -56,5 +74,12 @@ pub(super) async fn process(command: FederationCommand, body: Vec<&str>) -> Resu
 		FederationCommand::RemoteUserInRooms {
 			user_id,
 		} => remote_user_in_rooms(body, user_id).await?,
+		FederationCommand::ListBackoffs => list_backoffs(body).await?,
+		FederationCommand::RetryServer {
+			server_name,
+		} => retry_server(body, server_name).await?,
+		FederationCommand::DropQueue {
+			server_name,
+		} => drop_queue(body, server_name).await?,
 	})
 }
@@ -15,8 +15,9 @@ use service::admin::{CommandOutput, CommandResult, HandlerResult};
 
 use crate::{
 	appservice, appservice::AppserviceCommand, check, check::CheckCommand, debug, debug::DebugCommand, federation,
-	federation::FederationCommand, media, media::MediaCommand, query, query::QueryCommand, room, room::RoomCommand,
-	server, server::ServerCommand, services, user, user::UserCommand,
+	federation::FederationCommand, media, media::MediaCommand, query, query::QueryCommand, registration_token,
+	registration_token::RegistrationTokenCommand, report, report::ReportCommand, room, room::RoomCommand, server,
+	server::ServerCommand, services, user, user::UserCommand,
 };
 pub(crate) const PAGE_SIZE: usize = 100;
 
@@ -32,6 +33,14 @@ pub(crate) enum AdminCommand {
 	/// - Commands for managing local users
 	Users(UserCommand),
 
+	#[command(subcommand)]
+	/// - Commands for managing registration tokens
+	RegistrationTokens(RegistrationTokenCommand),
+
+	#[command(subcommand)]
+	/// - Commands for handling reports of events, rooms and users
+	Reports(ReportCommand),
+
 	#[command(subcommand)]
 	/// - Commands for managing rooms
 	Rooms(RoomCommand),
@@ -159,6 +168,8 @@ async fn process_admin_command(command: AdminCommand, body: Vec<&str>) -> Result
 		AdminCommand::Appservices(command) => appservice::process(command, body).await?,
 		AdminCommand::Media(command) => media::process(command, body).await?,
 		AdminCommand::Users(command) => user::process(command, body).await?,
+		AdminCommand::RegistrationTokens(command) => registration_token::process(command, body).await?,
+		AdminCommand::Reports(command) => report::process(command, body).await?,
 		AdminCommand::Rooms(command) => room::process(command, body).await?,
 		AdminCommand::Federation(command) => federation::process(command, body).await?,
 		AdminCommand::Server(command) => server::process(command, body).await?,
@@ -1,8 +1,11 @@
-use conduit::Result;
+use std::fmt::Write as _;
+
+use conduit::{config::MediaBackend, Result};
 use ruma::{events::room::message::RoomMessageEventContent, EventId, MxcUri};
+use service::media::{Quota, Usage};
 use tracing::{debug, info};
 
-use crate::services;
+use crate::{services, utils::parse_local_user_id};
 
 pub(super) async fn delete(
 	_body: Vec<&str>, mxc: Option<Box<MxcUri>>, event_id: Option<Box<EventId>>,
@@ -177,3 +180,94 @@ pub(super) async fn delete_past_remote_media(
 		"Deleted {deleted_count} total files.",
 	)))
 }
+
+pub(super) async fn migrate_storage(
+	_body: Vec<&str>, from: MediaBackend, to: MediaBackend,
+) -> Result<RoomMessageEventContent> {
+	if from == to {
+		return Ok(RoomMessageEventContent::text_plain(
+			"The source and destination backends must be different.",
+		));
+	}
+
+	let (copied, missing) = services().media.migrate_storage(from, to).await?;
+	info!("Copied {copied} media files from {from:?} to {to:?}, {missing} missing from the source");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Copied {copied} media files, {missing} files known to the database were missing from the source backend.",
+	)))
+}
+
+pub(super) async fn list_uploaders(_body: Vec<&str>, limit: usize) -> Result<RoomMessageEventContent> {
+	let total = services().media.total_usage();
+	let uploaders = services().media.top_uploaders(limit)?;
+
+	let mut msg = format!("All media, including remote media and thumbnails: {}\n\n", format_usage(total));
+	writeln!(msg, "Biggest uploaders ({}):", uploaders.len()).expect("should be able to write to string buffer");
+	for (user_id, usage) in uploaders {
+		writeln!(msg, "- {user_id}: {}", format_usage(usage)).expect("should be able to write to string buffer");
+	}
+
+	Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
+}
+
+pub(super) async fn usage(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+	let usage = services().media.usage(&user_id)?;
+	let quota = services().media.quota(&user_id)?;
+	let source = if services().media.quota_override(&user_id)?.is_some() {
+		"overridden"
+	} else {
+		"configured"
+	};
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} stores {}.\nQuota ({source}): {}.",
+		format_usage(usage),
+		format_quota(quota),
+	)))
+}
+
+pub(super) async fn set_quota(
+	_body: Vec<&str>, user_id: String, max_bytes: Option<u64>, max_files: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+	let quota = Quota {
+		max_bytes,
+		max_files,
+	};
+
+	services()
+		.media
+		.set_quota_override(&user_id, Some(&quota))?;
+	info!("Set the media quota of {user_id} to {quota:?}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"The media quota of {user_id} is now {}.",
+		format_quota(quota)
+	)))
+}
+
+pub(super) async fn reset_quota(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+	if services().media.quota_override(&user_id)?.is_none() {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} already has the configured media quota."
+		)));
+	}
+
+	services().media.set_quota_override(&user_id, None)?;
+	info!("Reset the media quota of {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"The media quota of {user_id} is now the configured one: {}.",
+		format_quota(services().media.quota(&user_id)?)
+	)))
+}
+
+fn format_usage(usage: Usage) -> String { format!("{} bytes in {} files", usage.bytes, usage.files) }
+
+fn format_quota(quota: Quota) -> String {
+	let limit = |max: Option<u64>| max.map_or_else(|| "unlimited".to_owned(), |max| max.to_string());
+	format!("{} bytes, {} files", limit(quota.max_bytes), limit(quota.max_files))
+}
@@ -1,7 +1,7 @@
 mod commands;
 
 use clap::Subcommand;
-use conduit::Result;
+use conduit::{config::MediaBackend, Result};
 use ruma::{events::room::message::RoomMessageEventContent, EventId, MxcUri};
 
 use self::commands::*;
@@ -36,6 +36,46 @@ pub(super) enum MediaCommand {
 		#[arg(short, long)]
 		force: bool,
 	},
+
+	/// - Copies all media files missing from a storage backend ("filesystem" or
+	///   "s3") to another one, e.g. before switching `media_storage.backend`
+	MigrateStorage {
+		/// The backend to copy the media files from
+		from: MediaBackend,
+
+		/// The backend to copy the media files to
+		to: MediaBackend,
+	},
+
+	/// - Lists the users storing the most media, with the storage used by all
+	///   media of the server
+	ListUploaders {
+		/// How many users to list
+		#[arg(short, long, default_value_t = 10)]
+		limit: usize,
+	},
+
+	/// - Shows the media storage used by a user and their quota
+	Usage {
+		user_id: String,
+	},
+
+	/// - Overrides the configured media quota of a user. Limits which aren't
+	///   given are unlimited for this user.
+	SetQuota {
+		user_id: String,
+		/// Maximum size in bytes of the media the user may store
+		#[arg(long)]
+		max_bytes: Option<u64>,
+		/// Maximum number of media files the user may store
+		#[arg(long)]
+		max_files: Option<u64>,
+	},
+
+	/// - Restores the configured media quota of a user
+	ResetQuota {
+		user_id: String,
+	},
 }
 
 pub(super) async fn process(command: MediaCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -49,5 +89,23 @@ pub(super) async fn process(command: MediaCommand, body: Vec<&str>) -> Result<Ro
 			duration,
 			force,
 		} => delete_past_remote_media(body, duration, force).await?,
+		MediaCommand::MigrateStorage {
+			from,
+			to,
+		} => migrate_storage(body, from, to).await?,
+		MediaCommand::ListUploaders {
+			limit,
+		} => list_uploaders(body, limit).await?,
+		MediaCommand::Usage {
+			user_id,
+		} => usage(body, user_id).await?,
+		MediaCommand::SetQuota {
+			user_id,
+			max_bytes,
+			max_files,
+		} => set_quota(body, user_id, max_bytes, max_files).await?,
+		MediaCommand::ResetQuota {
+			user_id,
+		} => reset_quota(body, user_id).await?,
 	})
 }
@@ -7,6 +7,9 @@ pub(crate) mod federation;
 pub(crate) mod handler;
 pub(crate) mod media;
 pub(crate) mod query;
+pub(crate) mod registration_token;
+pub(crate) mod report;
+pub(crate) mod rest;
 pub(crate) mod room;
 pub(crate) mod server;
 pub(crate) mod user;
@@ -18,6 +21,7 @@ extern crate conduit_service as service;
 
 pub(crate) use conduit::{mod_ctor, mod_dtor, Result};
 pub use handler::handle;
+pub use rest::routes;
 pub(crate) use service::{services, user_is_local};
 
 pub(crate) use crate::{
@@ -0,0 +1,141 @@
+use std::fmt::Write as _;
+
+use conduit::{
+	utils::{self, time::rfc2822_from_millis},
+	Error, Result,
+};
+use ruma::events::room::message::RoomMessageEventContent;
+use service::registration_tokens::RegistrationToken;
+
+use crate::services;
+
+pub(super) async fn create(
+	_body: Vec<&str>, token: Option<String>, length: Option<usize>, uses_allowed: Option<u32>,
+	expires_in: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let expiry_time = expires_in.as_deref().map(expiry_time).transpose()?;
+	let token = services()
+		.registration_tokens
+		.create(token, length, uses_allowed, expiry_time)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Created registration token `{}`, {}.",
+		token.token,
+		limits(&token)
+	)))
+}
+
+pub(super) async fn list(_body: Vec<&str>, valid: bool) -> Result<RoomMessageEventContent> {
+	let now = utils::millis_since_unix_epoch();
+	let tokens: Vec<_> = services()
+		.registration_tokens
+		.list()?
+		.into_iter()
+		.filter(|token| !valid || token.is_valid(now))
+		.collect();
+
+	if tokens.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No registration tokens found."));
+	}
+
+	let mut msg = format!(
+		"Found {} registration token(s):\n\n| Token | Uses allowed | Pending | Completed | Expires |\n| --- | --- | \
+		 --- | --- | --- |\n",
+		tokens.len()
+	);
+	for token in &tokens {
+		writeln!(
+			msg,
+			"| `{}` | {} | {} | {} | {} |",
+			token.token,
+			token
+				.uses_allowed
+				.map_or_else(|| "unlimited".to_owned(), |uses_allowed| uses_allowed.to_string()),
+			token.pending,
+			token.completed,
+			token.expiry_time.map_or_else(|| "never".to_owned(), date),
+		)
+		.expect("should be able to write to string buffer");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+pub(super) async fn show(_body: Vec<&str>, token: String) -> Result<RoomMessageEventContent> {
+	let Some(token) = services().registration_tokens.get(&token)? else {
+		return Ok(RoomMessageEventContent::text_plain("Registration token not found."));
+	};
+
+	let state = if token.is_valid(utils::millis_since_unix_epoch()) {
+		"valid"
+	} else {
+		"no longer valid"
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Registration token `{}` is {state}: {}; {} registration(s) pending, {} completed.",
+		token.token,
+		limits(&token),
+		token.pending,
+		token.completed
+	)))
+}
+
+pub(super) async fn update(
+	_body: Vec<&str>, token: String, uses_allowed: Option<u32>, unlimited: bool, expires_in: Option<String>,
+	never_expires: bool,
+) -> Result<RoomMessageEventContent> {
+	let uses_allowed = if unlimited {
+		Some(None)
+	} else {
+		uses_allowed.map(Some)
+	};
+
+	let expiry_time = if never_expires {
+		Some(None)
+	} else {
+		expires_in
+			.as_deref()
+			.map(expiry_time)
+			.transpose()?
+			.map(Some)
+	};
+
+	let token = services()
+		.registration_tokens
+		.update(&token, uses_allowed, expiry_time)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Updated registration token `{}`, {}.",
+		token.token,
+		limits(&token)
+	)))
+}
+
+pub(super) async fn revoke(_body: Vec<&str>, token: String) -> Result<RoomMessageEventContent> {
+	if !services().registration_tokens.revoke(&token)? {
+		return Ok(RoomMessageEventContent::text_plain("Registration token not found."));
+	}
+
+	Ok(RoomMessageEventContent::text_plain("Registration token revoked."))
+}
+
+fn expiry_time(expires_in: &str) -> Result<u64> {
+	let duration = cyborgtime::parse_duration(expires_in)
+		.map_err(|e| Error::Err(format!("Failed to parse duration {expires_in:?}: {e}")))?;
+
+	Ok(utils::millis_since_unix_epoch().saturating_add(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)))
+}
+
+fn limits(token: &RegistrationToken) -> String {
+	let uses = token.uses_allowed.map_or_else(
+		|| "unlimited uses".to_owned(),
+		|uses_allowed| format!("{uses_allowed} use(s) allowed"),
+	);
+	let expiry = token.expiry_time.map_or_else(
+		|| "never expires".to_owned(),
+		|expiry_time| format!("expires {}", rfc2822_from_millis(expiry_time)),
+	);
+
+	format!("{uses}, {expiry}")
+}
@@ -0,0 +1,102 @@
+mod commands;
+
+use clap::Subcommand;
+use conduit::Result;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use self::commands::*;
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+pub(super) enum RegistrationTokenCommand {
+	/// - Create a registration token
+	///
+	/// A random token is generated if none is given.
+	Create {
+		/// The token, 1 to 64 characters from A-Z, a-z, 0-9, '.', '_', '~' and
+		/// '-'
+		token: Option<String>,
+
+		/// Length of the generated token
+		#[arg(long)]
+		length: Option<usize>,
+
+		/// How many accounts can be registered with the token, unlimited by
+		/// default
+		#[arg(short, long)]
+		uses_allowed: Option<u32>,
+
+		/// How long the token can be used, e.g. "7d"; it never expires by
+		/// default
+		#[arg(short, long)]
+		expires_in: Option<String>,
+	},
+
+	/// - List registration tokens with their usage
+	List {
+		/// Only list tokens which can still be used
+		#[arg(long)]
+		valid: bool,
+	},
+
+	/// - Show a registration token with its usage
+	Show {
+		token: String,
+	},
+
+	/// - Change the limits of a registration token
+	Update {
+		token: String,
+
+		/// How many accounts can be registered with the token in total
+		#[arg(short, long, conflicts_with = "unlimited")]
+		uses_allowed: Option<u32>,
+
+		/// Remove the limit on how many accounts can be registered
+		#[arg(long)]
+		unlimited: bool,
+
+		/// How long the token can be used from now, e.g. "7d"
+		#[arg(short, long, conflicts_with = "never_expires")]
+		expires_in: Option<String>,
+
+		/// Remove the expiry time
+		#[arg(long)]
+		never_expires: bool,
+	},
+
+	/// - Revoke a registration token
+	///
+	/// Registrations in progress which already passed the token stage can
+	/// still complete.
+	Revoke {
+		token: String,
+	},
+}
+
+pub(super) async fn process(command: RegistrationTokenCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	Ok(match command {
+		RegistrationTokenCommand::Create {
+			token,
+			length,
+			uses_allowed,
+			expires_in,
+		} => create(body, token, length, uses_allowed, expires_in).await?,
+		RegistrationTokenCommand::List {
+			valid,
+		} => list(body, valid).await?,
+		RegistrationTokenCommand::Show {
+			token,
+		} => show(body, token).await?,
+		RegistrationTokenCommand::Update {
+			token,
+			uses_allowed,
+			unlimited,
+			expires_in,
+			never_expires,
+		} => update(body, token, uses_allowed, unlimited, expires_in, never_expires).await?,
+		RegistrationTokenCommand::Revoke {
+			token,
+		} => revoke(body, token).await?,
+	})
+}
@@ -0,0 +1,167 @@
+use std::fmt::Write as _;
+
+use conduit::{utils::time::rfc2822_from_millis, Error, Result};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+use service::reports::{Report, ReportFilter, ReportState};
+
+use crate::{services, utils::parse_user_id};
+
+pub(super) async fn list(
+	_body: Vec<&str>, state: Option<String>, all: bool, room_id: Option<OwnedRoomId>, user_id: Option<String>,
+	reporter: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let state = if all {
+		None
+	} else {
+		Some(
+			state
+				.as_deref()
+				.map_or(Ok(ReportState::Open), parse_state)?,
+		)
+	};
+
+	let reports = services().reports.list(&ReportFilter {
+		state,
+		room_id,
+		user_id: user_id.as_deref().map(parse_user_id).transpose()?,
+		reporter: reporter.as_deref().map(parse_user_id).transpose()?,
+	})?;
+
+	if reports.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No reports found."));
+	}
+
+	let mut msg = format!(
+		"Found {} report(s):\n\n| ID | State | Received | Reporter | Reported | Reason |\n| --- | --- | --- | --- | \
+		 --- | --- |\n",
+		reports.len()
+	);
+	for report in &reports {
+		writeln!(
+			msg,
+			"| {} | {} | {} | {} | {} | {} |",
+			report.id,
+			report.state,
+			rfc2822_from_millis(report.received_at),
+			report.reporter,
+			report.target,
+			report
+				.reason
+				.as_deref()
+				.unwrap_or("")
+				.replace('|', "\\|")
+				.replace('\n', " "),
+		)
+		.expect("should be able to write to string buffer");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+pub(super) async fn show(_body: Vec<&str>, id: u64) -> Result<RoomMessageEventContent> {
+	let Some(report) = services().reports.get(id)? else {
+		return Ok(RoomMessageEventContent::text_plain("Report not found."));
+	};
+
+	let mut msg = format!(
+		"Report {} of {}, {}:\n\n- Reporter: {}\n- Received: {}\n",
+		report.id,
+		report.target,
+		report.state,
+		report.reporter,
+		rfc2822_from_millis(report.received_at),
+	);
+	if let Some(score) = report.score {
+		writeln!(msg, "- Score: {score}").expect("should be able to write to string buffer");
+	}
+	if let Some(handled_at) = report.handled_at {
+		writeln!(msg, "- Handled: {}", rfc2822_from_millis(handled_at))
+			.expect("should be able to write to string buffer");
+	}
+	if let Some(note) = &report.note {
+		writeln!(msg, "- Note: {note}").expect("should be able to write to string buffer");
+	}
+	writeln!(msg, "\nReason:\n```\n{}\n```", report.reason.as_deref().unwrap_or(""))
+		.expect("should be able to write to string buffer");
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+pub(super) async fn resolve(
+	_body: Vec<&str>, ids: Vec<u64>, note: Option<String>, room_id: Option<OwnedRoomId>, user_id: Option<String>,
+	reporter: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let ids = select(ids, room_id, user_id, reporter)?;
+	set_state(&ids, ReportState::Resolved, note)
+}
+
+pub(super) async fn dismiss(
+	_body: Vec<&str>, ids: Vec<u64>, note: Option<String>, room_id: Option<OwnedRoomId>, user_id: Option<String>,
+	reporter: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let ids = select(ids, room_id, user_id, reporter)?;
+	set_state(&ids, ReportState::Dismissed, note)
+}
+
+pub(super) async fn reopen(_body: Vec<&str>, ids: Vec<u64>) -> Result<RoomMessageEventContent> {
+	set_state(&ids, ReportState::Open, None)
+}
+
+/// The given reports, and the open reports matching the filters if any is set
+fn select(
+	mut ids: Vec<u64>, room_id: Option<OwnedRoomId>, user_id: Option<String>, reporter: Option<String>,
+) -> Result<Vec<u64>> {
+	if room_id.is_some() || user_id.is_some() || reporter.is_some() {
+		let reports = services().reports.list(&ReportFilter {
+			state: Some(ReportState::Open),
+			room_id,
+			user_id: user_id.as_deref().map(parse_user_id).transpose()?,
+			reporter: reporter.as_deref().map(parse_user_id).transpose()?,
+		})?;
+		ids.extend(reports.iter().map(|report| report.id));
+	}
+
+	ids.sort_unstable();
+	ids.dedup();
+
+	Ok(ids)
+}
+
+fn set_state(ids: &[u64], state: ReportState, note: Option<String>) -> Result<RoomMessageEventContent> {
+	if ids.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No reports given or matching the filters."));
+	}
+
+	// Don't handle some of the reports if any is missing
+	let reports = ids
+		.iter()
+		.map(|id| {
+			services()
+				.reports
+				.get(*id)?
+				.ok_or_else(|| Error::Err(format!("Report {id} not found.")))
+		})
+		.collect::<Result<Vec<Report>>>()?;
+
+	for report in &reports {
+		services()
+			.reports
+			.set_state(report.id, state, note.clone())?;
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Marked {} report(s) as {state}.",
+		reports.len()
+	)))
+}
+
+fn parse_state(state: &str) -> Result<ReportState> {
+	match state {
+		"open" => Ok(ReportState::Open),
+		"resolved" => Ok(ReportState::Resolved),
+		"dismissed" => Ok(ReportState::Dismissed),
+		_ => Err(Error::Err(format!(
+			"Invalid report state {state:?}, expected open, resolved or dismissed."
+		))),
+	}
+}
@@ -0,0 +1,125 @@
+mod commands;
+mod tests;
+
+use clap::Subcommand;
+use conduit::Result;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+
+use self::commands::*;
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+pub(super) enum ReportCommand {
+	/// - List reports, oldest first, only open ones by default
+	List {
+		/// Only list reports in this state: open, resolved or dismissed
+		#[arg(short, long, conflicts_with = "all")]
+		state: Option<String>,
+
+		/// List reports in any state
+		#[arg(short, long)]
+		all: bool,
+
+		/// Only list reports of this room or events in it
+		#[arg(long)]
+		room_id: Option<OwnedRoomId>,
+
+		/// Only list reports of this user or their events
+		#[arg(long)]
+		user_id: Option<String>,
+
+		/// Only list reports by this user
+		#[arg(long)]
+		reporter: Option<String>,
+	},
+
+	/// - Show a report
+	Show {
+		id: u64,
+	},
+
+	/// - Resolve reports, after acting on them
+	///
+	/// Reports are given by ID, or all open reports matching the filters are
+	/// resolved.
+	Resolve {
+		ids: Vec<u64>,
+
+		/// Note about how the reports were handled
+		#[arg(short, long)]
+		note: Option<String>,
+
+		/// Resolve the open reports of this room or events in it
+		#[arg(long)]
+		room_id: Option<OwnedRoomId>,
+
+		/// Resolve the open reports of this user or their events
+		#[arg(long)]
+		user_id: Option<String>,
+
+		/// Resolve the open reports by this user
+		#[arg(long)]
+		reporter: Option<String>,
+	},
+
+	/// - Dismiss reports which need no action
+	///
+	/// Reports are given by ID, or all open reports matching the filters are
+	/// dismissed.
+	Dismiss {
+		ids: Vec<u64>,
+
+		/// Note about why the reports were dismissed
+		#[arg(short, long)]
+		note: Option<String>,
+
+		/// Dismiss the open reports of this room or events in it
+		#[arg(long)]
+		room_id: Option<OwnedRoomId>,
+
+		/// Dismiss the open reports of this user or their events
+		#[arg(long)]
+		user_id: Option<String>,
+
+		/// Dismiss the open reports by this user, e.g. when they spam reports
+		#[arg(long)]
+		reporter: Option<String>,
+	},
+
+	/// - Reopen resolved or dismissed reports
+	Reopen {
+		ids: Vec<u64>,
+	},
+}
+
+pub(super) async fn process(command: ReportCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	Ok(match command {
+		ReportCommand::List {
+			state,
+			all,
+			room_id,
+			user_id,
+			reporter,
+		} => list(body, state, all, room_id, user_id, reporter).await?,
+		ReportCommand::Show {
+			id,
+		} => show(body, id).await?,
+		ReportCommand::Resolve {
+			ids,
+			note,
+			room_id,
+			user_id,
+			reporter,
+		} => resolve(body, ids, note, room_id, user_id, reporter).await?,
+		ReportCommand::Dismiss {
+			ids,
+			note,
+			room_id,
+			user_id,
+			reporter,
+		} => dismiss(body, ids, note, room_id, user_id, reporter).await?,
+		ReportCommand::Reopen {
+			ids,
+		} => reopen(body, ids).await?,
+	})
+}
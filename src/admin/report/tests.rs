@@ -0,0 +1,75 @@
+#![cfg(test)]
+
+use ruma::{owned_room_id, user_id};
+use service::{
+	reports::{ReportState, ReportTarget},
+	tests::services,
+};
+
+use super::commands::{list, resolve};
+
+#[tokio::test]
+async fn list_and_resolve_reports() {
+	let services = services().await;
+	let reporter = user_id!("@reporter:example.com");
+	let room_id = owned_room_id!("!reported:example.com");
+
+	let room_report = services
+		.reports
+		.add(
+			reporter,
+			ReportTarget::Room {
+				room_id: room_id.clone(),
+			},
+			Some("spam | ads".to_owned()),
+			None,
+		)
+		.unwrap();
+	let user_report = services
+		.reports
+		.add(
+			reporter,
+			ReportTarget::User {
+				user_id: user_id!("@reported:example.com").to_owned(),
+			},
+			None,
+			None,
+		)
+		.unwrap();
+
+	let listed = list(Vec::new(), None, false, None, None, Some(reporter.to_string()))
+		.await
+		.unwrap();
+	let body = listed.body();
+	assert!(body.contains("Found 2 report(s)"), "{body}");
+	assert!(body.contains(r"spam \| ads"), "reasons can't break the table: {body}");
+
+	resolve(
+		Vec::new(),
+		Vec::new(),
+		Some("Room banned".to_owned()),
+		Some(room_id),
+		None,
+		None,
+	)
+	.await
+	.unwrap();
+
+	let room_report = services.reports.get(room_report.id).unwrap().unwrap();
+	assert_eq!(room_report.state, ReportState::Resolved);
+	assert_eq!(room_report.note.as_deref(), Some("Room banned"));
+	assert!(room_report.handled_at.is_some());
+	assert_eq!(
+		services.reports.get(user_report.id).unwrap().unwrap().state,
+		ReportState::Open,
+		"reports not matching the filter stay open"
+	);
+
+	let listed = list(Vec::new(), None, false, None, None, Some(reporter.to_string()))
+		.await
+		.unwrap();
+	assert!(listed.body().contains("Found 1 report(s)"));
+	assert!(resolve(Vec::new(), vec![u64::MAX], None, None, None, None)
+		.await
+		.is_err());
+}
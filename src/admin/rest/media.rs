@@ -0,0 +1,20 @@
+use api::admin::Admin;
+use axum::extract::{Json, Path};
+use ruma::OwnedServerName;
+use tracing::info;
+
+use super::Empty;
+use crate::{services, Result};
+
+/// # `DELETE /_conduwuit/admin/v1/media/{serverName}/{mediaId}`
+///
+/// Deletes a media file and its thumbnails, like `!admin media delete --mxc`.
+pub(super) async fn delete_media_route(
+	Admin(sender): Admin, Path((server_name, media_id)): Path<(OwnedServerName, String)>,
+) -> Result<Json<Empty>> {
+	let mxc = format!("mxc://{server_name}/{media_id}");
+	services().media.delete(&mxc).await?;
+	info!("Media {mxc} deleted by {sender} through the admin API");
+
+	Ok(Json(Empty {}))
+}
@@ -0,0 +1,65 @@
+//! JSON admin API under `/_conduwuit/admin/v1`, exposing the operations of
+//! the admin room commands to scripts. Requests are authenticated with the
+//! access token of a server admin.
+
+mod media;
+mod rooms;
+mod server;
+mod tests;
+mod users;
+
+use axum::{
+	routing::{delete, get, post, put},
+	Router,
+};
+use conduit::Error;
+use ruma::{api::client::error::ErrorKind, OwnedUserId, UserId};
+use serde::Serialize;
+
+use crate::{services, Result};
+
+/// Response of operations which return nothing else.
+#[derive(Serialize)]
+struct Empty {}
+
+/// Adds the admin API routes to the router.
+pub fn routes(router: Router) -> Router {
+	let router = router
+		.route("/_conduwuit/admin/v1/users", post(users::create_user_route))
+		.route(
+			"/_conduwuit/admin/v1/users/:user_id/deactivate",
+			post(users::deactivate_user_route),
+		)
+		.route(
+			"/_conduwuit/admin/v1/users/:user_id/reset_password",
+			post(users::reset_password_route),
+		)
+		.route("/_conduwuit/admin/v1/rooms", get(rooms::list_rooms_route))
+		.route("/_conduwuit/admin/v1/rooms/:room_id", get(rooms::get_room_route))
+		.route("/_conduwuit/admin/v1/rooms/:room_id/ban", post(rooms::ban_room_route))
+		.route(
+			"/_conduwuit/admin/v1/rooms/:room_id/directory",
+			put(rooms::set_room_directory_route),
+		)
+		.route(
+			"/_conduwuit/admin/v1/rooms/:room_id/federation",
+			put(rooms::set_room_federation_route),
+		)
+		.route(
+			"/_conduwuit/admin/v1/media/:server_name/:media_id",
+			delete(media::delete_media_route),
+		)
+		.route("/_conduwuit/admin/v1/server/backup", post(server::backup_route))
+		.route("/_conduwuit/admin/v1/server_notices", post(server::server_notice_route));
+
+	#[cfg(conduit_mods)]
+	let router = router.route("/_conduwuit/admin/v1/server/reload", post(server::reload_route));
+
+	router
+}
+
+/// Parses a user ID or localpart from a request path.
+fn parse_user_id(user_id: &str) -> Result<OwnedUserId> {
+	UserId::parse_with_server_name(user_id.to_lowercase(), services().globals.server_name())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidUsername, "Invalid user ID."))
+}
@@ -0,0 +1,175 @@
+use api::admin::Admin;
+use axum::{
+	extract::{Json, Path},
+	http::Uri,
+};
+use conduit::Error;
+use ruma::{api::client::error::ErrorKind, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::Empty;
+use crate::{get_room_info, handler::PAGE_SIZE, room, services, Result};
+
+#[derive(Deserialize)]
+struct ListRoomsQuery {
+	page: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct BanRoom {
+	/// Also evict admins and ignore errors while evicting
+	#[serde(default)]
+	force: bool,
+	#[serde(default)]
+	disable_federation: bool,
+}
+
+#[derive(Deserialize)]
+pub(super) struct RoomDirectory {
+	published: bool,
+}
+
+#[derive(Deserialize)]
+pub(super) struct RoomFederation {
+	enabled: bool,
+}
+
+#[derive(Serialize)]
+pub(super) struct RoomList {
+	rooms: Vec<RoomSummary>,
+	/// Page to request next, absent on the last page
+	#[serde(skip_serializing_if = "Option::is_none")]
+	next_page: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub(super) struct RoomSummary {
+	room_id: OwnedRoomId,
+	joined_members: u64,
+	name: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct RoomDetails {
+	room_id: OwnedRoomId,
+	name: String,
+	topic: Option<String>,
+	joined_members: Vec<RoomMember>,
+	published: bool,
+	banned: bool,
+	federation_disabled: bool,
+}
+
+#[derive(Serialize)]
+pub(super) struct RoomMember {
+	user_id: OwnedUserId,
+	displayname: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct BannedRoom {
+	room_id: OwnedRoomId,
+}
+
+/// # `GET /_conduwuit/admin/v1/rooms`
+///
+/// Lists the rooms the server knows about a `page` at a time, largest rooms
+/// first, like `!admin rooms list`.
+pub(super) async fn list_rooms_route(_: Admin, uri: Uri) -> Result<Json<RoomList>> {
+	let query: ListRoomsQuery = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid query parameters."))?;
+
+	let page = query.page.unwrap_or(1).max(1);
+	let rooms: Vec<_> = room::list_rooms(page)
+		.into_iter()
+		.map(|(room_id, joined_members, name)| RoomSummary {
+			room_id,
+			joined_members,
+			name,
+		})
+		.collect();
+
+	let next_page = (rooms.len() == PAGE_SIZE).then(|| page.saturating_add(1));
+
+	Ok(Json(RoomList {
+		rooms,
+		next_page,
+	}))
+}
+
+/// # `GET /_conduwuit/admin/v1/rooms/{roomId}`
+///
+/// Shows a room's name, topic, joined members and moderation state, like the
+/// `!admin rooms info` commands.
+pub(super) async fn get_room_route(_: Admin, Path(room_id): Path<OwnedRoomId>) -> Result<Json<RoomDetails>> {
+	if !services().rooms.metadata.exists(&room_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Room not found."));
+	}
+
+	let (room_id, _, name) = get_room_info(&room_id);
+	let joined_members = room::joined_members(&room_id)
+		.into_iter()
+		.map(|(user_id, displayname)| RoomMember {
+			user_id,
+			displayname,
+		})
+		.collect();
+
+	Ok(Json(RoomDetails {
+		topic: services().rooms.state_accessor.get_room_topic(&room_id)?,
+		joined_members,
+		published: services().rooms.directory.is_public_room(&room_id)?,
+		banned: services().rooms.metadata.is_banned(&room_id)?,
+		federation_disabled: services().rooms.metadata.is_disabled(&room_id)?,
+		room_id,
+		name,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/rooms/{roomIdOrAlias}/ban`
+///
+/// Bans a room and makes our users leave it, like `!admin rooms moderation
+/// ban-room`.
+pub(super) async fn ban_room_route(
+	Admin(sender): Admin, Path(room): Path<OwnedRoomOrAliasId>, body: Option<Json<BanRoom>>,
+) -> Result<Json<BannedRoom>> {
+	let (force, disable_federation) = body.map_or((false, false), |Json(body)| (body.force, body.disable_federation));
+	let room_id = room::ban_room_and_evict(&room, force, disable_federation).await?;
+	info!("Room {room_id} banned by {sender} through the admin API");
+
+	Ok(Json(BannedRoom {
+		room_id,
+	}))
+}
+
+/// # `PUT /_conduwuit/admin/v1/rooms/{roomId}/directory`
+///
+/// Publishes a room to or removes it from the room directory, like `!admin
+/// rooms directory publish` and `unpublish`.
+pub(super) async fn set_room_directory_route(
+	_: Admin, Path(room_id): Path<OwnedRoomId>, Json(body): Json<RoomDirectory>,
+) -> Result<Json<Empty>> {
+	if body.published {
+		services().rooms.directory.set_public(&room_id)?;
+	} else {
+		services().rooms.directory.set_not_public(&room_id)?;
+	}
+
+	Ok(Json(Empty {}))
+}
+
+/// # `PUT /_conduwuit/admin/v1/rooms/{roomId}/federation`
+///
+/// Enables or disables handling incoming federation for a room, like `!admin
+/// federation enable-room` and `disable-room`.
+pub(super) async fn set_room_federation_route(
+	_: Admin, Path(room_id): Path<OwnedRoomId>, Json(body): Json<RoomFederation>,
+) -> Result<Json<Empty>> {
+	services()
+		.rooms
+		.metadata
+		.disable_room(&room_id, !body.enabled)?;
+
+	Ok(Json(Empty {}))
+}
@@ -0,0 +1,82 @@
+use api::admin::Admin;
+use axum::extract::Json;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedEventId};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[cfg(conduit_mods)]
+use super::Empty;
+use crate::{server, services, utils::parse_active_local_user_id, Result};
+
+#[derive(Deserialize)]
+pub(super) struct ServerNotice {
+	/// Every active local user if not given
+	user_id: Option<String>,
+	content: RoomMessageEventContent,
+}
+
+#[derive(Serialize)]
+pub(super) struct SentServerNotice {
+	/// Users the notice was sent to
+	sent: usize,
+	/// Event of the notice, when sent to a single user
+	#[serde(skip_serializing_if = "Option::is_none")]
+	event_id: Option<OwnedEventId>,
+}
+
+#[derive(Serialize)]
+pub(super) struct Backups {
+	/// Listing of the backups after this one, as printed by `!admin server
+	/// list-backups`
+	backups: String,
+}
+
+/// # `POST /_conduwuit/admin/v1/server/backup`
+///
+/// Performs an online backup of the database, like `!admin server
+/// backup-database`.
+pub(super) async fn backup_route(_: Admin) -> Result<Json<Backups>> {
+	Ok(Json(Backups {
+		backups: server::backup().await?,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/server_notices`
+///
+/// Sends a server notice to a local user or to all of them, like `!admin
+/// server server-notice`.
+pub(super) async fn server_notice_route(
+	Admin(sender): Admin, Json(body): Json<ServerNotice>,
+) -> Result<Json<SentServerNotice>> {
+	if let Some(user_id) = body.user_id {
+		let user_id = parse_active_local_user_id(&user_id)?;
+		let event_id = services()
+			.server_notices
+			.send(&user_id, &body.content)
+			.await?;
+		info!("Server notice sent to {user_id} by {sender} through the admin API");
+
+		return Ok(Json(SentServerNotice {
+			sent: 1,
+			event_id: Some((*event_id).to_owned()),
+		}));
+	}
+
+	let sent = services().server_notices.send_all(&body.content).await?;
+	info!("Server notice sent to {sent} users by {sender} through the admin API");
+
+	Ok(Json(SentServerNotice {
+		sent,
+		event_id: None,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/server/reload`
+///
+/// Hot-reloads the server, like `!admin server reload`.
+#[cfg(conduit_mods)]
+pub(super) async fn reload_route(_: Admin) -> Result<Json<Empty>> {
+	services().server.reload()?;
+
+	Ok(Json(Empty {}))
+}
@@ -0,0 +1,99 @@
+#![cfg(test)]
+
+use api::admin::Admin;
+use axum::{
+	body::Body,
+	extract::Path,
+	http::{header, Request, StatusCode},
+	Router,
+};
+use conduit::{utils, Error};
+use ruma::{api::client::error::ErrorKind, user_id};
+use service::tests::services;
+use tower::ServiceExt;
+
+use super::{
+	routes,
+	users::{deactivate_user_route, reset_password_route},
+};
+use crate::user;
+
+/// Requests without an access token are rejected by the admin extractor,
+/// while unknown routes fall through to a 404.
+async fn status_without_token(method: &str, uri: &str) -> StatusCode {
+	let request = Request::builder()
+		.method(method)
+		.uri(uri)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(r#"{"content":{"msgtype":"m.text","body":"Maintenance tonight"}}"#))
+		.expect("valid request");
+
+	routes(Router::new())
+		.oneshot(request)
+		.await
+		.expect("infallible")
+		.status()
+}
+
+#[tokio::test]
+async fn server_notice_route() {
+	assert_eq!(
+		status_without_token("POST", "/_conduwuit/admin/v1/server_notices").await,
+		StatusCode::UNAUTHORIZED
+	);
+	assert_eq!(
+		status_without_token("POST", "/_conduwuit/admin/v1/server_notice").await,
+		StatusCode::NOT_FOUND
+	);
+}
+
+#[tokio::test]
+async fn deactivate_user_handler() {
+	let services = services().await;
+	let admin = || Admin(user_id!("@admin:example.com").to_owned());
+
+	let missing = deactivate_user_route(admin(), Path("missing".to_owned()), None).await;
+	assert!(
+		matches!(missing, Err(Error::BadRequest(ErrorKind::NotFound, _))),
+		"unknown users can't be deactivated"
+	);
+	assert!(!services
+		.users
+		.exists(user_id!("@missing:example.com"))
+		.unwrap());
+
+	let user_id = user_id!("@deactivated:example.com");
+	user::create_user(user_id, None).await.unwrap();
+	deactivate_user_route(admin(), Path("Deactivated".to_owned()), None)
+		.await
+		.unwrap();
+	assert!(services.users.is_deactivated(user_id).unwrap());
+}
+
+#[tokio::test]
+async fn reset_password_handler() {
+	let services = services().await;
+	let admin = || Admin(user_id!("@admin:example.com").to_owned());
+
+	let missing = reset_password_route(admin(), Path("nobody".to_owned())).await;
+	assert!(
+		matches!(missing, Err(Error::BadRequest(ErrorKind::NotFound, _))),
+		"unknown users have no password"
+	);
+
+	let user_id = user_id!("@forgetful:example.com");
+	user::create_user(user_id, Some("old".to_owned()))
+		.await
+		.unwrap();
+	reset_password_route(admin(), Path(user_id.to_string()))
+		.await
+		.unwrap();
+	assert!(
+		services
+			.users
+			.password_hash(user_id)
+			.unwrap()
+			.is_some_and(|hash| utils::hash::verify_password("old", &hash).is_err()),
+		"the old password doesn't work anymore"
+	);
+}
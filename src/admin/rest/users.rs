@@ -0,0 +1,85 @@
+use api::admin::Admin;
+use axum::extract::{Json, Path};
+use ruma::OwnedUserId;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::parse_user_id;
+use crate::{user, Result};
+
+#[derive(Deserialize)]
+pub(super) struct CreateUser {
+	/// User ID or localpart of the new user
+	username: String,
+	/// Generated if not given
+	password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct DeactivateUser {
+	#[serde(default = "default_true")]
+	leave_rooms: bool,
+}
+
+#[derive(Serialize)]
+pub(super) struct UserPassword {
+	user_id: OwnedUserId,
+	password: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct DeactivatedUser {
+	user_id: OwnedUserId,
+}
+
+/// # `POST /_conduwuit/admin/v1/users`
+///
+/// Creates a local user, like `!admin users create`.
+pub(super) async fn create_user_route(
+	Admin(sender): Admin, Json(body): Json<CreateUser>,
+) -> Result<Json<UserPassword>> {
+	let user_id = parse_user_id(&body.username)?;
+	let password = user::create_user(&user_id, body.password).await?;
+	info!("User {user_id} created by {sender} through the admin API");
+
+	Ok(Json(UserPassword {
+		user_id,
+		password,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/deactivate`
+///
+/// Deactivates a local user, making them leave all rooms unless `leave_rooms`
+/// is false, like `!admin users deactivate`.
+pub(super) async fn deactivate_user_route(
+	Admin(sender): Admin, Path(user_id): Path<String>, body: Option<Json<DeactivateUser>>,
+) -> Result<Json<DeactivatedUser>> {
+	let user_id = parse_user_id(&user_id)?;
+	let leave_rooms = body.map_or(true, |Json(body)| body.leave_rooms);
+	user::deactivate_user(&user_id, leave_rooms).await?;
+	info!("User {user_id} deactivated by {sender} through the admin API");
+
+	Ok(Json(DeactivatedUser {
+		user_id,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/reset_password`
+///
+/// Sets a generated password for a local user, like `!admin users
+/// reset-password`.
+pub(super) async fn reset_password_route(
+	Admin(sender): Admin, Path(user_id): Path<String>,
+) -> Result<Json<UserPassword>> {
+	let user_id = parse_user_id(&user_id)?;
+	let password = user::reset_user_password(&user_id)?;
+	info!("Password of {user_id} reset by {sender} through the admin API");
+
+	Ok(Json(UserPassword {
+		user_id,
+		password,
+	}))
+}
+
+fn default_true() -> bool { true }
@@ -8,7 +8,10 @@ use clap::Subcommand;
 use conduit::Result;
 use ruma::{events::room::message::RoomMessageEventContent, RoomId, RoomOrAliasId};
 
-use self::room_commands::list;
+use self::room_commands::{list, purge};
+pub(crate) use self::{
+	room_commands::list_rooms, room_info_commands::joined_members, room_moderation_commands::ban_room_and_evict,
+};
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Subcommand)]
@@ -33,6 +36,24 @@ pub(super) enum RoomCommand {
 	#[command(subcommand)]
 	/// - Manage the room directory
 	Directory(RoomDirectoryCommand),
+
+	/// - Deletes a room's events, state, membership and media from the database
+	///
+	/// The room must be banned with `rooms moderation ban-room` first so our
+	/// users are evicted and it isn't joined again. Runs in the background and
+	/// reports progress to the admin room.
+	Purge {
+		#[arg(short, long)]
+		/// Purge the room even if it isn't banned
+		force: bool,
+
+		#[arg(long)]
+		/// Don't delete local media referenced by the room's events
+		keep_media: bool,
+
+		/// The room ID to purge
+		room_id: Box<RoomId>,
+	},
 }
 
 #[cfg_attr(test, derive(Debug))]
@@ -180,5 +201,11 @@ pub(super) async fn process(command: RoomCommand, body: Vec<&str>) -> Result<Roo
 		RoomCommand::List {
 			page,
 		} => list(body, page).await?,
+
+		RoomCommand::Purge {
+			force,
+			keep_media,
+			room_id,
+		} => purge(body, force, keep_media, room_id).await?,
 	})
 }
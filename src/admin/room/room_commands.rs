@@ -1,27 +1,14 @@
 use std::fmt::Write;
 
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, RoomId};
+use tracing::error;
 
-use crate::{escape_html, get_room_info, handler::PAGE_SIZE, services, Result};
+use crate::{escape_html, get_room_info, handler::PAGE_SIZE, services, Result, Service};
 
 pub(super) async fn list(_body: Vec<&str>, page: Option<usize>) -> Result<RoomMessageEventContent> {
 	// TODO: i know there's a way to do this with clap, but i can't seem to find it
 	let page = page.unwrap_or(1);
-	let mut rooms = services()
-		.rooms
-		.metadata
-		.iter_ids()
-		.filter_map(Result::ok)
-		.map(|id: OwnedRoomId| get_room_info(&id))
-		.collect::<Vec<_>>();
-	rooms.sort_by_key(|r| r.1);
-	rooms.reverse();
-
-	let rooms = rooms
-		.into_iter()
-		.skip(page.saturating_sub(1).saturating_mul(PAGE_SIZE))
-		.take(PAGE_SIZE)
-		.collect::<Vec<_>>();
+	let rooms = list_rooms(page);
 
 	if rooms.is_empty() {
 		return Ok(RoomMessageEventContent::text_plain("No more rooms."));
@@ -54,3 +41,73 @@ pub(super) async fn list(_body: Vec<&str>, page: Option<usize>) -> Result<RoomMe
 	);
 	Ok(RoomMessageEventContent::text_html(output_plain, output_html))
 }
+
+/// Returns a page of the rooms the server knows about as (room ID, joined
+/// members, name), largest rooms first. Pages start at 1.
+pub(crate) fn list_rooms(page: usize) -> Vec<(OwnedRoomId, u64, String)> {
+	let mut rooms = services()
+		.rooms
+		.metadata
+		.iter_ids()
+		.filter_map(Result::ok)
+		.map(|id: OwnedRoomId| get_room_info(&id))
+		.collect::<Vec<_>>();
+	rooms.sort_by_key(|r| r.1);
+	rooms.reverse();
+
+	rooms
+		.into_iter()
+		.skip(page.saturating_sub(1).saturating_mul(PAGE_SIZE))
+		.take(PAGE_SIZE)
+		.collect()
+}
+
+pub(super) async fn purge(
+	_body: Vec<&str>, force: bool, keep_media: bool, room_id: Box<RoomId>,
+) -> Result<RoomMessageEventContent> {
+	if Service::get_admin_room()?.is_some_and(|admin_room_id| *admin_room_id == *room_id) {
+		return Ok(RoomMessageEventContent::text_plain("Not allowed to purge the admin room."));
+	}
+
+	if !force && !services().rooms.metadata.is_banned(&room_id)? {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Room is not banned. Ban it with `rooms moderation ban-room` first so local users are evicted, or use \
+			 --force.",
+		));
+	}
+
+	let room_id: OwnedRoomId = room_id.into();
+	services().server.runtime().spawn(async move {
+		let progress = |message: String| async move {
+			services()
+				.admin
+				.send_message(RoomMessageEventContent::notice_plain(message))
+				.await;
+		};
+
+		let message = match services()
+			.rooms
+			.purge
+			.purge_room(&room_id, !keep_media, progress)
+			.await
+		{
+			Ok(summary) => format!(
+				"Finished purging {room_id}: removed {} events, {} state snapshots and {} media files.",
+				summary.events, summary.state_snapshots, summary.media
+			),
+			Err(e) => {
+				error!("Failed to purge room {room_id}: {e}");
+				format!("Failed to purge room {room_id}, it may be partially deleted: {e}")
+			},
+		};
+
+		services()
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(message))
+			.await;
+	});
+
+	Ok(RoomMessageEventContent::text_plain(
+		"Purging the room in the background, progress will be reported here.",
+	))
+}
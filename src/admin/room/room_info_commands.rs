@@ -1,4 +1,4 @@
-use ruma::{events::room::message::RoomMessageEventContent, RoomId};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedUserId, RoomId};
 use service::services;
 
 use super::RoomInfoCommand;
@@ -24,25 +24,7 @@ async fn list_joined_members(_body: Vec<&str>, room_id: Box<RoomId>) -> Result<R
 		.flatten()
 		.unwrap_or_else(|| room_id.to_string());
 
-	let members = services()
-		.rooms
-		.state_cache
-		.room_members(&room_id)
-		.filter_map(Result::ok);
-
-	let member_info = members
-		.into_iter()
-		.map(|user_id| {
-			(
-				user_id.clone(),
-				services()
-					.users
-					.displayname(&user_id)
-					.unwrap_or(None)
-					.unwrap_or_else(|| user_id.to_string()),
-			)
-		})
-		.collect::<Vec<_>>();
+	let member_info = joined_members(&room_id);
 
 	let output_plain = format!(
 		"{} Members in Room \"{}\":\n```\n{}\n```",
@@ -58,6 +40,26 @@ async fn list_joined_members(_body: Vec<&str>, room_id: Box<RoomId>) -> Result<R
 	Ok(RoomMessageEventContent::notice_markdown(output_plain))
 }
 
+/// Returns the joined members of a room with their display names, or user IDs
+/// if they have none.
+pub(crate) fn joined_members(room_id: &RoomId) -> Vec<(OwnedUserId, String)> {
+	services()
+		.rooms
+		.state_cache
+		.room_members(room_id)
+		.filter_map(Result::ok)
+		.map(|user_id| {
+			let displayname = services()
+				.users
+				.displayname(&user_id)
+				.unwrap_or(None)
+				.unwrap_or_else(|| user_id.to_string());
+
+			(user_id, displayname)
+		})
+		.collect()
+}
+
 async fn view_room_topic(_body: Vec<&str>, room_id: Box<RoomId>) -> Result<RoomMessageEventContent> {
 	let Some(room_topic) = services().rooms.state_accessor.get_room_topic(&room_id)? else {
 		return Ok(RoomMessageEventContent::text_plain("Room does not have a room topic set."));
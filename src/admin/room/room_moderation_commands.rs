@@ -1,6 +1,8 @@
 use api::client::leave_room;
+use conduit::Error;
 use ruma::{
-	events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedUserId, RoomAliasId, RoomId, RoomOrAliasId,
+	api::client::error::ErrorKind, events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedUserId,
+	RoomAliasId, RoomId, RoomOrAliasId,
 };
 use tracing::{debug, error, info, warn};
 
@@ -31,24 +33,40 @@ async fn ban_room(
 ) -> Result<RoomMessageEventContent> {
 	debug!("Got room alias or ID: {}", room);
 
+	ban_room_and_evict(&room, force, disable_federation).await?;
+
+	if disable_federation {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Room banned, removed all our local users, and disabled incoming federation with room.",
+		));
+	}
+
+	Ok(RoomMessageEventContent::text_plain(
+		"Room banned and removed all our local users, use `!admin federation disable-room` to stop receiving new \
+		 inbound federation events as well if needed.",
+	))
+}
+
+/// Bans a room by ID or alias and makes our local users leave it, including
+/// admins if `force` is set. Returns the banned room's ID.
+pub(crate) async fn ban_room_and_evict(
+	room: &RoomOrAliasId, force: bool, disable_federation: bool,
+) -> Result<OwnedRoomId> {
 	let admin_room_alias = &services().globals.admin_alias;
 
 	if let Some(admin_room_id) = Service::get_admin_room()? {
 		if room.to_string().eq(&admin_room_id) || room.to_string().eq(admin_room_alias) {
-			return Ok(RoomMessageEventContent::text_plain("Not allowed to ban the admin room."));
+			return Err(Error::BadRequest(ErrorKind::forbidden(), "Not allowed to ban the admin room."));
 		}
 	}
 
 	let room_id = if room.is_room_id() {
-		let room_id = match RoomId::parse(&room) {
-			Ok(room_id) => room_id,
-			Err(e) => {
-				return Ok(RoomMessageEventContent::text_plain(format!(
-					"Failed to parse room ID {room}. Please note that this requires a full room ID \
-					 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`): {e}"
-				)))
-			},
-		};
+		let room_id = RoomId::parse(room).map_err(|e| {
+			Error::Err(format!(
+				"Failed to parse room ID {room}. Please note that this requires a full room ID \
+				 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`): {e}"
+			))
+		})?;
 
 		debug!("Room specified is a room ID, banning room ID");
 
@@ -56,15 +74,12 @@ async fn ban_room(
 
 		room_id
 	} else if room.is_room_alias_id() {
-		let room_alias = match RoomAliasId::parse(&room) {
-			Ok(room_alias) => room_alias,
-			Err(e) => {
-				return Ok(RoomMessageEventContent::text_plain(format!(
-					"Failed to parse room ID {room}. Please note that this requires a full room ID \
-					 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`): {e}"
-				)))
-			},
-		};
+		let room_alias = RoomAliasId::parse(room).map_err(|e| {
+			Error::Err(format!(
+				"Failed to parse room ID {room}. Please note that this requires a full room ID \
+				 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`): {e}"
+			))
+		})?;
 
 		debug!(
 			"Room specified is not a room ID, attempting to resolve room alias to a room ID locally, if not using \
@@ -76,31 +91,25 @@ async fn ban_room(
 		} else {
 			debug!("We don't have this room alias to a room ID locally, attempting to fetch room ID over federation");
 
-			match services()
+			let (room_id, servers) = services()
 				.rooms
 				.alias
 				.resolve_alias(&room_alias, None)
 				.await
-			{
-				Ok((room_id, servers)) => {
-					debug!(?room_id, ?servers, "Got federation response fetching room ID for {room}");
-					room_id
-				},
-				Err(e) => {
-					return Ok(RoomMessageEventContent::notice_plain(format!(
-						"Failed to resolve room alias {room} to a room ID: {e}"
-					)));
-				},
-			}
+				.map_err(|e| Error::Err(format!("Failed to resolve room alias {room} to a room ID: {e}")))?;
+
+			debug!(?room_id, ?servers, "Got federation response fetching room ID for {room}");
+			room_id
 		};
 
 		services().rooms.metadata.ban_room(&room_id, true)?;
 
 		room_id
 	} else {
-		return Ok(RoomMessageEventContent::text_plain(
+		return Err(Error::Err(
 			"Room specified is not a room ID or room alias. Please note that this requires a full room ID \
-			 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`)",
+			 (`!awIh6gGInaS5wLQJwa:example.com`) or a room alias (`#roomalias:example.com`)"
+				.to_owned(),
 		));
 	};
 
@@ -162,7 +171,7 @@ async fn ban_room(
 					"Error attempting to make local user {} leave room {} during room banning: {}",
 					&local_user, &room_id, e
 				);
-				return Ok(RoomMessageEventContent::text_plain(format!(
+				return Err(Error::Err(format!(
 					"Error attempting to make local user {} leave room {} during room banning (room is still banned \
 					 but not removing any more users): {}\nIf you would like to ignore errors, use --force",
 					&local_user, &room_id, e
@@ -173,15 +182,9 @@ async fn ban_room(
 
 	if disable_federation {
 		services().rooms.metadata.disable_room(&room_id, true)?;
-		return Ok(RoomMessageEventContent::text_plain(
-			"Room banned, removed all our local users, and disabled incoming federation with room.",
-		));
 	}
 
-	Ok(RoomMessageEventContent::text_plain(
-		"Room banned and removed all our local users, use `!admin federation disable-room` to stop receiving new \
-		 inbound federation events as well if needed.",
-	))
+	Ok(room_id)
 }
 
 async fn ban_list_of_rooms(body: Vec<&str>, force: bool, disable_federation: bool) -> Result<RoomMessageEventContent> {
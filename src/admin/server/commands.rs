@@ -1,7 +1,7 @@
 use conduit::{warn, Error, Result};
 use ruma::events::room::message::RoomMessageEventContent;
 
-use crate::services;
+use crate::{services, utils::parse_active_local_user_id};
 
 pub(super) async fn uptime(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
 	let seconds = services()
@@ -64,21 +64,25 @@ pub(super) async fn list_backups(_body: Vec<&str>) -> Result<RoomMessageEventCon
 }
 
 pub(super) async fn backup_database(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
-	let mut result = services()
+	let result = match backup().await {
+		Ok(backups) => backups,
+		Err(e) => e.to_string(),
+	};
+
+	Ok(RoomMessageEventContent::text_plain(&result))
+}
+
+/// Performs an online backup of the database and returns the list of backups.
+pub(crate) async fn backup() -> Result<String> {
+	services()
 		.server
 		.runtime()
-		.spawn_blocking(move || match services().globals.db.backup() {
-			Ok(()) => String::new(),
-			Err(e) => (*e).to_string(),
-		})
+		.spawn_blocking(move || services().globals.db.backup().map_err(|e| e.to_string()))
 		.await
-		.unwrap();
+		.map_err(|e| Error::Err(format!("Backup task failed: {e}")))?
+		.map_err(Error::Err)?;
 
-	if result.is_empty() {
-		result = services().globals.db.backup_list()?;
-	}
-
-	Ok(RoomMessageEventContent::text_plain(&result))
+	services().globals.db.backup_list()
 }
 
 pub(super) async fn list_database_files(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -94,6 +98,27 @@ pub(super) async fn admin_notice(_body: Vec<&str>, message: Vec<String>) -> Resu
 	Ok(RoomMessageEventContent::notice_plain("Notice was sent to #admins"))
 }
 
+pub(super) async fn server_notice(
+	_body: Vec<&str>, user_id: Option<String>, message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let content = RoomMessageEventContent::text_markdown(message.join(" "));
+
+	if let Some(user_id) = user_id {
+		let user_id = parse_active_local_user_id(&user_id)?;
+		services().server_notices.send(&user_id, &content).await?;
+
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"Server notice was sent to {user_id}."
+		)));
+	}
+
+	let sent = services().server_notices.send_all(&content).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Server notice was sent to {sent} user(s)."
+	)))
+}
+
 #[cfg(conduit_mods)]
 pub(super) async fn reload(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
 	services().server.reload()?;
@@ -4,6 +4,7 @@ use clap::Subcommand;
 use conduit::Result;
 use ruma::events::room::message::RoomMessageEventContent;
 
+pub(crate) use self::commands::backup;
 use self::commands::*;
 
 #[cfg_attr(test, derive(Debug))]
@@ -45,6 +46,18 @@ pub(super) enum ServerCommand {
 		message: Vec<String>,
 	},
 
+	/// - Send a server notice to a local user, or to all local users
+	///
+	/// The notice is sent in the user's server notices room, which is created
+	/// if needed. Markdown is supported.
+	ServerNotice {
+		/// The user to send the notice to, every active local user if not given
+		#[arg(short, long)]
+		user_id: Option<String>,
+
+		message: Vec<String>,
+	},
+
 	#[cfg(conduit_mods)]
 	/// - Hot-reload the server
 	Reload,
@@ -77,6 +90,10 @@ pub(super) async fn process(command: ServerCommand, body: Vec<&str>) -> Result<R
 		ServerCommand::AdminNotice {
 			message,
 		} => admin_notice(body, message).await?,
+		ServerCommand::ServerNotice {
+			user_id,
+			message,
+		} => server_notice(body, user_id, message).await?,
 		#[cfg(conduit_mods)]
 		ServerCommand::Reload => reload(body).await?,
 		#[cfg(unix)]
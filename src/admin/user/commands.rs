@@ -1,19 +1,20 @@
 use std::{collections::BTreeMap, fmt::Write as _};
 
 use api::client::{join_room_by_id_helper, leave_all_rooms, update_avatar_url, update_displayname};
-use conduit::{utils, Result};
+use conduit::{utils, Error, Result};
 use ruma::{
+	api::client::error::ErrorKind,
 	events::{
 		room::message::RoomMessageEventContent,
 		tag::{TagEvent, TagEventContent, TagInfo},
 		RoomAccountDataEventType,
 	},
-	OwnedRoomId, OwnedUserId, RoomId,
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use tracing::{error, info, warn};
 
 use crate::{
-	escape_html, get_room_info, services,
+	escape_html, get_room_info, services, user_is_local,
 	utils::{parse_active_local_user_id, parse_local_user_id},
 };
 
@@ -42,10 +43,32 @@ pub(super) async fn create(
 		return Ok(RoomMessageEventContent::text_plain(format!("Userid {user_id} already exists")));
 	}
 
+	let password = create_user(&user_id, password).await?;
+
+	// Inhibit login does not work for guests
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Created user with user_id: {user_id} and password: `{password}`"
+	)))
+}
+
+/// Creates a local user with the given password, or a generated one which is
+/// returned, and joins them to the auto-join rooms.
+pub(crate) async fn create_user(user_id: &UserId, password: Option<String>) -> Result<String> {
+	if !user_is_local(user_id) {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"User does not belong to our server.",
+		));
+	}
+
+	if services().users.exists(user_id)? {
+		return Err(Error::BadRequest(ErrorKind::UserInUse, "User ID already taken."));
+	}
+
 	let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
 	// Create user
-	services().users.create(&user_id, Some(password.as_str()))?;
+	services().users.create(user_id, Some(password.as_str()))?;
 
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
@@ -64,19 +87,19 @@ pub(super) async fn create(
 
 	services()
 		.users
-		.set_displayname(&user_id, Some(displayname))
+		.set_displayname(user_id, Some(displayname))
 		.await?;
 
 	// Initial account data
 	services().account_data.update(
 		None,
-		&user_id,
+		user_id,
 		ruma::events::GlobalAccountDataEventType::PushRules
 			.to_string()
 			.into(),
 		&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
 			content: ruma::events::push_rules::PushRulesEventContent {
-				global: ruma::push::Ruleset::server_default(&user_id),
+				global: ruma::push::Ruleset::server_default(user_id),
 			},
 		})
 		.expect("to json value always works"),
@@ -95,7 +118,7 @@ pub(super) async fn create(
 
 			if let Some(room_id_server_name) = room.server_name() {
 				match join_room_by_id_helper(
-					Some(&user_id),
+					Some(user_id),
 					room,
 					Some("Automatically joining this room upon registration".to_owned()),
 					&[room_id_server_name.to_owned(), services().globals.server_name().to_owned()],
@@ -117,10 +140,7 @@ pub(super) async fn create(
 
 	// we dont add a device since we're not the user, just the creator
 
-	// Inhibit login does not work for guests
-	Ok(RoomMessageEventContent::text_plain(format!(
-		"Created user with user_id: {user_id} and password: `{password}`"
-	)))
+	Ok(password)
 }
 
 pub(super) async fn deactivate(
@@ -136,8 +156,6 @@ pub(super) async fn deactivate(
 		));
 	}
 
-	services().users.deactivate_account(&user_id)?;
-
 	if !no_leave_rooms {
 		services()
 			.admin
@@ -145,21 +163,230 @@ pub(super) async fn deactivate(
 				"Making {user_id} leave all rooms after deactivation..."
 			)))
 			.await;
+	}
+
+	deactivate_user(&user_id, !no_leave_rooms).await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been deactivated"
+	)))
+}
+
+pub(super) async fn shadow_ban(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(&user_id)?;
+
+	if user_id == services().globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to shadow-ban the server service account.",
+		));
+	}
+
+	if services().users.is_admin(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to shadow-ban an admin, their commands would be dropped.",
+		));
+	}
+
+	if services().users.is_shadow_banned(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already shadow-banned."
+		)));
+	}
+
+	services().users.set_shadow_banned(&user_id, true)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been shadow-banned."
+	)))
+}
+
+pub(super) async fn unshadow_ban(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+
+	if !services().users.is_shadow_banned(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is not shadow-banned."
+		)));
+	}
+
+	services().users.set_shadow_banned(&user_id, false)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} is no longer shadow-banned."
+	)))
+}
+
+pub(super) async fn suspend(
+	_body: Vec<&str>, user_id: String, reason: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(&user_id)?;
+
+	if let Some(message) = check_restrictable(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(message));
+	}
+
+	if services().users.suspension(&user_id)?.is_some() {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already suspended."
+		)));
+	}
+
+	services().users.suspend(&user_id, reason.clone())?;
+	info!("Suspended user {user_id}, reason: {reason:?}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been suspended."
+	)))
+}
+
+pub(super) async fn unsuspend(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+
+	if services().users.suspension(&user_id)?.is_none() {
+		return Ok(RoomMessageEventContent::text_plain(format!("User {user_id} is not suspended.")));
+	}
+
+	services().users.unsuspend(&user_id)?;
+	info!("Lifted the suspension of user {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} is no longer suspended."
+	)))
+}
+
+pub(super) async fn lock(_body: Vec<&str>, user_id: String, reason: Option<String>) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(&user_id)?;
+
+	if let Some(message) = check_restrictable(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(message));
+	}
+
+	if services().users.account_lock(&user_id)?.is_some() {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already locked."
+		)));
+	}
+
+	services().users.lock_account(&user_id, reason.clone())?;
+	info!("Locked the account of user {user_id}, reason: {reason:?}");
+
+	Ok(RoomMessageEventContent::text_plain(format!("User {user_id} has been locked.")))
+}
+
+pub(super) async fn unlock(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(&user_id)?;
+
+	if services().users.account_lock(&user_id)?.is_none() {
+		return Ok(RoomMessageEventContent::text_plain(format!("User {user_id} is not locked.")));
+	}
+
+	services().users.unlock_account(&user_id)?;
+	info!("Unlocked the account of user {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} is no longer locked."
+	)))
+}
+
+pub(super) async fn list_restricted(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	let mut msg = String::new();
+	for (kind, users) in [
+		(
+			"Suspended",
+			services()
+				.users
+				.suspended_users()
+				.collect::<Result<Vec<_>>>()?,
+		),
+		(
+			"Locked",
+			services()
+				.users
+				.locked_users()
+				.collect::<Result<Vec<_>>>()?,
+		),
+	] {
+		writeln!(msg, "{kind} users ({}):", users.len()).expect("should be able to write to string buffer");
+		for (user_id, restriction) in users {
+			let since = utils::time::rfc2822_from_millis(restriction.since);
+			writeln!(
+				msg,
+				"- {user_id} since {since}: {}",
+				restriction.reason.as_deref().unwrap_or("no reason given")
+			)
+			.expect("should be able to write to string buffer");
+		}
+		msg.push('\n');
+	}
+
+	Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
+}
+
+pub(super) async fn link_sso(
+	_body: Vec<&str>, user_id: String, idp_id: String, subject: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(&user_id)?;
+
+	let previous = services().sso.link(&idp_id, &subject, &user_id)?;
+	info!("Linked subject {subject} of identity provider {idp_id} to user {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(match previous {
+		Some(previous) if previous != user_id => {
+			format!("Linked subject {subject} of {idp_id} to {user_id}, it was linked to {previous} before.")
+		},
+		_ => format!("Linked subject {subject} of {idp_id} to {user_id}."),
+	}))
+}
+
+/// Why the user can't be suspended or locked, if they can't
+fn check_restrictable(user_id: &UserId) -> Result<Option<&'static str>> {
+	if user_id == services().globals.server_user {
+		return Ok(Some("Not allowed to restrict the server service account."));
+	}
 
+	if services().users.is_admin(user_id)? {
+		return Ok(Some("Not allowed to restrict an admin, they could not lift it anymore."));
+	}
+
+	Ok(None)
+}
+
+/// Deactivates a local user, clearing their profile and making them leave all
+/// joined rooms if `leave_rooms` is set.
+pub(crate) async fn deactivate_user(user_id: &UserId, leave_rooms: bool) -> Result<()> {
+	if !user_is_local(user_id) {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"User does not belong to our server.",
+		));
+	}
+
+	if user_id == services().globals.server_user {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Not allowed to deactivate the server service account.",
+		));
+	}
+
+	if !services().users.exists(user_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "User does not exist on this server."));
+	}
+
+	services().users.deactivate_account(user_id)?;
+
+	if leave_rooms {
 		let all_joined_rooms: Vec<OwnedRoomId> = services()
 			.rooms
 			.state_cache
-			.rooms_joined(&user_id)
+			.rooms_joined(user_id)
 			.filter_map(Result::ok)
 			.collect();
-		update_displayname(user_id.clone(), None, all_joined_rooms.clone()).await?;
-		update_avatar_url(user_id.clone(), None, None, all_joined_rooms).await?;
-		leave_all_rooms(&user_id).await;
+		update_displayname(user_id.to_owned(), None, all_joined_rooms.clone()).await?;
+		update_avatar_url(user_id.to_owned(), None, None, all_joined_rooms).await?;
+		leave_all_rooms(user_id).await;
 	}
 
-	Ok(RoomMessageEventContent::text_plain(format!(
-		"User {user_id} has been deactivated"
-	)))
+	Ok(())
 }
 
 pub(super) async fn reset_password(_body: Vec<&str>, username: String) -> Result<RoomMessageEventContent> {
@@ -171,13 +398,8 @@ pub(super) async fn reset_password(_body: Vec<&str>, username: String) -> Result
 		));
 	}
 
-	let new_password = utils::random_string(AUTO_GEN_PASSWORD_LENGTH);
-
-	match services()
-		.users
-		.set_password(&user_id, Some(new_password.as_str()))
-	{
-		Ok(()) => Ok(RoomMessageEventContent::text_plain(format!(
+	match reset_user_password(&user_id) {
+		Ok(new_password) => Ok(RoomMessageEventContent::text_plain(format!(
 			"Successfully reset the password for user {user_id}: `{new_password}`"
 		))),
 		Err(e) => Ok(RoomMessageEventContent::text_plain(format!(
@@ -186,6 +408,27 @@ pub(super) async fn reset_password(_body: Vec<&str>, username: String) -> Result
 	}
 }
 
+/// Sets a generated password for a local user and returns it.
+pub(crate) fn reset_user_password(user_id: &UserId) -> Result<String> {
+	if !user_is_local(user_id) || !services().users.exists(user_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "User does not exist on this server."));
+	}
+
+	if user_id == services().globals.server_user {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Not allowed to set the password for the server account. Please use the emergency password config option.",
+		));
+	}
+
+	let new_password = utils::random_string(AUTO_GEN_PASSWORD_LENGTH);
+	services()
+		.users
+		.set_password(user_id, Some(new_password.as_str()))?;
+
+	Ok(new_password)
+}
+
 pub(super) async fn deactivate_all(
 	body: Vec<&str>, no_leave_rooms: bool, force: bool,
 ) -> Result<RoomMessageEventContent> {
@@ -5,6 +5,7 @@ use conduit::Result;
 use ruma::{events::room::message::RoomMessageEventContent, RoomId};
 
 use self::commands::*;
+pub(crate) use self::commands::{create_user, deactivate_user, reset_user_password};
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Subcommand)]
@@ -56,6 +57,68 @@ pub(super) enum UserCommand {
 		force: bool,
 	},
 
+	/// - Shadow-ban a user
+	///
+	/// Their messages, state events, invites and membership updates from
+	/// profile changes are accepted as if they were sent, but silently
+	/// dropped.
+	///
+	/// The user can still tell: their dropped events never come down sync,
+	/// and their own profile keeps the old displayname and avatar.
+	ShadowBan {
+		user_id: String,
+	},
+
+	/// - Lift the shadow-ban of a user
+	UnshadowBan {
+		user_id: String,
+	},
+
+	/// - Suspend a user
+	///
+	/// They can still read and leave rooms, but not send events, join, invite,
+	/// create rooms, upload media or change their profile.
+	Suspend {
+		user_id: String,
+		/// Reason recorded with the suspension
+		#[arg(short, long)]
+		reason: Option<String>,
+	},
+
+	/// - Lift the suspension of a user
+	Unsuspend {
+		user_id: String,
+	},
+
+	/// - Lock a user's account
+	///
+	/// All of their requests are refused and their clients are prompted to log
+	/// out, until the account is unlocked.
+	Lock {
+		user_id: String,
+		/// Reason recorded with the lock
+		#[arg(short, long)]
+		reason: Option<String>,
+	},
+
+	/// - Unlock a user's account
+	Unlock {
+		user_id: String,
+	},
+
+	/// - List suspended and locked users with the reasons given
+	ListRestricted,
+
+	/// - Link an identity provider's subject to a user
+	///
+	/// They can then log in to their existing account through SSO. The
+	/// subject is the `sub` claim of the provider's ID tokens.
+	LinkSso {
+		user_id: String,
+		idp_id: String,
+		subject: String,
+	},
+
 	/// - List local users in the database
 	List,
 
@@ -110,6 +173,32 @@ pub(super) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 			no_leave_rooms,
 			force,
 		} => deactivate_all(body, no_leave_rooms, force).await?,
+		UserCommand::ShadowBan {
+			user_id,
+		} => shadow_ban(body, user_id).await?,
+		UserCommand::UnshadowBan {
+			user_id,
+		} => unshadow_ban(body, user_id).await?,
+		UserCommand::Suspend {
+			user_id,
+			reason,
+		} => suspend(body, user_id, reason).await?,
+		UserCommand::Unsuspend {
+			user_id,
+		} => unsuspend(body, user_id).await?,
+		UserCommand::Lock {
+			user_id,
+			reason,
+		} => lock(body, user_id, reason).await?,
+		UserCommand::Unlock {
+			user_id,
+		} => unlock(body, user_id).await?,
+		UserCommand::ListRestricted => list_restricted(body).await?,
+		UserCommand::LinkSso {
+			user_id,
+			idp_id,
+			subject,
+		} => link_sso(body, user_id, idp_id, subject).await?,
 		UserCommand::ListJoinedRooms {
 			user_id,
 		} => list_joined_rooms(body, user_id).await?,
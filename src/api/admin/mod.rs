@@ -0,0 +1,60 @@
+mod registration_tokens;
+mod tests;
+
+use axum::{async_trait, extract::FromRequestParts, RequestPartsExt};
+use axum_extra::{
+	headers::{authorization::Bearer, Authorization},
+	TypedHeader,
+};
+use http::{request::Parts, Method};
+use ruma::{api::client::error::ErrorKind, OwnedUserId};
+use serde::Deserialize;
+
+pub(crate) use self::registration_tokens::*;
+use crate::{router::check_restrictions, services, Error, Result};
+
+/// Extractor authenticating admin API requests with the access token of a
+/// server admin, from the `Authorization` header or `access_token` query
+/// parameter. Locked admins are refused, suspended ones may only read.
+pub struct Admin(pub OwnedUserId);
+
+#[derive(Deserialize)]
+struct AccessToken {
+	access_token: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+	S: Send + Sync,
+{
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+		let bearer: Option<TypedHeader<Authorization<Bearer>>> = parts.extract().await?;
+		let token = match &bearer {
+			Some(TypedHeader(Authorization(bearer))) => Some(bearer.token().to_owned()),
+			None => serde_html_form::from_str::<AccessToken>(parts.uri.query().unwrap_or_default())
+				.ok()
+				.and_then(|query| query.access_token),
+		};
+
+		let token = token.ok_or(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."))?;
+		let Some((user_id, _)) = services().users.find_from_token(&token)? else {
+			return Err(Error::BadRequest(
+				ErrorKind::UnknownToken {
+					soft_logout: false,
+				},
+				"Unknown access token.",
+			));
+		};
+
+		check_restrictions(&user_id, false, || parts.method == Method::GET)?;
+
+		if !services().users.is_admin(&user_id)? {
+			return Err(Error::BadRequest(ErrorKind::forbidden(), "You are not a server admin."));
+		}
+
+		Ok(Self(user_id))
+	}
+}
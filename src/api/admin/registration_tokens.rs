@@ -0,0 +1,114 @@
+use axum::{
+	extract::{Json, Path},
+	response::IntoResponse,
+};
+use conduit::utils;
+use http::Uri;
+use ruma::api::client::error::ErrorKind;
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+
+use super::Admin;
+use crate::{services, Error, Result};
+
+#[derive(Deserialize)]
+struct ListQuery {
+	valid: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreateBody {
+	token: Option<String>,
+	length: Option<usize>,
+	uses_allowed: Option<u32>,
+	expiry_time: Option<u64>,
+}
+
+/// Fields left out are unchanged, `null` removes the limit.
+#[derive(Deserialize)]
+pub(crate) struct UpdateBody {
+	#[serde(default, deserialize_with = "double_option")]
+	uses_allowed: Option<Option<u32>>,
+	#[serde(default, deserialize_with = "double_option")]
+	expiry_time: Option<Option<u64>>,
+}
+
+/// # `GET /_synapse/admin/v1/registration_tokens`
+///
+/// Lists registration tokens, optionally only those which are still `valid` or
+/// not.
+pub(crate) async fn list_registration_tokens_route(_: Admin, uri: Uri) -> Result<impl IntoResponse> {
+	let query: ListQuery = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid query parameters."))?;
+
+	let now = utils::millis_since_unix_epoch();
+	let tokens: Vec<_> = services()
+		.registration_tokens
+		.list()?
+		.into_iter()
+		.filter(|token| {
+			query
+				.valid
+				.map_or(true, |valid| token.is_valid(now) == valid)
+		})
+		.collect();
+
+	Ok(Json(json!({
+		"registration_tokens": tokens,
+	})))
+}
+
+/// # `GET /_synapse/admin/v1/registration_tokens/{token}`
+pub(crate) async fn get_registration_token_route(_: Admin, Path(token): Path<String>) -> Result<impl IntoResponse> {
+	let token = services()
+		.registration_tokens
+		.get(&token)?
+		.ok_or(Error::BadRequest(ErrorKind::NotFound, "Registration token not found."))?;
+
+	Ok(Json(token))
+}
+
+/// # `POST /_synapse/admin/v1/registration_tokens/new`
+///
+/// Creates a registration token, a random one of `length` characters if no
+/// `token` is given.
+pub(crate) async fn create_registration_token_route(
+	_: Admin, Json(body): Json<CreateBody>,
+) -> Result<impl IntoResponse> {
+	let token = services()
+		.registration_tokens
+		.create(body.token, body.length, body.uses_allowed, body.expiry_time)?;
+
+	Ok(Json(token))
+}
+
+/// # `PUT /_synapse/admin/v1/registration_tokens/{token}`
+///
+/// Changes the limits of a registration token.
+pub(crate) async fn update_registration_token_route(
+	_: Admin, Path(token): Path<String>, Json(body): Json<UpdateBody>,
+) -> Result<impl IntoResponse> {
+	let token = services()
+		.registration_tokens
+		.update(&token, body.uses_allowed, body.expiry_time)?;
+
+	Ok(Json(token))
+}
+
+/// # `DELETE /_synapse/admin/v1/registration_tokens/{token}`
+pub(crate) async fn delete_registration_token_route(_: Admin, Path(token): Path<String>) -> Result<impl IntoResponse> {
+	if !services().registration_tokens.revoke(&token)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Registration token not found."));
+	}
+
+	Ok(Json(json!({})))
+}
+
+/// Tells a field set to `null` apart from a missing one.
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de>,
+{
+	Option::<T>::deserialize(deserializer).map(Some)
+}
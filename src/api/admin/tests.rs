@@ -0,0 +1,49 @@
+#![cfg(test)]
+
+use axum::extract::FromRequestParts;
+use http::{header, Method, Request};
+use ruma::{api::client::error::ErrorKind, device_id, user_id};
+use service::tests::services;
+
+use super::Admin;
+use crate::{Error, Result};
+
+async fn extract(method: Method, token: &str) -> Result<Admin> {
+	let (mut parts, ()) = Request::builder()
+		.method(method)
+		.uri("/_conduwuit/admin/v1/users")
+		.header(header::AUTHORIZATION, format!("Bearer {token}"))
+		.body(())
+		.expect("valid request")
+		.into_parts();
+
+	Admin::from_request_parts(&mut parts, &()).await
+}
+
+#[tokio::test]
+async fn restricted_accounts() {
+	let users = &services().await.users;
+	let (suspended, locked) = (user_id!("@admin-suspended:example.com"), user_id!("@admin-locked:example.com"));
+	for (user_id, token) in [(suspended, "suspended-token"), (locked, "locked-token")] {
+		users.create(user_id, Some("password")).unwrap();
+		users
+			.create_device(user_id, device_id!("DEVICE"), token, None)
+			.unwrap();
+	}
+
+	users.suspend(suspended, None).unwrap();
+	users.lock_account(locked, None).unwrap();
+
+	assert!(
+		matches!(
+			extract(Method::GET, "suspended-token").await,
+			Err(Error::BadRequest(ErrorKind::Forbidden { .. }, _))
+		),
+		"suspended users get as far as the admin check when reading"
+	);
+	assert!(matches!(
+		extract(Method::POST, "suspended-token").await,
+		Err(Error::UserSuspended(_))
+	));
+	assert!(matches!(extract(Method::GET, "locked-token").await, Err(Error::UserLocked(_))));
+}
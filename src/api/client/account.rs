@@ -1,27 +1,34 @@
-use std::fmt::Write;
-
+use axum::{
+	response::{Html, IntoResponse, Redirect},
+	Json,
+};
 use axum_client_ip::InsecureClientIp;
 use conduit::debug_info;
+use http::Uri;
 use register::RegistrationKind;
 use ruma::{
 	api::client::{
 		account::{
-			change_password, check_registration_token_validity, deactivate, get_3pids, get_username_availability,
+			add_3pid, change_password, check_registration_token_validity, deactivate, delete_3pid, get_3pids,
+			get_username_availability,
 			register::{self, LoginType},
-			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn, whoami,
+			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
+			request_password_change_token_via_email, request_password_change_token_via_msisdn, whoami,
 			ThirdPartyIdRemovalStatus,
 		},
 		error::ErrorKind,
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, UiaaInfo},
 	},
-	events::{room::message::RoomMessageEventContent, GlobalAccountDataEventType},
-	push, OwnedRoomId, UserId,
+	events::room::message::RoomMessageEventContent,
+	thirdparty::Medium,
+	OwnedClientSecret, OwnedRoomId, OwnedSessionId, UserId,
 };
+use serde::Deserialize;
 use tracing::{error, info, warn};
 
 use super::{join_room_by_id_helper, DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::{
-	service::user_is_local,
+	service::{ratelimit::Limit, threepid::Purpose, user_is_local},
 	services,
 	utils::{self},
 	Error, Result, Ruma,
@@ -29,6 +36,28 @@ use crate::{
 
 const RANDOM_USER_ID_LENGTH: usize = 10;
 
+/// Parameters of the link in validation emails, or the body clients send text
+/// message codes in.
+#[derive(Deserialize)]
+pub(crate) struct SubmitToken {
+	sid: OwnedSessionId,
+	client_secret: OwnedClientSecret,
+	token: String,
+}
+
+const VALIDATED_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Address validated</title>
+</head>
+<body>
+<p>Your email address has been validated, you can return to your client.</p>
+</body>
+</html>
+"#;
+
 /// # `GET /_matrix/client/v3/register/available`
 ///
 /// Checks if a username is valid and available on this server.
@@ -51,7 +80,7 @@ pub(crate) async fn get_register_available_route(
 		.ok_or(Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
 
 	// Check if username is creative enough
-	if services().users.exists(&user_id)? {
+	if services().users.exists(&user_id)? || services().server_notices.is_notices_user(&user_id) {
 		return Err(Error::BadRequest(ErrorKind::UserInUse, "Desired user ID is already taken."));
 	}
 
@@ -102,11 +131,17 @@ pub(crate) async fn register_route(
 		return Err(Error::BadRequest(ErrorKind::forbidden(), "Registration has been disabled."));
 	}
 
+	if body.appservice_info.is_none() {
+		services()
+			.ratelimit
+			.check_ip(Limit::Registration, body.client)?;
+	}
+
 	let is_guest = body.kind == RegistrationKind::Guest;
 
 	if is_guest
 		&& (!services().globals.allow_guest_registration()
-			|| (services().globals.allow_registration() && services().globals.config.registration_token.is_some()))
+			|| (services().globals.allow_registration() && services().registration_tokens.required()))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, rejecting guest registration \
@@ -138,7 +173,9 @@ pub(crate) async fn register_route(
 					.filter(|user_id| !user_id.is_historical() && user_is_local(user_id))
 					.ok_or(Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
 
-			if services().users.exists(&proposed_user_id)? {
+			if services().users.exists(&proposed_user_id)?
+				|| services().server_notices.is_notices_user(&proposed_user_id)
+			{
 				return Err(Error::BadRequest(ErrorKind::UserInUse, "Desired user ID is already taken."));
 			}
 
@@ -178,7 +215,7 @@ pub(crate) async fn register_route(
 
 	// UIAA
 	let mut uiaainfo;
-	let skip_auth = if services().globals.config.registration_token.is_some() {
+	let skip_auth = if services().registration_tokens.required() {
 		// Registration token required
 		uiaainfo = UiaaInfo {
 			flows: vec![AuthFlow {
@@ -204,17 +241,23 @@ pub(crate) async fn register_route(
 		body.appservice_info.is_some() || is_guest
 	};
 
+	let mut uiaa_session = None;
 	if !skip_auth {
 		if let Some(auth) = &body.auth {
-			let (worked, uiaainfo) = services().uiaa.try_auth(
-				&UserId::parse_with_server_name("", services().globals.server_name()).expect("we know this is valid"),
-				"".into(),
-				auth,
-				&uiaainfo,
-			)?;
+			let (worked, uiaainfo) = services()
+				.uiaa
+				.try_auth(
+					&UserId::parse_with_server_name("", services().globals.server_name())
+						.expect("we know this is valid"),
+					"".into(),
+					auth,
+					&uiaainfo,
+				)
+				.await?;
 			if !worked {
 				return Err(Error::Uiaa(uiaainfo));
 			}
+			uiaa_session = uiaainfo.session;
 		// Success!
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
@@ -237,35 +280,13 @@ pub(crate) async fn register_route(
 	};
 
 	// Create user
-	services().users.create(&user_id, password)?;
-
-	// Default to pretty displayname
-	let mut displayname = user_id.localpart().to_owned();
-
-	// If `new_user_displayname_suffix` is set, registration will push whatever
-	// content is set to the user's display name with a space before it
-	if !services().globals.new_user_displayname_suffix().is_empty() {
-		write!(displayname, " {}", services().globals.config.new_user_displayname_suffix)
-			.expect("should be able to write to string buffer");
-	}
-
 	services()
 		.users
-		.set_displayname(&user_id, Some(displayname.clone()))
+		.register(&user_id, password, None, is_guest)
 		.await?;
-
-	// Initial account data
-	services().account_data.update(
-		None,
-		&user_id,
-		GlobalAccountDataEventType::PushRules.to_string().into(),
-		&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
-			content: ruma::events::push_rules::PushRulesEventContent {
-				global: push::Ruleset::server_default(&user_id),
-			},
-		})
-		.expect("to json always works"),
-	)?;
+	if let Some(session) = &uiaa_session {
+		services().registration_tokens.complete(session)?;
+	}
 
 	// Inhibit login does not work for guests
 	if !is_guest && body.inhibit_login {
@@ -343,54 +364,8 @@ pub(crate) async fn register_route(
 		}
 	}
 
-	// If this is the first real user, grant them admin privileges except for guest
-	// users Note: the server user, @conduit:servername, is generated first
-	if !is_guest {
-		if let Some(admin_room) = service::admin::Service::get_admin_room()? {
-			if services()
-				.rooms
-				.state_cache
-				.room_joined_count(&admin_room)?
-				== Some(1)
-			{
-				service::admin::make_user_admin(&user_id, displayname).await?;
-
-				warn!("Granting {user_id} admin privileges as the first user");
-			}
-		}
-	}
-
-	if body.appservice_info.is_none()
-		&& !services().globals.config.auto_join_rooms.is_empty()
-		&& (services().globals.allow_guests_auto_join_rooms() || !is_guest)
-	{
-		for room in &services().globals.config.auto_join_rooms {
-			if !services()
-				.rooms
-				.state_cache
-				.server_in_room(services().globals.server_name(), room)?
-			{
-				warn!("Skipping room {room} to automatically join as we have never joined before.");
-				continue;
-			}
-
-			if let Some(room_id_server_name) = room.server_name() {
-				if let Err(e) = join_room_by_id_helper(
-					Some(&user_id),
-					room,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[room_id_server_name.to_owned(), services().globals.server_name().to_owned()],
-					None,
-				)
-				.await
-				{
-					// don't return this error so we don't fail registrations
-					error!("Failed to automatically join room {room} for user {user_id}: {e}");
-				} else {
-					info!("Automatically joined room {room} for user {user_id}");
-				};
-			}
-		}
+	if body.appservice_info.is_none() && (services().globals.allow_guests_auto_join_rooms() || !is_guest) {
+		auto_join_rooms(&user_id).await?;
 	}
 
 	Ok(register::v3::Response {
@@ -402,12 +377,47 @@ pub(crate) async fn register_route(
 	})
 }
 
+/// Joins a newly registered user to the rooms in `auto_join_rooms`.
+pub(crate) async fn auto_join_rooms(user_id: &UserId) -> Result<()> {
+	for room in &services().globals.config.auto_join_rooms {
+		if !services()
+			.rooms
+			.state_cache
+			.server_in_room(services().globals.server_name(), room)?
+		{
+			warn!("Skipping room {room} to automatically join as we have never joined before.");
+			continue;
+		}
+
+		if let Some(room_id_server_name) = room.server_name() {
+			if let Err(e) = join_room_by_id_helper(
+				Some(user_id),
+				room,
+				Some("Automatically joining this room upon registration".to_owned()),
+				&[room_id_server_name.to_owned(), services().globals.server_name().to_owned()],
+				None,
+			)
+			.await
+			{
+				// don't return this error so we don't fail registrations
+				error!("Failed to automatically join room {room} for user {user_id}: {e}");
+			} else {
+				info!("Automatically joined room {room} for user {user_id}");
+			};
+		}
+	}
+
+	Ok(())
+}
+
 /// # `POST /_matrix/client/r0/account/password`
 ///
 /// Changes the password of this account.
 ///
 /// - Requires UIAA to verify user password
 /// - Changes the password of the sender user
+/// - Without an access token, resets the password of the user an email address
+///   validated through `/account/password/email/requestToken` is bound to
 /// - The password hash is calculated using argon2 with 32 character salt, the
 ///   plain password is
 /// not saved
@@ -423,13 +433,13 @@ pub(crate) async fn register_route(
 pub(crate) async fn change_password_route(
 	InsecureClientIp(client): InsecureClientIp, body: Ruma<change_password::v3::Request>,
 ) -> Result<change_password::v3::Response> {
-	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let Some(sender_user) = body.sender_user.as_ref() else {
+		return reset_password(body).await;
+	};
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	let mut uiaainfo = UiaaInfo {
-		flows: vec![AuthFlow {
-			stages: vec![AuthType::Password],
-		}],
+		flows: uiaa_flows(sender_user)?,
 		completed: Vec::new(),
 		params: Box::default(),
 		session: None,
@@ -439,7 +449,8 @@ pub(crate) async fn change_password_route(
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -481,6 +492,65 @@ pub(crate) async fn change_password_route(
 	Ok(change_password::v3::Response {})
 }
 
+/// Resets the password of a logged out user, who proves their identity with an
+/// email address bound to their account.
+async fn reset_password(body: Ruma<change_password::v3::Request>) -> Result<change_password::v3::Response> {
+	let Some(AuthData::EmailIdentity(EmailIdentity {
+		thirdparty_id_creds,
+		..
+	})) = &body.auth
+	else {
+		return Err(Error::Uiaa(UiaaInfo {
+			flows: vec![AuthFlow {
+				stages: vec![AuthType::EmailIdentity],
+			}],
+			completed: Vec::new(),
+			params: Box::default(),
+			session: None,
+			auth_error: None,
+		}));
+	};
+
+	let user_id = services()
+		.threepid
+		.authenticate(&thirdparty_id_creds.sid, &thirdparty_id_creds.client_secret)?;
+
+	if services().users.is_deactivated(&user_id)? {
+		return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+	}
+
+	if services().ldap.handles(&user_id) {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"The password of this account is managed by the LDAP server.",
+		));
+	}
+
+	services()
+		.users
+		.set_password(&user_id, Some(&body.new_password))?;
+
+	if body.logout_devices {
+		for id in services()
+			.users
+			.all_device_ids(&user_id)
+			.filter_map(Result::ok)
+		{
+			services().users.remove_device(&user_id, &id)?;
+		}
+	}
+
+	info!("User {user_id} reset their password.");
+	services()
+		.admin
+		.send_message(RoomMessageEventContent::notice_plain(format!(
+			"User {user_id} reset their password."
+		)))
+		.await;
+
+	Ok(change_password::v3::Response {})
+}
+
 /// # `GET _matrix/client/r0/account/whoami`
 ///
 /// Get `user_id` of the sender user.
@@ -508,6 +578,7 @@ pub(crate) async fn whoami_route(body: Ruma<whoami::v3::Request>) -> Result<whoa
 /// - Forgets all to-device events
 /// - Triggers device list updates
 /// - Removes ability to log in again
+/// - Unbinds all third party identifiers
 #[tracing::instrument(skip_all, fields(%client), name = "deactivate")]
 pub(crate) async fn deactivate_route(
 	InsecureClientIp(client): InsecureClientIp, body: Ruma<deactivate::v3::Request>,
@@ -516,9 +587,7 @@ pub(crate) async fn deactivate_route(
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	let mut uiaainfo = UiaaInfo {
-		flows: vec![AuthFlow {
-			stages: vec![AuthType::Password],
-		}],
+		flows: uiaa_flows(sender_user)?,
 		completed: Vec::new(),
 		params: Box::default(),
 		session: None,
@@ -528,7 +597,8 @@ pub(crate) async fn deactivate_route(
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -545,6 +615,7 @@ pub(crate) async fn deactivate_route(
 
 	// Remove devices and mark account as deactivated
 	services().users.deactivate_account(sender_user)?;
+	services().threepid.remove_all(sender_user)?;
 
 	// Remove profile pictures and display name
 	let all_joined_rooms: Vec<OwnedRoomId> = services()
@@ -575,12 +646,16 @@ pub(crate) async fn deactivate_route(
 /// # `GET _matrix/client/v3/account/3pid`
 ///
 /// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
 pub(crate) async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let threepids = services()
+		.threepid
+		.threepids(sender_user)
+		.filter_map(Result::ok)
+		.collect();
 
-	Ok(get_3pids::v3::Response::new(Vec::new()))
+	Ok(get_3pids::v3::Response::new(threepids))
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
@@ -588,15 +663,29 @@ pub(crate) async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Res
 /// "This API should be used to request validation tokens when adding an email
 /// address to an account"
 ///
+/// - Sends an email with a link to validate the address
 /// - 403 signals that The homeserver does not allow the third party identifier
 ///   as a contact option.
 pub(crate) async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
 ) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
+	services()
+		.ratelimit
+		.check_ip(Limit::ThreepidValidation, body.client)?;
+
+	let sid = services()
+		.threepid
+		.request_token(
+			Purpose::Add,
+			Medium::Email,
+			&body.email,
+			&body.client_secret,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response::new(sid))
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
@@ -604,34 +693,224 @@ pub(crate) async fn request_3pid_management_token_via_email_route(
 /// "This API should be used to request validation tokens when adding an phone
 /// number to an account"
 ///
+/// - Sends a text message with a code the client submits to `submit_url`
+/// - The phone number must be in international format, `country` is not used
 /// - 403 signals that The homeserver does not allow the third party identifier
 ///   as a contact option.
 pub(crate) async fn request_3pid_management_token_via_msisdn_route(
-	_body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
+	body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
 ) -> Result<request_3pid_management_token_via_msisdn::v3::Response> {
+	services()
+		.ratelimit
+		.check_ip(Limit::ThreepidValidation, body.client)?;
+
+	let sid = services()
+		.threepid
+		.request_token(
+			Purpose::Add,
+			Medium::Msisdn,
+			&body.phone_number,
+			&body.client_secret,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	let mut response = request_3pid_management_token_via_msisdn::v3::Response::new(sid);
+	response.submit_url = Some(services().threepid.msisdn_submit_url());
+
+	Ok(response)
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Adds a third party identifier validated through one of the `requestToken`
+/// endpoints to the sender user's account.
+///
+/// - Requires UIAA to verify the user's identity
+pub(crate) async fn add_3pid_route(body: Ruma<add_3pid::v3::Request>) -> Result<add_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: uiaa_flows(sender_user)?,
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services()
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services()
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json)?;
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let threepid = services()
+		.threepid
+		.take_validated(&body.sid, &body.client_secret, Purpose::Add)?;
+	services().threepid.add(sender_user, &threepid)?;
+
+	info!("User {sender_user} added a third party identifier.");
+
+	Ok(add_3pid::v3::Response::new())
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes a third party identifier from the sender user's account.
+///
+/// - Identity servers are not contacted, so the result is always `no-support`
+pub(crate) async fn delete_3pid_route(body: Ruma<delete_3pid::v3::Request>) -> Result<delete_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	if !services()
+		.threepid
+		.remove(sender_user, &body.medium, &body.address)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidNotFound,
+			"Third party identifier is not bound to this account.",
+		));
+	}
+
+	Ok(delete_3pid::v3::Response::new(ThirdPartyIdRemovalStatus::NoSupport))
+}
+
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a validation email to an address bound to an account, which lets the
+/// user change their password without being logged in. Unknown addresses get
+/// the same response, so accounts can't be found by their email address.
+pub(crate) async fn request_password_change_token_via_email_route(
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	if !services().threepid.password_reset_enabled() {
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidDenied,
+			"Password reset by email is not enabled.",
+		));
+	}
+
+	services()
+		.ratelimit
+		.check_ip(Limit::ThreepidValidation, body.client)?;
+
+	let sid = services()
+		.threepid
+		.request_token(
+			Purpose::Authenticate,
+			Medium::Email,
+			&body.email,
+			&body.client_secret,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response::new(sid))
+}
+
+/// # `POST /_matrix/client/v3/account/password/msisdn/requestToken`
+///
+/// Password reset is only supported by email.
+pub(crate) async fn request_password_change_token_via_msisdn_route(
+	_body: Ruma<request_password_change_token_via_msisdn::v3::Request>,
+) -> Result<request_password_change_token_via_msisdn::v3::Response> {
 	Err(Error::BadRequest(
 		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
+		"Password reset by phone number is not supported.",
 	))
 }
 
+/// # `GET /_conduwuit/client/3pid/submit_token`
+///
+/// Link sent in validation emails. Validates the session and redirects the
+/// user to the client's `next_link` if it gave one.
+pub(crate) async fn submit_3pid_token_link_route(uri: Uri) -> Result<impl IntoResponse> {
+	let query: SubmitToken = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid validation link."))?;
+
+	let next_link = services()
+		.threepid
+		.submit_token(&query.sid, &query.client_secret, &query.token)?
+		.filter(|next_link| next_link.starts_with("https://") || next_link.starts_with("http://"));
+
+	Ok(match next_link {
+		Some(next_link) => Redirect::to(&next_link).into_response(),
+		None => Html(VALIDATED_PAGE).into_response(),
+	})
+}
+
+/// # `POST /_conduwuit/client/3pid/submit_token`
+///
+/// The `submit_url` clients send codes from validation text messages to.
+pub(crate) async fn submit_3pid_token_route(Json(body): Json<SubmitToken>) -> Result<impl IntoResponse> {
+	services()
+		.threepid
+		.submit_token(&body.sid, &body.client_secret, &body.token)?;
+
+	Ok(Json(serde_json::json!({
+		"success": true
+	})))
+}
+
+/// The UIAA flows for confirming the identity of a logged in user: their
+/// password, or an email address bound to their account if password reset by
+/// email is enabled, as that's where the token is requested.
+fn uiaa_flows(user_id: &UserId) -> Result<Vec<AuthFlow>> {
+	let mut flows = vec![AuthFlow {
+		stages: vec![AuthType::Password],
+	}];
+
+	if services().threepid.password_reset_enabled() && has_email(user_id)? {
+		flows.push(AuthFlow {
+			stages: vec![AuthType::EmailIdentity],
+		});
+	}
+
+	Ok(flows)
+}
+
+fn has_email(user_id: &UserId) -> Result<bool> {
+	for threepid in services().threepid.threepids(user_id) {
+		if threepid?.medium == Medium::Email {
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
 /// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
 ///
 /// Checks if the provided registration token is valid at the time of checking
 ///
-/// Currently does not have any ratelimiting, and this isn't very practical as
-/// there is only one registration token allowed.
+/// Currently does not have any ratelimiting.
 pub(crate) async fn check_registration_token_validity(
 	body: Ruma<check_registration_token_validity::v1::Request>,
 ) -> Result<check_registration_token_validity::v1::Response> {
-	let Some(reg_token) = services().globals.config.registration_token.clone() else {
+	if !services().registration_tokens.required() {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
 			"Server does not allow token registration.",
 		));
-	};
+	}
 
 	Ok(check_registration_token_validity::v1::Response {
-		valid: reg_token == body.token,
+		valid: services().registration_tokens.is_valid(&body.token)?,
 	})
 }
@@ -0,0 +1,230 @@
+use ruma::api::client::{
+	dehydrated_device::{delete_dehydrated_device, get_dehydrated_device, get_events, put_dehydrated_device},
+	error::ErrorKind,
+};
+
+use crate::{service::users::DehydratedDevice, services, Error, Result, Ruma};
+
+/// Maximum to-device events returned per request to the dehydrated device
+/// events endpoint
+const EVENTS_LIMIT: usize = 100;
+
+/// # `PUT /_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device`
+///
+/// Stores a dehydrated device and its keys, replacing the previous dehydrated
+/// device of the sender user.
+pub(crate) async fn put_dehydrated_device_route(
+	body: Ruma<put_dehydrated_device::unstable::Request>,
+) -> Result<put_dehydrated_device::unstable::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	if services()
+		.users
+		.get_device_metadata(sender_user, &body.device_id)?
+		.is_some()
+		&& !services()
+			.users
+			.is_dehydrated_device(sender_user, &body.device_id)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Device ID is already in use by another device.",
+		));
+	}
+
+	services().users.set_dehydrated_device(
+		sender_user,
+		&DehydratedDevice {
+			device_id: body.device_id.clone(),
+			device_data: body.device_data.clone(),
+		},
+		body.initial_device_display_name.clone(),
+	)?;
+
+	services()
+		.users
+		.add_device_keys(sender_user, &body.device_id, &body.device_keys)?;
+
+	// Fallback keys are not supported yet, like in `upload_keys`
+	for (key_key, key_value) in &body.one_time_keys {
+		services()
+			.users
+			.add_one_time_key(sender_user, &body.device_id, key_key, key_value)?;
+	}
+
+	Ok(put_dehydrated_device::unstable::Response {
+		device_id: body.device_id.clone(),
+	})
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device`
+///
+/// Gets the dehydrated device of the sender user to rehydrate it.
+pub(crate) async fn get_dehydrated_device_route(
+	body: Ruma<get_dehydrated_device::unstable::Request>,
+) -> Result<get_dehydrated_device::unstable::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let device = services()
+		.users
+		.dehydrated_device(sender_user)?
+		.ok_or(Error::BadRequest(ErrorKind::NotFound, "No dehydrated device is stored."))?;
+
+	Ok(get_dehydrated_device::unstable::Response {
+		device_id: device.device_id,
+		device_data: device.device_data,
+	})
+}
+
+/// # `DELETE /_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device`
+///
+/// Deletes the dehydrated device of the sender user and its pending to-device
+/// events.
+pub(crate) async fn delete_dehydrated_device_route(
+	body: Ruma<delete_dehydrated_device::unstable::Request>,
+) -> Result<delete_dehydrated_device::unstable::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let device_id = services()
+		.users
+		.remove_dehydrated_device(sender_user)?
+		.ok_or(Error::BadRequest(ErrorKind::NotFound, "No dehydrated device is stored."))?;
+
+	Ok(delete_dehydrated_device::unstable::Response {
+		device_id,
+	})
+}
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device/{deviceId}/events`
+///
+/// Gets the to-device events sent to the dehydrated device while the user was
+/// offline, a batch at a time. Events up to `next_batch` were received by the
+/// client and are deleted.
+pub(crate) async fn get_dehydrated_events_route(
+	body: Ruma<get_events::unstable::Request>,
+) -> Result<get_events::unstable::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	if !services()
+		.users
+		.is_dehydrated_device(sender_user, &body.device_id)?
+	{
+		return Err(Error::BadRequest(ErrorKind::NotFound, "No dehydrated device with this ID."));
+	}
+
+	let since: Option<u64> = body
+		.next_batch
+		.as_deref()
+		.map(str::parse)
+		.transpose()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid next_batch."))?;
+
+	if let Some(since) = since {
+		services()
+			.users
+			.remove_to_device_events(sender_user, &body.device_id, since)?;
+	}
+
+	let events = services()
+		.users
+		.get_to_device_events_with_count(sender_user, &body.device_id, EVENTS_LIMIT)?;
+
+	let next_batch = events
+		.last()
+		.map(|(count, _)| *count)
+		.or(since)
+		.map(|count| count.to_string());
+
+	Ok(get_events::unstable::Response {
+		events: events.into_iter().map(|(_, event)| event).collect(),
+		next_batch,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::IpAddr;
+
+	use ruma::{
+		api::client::{dehydrated_device::get_events, error::ErrorKind},
+		device_id, user_id, DeviceId, UserId,
+	};
+	use serde_json::json;
+	use service::tests::services;
+
+	use super::{get_dehydrated_events_route, DehydratedDevice, EVENTS_LIMIT};
+	use crate::{Error, Ruma};
+
+	fn request(
+		user_id: &UserId, device_id: &DeviceId, next_batch: Option<String>,
+	) -> Ruma<get_events::unstable::Request> {
+		let mut body = get_events::unstable::Request::new(device_id.to_owned());
+		body.next_batch = next_batch;
+
+		Ruma {
+			body,
+			origin: None,
+			sender_user: Some(user_id.to_owned()),
+			sender_device: Some(device_id!("CLIENT").to_owned()),
+			appservice_info: None,
+			json_body: None,
+			client: IpAddr::from([192, 0, 2, 1]),
+		}
+	}
+
+	#[tokio::test]
+	async fn events_are_paginated() {
+		let services = services().await;
+		let user_id = user_id!("@rehydrating:example.com");
+		let device_id = device_id!("DEHYDRATED");
+
+		services.users.create(user_id, Some("password")).unwrap();
+		services
+			.users
+			.set_dehydrated_device(
+				user_id,
+				&DehydratedDevice {
+					device_id: device_id.to_owned(),
+					device_data: serde_json::from_value(json!({ "algorithm": "m.dehydration.v1.olm" })).unwrap(),
+				},
+				None,
+			)
+			.unwrap();
+
+		for i in 0..=EVENTS_LIMIT {
+			services
+				.users
+				.add_to_device_event(
+					user_id!("@sender:example.com"),
+					user_id,
+					device_id,
+					"m.room.encrypted",
+					json!({ "index": i }),
+				)
+				.unwrap();
+		}
+
+		let first = get_dehydrated_events_route(request(user_id, device_id, None))
+			.await
+			.unwrap();
+		assert_eq!(first.events.len(), EVENTS_LIMIT, "a batch at a time");
+
+		let second = get_dehydrated_events_route(request(user_id, device_id, first.next_batch.clone()))
+			.await
+			.unwrap();
+		assert_eq!(second.events.len(), 1, "the events of the first batch are deleted");
+		assert_ne!(second.next_batch, first.next_batch);
+
+		let last = get_dehydrated_events_route(request(user_id, device_id, second.next_batch.clone()))
+			.await
+			.unwrap();
+		assert!(last.events.is_empty());
+		assert_eq!(last.next_batch, second.next_batch, "next_batch stays at the last event");
+
+		let unknown = get_dehydrated_events_route(request(user_id, device_id!("CLIENT"), None)).await;
+		assert!(
+			matches!(unknown, Err(Error::BadRequest(ErrorKind::NotFound, _))),
+			"only the dehydrated device's events can be fetched"
+		);
+	}
+}
@@ -13,10 +13,17 @@ use crate::{services, utils, Error, Result, Ruma};
 pub(crate) async fn get_devices_route(body: Ruma<get_devices::v3::Request>) -> Result<get_devices::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	// The dehydrated device isn't a device the user signed in with
+	let dehydrated_device = services()
+		.users
+		.dehydrated_device(sender_user)?
+		.map(|device| device.device_id);
+
 	let devices: Vec<device::Device> = services()
 		.users
 		.all_devices_metadata(sender_user)
 		.filter_map(Result::ok) // Filter out buggy devices
+		.filter(|device| dehydrated_device.as_ref() != Some(&device.device_id))
 		.collect();
 
 	Ok(get_devices::v3::Response {
@@ -88,7 +95,8 @@ pub(crate) async fn delete_device_route(body: Ruma<delete_device::v3::Request>)
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -142,7 +150,8 @@ pub(crate) async fn delete_devices_route(
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
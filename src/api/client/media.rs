@@ -5,13 +5,19 @@ use std::{io::Cursor, sync::Arc, time::Duration};
 use image::io::Reader as ImgReader;
 use ipaddress::IPAddress;
 use reqwest::Url;
-use ruma::api::client::{
-	error::{ErrorKind, RetryAfter},
-	media::{
-		create_content, get_content, get_content_as_filename, get_content_thumbnail, get_media_config,
-		get_media_preview,
+use ruma::{
+	api::client::{
+		authenticated_media,
+		error::{ErrorKind, RetryAfter},
+		media::{
+			create_content, get_content, get_content_as_filename, get_content_thumbnail, get_media_config,
+			get_media_preview,
+		},
 	},
+	media::Method,
+	ServerName, UInt,
 };
+use serde_json::value::RawValue as RawJsonValue;
 use tracing::{debug, error, warn};
 use webpage::HTML;
 
@@ -19,6 +25,7 @@ use crate::{
 	debug_warn,
 	service::{
 		media::{FileMeta, UrlPreviewData},
+		ratelimit::Limit,
 		server_is_ours,
 	},
 	services,
@@ -61,44 +68,26 @@ pub(crate) async fn get_media_config_v1_route(
 	get_media_config_route(body).await.map(RumaResponse)
 }
 
+/// # `GET /_matrix/client/v1/media/config`
+///
+/// Returns max upload size.
+pub(crate) async fn get_media_config_auth_route(
+	_body: Ruma<authenticated_media::get_media_config::v1::Request>,
+) -> Result<authenticated_media::get_media_config::v1::Response> {
+	Ok(authenticated_media::get_media_config::v1::Response {
+		upload_size: services().globals.max_request_size().into(),
+	})
+}
+
 /// # `GET /_matrix/media/v3/preview_url`
 ///
 /// Returns URL preview.
 pub(crate) async fn get_media_preview_route(
 	body: Ruma<get_media_preview::v3::Request>,
 ) -> Result<get_media_preview::v3::Response> {
-	let url = &body.url;
-	if !url_preview_allowed(url) {
-		return Err(Error::BadRequest(ErrorKind::forbidden(), "URL is not allowed to be previewed"));
-	}
-
-	match get_url_preview(url).await {
-		Ok(preview) => {
-			let res = serde_json::value::to_raw_value(&preview).map_err(|e| {
-				error!("Failed to convert UrlPreviewData into a serde json value: {}", e);
-				Error::BadRequest(
-					ErrorKind::LimitExceeded {
-						retry_after: Some(RetryAfter::Delay(Duration::from_secs(5))),
-					},
-					"Failed to generate a URL preview, try again later.",
-				)
-			})?;
-
-			Ok(get_media_preview::v3::Response::from_raw_value(res))
-		},
-		Err(e) => {
-			warn!("Failed to generate a URL preview: {e}");
-
-			// there doesn't seem to be an agreed-upon error code in the spec.
-			// the only response codes in the preview_url spec page are 200 and 429.
-			Err(Error::BadRequest(
-				ErrorKind::LimitExceeded {
-					retry_after: Some(RetryAfter::Delay(Duration::from_secs(5))),
-				},
-				"Failed to generate a URL preview, try again later.",
-			))
-		},
-	}
+	url_preview(&body.url)
+		.await
+		.map(get_media_preview::v3::Response::from_raw_value)
 }
 
 /// # `GET /_matrix/media/v1/preview_url`
@@ -114,6 +103,17 @@ pub(crate) async fn get_media_preview_v1_route(
 	get_media_preview_route(body).await.map(RumaResponse)
 }
 
+/// # `GET /_matrix/client/v1/media/preview_url`
+///
+/// Returns URL preview.
+pub(crate) async fn get_media_preview_auth_route(
+	body: Ruma<authenticated_media::get_media_preview::v1::Request>,
+) -> Result<authenticated_media::get_media_preview::v1::Response> {
+	url_preview(&body.url)
+		.await
+		.map(authenticated_media::get_media_preview::v1::Response::from_raw_value)
+}
+
 /// # `POST /_matrix/media/v3/upload`
 ///
 /// Permanently save media in the server.
@@ -125,6 +125,12 @@ pub(crate) async fn create_content_route(
 ) -> Result<create_content::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if body.appservice_info.is_none() {
+		services()
+			.ratelimit
+			.check_user(Limit::MediaUpload, sender_user)?;
+	}
+
 	let mxc = format!(
 		"mxc://{}/{}",
 		services().globals.server_name(),
@@ -178,57 +184,27 @@ pub(crate) async fn create_content_v1_route(
 /// Load media from our server or over federation.
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_route(body: Ruma<get_content::v3::Request>) -> Result<get_content::v3::Response> {
 	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+	check_legacy_access(&mxc)?;
 
-	if let Some(FileMeta {
+	let FileMeta {
 		content_type,
 		file,
 		content_disposition,
-	}) = services().media.get(&mxc).await?
-	{
-		let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
-
-		Ok(get_content::v3::Response {
-			file,
-			content_type,
-			content_disposition,
-			cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-			cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-		})
-	} else if !server_is_ours(&body.server_name) && body.allow_remote {
-		let response = get_remote_content(
-			&mxc,
-			&body.server_name,
-			body.media_id.clone(),
-			body.allow_redirect,
-			body.timeout_ms,
-		)
-		.await
-		.map_err(|e| {
-			debug_warn!("Fetching media `{}` failed: {:?}", mxc, e);
-			Error::BadRequest(ErrorKind::NotFound, "Remote media error.")
-		})?;
-
-		let content_disposition = Some(make_content_disposition(
-			&response.content_type,
-			response.content_disposition,
-			None,
-		));
-
-		Ok(get_content::v3::Response {
-			file: response.file,
-			content_type: response.content_type,
-			content_disposition,
-			cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-			cache_control: Some(CACHE_CONTROL_IMMUTABLE.to_owned()),
-		})
-	} else {
-		Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."))
-	}
+	} = fetch_content(&mxc, &body.server_name, &body.media_id, body.allow_remote, body.timeout_ms).await?;
+
+	let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
+
+	Ok(get_content::v3::Response {
+		file,
+		content_type,
+		content_disposition,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+	})
 }
 
 /// # `GET /_matrix/media/v1/download/{serverName}/{mediaId}`
@@ -240,7 +216,6 @@ pub(crate) async fn get_content_route(body: Ruma<get_content::v3::Request>) -> R
 /// See <https://spec.matrix.org/legacy/legacy/#id27>
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_v1_route(
@@ -249,71 +224,66 @@ pub(crate) async fn get_content_v1_route(
 	get_content_route(body).await.map(RumaResponse)
 }
 
+/// # `GET /_matrix/client/v1/media/download/{serverName}/{mediaId}`
+///
+/// Load media from our server or over federation, requiring authentication.
+///
+/// - Uses client-provided `timeout_ms` if available, else defaults to 20
+///   seconds
+pub(crate) async fn get_content_auth_route(
+	body: Ruma<authenticated_media::get_content::v1::Request>,
+) -> Result<authenticated_media::get_content::v1::Response> {
+	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+
+	let FileMeta {
+		content_type,
+		file,
+		content_disposition,
+	} = fetch_content(&mxc, &body.server_name, &body.media_id, true, body.timeout_ms).await?;
+
+	let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
+
+	Ok(authenticated_media::get_content::v1::Response {
+		file,
+		content_type,
+		content_disposition,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+	})
+}
+
 /// # `GET /_matrix/media/v3/download/{serverName}/{mediaId}/{fileName}`
 ///
 /// Load media from our server or over federation, permitting desired filename.
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_as_filename_route(
 	body: Ruma<get_content_as_filename::v3::Request>,
 ) -> Result<get_content_as_filename::v3::Response> {
 	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+	check_legacy_access(&mxc)?;
 
-	if let Some(FileMeta {
+	let FileMeta {
 		content_type,
 		file,
 		content_disposition,
-	}) = services().media.get(&mxc).await?
-	{
-		let content_disposition = Some(make_content_disposition(
-			&content_type,
-			content_disposition,
-			Some(body.filename.clone()),
-		));
-
-		Ok(get_content_as_filename::v3::Response {
-			file,
-			content_type,
-			content_disposition,
-			cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-			cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-		})
-	} else if !server_is_ours(&body.server_name) && body.allow_remote {
-		match get_remote_content(
-			&mxc,
-			&body.server_name,
-			body.media_id.clone(),
-			body.allow_redirect,
-			body.timeout_ms,
-		)
-		.await
-		{
-			Ok(remote_content_response) => {
-				let content_disposition = Some(make_content_disposition(
-					&remote_content_response.content_type,
-					remote_content_response.content_disposition,
-					None,
-				));
-
-				Ok(get_content_as_filename::v3::Response {
-					content_disposition,
-					content_type: remote_content_response.content_type,
-					file: remote_content_response.file,
-					cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-					cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-				})
-			},
-			Err(e) => {
-				debug_warn!("Fetching media `{}` failed: {:?}", mxc, e);
-				Err(Error::BadRequest(ErrorKind::NotFound, "Remote media error."))
-			},
-		}
-	} else {
-		Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."))
-	}
+	} = fetch_content(&mxc, &body.server_name, &body.media_id, body.allow_remote, body.timeout_ms).await?;
+
+	let content_disposition = Some(make_content_disposition(
+		&content_type,
+		content_disposition,
+		Some(body.filename.clone()),
+	));
+
+	Ok(get_content_as_filename::v3::Response {
+		file,
+		content_type,
+		content_disposition,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+	})
 }
 
 /// # `GET /_matrix/media/v1/download/{serverName}/{mediaId}/{fileName}`
@@ -325,7 +295,6 @@ pub(crate) async fn get_content_as_filename_route(
 /// See <https://spec.matrix.org/legacy/legacy/#id27>
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_as_filename_v1_route(
@@ -334,111 +303,78 @@ pub(crate) async fn get_content_as_filename_v1_route(
 	get_content_as_filename_route(body).await.map(RumaResponse)
 }
 
+/// # `GET /_matrix/client/v1/media/download/{serverName}/{mediaId}/{fileName}`
+///
+/// Load media from our server or over federation, permitting desired filename
+/// and requiring authentication.
+///
+/// - Uses client-provided `timeout_ms` if available, else defaults to 20
+///   seconds
+pub(crate) async fn get_content_as_filename_auth_route(
+	body: Ruma<authenticated_media::get_content_as_filename::v1::Request>,
+) -> Result<authenticated_media::get_content_as_filename::v1::Response> {
+	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+
+	let FileMeta {
+		content_type,
+		file,
+		content_disposition,
+	} = fetch_content(&mxc, &body.server_name, &body.media_id, true, body.timeout_ms).await?;
+
+	let content_disposition = Some(make_content_disposition(
+		&content_type,
+		content_disposition,
+		Some(body.filename.clone()),
+	));
+
+	Ok(authenticated_media::get_content_as_filename::v1::Response {
+		file,
+		content_type,
+		content_disposition,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+	})
+}
+
 /// # `GET /_matrix/media/v3/thumbnail/{serverName}/{mediaId}`
 ///
 /// Load media thumbnail from our server or over federation.
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_thumbnail_route(
 	body: Ruma<get_content_thumbnail::v3::Request>,
 ) -> Result<get_content_thumbnail::v3::Response> {
 	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+	check_legacy_thumbnail_access(&mxc, body.width, body.height)?;
 
-	if let Some(FileMeta {
+	let FileMeta {
 		content_type,
 		file,
 		content_disposition,
-	}) = services()
-		.media
-		.get_thumbnail(
-			&mxc,
-			body.width
-				.try_into()
-				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Width is invalid."))?,
-			body.height
-				.try_into()
-				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Height is invalid."))?,
-		)
-		.await?
-	{
-		let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
-
-		Ok(get_content_thumbnail::v3::Response {
-			file,
-			content_type,
-			cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-			cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-			content_disposition,
-		})
-	} else if !server_is_ours(&body.server_name) && body.allow_remote {
-		if services()
-			.globals
-			.prevent_media_downloads_from()
-			.contains(&body.server_name)
-		{
-			// we'll lie to the client and say the blocked server's media was not found and
-			// log. the client has no way of telling anyways so this is a security bonus.
-			debug_warn!("Received request for media `{}` on blocklisted server", mxc);
-			return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
-		}
-
-		match services()
-			.sending
-			.send_federation_request(
-				&body.server_name,
-				get_content_thumbnail::v3::Request {
-					allow_remote: body.allow_remote,
-					height: body.height,
-					width: body.width,
-					method: body.method.clone(),
-					server_name: body.server_name.clone(),
-					media_id: body.media_id.clone(),
-					timeout_ms: body.timeout_ms,
-					allow_redirect: body.allow_redirect,
-					animated: body.animated,
-				},
-			)
-			.await
-		{
-			Ok(get_thumbnail_response) => {
-				services()
-					.media
-					.upload_thumbnail(
-						None,
-						&mxc,
-						None,
-						get_thumbnail_response.content_type.as_deref(),
-						body.width.try_into().expect("all UInts are valid u32s"),
-						body.height.try_into().expect("all UInts are valid u32s"),
-						&get_thumbnail_response.file,
-					)
-					.await?;
-
-				let content_disposition = Some(make_content_disposition(
-					&get_thumbnail_response.content_type,
-					get_thumbnail_response.content_disposition,
-					None,
-				));
-
-				Ok(get_content_thumbnail::v3::Response {
-					file: get_thumbnail_response.file,
-					content_type: get_thumbnail_response.content_type,
-					cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-					cache_control: Some(CACHE_CONTROL_IMMUTABLE.to_owned()),
-					content_disposition,
-				})
-			},
-			Err(e) => {
-				debug_warn!("Fetching media `{}` failed: {:?}", mxc, e);
-				Err(Error::BadRequest(ErrorKind::NotFound, "Remote media error."))
-			},
-		}
-	} else {
-		Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."))
-	}
+	} = fetch_thumbnail(
+		&mxc,
+		&body.server_name,
+		&body.media_id,
+		body.width,
+		body.height,
+		body.method.clone(),
+		body.animated,
+		body.allow_remote,
+		body.timeout_ms,
+	)
+	.await?;
+
+	let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
+
+	Ok(get_content_thumbnail::v3::Response {
+		file,
+		content_type,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+		content_disposition,
+	})
 }
 
 /// # `GET /_matrix/media/v1/thumbnail/{serverName}/{mediaId}`
@@ -450,7 +386,6 @@ pub(crate) async fn get_content_thumbnail_route(
 /// See <https://spec.matrix.org/legacy/legacy/#id27>
 ///
 /// - Only allows federation if `allow_remote` is true
-/// - Only redirects if `allow_redirect` is true
 /// - Uses client-provided `timeout_ms` if available, else defaults to 20
 ///   seconds
 pub(crate) async fn get_content_thumbnail_v1_route(
@@ -459,58 +394,169 @@ pub(crate) async fn get_content_thumbnail_v1_route(
 	get_content_thumbnail_route(body).await.map(RumaResponse)
 }
 
-async fn get_remote_content(
-	mxc: &str, server_name: &ruma::ServerName, media_id: String, allow_redirect: bool, timeout_ms: Duration,
-) -> Result<get_content::v3::Response, Error> {
-	if services()
-		.globals
-		.prevent_media_downloads_from()
-		.contains(&server_name.to_owned())
-	{
-		// we'll lie to the client and say the blocked server's media was not found and
-		// log. the client has no way of telling anyways so this is a security bonus.
-		debug_warn!("Received request for media `{mxc}` on blocklisted server");
+/// # `GET /_matrix/client/v1/media/thumbnail/{serverName}/{mediaId}`
+///
+/// Load media thumbnail from our server or over federation, requiring
+/// authentication.
+///
+/// - Uses client-provided `timeout_ms` if available, else defaults to 20
+///   seconds
+pub(crate) async fn get_content_thumbnail_auth_route(
+	body: Ruma<authenticated_media::get_content_thumbnail::v1::Request>,
+) -> Result<authenticated_media::get_content_thumbnail::v1::Response> {
+	let mxc = format!("mxc://{}/{}", body.server_name, body.media_id);
+
+	let FileMeta {
+		content_type,
+		file,
+		content_disposition,
+	} = fetch_thumbnail(
+		&mxc,
+		&body.server_name,
+		&body.media_id,
+		body.width,
+		body.height,
+		body.method.clone(),
+		body.animated,
+		true,
+		body.timeout_ms,
+	)
+	.await?;
+
+	let content_disposition = Some(make_content_disposition(&content_type, content_disposition, None));
+
+	Ok(authenticated_media::get_content_thumbnail::v1::Response {
+		file,
+		content_type,
+		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
+		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+		content_disposition,
+	})
+}
+
+/// Loads media from our server, or over federation if it's remote and
+/// `allow_remote` is set.
+async fn fetch_content(
+	mxc: &str, server_name: &ServerName, media_id: &str, allow_remote: bool, timeout_ms: Duration,
+) -> Result<FileMeta> {
+	if let Some(meta) = services().media.get(mxc).await? {
+		return Ok(meta);
+	}
+
+	if server_is_ours(server_name) || !allow_remote {
 		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
 	}
 
-	let content_response = services()
-		.sending
-		.send_federation_request(
-			server_name,
-			get_content::v3::Request {
-				allow_remote: true,
-				server_name: server_name.to_owned(),
-				media_id,
-				timeout_ms,
-				allow_redirect,
-			},
+	services()
+		.media
+		.fetch_remote_content(mxc, server_name, media_id, timeout_ms)
+		.await
+		.map_err(|e| {
+			debug_warn!("Fetching media `{}` failed: {:?}", mxc, e);
+			Error::BadRequest(ErrorKind::NotFound, "Remote media error.")
+		})
+}
+
+/// Loads a thumbnail from our server, or over federation if it's remote and
+/// `allow_remote` is set.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_thumbnail(
+	mxc: &str, server_name: &ServerName, media_id: &str, width: UInt, height: UInt, method: Option<Method>,
+	animated: Option<bool>, allow_remote: bool, timeout_ms: Duration,
+) -> Result<FileMeta> {
+	if let Some(meta) = services()
+		.media
+		.get_thumbnail(
+			mxc,
+			width
+				.try_into()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Width is invalid."))?,
+			height
+				.try_into()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Height is invalid."))?,
 		)
-		.await?;
+		.await?
+	{
+		return Ok(meta);
+	}
 
-	let content_disposition = Some(make_content_disposition(
-		&content_response.content_type,
-		content_response.content_disposition,
-		None,
-	));
+	if server_is_ours(server_name) || !allow_remote {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	}
 
 	services()
 		.media
-		.create(
-			None,
-			mxc,
-			content_disposition.as_deref(),
-			content_response.content_type.as_deref(),
-			&content_response.file,
-		)
-		.await?;
+		.fetch_remote_thumbnail(mxc, server_name, media_id, width, height, method, animated, timeout_ms)
+		.await
+		.map_err(|e| {
+			debug_warn!("Fetching media `{}` failed: {:?}", mxc, e);
+			Error::BadRequest(ErrorKind::NotFound, "Remote media error.")
+		})
+}
 
-	Ok(get_content::v3::Response {
-		file: content_response.file,
-		content_type: content_response.content_type,
-		content_disposition,
-		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.to_owned()),
-		cache_control: Some(CACHE_CONTROL_IMMUTABLE.to_owned()),
-	})
+/// Media stored while `freeze_legacy_media` was enabled is hidden from the
+/// unauthenticated endpoints as if it didn't exist. This is checked before
+/// fetching, so these requests can't make us fetch media over federation
+/// while frozen.
+fn check_legacy_access(mxc: &str) -> Result<()> {
+	if services().media.is_authenticated_only(mxc)? {
+		debug_warn!("Refusing unauthenticated request for frozen media `{mxc}`");
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	}
+
+	Ok(())
+}
+
+/// Like `check_legacy_access`, for the thumbnail of the requested size
+fn check_legacy_thumbnail_access(mxc: &str, width: UInt, height: UInt) -> Result<()> {
+	let width = width
+		.try_into()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Width is invalid."))?;
+	let height = height
+		.try_into()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Height is invalid."))?;
+
+	if services()
+		.media
+		.is_thumbnail_authenticated_only(mxc, width, height)?
+	{
+		debug_warn!("Refusing unauthenticated request for frozen thumbnail of `{mxc}`");
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	}
+
+	Ok(())
+}
+
+/// Generates or loads a cached preview of the URL as the JSON object sent to
+/// clients.
+async fn url_preview(url: &str) -> Result<Box<RawJsonValue>> {
+	if !url_preview_allowed(url) {
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "URL is not allowed to be previewed"));
+	}
+
+	match get_url_preview(url).await {
+		Ok(preview) => serde_json::value::to_raw_value(&preview).map_err(|e| {
+			error!("Failed to convert UrlPreviewData into a serde json value: {}", e);
+			Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(Duration::from_secs(5))),
+				},
+				"Failed to generate a URL preview, try again later.",
+			)
+		}),
+		Err(e) => {
+			warn!("Failed to generate a URL preview: {e}");
+
+			// there doesn't seem to be an agreed-upon error code in the spec.
+			// the only response codes in the preview_url spec page are 200 and 429.
+			Err(Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(Duration::from_secs(5))),
+				},
+				"Failed to generate a URL preview, try again later.",
+			))
+		},
+	}
 }
 
 async fn download_image(client: &reqwest::Client, url: &str) -> Result<UrlPreviewData> {
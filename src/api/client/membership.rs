@@ -12,6 +12,7 @@ use ruma::{
 	api::{
 		client::{
 			error::ErrorKind,
+			knock::knock_room,
 			membership::{
 				ban_user, forget_room, get_member_events, invite_user, join_room_by_id, join_room_by_id_or_alias,
 				joined_members, joined_rooms, kick_user, leave_room, unban_user, ThirdPartySigned,
@@ -40,6 +41,7 @@ use crate::{
 	client::{update_avatar_url, update_displayname},
 	service::{
 		pdu::{gen_event_id_canonical_json, PduBuilder},
+		ratelimit::Limit,
 		server_is_ours, user_is_local,
 	},
 	services, utils, Error, PduEvent, Result, Ruma,
@@ -170,6 +172,10 @@ pub(crate) async fn join_room_by_id_route(
 ) -> Result<join_room_by_id::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if body.appservice_info.is_none() {
+		services().ratelimit.check_user(Limit::Join, sender_user)?;
+	}
+
 	banned_room_check(sender_user, Some(&body.room_id), body.room_id.server_name(), client_ip).await?;
 
 	// There is no body.server_name for /roomId/join
@@ -222,6 +228,11 @@ pub(crate) async fn join_room_by_id_or_alias_route(
 	InsecureClientIp(client): InsecureClientIp, body: Ruma<join_room_by_id_or_alias::v3::Request>,
 ) -> Result<join_room_by_id_or_alias::v3::Response> {
 	let sender_user = body.sender_user.as_deref().expect("user is authenticated");
+
+	if body.appservice_info.is_none() {
+		services().ratelimit.check_user(Limit::Join, sender_user)?;
+	}
+
 	let body = body.body;
 
 	let (servers, room_id) = match OwnedRoomId::try_from(body.room_id_or_alias) {
@@ -311,6 +322,57 @@ pub(crate) async fn join_room_by_id_or_alias_route(
 	})
 }
 
+/// # `POST /_matrix/client/v3/knock/{roomIdOrAlias}`
+///
+/// Tries to knock on a room to ask for an invite.
+///
+/// - If the server is in the room: creates the knock event locally
+/// - If the server is not in the room: asks the servers given in the query, the
+///   alias server or the room ID server to let us knock over federation
+#[tracing::instrument(skip_all, fields(%client), name = "knock")]
+pub(crate) async fn knock_room_route(
+	InsecureClientIp(client): InsecureClientIp, body: Ruma<knock_room::v3::Request>,
+) -> Result<knock_room::v3::Response> {
+	let sender_user = body.sender_user.as_deref().expect("user is authenticated");
+
+	if body.appservice_info.is_none() {
+		services().ratelimit.check_user(Limit::Join, sender_user)?;
+	}
+
+	let body = body.body;
+
+	let (servers, room_id) = match OwnedRoomId::try_from(body.room_id_or_alias) {
+		Ok(room_id) => {
+			banned_room_check(sender_user, Some(&room_id), room_id.server_name(), client).await?;
+
+			let mut servers = body.server_name.clone();
+			if let Some(server) = room_id.server_name() {
+				servers.push(server.to_owned());
+			}
+
+			(servers, room_id)
+		},
+		Err(room_alias) => {
+			let (room_id, pre_servers) = services()
+				.rooms
+				.alias
+				.resolve_alias(&room_alias, Some(&body.server_name.clone()))
+				.await?;
+
+			banned_room_check(sender_user, Some(&room_id), Some(room_alias.server_name()), client).await?;
+
+			let mut servers = body.server_name;
+			servers.extend(pre_servers.unwrap_or_default());
+
+			(servers, room_id)
+		},
+	};
+
+	knock_room_by_id_helper(sender_user, &room_id, body.reason, &servers).await?;
+
+	Ok(knock_room::v3::Response::new(room_id))
+}
+
 /// # `POST /_matrix/client/v3/rooms/{roomId}/leave`
 ///
 /// Tries to leave the sender user from a room.
@@ -326,7 +388,8 @@ pub(crate) async fn leave_room_route(body: Ruma<leave_room::v3::Request>) -> Res
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/invite`
 ///
-/// Tries to send an invite event into the room.
+/// Tries to send an invite event into the room. Inviting a user who knocked
+/// accepts their knock.
 #[tracing::instrument(skip_all, fields(%client), name = "invite")]
 pub(crate) async fn invite_user_route(
 	InsecureClientIp(client): InsecureClientIp, body: Ruma<invite_user::v3::Request>,
@@ -346,6 +409,11 @@ pub(crate) async fn invite_user_route(
 
 	banned_room_check(sender_user, Some(&body.room_id), body.room_id.server_name(), client).await?;
 
+	// Shadow-banned users' invites pretend to succeed
+	if services().users.is_shadow_banned(sender_user)? {
+		return Ok(invite_user::v3::Response {});
+	}
+
 	if let invite_user::v3::InvitationRecipient::UserId {
 		user_id,
 	} = &body.recipient
@@ -359,7 +427,8 @@ pub(crate) async fn invite_user_route(
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/kick`
 ///
-/// Tries to send a kick event into the room.
+/// Tries to send a kick event into the room. Kicking a user who knocked
+/// denies their knock.
 pub(crate) async fn kick_user_route(body: Ruma<kick_user::v3::Request>) -> Result<kick_user::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
@@ -649,6 +718,243 @@ pub(crate) async fn joined_members_route(
 	})
 }
 
+async fn knock_room_by_id_helper(
+	sender_user: &UserId, room_id: &RoomId, reason: Option<String>, servers: &[OwnedServerName],
+) -> Result<()> {
+	if services()
+		.rooms
+		.state_cache
+		.is_knocked(sender_user, room_id)?
+	{
+		info!("{sender_user} has already knocked on {room_id}");
+		return Ok(());
+	}
+
+	if services()
+		.rooms
+		.state_cache
+		.is_joined(sender_user, room_id)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"You are already joined to this room.",
+		));
+	}
+
+	if services()
+		.rooms
+		.state_cache
+		.is_invited(sender_user, room_id)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"You are already invited to this room, join it instead.",
+		));
+	}
+
+	let state_lock = services().globals.roomid_mutex_state.lock(room_id).await;
+
+	// Ask a remote server if we are not participating in this room
+	if !services()
+		.rooms
+		.state_cache
+		.server_in_room(services().globals.server_name(), room_id)?
+	{
+		knock_room_helper_remote(sender_user, room_id, reason, servers, state_lock).await
+	} else {
+		knock_room_helper_local(sender_user, room_id, reason, state_lock).await
+	}
+}
+
+async fn knock_room_helper_local(
+	sender_user: &UserId, room_id: &RoomId, reason: Option<String>, state_lock: mutex_map::Guard<()>,
+) -> Result<()> {
+	info!("We can knock locally");
+
+	if !services().rooms.state_accessor.allows_knocking(room_id)? {
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "This room does not allow knocking."));
+	}
+
+	let event = RoomMemberEventContent {
+		membership: MembershipState::Knock,
+		displayname: services().users.displayname(sender_user)?,
+		avatar_url: services().users.avatar_url(sender_user)?,
+		is_direct: None,
+		third_party_invite: None,
+		blurhash: services().users.blurhash(sender_user)?,
+		reason,
+		join_authorized_via_users_server: None,
+	};
+
+	services()
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				event_type: TimelineEventType::RoomMember,
+				content: to_raw_value(&event).expect("event is valid, we just created it"),
+				unsigned: None,
+				state_key: Some(sender_user.to_string()),
+				redacts: None,
+			},
+			sender_user,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	Ok(())
+}
+
+async fn knock_room_helper_remote(
+	sender_user: &UserId, room_id: &RoomId, reason: Option<String>, servers: &[OwnedServerName],
+	state_lock: mutex_map::Guard<()>,
+) -> Result<()> {
+	info!("Knocking on {room_id} over federation.");
+
+	let (make_knock_response, remote_server) = make_knock_request(sender_user, room_id, servers).await?;
+
+	info!("make_knock finished");
+
+	let room_version_id = make_knock_response.room_version;
+	if !services()
+		.globals
+		.supported_room_versions()
+		.contains(&room_version_id)
+	{
+		return Err(Error::BadServerResponse("Room version is not supported"));
+	}
+
+	let mut knock_event_stub: CanonicalJsonObject = serde_json::from_str(make_knock_response.event.get())
+		.map_err(|_| Error::BadServerResponse("Invalid make_knock event json received from server."))?;
+
+	knock_event_stub.insert(
+		"origin".to_owned(),
+		CanonicalJsonValue::String(services().globals.server_name().as_str().to_owned()),
+	);
+	knock_event_stub.insert(
+		"origin_server_ts".to_owned(),
+		CanonicalJsonValue::Integer(
+			utils::millis_since_unix_epoch()
+				.try_into()
+				.expect("Timestamp is valid js_int value"),
+		),
+	);
+	knock_event_stub.insert(
+		"content".to_owned(),
+		to_canonical_value(RoomMemberEventContent {
+			membership: MembershipState::Knock,
+			displayname: services().users.displayname(sender_user)?,
+			avatar_url: services().users.avatar_url(sender_user)?,
+			is_direct: None,
+			third_party_invite: None,
+			blurhash: services().users.blurhash(sender_user)?,
+			reason,
+			join_authorized_via_users_server: None,
+		})
+		.expect("event is valid, we just created it"),
+	);
+
+	// knocking requires room v7 or above, which removed the "event_id" field from
+	// the remote PDU format
+	knock_event_stub.remove("event_id");
+
+	// In order to create a compatible ref hash (EventID) the `hashes` field needs
+	// to be present
+	ruma::signatures::hash_and_sign_event(
+		services().globals.server_name().as_str(),
+		services().globals.keypair(),
+		&mut knock_event_stub,
+		&room_version_id,
+	)
+	.expect("event is valid, we just created it");
+
+	// Generate event id
+	let event_id = EventId::parse(format!(
+		"${}",
+		ruma::signatures::reference_hash(&knock_event_stub, &room_version_id)
+			.expect("ruma can calculate reference hashes")
+	))
+	.expect("ruma's reference hashes are valid event ids");
+
+	// Add event_id back
+	knock_event_stub.insert("event_id".to_owned(), CanonicalJsonValue::String(event_id.as_str().to_owned()));
+
+	// It has enough fields to be called a proper event now
+	let knock_event = knock_event_stub;
+
+	info!("Asking {remote_server} for send_knock in room {room_id}");
+	let send_knock_response = services()
+		.sending
+		.send_federation_request(
+			&remote_server,
+			federation::knock::send_knock::v1::Request {
+				room_id: room_id.to_owned(),
+				event_id: event_id.clone(),
+				pdu: PduEvent::convert_to_outgoing_federation_event(knock_event.clone()),
+			},
+		)
+		.await?;
+
+	info!("send_knock finished");
+
+	services()
+		.rooms
+		.outlier
+		.add_pdu_outlier(&event_id, &knock_event)?;
+
+	// We aren't in the room, so the stripped state from the response is all we
+	// can show the user until they're invited or rejected
+	services().rooms.state_cache.update_membership(
+		room_id,
+		sender_user,
+		RoomMemberEventContent::new(MembershipState::Knock),
+		sender_user,
+		Some(send_knock_response.knock_room_state),
+		Some(vec![remote_server]),
+		false,
+	)?;
+
+	drop(state_lock);
+
+	Ok(())
+}
+
+async fn make_knock_request(
+	sender_user: &UserId, room_id: &RoomId, servers: &[OwnedServerName],
+) -> Result<(federation::knock::create_knock_event_template::v1::Response, OwnedServerName)> {
+	let mut make_knock_response_and_server =
+		Err(Error::BadServerResponse("No server available to assist in knocking."));
+
+	for remote_server in servers {
+		if server_is_ours(remote_server) {
+			continue;
+		}
+
+		info!("Asking {remote_server} for make_knock");
+		let make_knock_response = services()
+			.sending
+			.send_federation_request(
+				remote_server,
+				federation::knock::create_knock_event_template::v1::Request {
+					room_id: room_id.to_owned(),
+					user_id: sender_user.to_owned(),
+					ver: services().globals.supported_room_versions(),
+				},
+			)
+			.await;
+
+		trace!("make_knock response: {:?}", make_knock_response);
+		make_knock_response_and_server = make_knock_response.map(|r| (r, remote_server.clone()));
+
+		if make_knock_response_and_server.is_ok() {
+			break;
+		}
+	}
+
+	make_knock_response_and_server
+}
+
 pub async fn join_room_by_id_helper(
 	sender_user: Option<&UserId>, room_id: &RoomId, reason: Option<String>, servers: &[OwnedServerName],
 	third_party_signed: Option<&ThirdPartySigned>,
@@ -1555,6 +1861,13 @@ pub async fn leave_all_rooms(user_id: &UserId) {
 				.rooms_invited(user_id)
 				.map(|t| t.map(|(r, _)| r)),
 		)
+		.chain(
+			services()
+				.rooms
+				.state_cache
+				.rooms_knocked(user_id)
+				.map(|t| t.map(|(r, _)| r)),
+		)
 		.collect::<Vec<_>>();
 
 	for room_id in all_rooms {
@@ -1584,13 +1897,20 @@ pub async fn leave_room(user_id: &UserId, room_id: &RoomId, reason: Option<Strin
 			// Don't tell the client about this error
 		}
 
-		let last_state = services()
+		let last_state = match services()
 			.rooms
 			.state_cache
 			.invite_state(user_id, room_id)?
-			.map_or_else(|| services().rooms.state_cache.left_state(user_id, room_id), |s| Ok(Some(s)))?;
+		{
+			Some(state) => Some(state),
+			None => services()
+				.rooms
+				.state_cache
+				.knock_state(user_id, room_id)?
+				.map_or_else(|| services().rooms.state_cache.left_state(user_id, room_id), |s| Ok(Some(s)))?,
+		};
 
-		// We always drop the invite, we can't rely on other servers
+		// We always drop the invite or knock, we can't rely on other servers
 		services().rooms.state_cache.update_membership(
 			room_id,
 			user_id,
@@ -1660,11 +1980,19 @@ pub async fn leave_room(user_id: &UserId, room_id: &RoomId, reason: Option<Strin
 async fn remote_leave_room(user_id: &UserId, room_id: &RoomId) -> Result<()> {
 	let mut make_leave_response_and_server = Err(Error::BadServerResponse("No server available to assist in leaving."));
 
-	let invite_state = services()
+	// Rescinding a knock works just like rejecting an invite
+	let invite_state = match services()
 		.rooms
 		.state_cache
 		.invite_state(user_id, room_id)?
-		.ok_or(Error::BadRequest(ErrorKind::BadState, "User is not invited."))?;
+	{
+		Some(state) => state,
+		None => services()
+			.rooms
+			.state_cache
+			.knock_state(user_id, room_id)?
+			.ok_or(Error::BadRequest(ErrorKind::BadState, "User is not invited or knocking."))?,
+	};
 
 	let mut servers: HashSet<OwnedServerName> = services()
 		.rooms
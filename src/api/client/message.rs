@@ -12,7 +12,11 @@ use ruma::{
 };
 use serde_json::{from_str, Value};
 
-use crate::{service::pdu::PduBuilder, services, utils, Error, PduEvent, Result, Ruma};
+use super::shadow_ban_event_id;
+use crate::{
+	service::{pdu::PduBuilder, ratelimit::Limit},
+	services, utils, Error, PduEvent, Result, Ruma,
+};
 
 /// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
 ///
@@ -72,6 +76,24 @@ pub(crate) async fn send_message_event_route(
 		});
 	}
 
+	if body.appservice_info.is_none() {
+		services()
+			.ratelimit
+			.check_user(Limit::Message, sender_user)?;
+	}
+
+	// Shadow-banned users get an event ID as if their event was sent
+	if services().users.is_shadow_banned(sender_user)? {
+		let event_id = shadow_ban_event_id();
+		services()
+			.transaction_ids
+			.add_txnid(sender_user, sender_device, &body.txn_id, event_id.as_bytes())?;
+
+		return Ok(send_message_event::v3::Response {
+			event_id,
+		});
+	}
+
 	let mut unsigned = BTreeMap::new();
 	unsigned.insert("transaction_id".to_owned(), body.txn_id.to_string().into());
 
@@ -4,6 +4,7 @@ pub(super) mod backup;
 pub(super) mod capabilities;
 pub(super) mod config;
 pub(super) mod context;
+pub(super) mod dehydrated_device;
 pub(super) mod device;
 pub(super) mod directory;
 pub(super) mod filter;
@@ -22,9 +23,11 @@ pub(super) mod room;
 pub(super) mod search;
 pub(super) mod session;
 pub(super) mod space;
+pub(super) mod sso;
 pub(super) mod state;
 pub(super) mod sync;
 pub(super) mod tag;
+mod tests;
 pub(super) mod thirdparty;
 pub(super) mod threads;
 pub(super) mod to_device;
@@ -40,6 +43,7 @@ pub(super) use backup::*;
 pub(super) use capabilities::*;
 pub(super) use config::*;
 pub(super) use context::*;
+pub(super) use dehydrated_device::*;
 pub(super) use device::*;
 pub(super) use directory::*;
 pub(super) use filter::*;
@@ -60,6 +64,7 @@ pub(super) use room::*;
 pub(super) use search::*;
 pub(super) use session::*;
 pub(super) use space::*;
+pub(super) use sso::*;
 pub(super) use state::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
@@ -72,6 +77,8 @@ pub(super) use unversioned::*;
 pub(super) use user_directory::*;
 pub(super) use voip::*;
 
+use crate::utils;
+
 /// generated device ID length
 const DEVICE_ID_LENGTH: usize = 10;
 
@@ -80,3 +87,14 @@ const TOKEN_LENGTH: usize = 32;
 
 /// generated user session ID length
 const SESSION_ID_LENGTH: usize = service::uiaa::SESSION_ID_LENGTH;
+
+/// generated fake event ID length, like the reference hashes of event IDs since
+/// room version 4
+const SHADOW_BAN_EVENT_ID_LENGTH: usize = 43;
+
+/// Event ID returned to shadow-banned users instead of the one of their event,
+/// which is dropped
+fn shadow_ban_event_id() -> ruma::OwnedEventId {
+	ruma::EventId::parse(format!("${}", utils::random_string(SHADOW_BAN_EVENT_ID_LENGTH)))
+		.expect("random alphanumeric event ID is valid")
+}
@@ -27,6 +27,11 @@ pub(crate) async fn set_displayname_route(
 	body: Ruma<set_display_name::v3::Request>,
 ) -> Result<set_display_name::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	// Profile changes of shadow-banned users are silently dropped
+	if services().users.is_shadow_banned(sender_user)? {
+		return Ok(set_display_name::v3::Response {});
+	}
+
 	let all_joined_rooms: Vec<OwnedRoomId> = services()
 		.rooms
 		.state_cache
@@ -111,6 +116,11 @@ pub(crate) async fn set_avatar_url_route(
 	body: Ruma<set_avatar_url::v3::Request>,
 ) -> Result<set_avatar_url::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	// Profile changes of shadow-banned users are silently dropped
+	if services().users.is_shadow_banned(sender_user)? {
+		return Ok(set_avatar_url::v3::Response {});
+	}
+
 	let all_joined_rooms: Vec<OwnedRoomId> = services()
 		.rooms
 		.state_cache
@@ -6,8 +6,12 @@ use ruma::{
 			set_pusher, set_pushrule, set_pushrule_actions, set_pushrule_enabled, RuleScope,
 		},
 	},
-	events::{push_rules::PushRulesEvent, GlobalAccountDataEventType},
+	events::{
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		GlobalAccountDataEventType,
+	},
 	push::{InsertPushRuleError, RemovePushRuleError, Ruleset},
+	UserId,
 };
 
 use crate::{services, Error, Result, Ruma};
@@ -30,8 +34,11 @@ pub(crate) async fn get_pushrules_all_route(
 			.map_err(|_| Error::bad_database("Invalid account data event in db."))?
 			.content;
 
+		let mut global = account_data.global;
+		global.update_with_server_default(Ruleset::server_default(sender_user));
+
 		Ok(get_pushrules_all::v3::Response {
-			global: account_data.global,
+			global,
 		})
 	} else {
 		services().account_data.update(
@@ -39,7 +46,7 @@ pub(crate) async fn get_pushrules_all_route(
 			sender_user,
 			GlobalAccountDataEventType::PushRules.to_string().into(),
 			&serde_json::to_value(PushRulesEvent {
-				content: ruma::events::push_rules::PushRulesEventContent {
+				content: PushRulesEventContent {
 					global: Ruleset::server_default(sender_user),
 				},
 			})
@@ -58,19 +65,8 @@ pub(crate) async fn get_pushrules_all_route(
 pub(crate) async fn get_pushrule_route(body: Ruma<get_pushrule::v3::Request>) -> Result<get_pushrule::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?
-		.content;
-
-	let rule = account_data
-		.global
-		.get(body.kind.clone(), &body.rule_id)
-		.map(Into::into);
+	let global = services().pusher.get_ruleset(sender_user)?;
+	let rule = global.get(body.kind.clone(), &body.rule_id).map(Into::into);
 
 	if let Some(rule) = rule {
 		Ok(get_pushrule::v3::Response {
@@ -95,20 +91,8 @@ pub(crate) async fn set_pushrule_route(body: Ruma<set_pushrule::v3::Request>) ->
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let mut account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?;
-
-	if let Err(error) =
-		account_data
-			.content
-			.global
-			.insert(body.rule.clone(), body.after.as_deref(), body.before.as_deref())
-	{
+	let mut global = services().pusher.get_ruleset(sender_user)?;
+	if let Err(error) = global.insert(body.rule.clone(), body.after.as_deref(), body.before.as_deref()) {
 		let err = match error {
 			InsertPushRuleError::ServerDefaultRuleId => Error::BadRequest(
 				ErrorKind::InvalidParam,
@@ -134,12 +118,7 @@ pub(crate) async fn set_pushrule_route(body: Ruma<set_pushrule::v3::Request>) ->
 		return Err(err);
 	}
 
-	services().account_data.update(
-		None,
-		sender_user,
-		GlobalAccountDataEventType::PushRules.to_string().into(),
-		&serde_json::to_value(account_data).expect("to json value always works"),
-	)?;
+	update_ruleset(sender_user, global)?;
 
 	Ok(set_pushrule::v3::Response {})
 }
@@ -159,16 +138,7 @@ pub(crate) async fn get_pushrule_actions_route(
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?
-		.content;
-
-	let global = account_data.global;
+	let global = services().pusher.get_ruleset(sender_user)?;
 	let actions = global
 		.get(body.kind.clone(), &body.rule_id)
 		.map(|rule| rule.actions().to_owned())
@@ -194,29 +164,15 @@ pub(crate) async fn set_pushrule_actions_route(
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let mut account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?;
-
-	if account_data
-		.content
-		.global
+	let mut global = services().pusher.get_ruleset(sender_user)?;
+	if global
 		.set_actions(body.kind.clone(), &body.rule_id, body.actions.clone())
 		.is_err()
 	{
 		return Err(Error::BadRequest(ErrorKind::NotFound, "Push rule not found."));
 	}
 
-	services().account_data.update(
-		None,
-		sender_user,
-		GlobalAccountDataEventType::PushRules.to_string().into(),
-		&serde_json::to_value(account_data).expect("to json value always works"),
-	)?;
+	update_ruleset(sender_user, global)?;
 
 	Ok(set_pushrule_actions::v3::Response {})
 }
@@ -236,15 +192,7 @@ pub(crate) async fn get_pushrule_enabled_route(
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?;
-
-	let global = account_data.content.global;
+	let global = services().pusher.get_ruleset(sender_user)?;
 	let enabled = global
 		.get(body.kind.clone(), &body.rule_id)
 		.map(ruma::push::AnyPushRuleRef::enabled)
@@ -270,29 +218,15 @@ pub(crate) async fn set_pushrule_enabled_route(
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let mut account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?;
-
-	if account_data
-		.content
-		.global
+	let mut global = services().pusher.get_ruleset(sender_user)?;
+	if global
 		.set_enabled(body.kind.clone(), &body.rule_id, body.enabled)
 		.is_err()
 	{
 		return Err(Error::BadRequest(ErrorKind::NotFound, "Push rule not found."));
 	}
 
-	services().account_data.update(
-		None,
-		sender_user,
-		GlobalAccountDataEventType::PushRules.to_string().into(),
-		&serde_json::to_value(account_data).expect("to json value always works"),
-	)?;
+	update_ruleset(sender_user, global)?;
 
 	Ok(set_pushrule_enabled::v3::Response {})
 }
@@ -312,19 +246,8 @@ pub(crate) async fn delete_pushrule_route(
 		));
 	}
 
-	let event = services()
-		.account_data
-		.get(None, sender_user, GlobalAccountDataEventType::PushRules.to_string().into())?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "PushRules event not found."))?;
-
-	let mut account_data = serde_json::from_str::<PushRulesEvent>(event.get())
-		.map_err(|_| Error::bad_database("Invalid account data event in db."))?;
-
-	if let Err(error) = account_data
-		.content
-		.global
-		.remove(body.kind.clone(), &body.rule_id)
-	{
+	let mut global = services().pusher.get_ruleset(sender_user)?;
+	if let Err(error) = global.remove(body.kind.clone(), &body.rule_id) {
 		let err = match error {
 			RemovePushRuleError::ServerDefault => {
 				Error::BadRequest(ErrorKind::InvalidParam, "Cannot delete a server-default pushrule.")
@@ -336,14 +259,29 @@ pub(crate) async fn delete_pushrule_route(
 		return Err(err);
 	}
 
+	update_ruleset(sender_user, global)?;
+
+	Ok(delete_pushrule::v3::Response {})
+}
+
+/// Stores the push rules of a user and recounts their unread notifications
+/// with them in the background.
+fn update_ruleset(user_id: &UserId, global: Ruleset) -> Result<()> {
 	services().account_data.update(
 		None,
-		sender_user,
+		user_id,
 		GlobalAccountDataEventType::PushRules.to_string().into(),
-		&serde_json::to_value(account_data).expect("to json value always works"),
+		&serde_json::to_value(PushRulesEvent {
+			content: PushRulesEventContent {
+				global,
+			},
+		})
+		.expect("to json value always works"),
 	)?;
 
-	Ok(delete_pushrule::v3::Response {})
+	services().rooms.user.schedule_recount(user_id);
+
+	Ok(())
 }
 
 /// # `GET /_matrix/client/r0/pushers`
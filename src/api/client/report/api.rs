@@ -0,0 +1,125 @@
+//! Requests reporting rooms (MSC4151) and users, which our ruma doesn't have
+//! yet. Both share the empty response of reporting events.
+
+use bytes::BufMut;
+use ruma::{
+	api::{
+		error::{DeserializationError, FromHttpRequestError, IntoHttpError, MatrixError},
+		AuthScheme, IncomingRequest, Metadata, OutgoingResponse, VersionHistory,
+	},
+	IdParseError, OwnedRoomId, OwnedUserId,
+};
+use serde::{Deserialize, Serialize};
+
+// The stable paths are listed with the unstable ones, since our ruma doesn't
+// know the Matrix versions stabilising them.
+const REPORT_ROOM_METADATA: Metadata = Metadata {
+	method: http::Method::POST,
+	rate_limited: true,
+	authentication: AuthScheme::AccessToken,
+	history: VersionHistory::new(
+		&[
+			"/_matrix/client/unstable/org.matrix.msc4151/rooms/:room_id/report",
+			"/_matrix/client/v3/rooms/:room_id/report",
+		],
+		&[],
+		None,
+		None,
+	),
+};
+
+const REPORT_USER_METADATA: Metadata = Metadata {
+	method: http::Method::POST,
+	rate_limited: true,
+	authentication: AuthScheme::AccessToken,
+	history: VersionHistory::new(&["/_matrix/client/v3/users/:user_id/report"], &[], None, None),
+};
+
+pub(crate) struct ReportRoomRequest {
+	pub(crate) room_id: OwnedRoomId,
+	pub(crate) reason: Option<String>,
+}
+
+pub(crate) struct ReportUserRequest {
+	pub(crate) user_id: OwnedUserId,
+	pub(crate) reason: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct RequestBody {
+	reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Response {}
+
+impl IncomingRequest for ReportRoomRequest {
+	type EndpointError = MatrixError;
+	type OutgoingResponse = Response;
+
+	const METADATA: Metadata = REPORT_ROOM_METADATA;
+
+	fn try_from_http_request<B, S>(request: http::Request<B>, path_args: &[S]) -> Result<Self, FromHttpRequestError>
+	where
+		B: AsRef<[u8]>,
+		S: AsRef<str>,
+	{
+		let (room_id, body) = parse(&request, path_args)?;
+
+		Ok(Self {
+			room_id,
+			reason: body.reason,
+		})
+	}
+}
+
+impl IncomingRequest for ReportUserRequest {
+	type EndpointError = MatrixError;
+	type OutgoingResponse = Response;
+
+	const METADATA: Metadata = REPORT_USER_METADATA;
+
+	fn try_from_http_request<B, S>(request: http::Request<B>, path_args: &[S]) -> Result<Self, FromHttpRequestError>
+	where
+		B: AsRef<[u8]>,
+		S: AsRef<str>,
+	{
+		let (user_id, body) = parse(&request, path_args)?;
+
+		Ok(Self {
+			user_id,
+			reason: body.reason,
+		})
+	}
+}
+
+impl OutgoingResponse for Response {
+	fn try_into_http_response<T: Default + BufMut>(self) -> Result<http::Response<T>, IntoHttpError> {
+		Ok(http::Response::builder()
+			.header(http::header::CONTENT_TYPE, "application/json")
+			.body(ruma::serde::json_to_buf(&self)?)?)
+	}
+}
+
+/// Parses the ID in the path and the body of a report request
+fn parse<B, S, T>(request: &http::Request<B>, path_args: &[S]) -> Result<(T, RequestBody), FromHttpRequestError>
+where
+	B: AsRef<[u8]>,
+	S: AsRef<str>,
+	T: TryFrom<String, Error = IdParseError>,
+{
+	let id = path_args
+		.first()
+		.map(|id| id.as_ref().to_owned())
+		.unwrap_or_default();
+	let id = T::try_from(id).map_err(DeserializationError::from)?;
+
+	let body = request.body().as_ref();
+	let body = if body.is_empty() {
+		RequestBody::default()
+	} else {
+		serde_json::from_slice(body)?
+	};
+
+	Ok((id, body))
+}
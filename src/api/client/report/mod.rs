@@ -0,0 +1,255 @@
+mod api;
+
+use std::time::Duration;
+
+use rand::Rng;
+use ruma::{
+	api::client::{error::ErrorKind, room::report_content},
+	events::room::message,
+	int, EventId, RoomId, UserId,
+};
+use tokio::time::sleep;
+use tracing::info;
+
+use crate::{
+	debug_info,
+	service::{
+		pdu::PduEvent,
+		reports::{Report, ReportTarget},
+		user_is_local,
+	},
+	services,
+	utils::HtmlEscape,
+	Error, Result, Ruma,
+};
+
+/// # `POST /_matrix/client/v3/rooms/{roomId}/report/{eventId}`
+///
+/// Reports an inappropriate event to homeserver admins
+pub(crate) async fn report_event_route(
+	body: Ruma<report_content::v3::Request>,
+) -> Result<report_content::v3::Response> {
+	// user authentication
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	info!(
+		"Received /report request by user {sender_user} for room {} and event ID {}",
+		body.room_id, body.event_id
+	);
+
+	// check if we know about the reported event ID or if it's invalid
+	let Some(pdu) = services().rooms.timeline.get_pdu(&body.event_id)? else {
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"Event ID is not known to us or Event ID is invalid",
+		));
+	};
+
+	is_report_valid(&pdu.event_id, &body.room_id, sender_user, &body.reason, body.score, &pdu)?;
+
+	let report = services().reports.add(
+		sender_user,
+		ReportTarget::Event {
+			room_id: pdu.room_id.clone(),
+			event_id: (*pdu.event_id).to_owned(),
+			sender: pdu.sender.clone(),
+		},
+		body.reason.clone(),
+		body.score.map(i64::from),
+	)?;
+	notify_admins(&report).await;
+
+	delay_response().await?;
+
+	Ok(report_content::v3::Response {})
+}
+
+/// # `POST /_matrix/client/v3/rooms/{roomId}/report`
+///
+/// Reports an inappropriate room to homeserver admins (MSC4151)
+pub(crate) async fn report_room_route(body: Ruma<api::ReportRoomRequest>) -> Result<api::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	info!("Received /report request by user {sender_user} for room {}", body.room_id);
+
+	if !services().rooms.metadata.exists(&body.room_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Room is not known to us."));
+	}
+	is_reason_valid(&body.reason)?;
+
+	let report = services().reports.add(
+		sender_user,
+		ReportTarget::Room {
+			room_id: body.room_id.clone(),
+		},
+		body.reason.clone(),
+		None,
+	)?;
+	notify_admins(&report).await;
+
+	delay_response().await?;
+
+	Ok(api::Response {})
+}
+
+/// # `POST /_matrix/client/v3/users/{userId}/report`
+///
+/// Reports an inappropriate user to homeserver admins
+pub(crate) async fn report_user_route(body: Ruma<api::ReportUserRequest>) -> Result<api::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	info!("Received /report request by user {sender_user} for user {}", body.user_id);
+
+	// Remote users can't be checked, reporting them is fine as long as they are
+	// valid
+	if user_is_local(&body.user_id) && !services().users.exists(&body.user_id)? {
+		// Don't let the response time tell whether the user exists
+		delay_response().await?;
+		return Err(Error::BadRequest(ErrorKind::NotFound, "User is not known to us."));
+	}
+	is_reason_valid(&body.reason)?;
+
+	let report = services().reports.add(
+		sender_user,
+		ReportTarget::User {
+			user_id: body.user_id.clone(),
+		},
+		body.reason.clone(),
+		None,
+	)?;
+	notify_admins(&report).await;
+
+	delay_response().await?;
+
+	Ok(api::Response {})
+}
+
+/// Sends a message to the admin room that a report was received, with an
+/// @room ping for urgency
+async fn notify_admins(report: &Report) {
+	let (target, target_html) = match &report.target {
+		ReportTarget::Event {
+			room_id,
+			event_id,
+			sender,
+		} => (
+			format!("Event ID: {event_id}\nRoom ID: {room_id}\nSent By: {sender}"),
+			format!(
+				"<li>Event Info<ul><li>Event ID: <code>{event_id}</code> <a \
+				 href=\"https://matrix.to/#/{room_id}/{event_id}\">🔗</a></li><li>Room ID: \
+				 <code>{room_id}</code></li><li>Sent By: <a href=\"https://matrix.to/#/{sender}\">{sender}</a></li></ul></li>"
+			),
+		),
+		ReportTarget::Room {
+			room_id,
+		} => (
+			format!("Room ID: {room_id}"),
+			format!(
+				"<li>Room Info<ul><li>Room ID: <code>{room_id}</code> <a \
+				 href=\"https://matrix.to/#/{room_id}\">🔗</a></li></ul></li>"
+			),
+		),
+		ReportTarget::User {
+			user_id,
+		} => (
+			format!("User ID: {user_id}"),
+			format!(
+				"<li>User Info<ul><li>User ID: <a href=\"https://matrix.to/#/{user_id}\">{user_id}</a></li></ul></li>"
+			),
+		),
+	};
+	let score = report
+		.score
+		.map_or_else(String::new, |score| format!("Report Score: {score}\n"));
+	let score_html = report
+		.score
+		.map_or_else(String::new, |score| format!("<li>Report Score: {score}</li>"));
+	let reason = report.reason.as_deref().unwrap_or("");
+
+	services()
+		.admin
+		.send_message(message::RoomMessageEventContent::text_html(
+			format!(
+				"@room Report {} received from: {}\n\n{target}\n\n{score}Report Reason: {reason}\n\nHandle it with \
+				 `!admin reports resolve {0}` or `!admin reports dismiss {0}`.",
+				report.id, report.reporter,
+			),
+			format!(
+				"<details><summary>@room Report {0} received from: <a \
+				 href=\"https://matrix.to/#/{1}\">{1}</a></summary><ul>{target_html}<li>Report \
+				 Info<ul>{score_html}<li>Report Reason: {2}</li></ul></li></ul><p>Handle it with <code>!admin reports \
+				 resolve {0}</code> or <code>!admin reports dismiss {0}</code>.</p></details>",
+				report.id,
+				report.reporter,
+				HtmlEscape(reason),
+			),
+		))
+		.await;
+}
+
+/// in the following order:
+///
+/// check if the room ID from the URI matches the PDU's room ID
+/// check if reporting user is in the reporting room
+/// check if score is in valid range
+/// check if report reasoning is less than or equal to 750 characters
+fn is_report_valid(
+	event_id: &EventId, room_id: &RoomId, sender_user: &UserId, reason: &Option<String>, score: Option<ruma::Int>,
+	pdu: &std::sync::Arc<PduEvent>,
+) -> Result<bool> {
+	debug_info!("Checking if report from user {sender_user} for event {event_id} in room {room_id} is valid");
+
+	if room_id != pdu.room_id {
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"Event ID does not belong to the reported room",
+		));
+	}
+
+	if !services()
+		.rooms
+		.state_cache
+		.room_members(&pdu.room_id)
+		.filter_map(Result::ok)
+		.any(|user_id| user_id == *sender_user)
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"You are not in the room you are reporting.",
+		));
+	}
+
+	if score.map(|s| s > int!(0) || s < int!(-100)) == Some(true) {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Invalid score, must be within 0 to -100",
+		));
+	};
+
+	is_reason_valid(reason)?;
+
+	Ok(true)
+}
+
+/// check if report reasoning is less than or equal to 750 characters
+fn is_reason_valid(reason: &Option<String>) -> Result<()> {
+	if reason.clone().map(|s| s.len() >= 750) == Some(true) {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Reason too long, should be 750 characters or fewer",
+		));
+	};
+
+	Ok(())
+}
+
+/// even though this is kinda security by obscurity, let's still make a small
+/// random delay sending a successful response per spec suggestion regarding
+/// enumerating for potential events existing in our server.
+async fn delay_response() -> Result<()> {
+	let time_to_wait = rand::thread_rng().gen_range(8..21);
+	debug_info!("Got successful /report request, waiting {time_to_wait} seconds before sending successful response.");
+	sleep(Duration::from_secs(time_to_wait)).await;
+
+	Ok(())
+}
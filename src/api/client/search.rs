@@ -14,14 +14,14 @@ use ruma::{
 };
 use tracing::debug;
 
-use crate::{services, Error, Result, Ruma};
+use crate::{service::rooms::search::RoomQuery, services, Error, Result, Ruma};
 
 /// # `POST /_matrix/client/r0/search`
 ///
 /// Searches rooms for messages.
 ///
-/// - Only works if the user is currently joined to the room (TODO: Respect
-///   history visibility)
+/// - Only works if the user is currently joined to the room
+/// - Supports quoted phrases, `prefix*` terms and `order_by: rank`
 pub(crate) async fn search_events_route(body: Ruma<search_events::v3::Request>) -> Result<search_events::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
@@ -88,8 +88,6 @@ pub(crate) async fn search_events_route(body: Ruma<search_events::v3::Request>)
 		}
 	}
 
-	let mut searches = Vec::new();
-
 	for room_id in &room_ids {
 		if !services()
 			.rooms
@@ -101,14 +99,6 @@ pub(crate) async fn search_events_route(body: Ruma<search_events::v3::Request>)
 				"You don't have permission to view this room.",
 			));
 		}
-
-		if let Some(search) = services()
-			.rooms
-			.search
-			.search_pdus(room_id, &search_criteria.search_term)?
-		{
-			searches.push(search.0.peekable());
-		}
 	}
 
 	let skip: usize = match body.next_batch.as_ref().map(|s| s.parse()) {
@@ -117,71 +107,42 @@ pub(crate) async fn search_events_route(body: Ruma<search_events::v3::Request>)
 		None => 0, // Default to the start
 	};
 
-	let mut results = Vec::new();
-	let next_batch = skip.saturating_add(limit);
-
-	for _ in 0..next_batch {
-		if let Some(s) = searches
-			.iter_mut()
-			.map(|s| (s.peek().cloned(), s))
-			.max_by_key(|(peek, _)| peek.clone())
-			.and_then(|(_, i)| i.next())
-		{
-			results.push(s);
-		}
-	}
+	let query = RoomQuery {
+		rooms: &room_ids,
+		user_id: sender_user,
+		criteria: search_criteria,
+		limit,
+		skip,
+	};
 
-	let results: Vec<_> = results
-		.iter()
-		.skip(skip)
-		.filter_map(|result| {
-			services()
-				.rooms
-				.timeline
-				.get_pdu_from_id(result)
-				.ok()?
-				.filter(|pdu| {
-					!pdu.is_redacted()
-						&& services()
-							.rooms
-							.state_accessor
-							.user_can_see_event(sender_user, &pdu.room_id, &pdu.event_id)
-							.unwrap_or(false)
-				})
-				.map(|pdu| pdu.to_room_event())
-		})
-		.map(|result| {
-			Ok::<_, Error>(SearchResult {
-				context: EventContextResult {
-					end: None,
-					events_after: Vec::new(),
-					events_before: Vec::new(),
-					profile_info: BTreeMap::new(),
-					start: None,
-				},
-				rank: None,
-				result: Some(result),
-			})
+	let search = services().rooms.search.search_pdus(&query)?;
+
+	let results: Vec<_> = search
+		.results
+		.into_iter()
+		.map(|(pdu, rank)| SearchResult {
+			context: EventContextResult {
+				end: None,
+				events_after: Vec::new(),
+				events_before: Vec::new(),
+				profile_info: BTreeMap::new(),
+				start: None,
+			},
+			rank,
+			result: Some(pdu.to_room_event()),
 		})
-		.filter_map(Result::ok)
-		.take(limit)
 		.collect();
 
-	let more_unloaded_results = searches.iter_mut().any(|s| s.peek().is_some());
-	let next_batch = more_unloaded_results.then(|| next_batch.to_string());
+	let next_batch = search.more.then(|| skip.saturating_add(limit).to_string());
 
 	Ok(search_events::v3::Response::new(ResultCategories {
 		room_events: ResultRoomEvents {
-			count: Some(results.len().try_into().unwrap_or_else(|_| uint!(0))),
+			count: Some(search.count.try_into().unwrap_or_else(|_| uint!(0))),
 			groups: BTreeMap::new(), // TODO
 			next_batch,
 			results,
 			state: room_states,
-			highlights: search_criteria
-				.search_term
-				.split_terminator(|c: char| !c.is_alphanumeric())
-				.map(str::to_lowercase)
-				.collect(),
+			highlights: search.highlights,
 		},
 	}))
 }
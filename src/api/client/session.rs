@@ -4,7 +4,7 @@ use ruma::{
 		session::{
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType},
+				v3::{ApplicationServiceLoginType, IdentityProvider, PasswordLoginType, SsoLoginType, TokenLoginType},
 			},
 			login::{
 				self,
@@ -19,8 +19,8 @@ use ruma::{
 use serde::Deserialize;
 use tracing::{debug, info, warn};
 
-use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
-use crate::{services, utils, utils::hash, Error, Result, Ruma};
+use super::{auto_join_rooms, leave_room, DEVICE_ID_LENGTH, TOKEN_LENGTH};
+use crate::{debug_info, service::ratelimit::Limit, services, utils, utils::hash, Error, Result, Ruma};
 
 #[derive(Debug, Deserialize)]
 struct Claims {
@@ -35,10 +35,34 @@ struct Claims {
 pub(crate) async fn get_login_types_route(
 	_body: Ruma<get_login_types::v3::Request>,
 ) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
+	let mut flows = vec![
 		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
 		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
-	]))
+	];
+
+	let providers = services().sso.providers();
+	if !providers.is_empty() {
+		let mut sso = SsoLoginType::default();
+		sso.identity_providers = providers
+			.iter()
+			.map(|provider| {
+				let mut idp = IdentityProvider::new(
+					provider.id.clone(),
+					provider.name.clone().unwrap_or_else(|| provider.id.clone()),
+				);
+				idp.icon.clone_from(&provider.icon);
+				idp
+			})
+			.collect();
+
+		flows.push(get_login_types::v3::LoginType::Sso(sso));
+	}
+
+	if !providers.is_empty() || services().globals.jwt_decoding_key().is_some() {
+		flows.push(get_login_types::v3::LoginType::Token(TokenLoginType::default()));
+	}
+
+	Ok(get_login_types::v3::Response::new(flows))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -46,8 +70,9 @@ pub(crate) async fn get_login_types_route(
 /// Authenticates the user and returns an access token it can use in subsequent
 /// requests.
 ///
-/// - The user needs to authenticate using their password (or if enabled using a
-///   json web token)
+/// - The user needs to authenticate using their password, checked against LDAP
+///   if enabled (or if enabled using a json web token, or the login token from
+///   SSO login)
 /// - If `device_id` is known: invalidates old access token of that device
 /// - If `device_id` is unknown: creates a new device
 /// - Returns access token that is associated with the user and device
@@ -56,6 +81,10 @@ pub(crate) async fn get_login_types_route(
 /// /_matrix/client/r0/login`](fn.get_supported_versions_route.html) to see
 /// supported login types.
 pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Response> {
+	if body.appservice_info.is_none() {
+		services().ratelimit.check_ip(Limit::Login, body.client)?;
+	}
+
 	// Validate login method
 	// TODO: Other login methods
 	let user_id = match &body.login_info {
@@ -77,26 +106,41 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 			}
 			.map_err(|_| Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
 
-			let hash = services()
-				.users
-				.password_hash(&user_id)?
-				.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."))?;
+			if services().ldap.handles(&user_id) {
+				let login = services().ldap.login(&user_id, password).await?;
+				if login.created {
+					auto_join_rooms(&login.user_id).await?;
+				}
 
-			if hash.is_empty() {
-				return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
-			}
+				if let Some(is_admin) = login.is_admin {
+					sync_ldap_admin(&login.user_id, is_admin).await?;
+				}
 
-			if hash::verify_password(password, &hash).is_err() {
-				return Err(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."));
-			}
+				login.user_id
+			} else {
+				let hash = services()
+					.users
+					.password_hash(&user_id)?
+					.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."))?;
 
-			user_id
+				if hash.is_empty() {
+					return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+				}
+
+				if hash::verify_password(password, &hash).is_err() {
+					return Err(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."));
+				}
+
+				user_id
+			}
 		},
 		login::v3::LoginInfo::Token(login::v3::Token {
 			token,
 		}) => {
 			debug!("Got token login type");
-			if let Some(jwt_decoding_key) = services().globals.jwt_decoding_key() {
+			if let Some(user_id) = services().sso.take_login_token(token) {
+				user_id
+			} else if let Some(jwt_decoding_key) = services().globals.jwt_decoding_key() {
 				let token =
 					jsonwebtoken::decode::<Claims>(token, jwt_decoding_key, &jsonwebtoken::Validation::default())
 						.map_err(|e| {
@@ -113,7 +157,7 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 			} else {
 				return Err(Error::BadRequest(
 					ErrorKind::Unknown,
-					"Token login is not supported (server has no jwt decoding key).",
+					"Token is invalid or expired, and the server has no jwt decoding key.",
 				));
 			}
 		},
@@ -202,6 +246,32 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 	})
 }
 
+/// Grants or revokes admin privileges to match the LDAP group membership of
+/// a user logging in. Revoking makes them leave the admin room like admins
+/// giving up their privileges do.
+async fn sync_ldap_admin(user_id: &UserId, is_admin: bool) -> Result<()> {
+	match (is_admin, services().users.is_admin(user_id)?) {
+		(true, false) => {
+			let displayname = services()
+				.users
+				.displayname(user_id)?
+				.unwrap_or_else(|| user_id.localpart().to_owned());
+
+			service::admin::make_user_admin(user_id, displayname).await?;
+			debug_info!(%user_id, "Granted admin privileges from LDAP group membership");
+		},
+		(false, true) => {
+			if let Some(admin_room) = service::admin::Service::get_admin_room()? {
+				leave_room(user_id, &admin_room, Some("Admin privileges revoked by LDAP".to_owned())).await?;
+				debug_info!(%user_id, "Revoked admin privileges from LDAP group membership");
+			}
+		},
+		_ => {},
+	}
+
+	Ok(())
+}
+
 /// # `POST /_matrix/client/v3/logout`
 ///
 /// Log out the current device.
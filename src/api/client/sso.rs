@@ -0,0 +1,180 @@
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use http::Uri;
+use ruma::api::client::{
+	error::ErrorKind,
+	session::{sso_login, sso_login_with_provider},
+};
+use serde::Deserialize;
+use tracing::warn;
+
+use super::auto_join_rooms;
+use crate::{services, utils::HtmlEscape, Error, Result, Ruma};
+
+/// Query parameters identity providers redirect back with.
+#[derive(Deserialize)]
+struct CallbackQuery {
+	state: String,
+	code: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+const LOGIN_FALLBACK_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Log in</title>
+</head>
+<body>
+<p id="status">Log in with:</p>
+<ul id="providers">{providers}</ul>
+<script>
+const redirectUrl = location.origin + location.pathname;
+for (const link of document.querySelectorAll("a[data-idp]")) {
+	link.href = "/_matrix/client/v3/login/sso/redirect/" + encodeURIComponent(link.dataset.idp)
+		+ "?redirectUrl=" + encodeURIComponent(redirectUrl);
+}
+
+const loginToken = new URLSearchParams(location.search).get("loginToken");
+if (loginToken) {
+	const status = document.getElementById("status");
+	document.getElementById("providers").hidden = true;
+	status.textContent = "Logging in...";
+	fetch("/_matrix/client/v3/login", {
+		method: "POST",
+		headers: { "Content-Type": "application/json" },
+		body: JSON.stringify({ type: "m.login.token", token: loginToken }),
+	})
+		.then((response) => response.json().then((body) => ({ ok: response.ok, body })))
+		.then(({ ok, body }) => {
+			if (!ok) {
+				throw new Error(body.error);
+			}
+			if (window.matrixLogin && window.matrixLogin.onLogin) {
+				window.matrixLogin.onLogin(body);
+			}
+			status.textContent = "You are now logged in, you can close this page.";
+		})
+		.catch((error) => {
+			status.textContent = "Login failed: " + error.message;
+		});
+}
+</script>
+</body>
+</html>
+"#;
+
+const CONFIRM_REDIRECT_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Continue to {destination}</title>
+</head>
+<body>
+<p>You are about to log in to <strong>{destination}</strong> as {user_id}.</p>
+<p>Continuing gives this site access to your account. Only continue if you trust it and started this login yourself.</p>
+<p><a href="{redirect_url}">Continue to {destination}</a></p>
+</body>
+</html>
+"#;
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user to the first configured identity provider to log in.
+pub(crate) async fn sso_login_route(body: Ruma<sso_login::v3::Request>) -> Result<sso_login::v3::Response> {
+	let location = services()
+		.sso
+		.authorization_url(None, &body.redirect_url)
+		.await?;
+
+	Ok(sso_login::v3::Response::new(location))
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Redirects the user to the given identity provider to log in.
+pub(crate) async fn sso_login_with_provider_route(
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	let location = services()
+		.sso
+		.authorization_url(Some(&body.idp_id), &body.redirect_url)
+		.await?;
+
+	Ok(sso_login_with_provider::v3::Response::new(location))
+}
+
+/// # `GET /_conduwuit/client/oidc/callback`
+///
+/// Where identity providers send the user back to after logging in. Redirects
+/// to the client's `redirectUrl` with a `loginToken` it can log in with using
+/// `m.login.token`.
+///
+/// - Creates the account on first login if the identity provider allows it
+/// - Asks the user to confirm redirect URLs which aren't trusted, as anyone can
+///   start a login with their own
+pub(crate) async fn oidc_callback_route(uri: Uri) -> Result<Response> {
+	let query: CallbackQuery = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid SSO callback parameters."))?;
+
+	if let Some(error) = query.error {
+		warn!(
+			"Identity provider returned an error: {error}: {}",
+			query.error_description.unwrap_or_default()
+		);
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Login at the identity provider failed.",
+		));
+	}
+
+	let code = query
+		.code
+		.ok_or(Error::BadRequest(ErrorKind::MissingParam, "Missing authorization code."))?;
+
+	let login = services().sso.complete_login(&query.state, &code).await?;
+	if login.created {
+		auto_join_rooms(&login.user_id).await?;
+	}
+
+	if login.trusted {
+		return Ok(Redirect::to(login.redirect_url.as_str()).into_response());
+	}
+
+	// Apps registering a custom scheme have no host to show
+	let destination = login
+		.redirect_url
+		.host_str()
+		.unwrap_or_else(|| login.redirect_url.scheme());
+
+	Ok(Html(
+		CONFIRM_REDIRECT_PAGE
+			.replace("{destination}", &HtmlEscape(destination).to_string())
+			.replace("{user_id}", &HtmlEscape(login.user_id.as_str()).to_string())
+			.replace("{redirect_url}", &HtmlEscape(login.redirect_url.as_str()).to_string()),
+	)
+	.into_response())
+}
+
+/// # `GET /_matrix/static/client/login/`
+///
+/// Login fallback page for clients without native SSO support, offering the
+/// configured identity providers.
+pub(crate) async fn login_fallback_route() -> impl IntoResponse {
+	let providers: String = services()
+		.sso
+		.providers()
+		.iter()
+		.map(|provider| {
+			format!(
+				r#"<li><a data-idp="{}">{}</a></li>"#,
+				HtmlEscape(&provider.id),
+				HtmlEscape(provider.name.as_deref().unwrap_or(&provider.id))
+			)
+		})
+		.collect();
+
+	Html(LOGIN_FALLBACK_PAGE.replace("{providers}", &providers))
+}
@@ -18,8 +18,9 @@ use ruma::{
 	EventId, RoomId, UserId,
 };
 
+use super::shadow_ban_event_id;
 use crate::{
-	service::{pdu::PduBuilder, server_is_ours},
+	service::{pdu::PduBuilder, ratelimit::Limit, server_is_ours},
 	services, Error, Result, Ruma, RumaResponse,
 };
 
@@ -36,6 +37,12 @@ pub(crate) async fn send_state_event_for_key_route(
 ) -> Result<send_state_event::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if body.appservice_info.is_none() {
+		services()
+			.ratelimit
+			.check_user(Limit::Message, sender_user)?;
+	}
+
 	let event_id = send_state_event_for_key_helper(
 		sender_user,
 		&body.room_id,
@@ -172,6 +179,12 @@ async fn send_state_event_for_key_helper(
 	sender: &UserId, room_id: &RoomId, event_type: &StateEventType, json: &Raw<AnyStateEventContent>, state_key: String,
 ) -> Result<Arc<EventId>> {
 	allowed_to_send_state_event(room_id, event_type, json).await?;
+
+	// Shadow-banned users get an event ID as if their event was sent
+	if services().users.is_shadow_banned(sender)? {
+		return Ok(shadow_ban_event_id().as_ref().into());
+	}
+
 	let state_lock = services().globals.roomid_mutex_state.lock(room_id).await;
 	let event_id = services()
 		.rooms
@@ -11,8 +11,8 @@ use ruma::{
 		sync::sync_events::{
 			self,
 			v3::{
-				Ephemeral, Filter, GlobalAccountData, InviteState, InvitedRoom, JoinedRoom, LeftRoom, Presence,
-				RoomAccountData, RoomSummary, Rooms, State, Timeline, ToDevice,
+				Ephemeral, Filter, GlobalAccountData, InviteState, InvitedRoom, JoinedRoom, KnockState, KnockedRoom,
+				LeftRoom, Presence, RoomAccountData, RoomSummary, Rooms, State, Timeline, ToDevice,
 			},
 			v4::SlidingOp,
 			DeviceLists, UnreadNotificationsCount,
@@ -25,10 +25,14 @@ use ruma::{
 		StateEventType, TimelineEventType,
 	},
 	serde::Raw,
-	uint, DeviceId, EventId, OwnedUserId, RoomId, UInt, UserId,
+	uint, DeviceId, EventId, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
 };
 use tracing::{error, Instrument as _, Span};
 
+mod v5;
+
+pub(crate) use v5::sync_events_v5_route;
+
 use crate::{service::pdu::EventHash, services, utils, Error, PduEvent, Result, Ruma, RumaResponse};
 
 /// # `GET /_matrix/client/r0/sync`
@@ -63,6 +67,10 @@ use crate::{service::pdu::EventHash, services, utils, Error, PduEvent, Result, R
 /// - If the user was invited after `since`: A subset of the state of the room
 ///   at the point of the invite
 ///
+/// For knocked rooms:
+/// - If the user knocked after `since`: A subset of the state of the room at
+///   the point of the knock
+///
 /// For left rooms:
 /// - If the user left after `since`: `prev_batch` token, empty state (TODO:
 ///   subset of the state at the point of the leave)
@@ -217,6 +225,39 @@ pub(crate) async fn sync_events_route(
 		);
 	}
 
+	let mut knocked_rooms = BTreeMap::new();
+	let all_knocked_rooms: Vec<_> = services()
+		.rooms
+		.state_cache
+		.rooms_knocked(&sender_user)
+		.collect();
+	for result in all_knocked_rooms {
+		let (room_id, knock_state_events) = result?;
+
+		// Get and drop the lock to wait for remaining operations to finish
+		let insert_lock = services().globals.roomid_mutex_insert.lock(&room_id).await;
+		drop(insert_lock);
+
+		let knock_count = services()
+			.rooms
+			.state_cache
+			.get_knock_count(&room_id, &sender_user)?;
+
+		// Knocked before last sync
+		if Some(since) >= knock_count {
+			continue;
+		}
+
+		knocked_rooms.insert(
+			room_id.clone(),
+			KnockedRoom {
+				knock_state: KnockState {
+					events: knock_state_events,
+				},
+			},
+		);
+	}
+
 	for user_id in left_encrypted_users {
 		let dont_share_encrypted_room = services()
 			.rooms
@@ -252,7 +293,7 @@ pub(crate) async fn sync_events_route(
 			leave: left_rooms,
 			join: joined_rooms,
 			invite: invited_rooms,
-			knock: BTreeMap::new(), // TODO
+			knock: knocked_rooms,
 		},
 		presence: Presence {
 			events: presence_updates
@@ -303,6 +344,7 @@ pub(crate) async fn sync_events_route(
 			duration = Duration::from_secs(30);
 		}
 
+		let _long_poll = services().server.metrics.sync_long_poll("v3");
 		#[allow(clippy::let_underscore_must_use)]
 		{
 			_ = tokio::time::timeout(duration, watcher).await;
@@ -1062,6 +1104,176 @@ fn share_encrypted_room(sender_user: &UserId, user_id: &UserId, ignore_room: &Ro
 		.any(|encrypted| encrypted))
 }
 
+/// Device list changes of users the sender shares encrypted rooms with since
+/// `globalsince`, and users the sender no longer shares an encrypted room
+/// with, for the sliding sync e2ee extension
+async fn sliding_sync_device_lists(
+	sender_user: &UserId, all_joined_rooms: &[OwnedRoomId], globalsince: u64,
+) -> Result<(HashSet<OwnedUserId>, HashSet<OwnedUserId>)> {
+	let mut left_encrypted_users = HashSet::new(); // Users that have left any encrypted rooms the sender was in
+	let mut device_list_changes = HashSet::new();
+	let mut device_list_left = HashSet::new();
+
+	// Look for device list updates of this account
+	device_list_changes.extend(
+		services()
+			.users
+			.keys_changed(sender_user.as_ref(), globalsince, None)
+			.filter_map(Result::ok),
+	);
+
+	for room_id in all_joined_rooms {
+		let Some(current_shortstatehash) = services().rooms.state.get_room_shortstatehash(room_id)? else {
+			error!("Room {} has no state", room_id);
+			continue;
+		};
+
+		let since_shortstatehash = services()
+			.rooms
+			.user
+			.get_token_shortstatehash(room_id, globalsince)?;
+
+		let since_sender_member: Option<RoomMemberEventContent> = since_shortstatehash
+			.and_then(|shortstatehash| {
+				services()
+					.rooms
+					.state_accessor
+					.state_get(shortstatehash, &StateEventType::RoomMember, sender_user.as_str())
+					.transpose()
+			})
+			.transpose()?
+			.and_then(|pdu| {
+				serde_json::from_str(pdu.content.get())
+					.map_err(|_| Error::bad_database("Invalid PDU in database."))
+					.ok()
+			});
+
+		let encrypted_room = services()
+			.rooms
+			.state_accessor
+			.state_get(current_shortstatehash, &StateEventType::RoomEncryption, "")?
+			.is_some();
+
+		if let Some(since_shortstatehash) = since_shortstatehash {
+			// Skip if there are only timeline changes
+			if since_shortstatehash == current_shortstatehash {
+				continue;
+			}
+
+			let since_encryption =
+				services()
+					.rooms
+					.state_accessor
+					.state_get(since_shortstatehash, &StateEventType::RoomEncryption, "")?;
+
+			let joined_since_last_sync =
+				since_sender_member.map_or(true, |member| member.membership != MembershipState::Join);
+
+			let new_encrypted_room = encrypted_room && since_encryption.is_none();
+			if encrypted_room {
+				let current_state_ids = services()
+					.rooms
+					.state_accessor
+					.state_full_ids(current_shortstatehash)
+					.await?;
+				let since_state_ids = services()
+					.rooms
+					.state_accessor
+					.state_full_ids(since_shortstatehash)
+					.await?;
+
+				for (key, id) in current_state_ids {
+					if since_state_ids.get(&key) != Some(&id) {
+						let Some(pdu) = services().rooms.timeline.get_pdu(&id)? else {
+							error!("Pdu in state not found: {}", id);
+							continue;
+						};
+						if pdu.kind == TimelineEventType::RoomMember {
+							if let Some(state_key) = &pdu.state_key {
+								let user_id = UserId::parse(state_key.clone())
+									.map_err(|_| Error::bad_database("Invalid UserId in member PDU."))?;
+
+								if *user_id == *sender_user {
+									continue;
+								}
+
+								let new_membership = serde_json::from_str::<RoomMemberEventContent>(pdu.content.get())
+									.map_err(|_| Error::bad_database("Invalid PDU in database."))?
+									.membership;
+
+								match new_membership {
+									MembershipState::Join => {
+										// A new user joined an encrypted room
+										if !share_encrypted_room(sender_user, &user_id, room_id)? {
+											device_list_changes.insert(user_id);
+										}
+									},
+									MembershipState::Leave => {
+										// Write down users that have left encrypted rooms we are in
+										left_encrypted_users.insert(user_id);
+									},
+									_ => {},
+								}
+							}
+						}
+					}
+				}
+				if joined_since_last_sync || new_encrypted_room {
+					// If the user is in a new encrypted room, give them all joined users
+					device_list_changes.extend(
+						services()
+							.rooms
+							.state_cache
+							.room_members(room_id)
+							.flatten()
+							.filter(|user_id| {
+								// Don't send key updates from the sender to the sender
+								**user_id != *sender_user
+							})
+							.filter(|user_id| {
+								// Only send keys if the sender doesn't share an encrypted room with the target
+								// already
+								!share_encrypted_room(sender_user, user_id, room_id).unwrap_or(false)
+							}),
+					);
+				}
+			}
+		}
+		// Look for device list updates in this room
+		device_list_changes.extend(
+			services()
+				.users
+				.keys_changed(room_id.as_ref(), globalsince, None)
+				.filter_map(Result::ok),
+		);
+	}
+	for user_id in left_encrypted_users {
+		let dont_share_encrypted_room = services()
+			.rooms
+			.user
+			.get_shared_rooms(vec![sender_user.to_owned(), user_id.clone()])?
+			.filter_map(Result::ok)
+			.filter_map(|other_room_id| {
+				Some(
+					services()
+						.rooms
+						.state_accessor
+						.room_state_get(&other_room_id, &StateEventType::RoomEncryption, "")
+						.ok()?
+						.is_some(),
+				)
+			})
+			.all(|encrypted| !encrypted);
+		// If the user doesn't share an encrypted room with the target anymore, we need
+		// to tell them
+		if dont_share_encrypted_room {
+			device_list_left.insert(user_id);
+		}
+	}
+
+	Ok((device_list_changes, device_list_left))
+}
+
 /// POST `/_matrix/client/unstable/org.matrix.msc3575/sync`
 ///
 /// Sliding Sync endpoint (future endpoint: `/_matrix/client/v4/sync`)
@@ -1111,169 +1323,11 @@ pub(crate) async fn sync_events_v4_route(
 			.remove_to_device_events(&sender_user, &sender_device, globalsince)?;
 	}
 
-	let mut left_encrypted_users = HashSet::new(); // Users that have left any encrypted rooms the sender was in
-	let mut device_list_changes = HashSet::new();
-	let mut device_list_left = HashSet::new();
-
-	if body.extensions.e2ee.enabled.unwrap_or(false) {
-		// Look for device list updates of this account
-		device_list_changes.extend(
-			services()
-				.users
-				.keys_changed(sender_user.as_ref(), globalsince, None)
-				.filter_map(Result::ok),
-		);
-
-		for room_id in &all_joined_rooms {
-			let Some(current_shortstatehash) = services().rooms.state.get_room_shortstatehash(room_id)? else {
-				error!("Room {} has no state", room_id);
-				continue;
-			};
-
-			let since_shortstatehash = services()
-				.rooms
-				.user
-				.get_token_shortstatehash(room_id, globalsince)?;
-
-			let since_sender_member: Option<RoomMemberEventContent> = since_shortstatehash
-				.and_then(|shortstatehash| {
-					services()
-						.rooms
-						.state_accessor
-						.state_get(shortstatehash, &StateEventType::RoomMember, sender_user.as_str())
-						.transpose()
-				})
-				.transpose()?
-				.and_then(|pdu| {
-					serde_json::from_str(pdu.content.get())
-						.map_err(|_| Error::bad_database("Invalid PDU in database."))
-						.ok()
-				});
-
-			let encrypted_room = services()
-				.rooms
-				.state_accessor
-				.state_get(current_shortstatehash, &StateEventType::RoomEncryption, "")?
-				.is_some();
-
-			if let Some(since_shortstatehash) = since_shortstatehash {
-				// Skip if there are only timeline changes
-				if since_shortstatehash == current_shortstatehash {
-					continue;
-				}
-
-				let since_encryption = services().rooms.state_accessor.state_get(
-					since_shortstatehash,
-					&StateEventType::RoomEncryption,
-					"",
-				)?;
-
-				let joined_since_last_sync =
-					since_sender_member.map_or(true, |member| member.membership != MembershipState::Join);
-
-				let new_encrypted_room = encrypted_room && since_encryption.is_none();
-				if encrypted_room {
-					let current_state_ids = services()
-						.rooms
-						.state_accessor
-						.state_full_ids(current_shortstatehash)
-						.await?;
-					let since_state_ids = services()
-						.rooms
-						.state_accessor
-						.state_full_ids(since_shortstatehash)
-						.await?;
-
-					for (key, id) in current_state_ids {
-						if since_state_ids.get(&key) != Some(&id) {
-							let Some(pdu) = services().rooms.timeline.get_pdu(&id)? else {
-								error!("Pdu in state not found: {}", id);
-								continue;
-							};
-							if pdu.kind == TimelineEventType::RoomMember {
-								if let Some(state_key) = &pdu.state_key {
-									let user_id = UserId::parse(state_key.clone())
-										.map_err(|_| Error::bad_database("Invalid UserId in member PDU."))?;
-
-									if user_id == sender_user {
-										continue;
-									}
-
-									let new_membership =
-										serde_json::from_str::<RoomMemberEventContent>(pdu.content.get())
-											.map_err(|_| Error::bad_database("Invalid PDU in database."))?
-											.membership;
-
-									match new_membership {
-										MembershipState::Join => {
-											// A new user joined an encrypted room
-											if !share_encrypted_room(&sender_user, &user_id, room_id)? {
-												device_list_changes.insert(user_id);
-											}
-										},
-										MembershipState::Leave => {
-											// Write down users that have left encrypted rooms we are in
-											left_encrypted_users.insert(user_id);
-										},
-										_ => {},
-									}
-								}
-							}
-						}
-					}
-					if joined_since_last_sync || new_encrypted_room {
-						// If the user is in a new encrypted room, give them all joined users
-						device_list_changes.extend(
-							services()
-								.rooms
-								.state_cache
-								.room_members(room_id)
-								.flatten()
-								.filter(|user_id| {
-									// Don't send key updates from the sender to the sender
-									&sender_user != user_id
-								})
-								.filter(|user_id| {
-									// Only send keys if the sender doesn't share an encrypted room with the target
-									// already
-									!share_encrypted_room(&sender_user, user_id, room_id).unwrap_or(false)
-								}),
-						);
-					}
-				}
-			}
-			// Look for device list updates in this room
-			device_list_changes.extend(
-				services()
-					.users
-					.keys_changed(room_id.as_ref(), globalsince, None)
-					.filter_map(Result::ok),
-			);
-		}
-		for user_id in left_encrypted_users {
-			let dont_share_encrypted_room = services()
-				.rooms
-				.user
-				.get_shared_rooms(vec![sender_user.clone(), user_id.clone()])?
-				.filter_map(Result::ok)
-				.filter_map(|other_room_id| {
-					Some(
-						services()
-							.rooms
-							.state_accessor
-							.room_state_get(&other_room_id, &StateEventType::RoomEncryption, "")
-							.ok()?
-							.is_some(),
-					)
-				})
-				.all(|encrypted| !encrypted);
-			// If the user doesn't share an encrypted room with the target anymore, we need
-			// to tell them
-			if dont_share_encrypted_room {
-				device_list_left.insert(user_id);
-			}
-		}
-	}
+	let (device_list_changes, device_list_left) = if body.extensions.e2ee.enabled.unwrap_or(false) {
+		sliding_sync_device_lists(&sender_user, &all_joined_rooms, globalsince).await?
+	} else {
+		(HashSet::new(), HashSet::new())
+	};
 
 	let mut lists = BTreeMap::new();
 	let mut todo_rooms = BTreeMap::new(); // and required state
@@ -1561,6 +1615,7 @@ pub(crate) async fn sync_events_v4_route(
 		if duration.as_secs() > 30 {
 			duration = Duration::from_secs(30);
 		}
+		let _long_poll = services().server.metrics.sync_long_poll("msc3575");
 		#[allow(clippy::let_underscore_must_use)]
 		{
 			_ = tokio::time::timeout(duration, watcher).await;
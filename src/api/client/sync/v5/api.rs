@@ -0,0 +1,284 @@
+//! Request and response of simplified sliding sync (MSC4186), which our ruma
+//! doesn't have yet.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use bytes::BufMut;
+use ruma::{
+	api::{
+		client::sync::sync_events::{DeviceLists, UnreadNotificationsCount},
+		error::{FromHttpRequestError, IntoHttpError, MatrixError},
+		AuthScheme, IncomingRequest, Metadata, OutgoingResponse, VersionHistory,
+	},
+	events::{
+		receipt::SyncReceiptEvent, typing::SyncTypingEvent, AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent,
+		AnyStrippedStateEvent, AnySyncStateEvent, AnySyncTimelineEvent, AnyToDeviceEvent, StateEventType,
+	},
+	room::RoomType,
+	serde::Raw,
+	DeviceKeyAlgorithm, JsOption, OwnedMxcUri, OwnedRoomId, OwnedUserId, UInt,
+};
+use serde::{Deserialize, Serialize};
+
+const METADATA: Metadata = Metadata {
+	method: http::Method::POST,
+	rate_limited: false,
+	authentication: AuthScheme::AccessToken,
+	history: VersionHistory::new(
+		&["/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"],
+		&[],
+		None,
+		None,
+	),
+};
+
+#[derive(Default)]
+pub(crate) struct Request {
+	/// `pos` of the previous response, absent to start a new connection
+	pub(crate) pos: Option<String>,
+	/// How long to wait for new data when there is none
+	pub(crate) timeout: Option<Duration>,
+	pub(crate) body: RequestBody,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct RequestBody {
+	/// Allows clients to keep several connections, e.g. one for the room list
+	/// and one for encryption
+	pub(crate) conn_id: Option<String>,
+	pub(crate) txn_id: Option<String>,
+	#[serde(default)]
+	pub(crate) lists: BTreeMap<String, List>,
+	#[serde(default)]
+	pub(crate) room_subscriptions: BTreeMap<OwnedRoomId, RoomSubscription>,
+	#[serde(default)]
+	pub(crate) extensions: Extensions,
+}
+
+#[derive(Deserialize)]
+struct RequestQuery {
+	pos: Option<String>,
+	timeout: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct List {
+	/// Ranges of the list to send, ordered by recency
+	#[serde(default)]
+	pub(crate) ranges: Vec<(UInt, UInt)>,
+	#[serde(flatten)]
+	pub(crate) room_details: RoomSubscription,
+	#[serde(default)]
+	pub(crate) filters: ListFilters,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct RoomSubscription {
+	/// State to send, `*` matching any type or state key, `$LAZY` the members
+	/// of timeline senders and `$ME` the sender
+	#[serde(default)]
+	pub(crate) required_state: Vec<(StateEventType, String)>,
+	pub(crate) timeline_limit: Option<UInt>,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct ListFilters {
+	pub(crate) is_dm: Option<bool>,
+	pub(crate) is_encrypted: Option<bool>,
+	pub(crate) is_invite: Option<bool>,
+	/// Room types to include, `null` for rooms without a type
+	#[serde(default)]
+	pub(crate) room_types: Vec<Option<RoomType>>,
+	#[serde(default)]
+	pub(crate) not_room_types: Vec<Option<RoomType>>,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct Extensions {
+	#[serde(default)]
+	pub(crate) to_device: ToDeviceConfig,
+	#[serde(default)]
+	pub(crate) e2ee: ExtensionConfig,
+	#[serde(default)]
+	pub(crate) account_data: ExtensionConfig,
+	#[serde(default)]
+	pub(crate) receipts: ExtensionConfig,
+	#[serde(default)]
+	pub(crate) typing: ExtensionConfig,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct ExtensionConfig {
+	pub(crate) enabled: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct ToDeviceConfig {
+	pub(crate) enabled: Option<bool>,
+	pub(crate) limit: Option<UInt>,
+	/// `next_batch` of the previous response, up to which events were
+	/// received
+	pub(crate) since: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Response {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) txn_id: Option<String>,
+	pub(crate) pos: String,
+	pub(crate) lists: BTreeMap<String, ListResponse>,
+	pub(crate) rooms: BTreeMap<OwnedRoomId, Room>,
+	pub(crate) extensions: ExtensionsResponse,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ListResponse {
+	/// Rooms matching the list's filters
+	pub(crate) count: UInt,
+}
+
+#[derive(Default, Serialize)]
+pub(crate) struct Room {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) name: Option<String>,
+	#[serde(skip_serializing_if = "JsOption::is_undefined")]
+	pub(crate) avatar: JsOption<OwnedMxcUri>,
+	/// Whether this is the first time the room is sent on the connection
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) initial: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) is_dm: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) invite_state: Option<Vec<Raw<AnyStrippedStateEvent>>>,
+	#[serde(flatten)]
+	pub(crate) unread_notifications: UnreadNotificationsCount,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub(crate) timeline: Vec<Raw<AnySyncTimelineEvent>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub(crate) required_state: Vec<Raw<AnySyncStateEvent>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) prev_batch: Option<String>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub(crate) limited: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) joined_count: Option<UInt>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) invited_count: Option<UInt>,
+	/// Timeline events which are new since the previous response
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) num_live: Option<UInt>,
+	/// Position of the room in the recency order of lists
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) bump_stamp: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) heroes: Option<Vec<Hero>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Hero {
+	pub(crate) user_id: OwnedUserId,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) displayname: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) avatar_url: Option<OwnedMxcUri>,
+}
+
+#[derive(Default, Serialize)]
+pub(crate) struct ExtensionsResponse {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) to_device: Option<ToDevice>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) e2ee: Option<E2ee>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) account_data: Option<AccountData>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) receipts: Option<Receipts>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub(crate) typing: Option<Typing>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ToDevice {
+	pub(crate) next_batch: String,
+	pub(crate) events: Vec<Raw<AnyToDeviceEvent>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct E2ee {
+	pub(crate) device_lists: DeviceLists,
+	pub(crate) device_one_time_keys_count: BTreeMap<DeviceKeyAlgorithm, UInt>,
+	/// Fallback keys are not supported yet
+	pub(crate) device_unused_fallback_key_types: Vec<DeviceKeyAlgorithm>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AccountData {
+	pub(crate) global: Vec<Raw<AnyGlobalAccountDataEvent>>,
+	pub(crate) rooms: BTreeMap<OwnedRoomId, Vec<Raw<AnyRoomAccountDataEvent>>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Receipts {
+	pub(crate) rooms: BTreeMap<OwnedRoomId, Raw<SyncReceiptEvent>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Typing {
+	pub(crate) rooms: BTreeMap<OwnedRoomId, Raw<SyncTypingEvent>>,
+}
+
+impl ExtensionsResponse {
+	/// Whether the extensions have anything new to send
+	pub(crate) fn is_empty(&self) -> bool {
+		self.to_device
+			.as_ref()
+			.map_or(true, |to_device| to_device.events.is_empty())
+			&& self.e2ee.as_ref().map_or(true, |e2ee| {
+				e2ee.device_lists.changed.is_empty() && e2ee.device_lists.left.is_empty()
+			}) && self.account_data.as_ref().map_or(true, |account_data| {
+			account_data.global.is_empty() && account_data.rooms.is_empty()
+		}) && self
+			.receipts
+			.as_ref()
+			.map_or(true, |receipts| receipts.rooms.is_empty())
+			&& self
+				.typing
+				.as_ref()
+				.map_or(true, |typing| typing.rooms.is_empty())
+	}
+}
+
+impl IncomingRequest for Request {
+	type EndpointError = MatrixError;
+	type OutgoingResponse = Response;
+
+	const METADATA: Metadata = METADATA;
+
+	fn try_from_http_request<B, S>(request: http::Request<B>, _path_args: &[S]) -> Result<Self, FromHttpRequestError>
+	where
+		B: AsRef<[u8]>,
+		S: AsRef<str>,
+	{
+		let query: RequestQuery = serde_html_form::from_str(request.uri().query().unwrap_or_default())?;
+		let body = request.body().as_ref();
+		let body = if body.is_empty() {
+			RequestBody::default()
+		} else {
+			serde_json::from_slice(body)?
+		};
+
+		Ok(Self {
+			pos: query.pos,
+			timeout: query.timeout.map(Duration::from_millis),
+			body,
+		})
+	}
+}
+
+impl OutgoingResponse for Response {
+	fn try_into_http_response<T: Default + BufMut>(self) -> Result<http::Response<T>, IntoHttpError> {
+		Ok(http::Response::builder()
+			.header(http::header::CONTENT_TYPE, "application/json")
+			.body(ruma::serde::json_to_buf(&self)?)?)
+	}
+}
@@ -0,0 +1,762 @@
+mod api;
+
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	sync::Arc,
+	time::Duration,
+};
+
+use conduit::PduCount;
+use ruma::{
+	api::client::{error::ErrorKind, sync::sync_events::DeviceLists},
+	events::{
+		direct::DirectEvent,
+		receipt::{ReceiptEventContent, SyncReceiptEvent},
+		room::create::RoomCreateEventContent,
+		GlobalAccountDataEventType, StateEventType, TimelineEventType,
+	},
+	serde::Raw,
+	DeviceId, JsOption, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
+};
+use tracing::error;
+
+use super::{load_timeline, sliding_sync_device_lists};
+use crate::{
+	service::users::{SlidingSyncResponse, SlidingSyncRoom},
+	services, Error, PduEvent, Result, Ruma,
+};
+
+/// Timeline events sent per room when the request doesn't say
+const TIMELINE_LIMIT_DEFAULT: u64 = 10;
+const TIMELINE_LIMIT_MAX: u64 = 100;
+const TO_DEVICE_LIMIT_DEFAULT: usize = 100;
+/// Members sent for clients to name and draw rooms without a name
+const HEROES_LIMIT: usize = 5;
+
+type RequiredState = BTreeSet<(StateEventType, String)>;
+
+/// # `POST /_matrix/client/unstable/org.matrix.simplified_msc3575/sync`
+///
+/// Simplified sliding sync (MSC4186): the client requests ranges of filtered
+/// room lists ordered by recency, and subscribes to rooms explicitly. Rooms
+/// sent on a connection are persisted with it, so later responses only carry
+/// what changed, even after a restart. What a response sent only counts as
+/// received once the client sends its `pos` back, and a `pos` the connection
+/// doesn't know is refused with `M_UNKNOWN_POS`.
+pub(crate) async fn sync_events_v5_route(body: Ruma<api::Request>) -> Result<api::Response> {
+	let sender_user = body.sender_user.clone().expect("user is authenticated");
+	let sender_device = body.sender_device.clone().expect("user is authenticated");
+	let request = body.body;
+
+	// Setup watchers, so if there's no response, we can wait for them
+	let watcher = services().globals.watch(&sender_user, &sender_device);
+	let next_batch = services().globals.current_count()?;
+
+	let pos: Option<u64> = request
+		.pos
+		.as_deref()
+		.map(str::parse)
+		.transpose()
+		.map_err(|_| Error::BadRequest(ErrorKind::UnknownPos, "Invalid pos."))?;
+
+	let conn_id = request.body.conn_id.clone().unwrap_or_default();
+	let mut connection = services()
+		.users
+		.sliding_sync_connection(&sender_user, &sender_device, &conn_id, pos)?;
+	let globalsince = connection.since;
+	let mut sent_rooms = connection.rooms.clone();
+
+	let joined_rooms: Vec<OwnedRoomId> = services()
+		.rooms
+		.state_cache
+		.rooms_joined(&sender_user)
+		.filter_map(Result::ok)
+		.collect();
+	let invited_rooms: BTreeMap<_, _> = services()
+		.rooms
+		.state_cache
+		.rooms_invited(&sender_user)
+		.filter_map(Result::ok)
+		.collect();
+	let direct_rooms = direct_rooms(&sender_user)?;
+
+	let mut bump_stamps = HashMap::with_capacity(joined_rooms.len().saturating_add(invited_rooms.len()));
+	for room_id in &joined_rooms {
+		bump_stamps.insert(room_id.clone(), bump_stamp(&sender_user, room_id)?);
+	}
+	for room_id in invited_rooms.keys() {
+		let invite_count = services()
+			.rooms
+			.state_cache
+			.get_invite_count(room_id, &sender_user)?
+			.unwrap_or(0);
+		bump_stamps.insert(room_id.clone(), invite_count);
+	}
+
+	let mut recent_rooms: Vec<&OwnedRoomId> = bump_stamps.keys().collect();
+	recent_rooms.sort_by_key(|room_id| Reverse(bump_stamps[*room_id]));
+
+	let mut todo_rooms = BTreeMap::new();
+	let mut lists = BTreeMap::new();
+	for (list_id, list) in &request.body.lists {
+		let mut list_rooms = Vec::new();
+		for room_id in &recent_rooms {
+			if matches_filters(
+				room_id,
+				&list.filters,
+				invited_rooms.contains_key(*room_id),
+				direct_rooms.contains(*room_id),
+			)? {
+				list_rooms.push(*room_id);
+			}
+		}
+
+		for (start, end) in &list.ranges {
+			let start = usize::try_from(u64::from(*start)).unwrap_or(usize::MAX);
+			let end = usize::try_from(u64::from(*end)).unwrap_or(usize::MAX);
+			for room_id in list_rooms.iter().take(end.saturating_add(1)).skip(start) {
+				add_todo_room(&mut todo_rooms, room_id, &list.room_details);
+			}
+		}
+
+		lists.insert(
+			list_id.clone(),
+			api::ListResponse {
+				count: UInt::try_from(list_rooms.len()).unwrap_or(UInt::MAX),
+			},
+		);
+	}
+
+	for (room_id, subscription) in &request.body.room_subscriptions {
+		if bump_stamps.contains_key(room_id) {
+			add_todo_room(&mut todo_rooms, room_id, subscription);
+		}
+	}
+
+	let mut rooms = BTreeMap::new();
+	for (room_id, (required_state, timeline_limit)) in todo_rooms {
+		let sent = connection.rooms.get(&room_id);
+		let bump_stamp = bump_stamps.get(&room_id).copied();
+		let is_dm = Some(direct_rooms.contains(&room_id));
+
+		if let Some(invite_state) = invited_rooms.get(&room_id) {
+			// Invites are sent once
+			if sent.map_or(true, |sent| !sent.invited) {
+				rooms.insert(
+					room_id.clone(),
+					api::Room {
+						initial: Some(true),
+						is_dm,
+						invite_state: Some(invite_state.clone()),
+						bump_stamp,
+						..Default::default()
+					},
+				);
+			}
+
+			if sent.map_or(true, |sent| !sent.invited || sent.required_state != required_state) {
+				sent_rooms.insert(
+					room_id,
+					SlidingSyncRoom {
+						since: next_batch,
+						required_state,
+						invited: true,
+						lazy_members: BTreeSet::new(),
+					},
+				);
+			}
+			continue;
+		}
+
+		// Rooms sent as invites are sent from scratch once joined
+		let roomsince = sent
+			.filter(|sent| !sent.invited)
+			.map_or(0, |sent| sent.since.min(globalsince));
+		let state_changed = sent.map_or(true, |sent| sent.required_state != required_state);
+
+		// Members are sent again with the rest of the state
+		let mut lazy_members = sent
+			.filter(|_| roomsince != 0 && !state_changed)
+			.map(|sent| sent.lazy_members.clone())
+			.unwrap_or_default();
+
+		if let Some(room) = load_room(
+			&sender_user,
+			&room_id,
+			&required_state,
+			timeline_limit,
+			roomsince,
+			globalsince,
+			state_changed,
+			&mut lazy_members,
+		)
+		.await?
+		{
+			rooms.insert(
+				room_id.clone(),
+				api::Room {
+					is_dm,
+					bump_stamp,
+					..room
+				},
+			);
+		} else if sent.is_some_and(|sent| !sent.invited && !state_changed) {
+			// Rooms with nothing new keep their entry, so it isn't stored again
+			continue;
+		}
+
+		sent_rooms.insert(
+			room_id,
+			SlidingSyncRoom {
+				since: next_batch,
+				required_state,
+				invited: false,
+				lazy_members,
+			},
+		);
+	}
+
+	// Rooms the user left are sent once more with their membership event
+	let left_rooms: Vec<OwnedRoomId> = sent_rooms
+		.keys()
+		.filter(|room_id| !bump_stamps.contains_key(*room_id))
+		.cloned()
+		.collect();
+	for room_id in left_rooms {
+		sent_rooms.remove(&room_id);
+		if let Some(pdu) = services().rooms.state_accessor.room_state_get(
+			&room_id,
+			&StateEventType::RoomMember,
+			sender_user.as_str(),
+		)? {
+			rooms.insert(
+				room_id,
+				api::Room {
+					timeline: vec![pdu.to_sync_room_event()],
+					required_state: vec![pdu.to_sync_state_event()],
+					..Default::default()
+				},
+			);
+		}
+	}
+
+	// Room extensions cover the joined rooms of the connection, from scratch for
+	// rooms sent for the first time
+	let extension_rooms: Vec<(OwnedRoomId, u64)> = sent_rooms
+		.iter()
+		.filter(|(_, sent)| !sent.invited)
+		.map(|(room_id, _)| {
+			let initial = rooms.get(room_id).and_then(|room| room.initial) == Some(true);
+			(
+				room_id.clone(),
+				if initial {
+					0
+				} else {
+					globalsince
+				},
+			)
+		})
+		.collect();
+
+	let extensions = load_extensions(
+		&sender_user,
+		&sender_device,
+		&request.body.extensions,
+		&joined_rooms,
+		&extension_rooms,
+		globalsince,
+	)
+	.await?;
+
+	// Only the rooms which changed are stored
+	let mut changes: BTreeMap<_, _> = connection
+		.rooms
+		.keys()
+		.filter(|room_id| !sent_rooms.contains_key(*room_id))
+		.map(|room_id| (room_id.clone(), None))
+		.collect();
+	changes.extend(
+		sent_rooms
+			.into_iter()
+			.filter(|(room_id, room)| connection.rooms.get(room_id) != Some(room))
+			.map(|(room_id, room)| (room_id, Some(room))),
+	);
+
+	let pos = connection.pos.saturating_add(1);
+	connection.pending = Some(SlidingSyncResponse {
+		pos,
+		since: next_batch,
+		rooms: changes,
+	});
+	services()
+		.users
+		.save_sliding_sync_connection(&sender_user, &sender_device, &conn_id, &mut connection)?;
+
+	if rooms.is_empty() && extensions.is_empty() {
+		// Hang a few seconds so requests are not spammed
+		// Stop hanging if new info arrives
+		let mut duration = request.timeout.unwrap_or_default();
+		if duration.as_secs() > 30 {
+			duration = Duration::from_secs(30);
+		}
+		let _long_poll = services().server.metrics.sync_long_poll("msc4186");
+		#[allow(clippy::let_underscore_must_use)]
+		{
+			_ = tokio::time::timeout(duration, watcher).await;
+		}
+	}
+
+	Ok(api::Response {
+		txn_id: request.body.txn_id,
+		pos: pos.to_string(),
+		lists,
+		rooms,
+		extensions,
+	})
+}
+
+fn add_todo_room(
+	todo_rooms: &mut BTreeMap<OwnedRoomId, (RequiredState, u64)>, room_id: &OwnedRoomId,
+	subscription: &api::RoomSubscription,
+) {
+	let limit = subscription
+		.timeline_limit
+		.map_or(TIMELINE_LIMIT_DEFAULT, u64::from)
+		.min(TIMELINE_LIMIT_MAX);
+
+	let (required_state, timeline_limit) = todo_rooms.entry(room_id.clone()).or_default();
+	required_state.extend(subscription.required_state.iter().cloned());
+	*timeline_limit = (*timeline_limit).max(limit);
+}
+
+/// Loads a joined room, `None` if nothing changed since `roomsince`. The
+/// members whose membership event is sent are added to `lazy_members`.
+#[allow(clippy::too_many_arguments)]
+async fn load_room(
+	sender_user: &UserId, room_id: &RoomId, required_state: &RequiredState, timeline_limit: u64, roomsince: u64,
+	globalsince: u64, state_changed: bool, lazy_members: &mut BTreeSet<OwnedUserId>,
+) -> Result<Option<api::Room>> {
+	let initial = roomsince == 0;
+	let (timeline_pdus, limited) = load_timeline(sender_user, room_id, PduCount::Normal(roomsince), timeline_limit)?;
+
+	let mut state = required_state_events(sender_user, room_id, required_state, &timeline_pdus).await?;
+
+	// Members of the timeline senders, which `$LAZY` sends if the client didn't
+	// get them yet
+	let lazy_senders: BTreeSet<&UserId> = if required_state.contains(&(StateEventType::RoomMember, "$LAZY".to_owned()))
+	{
+		timeline_pdus.iter().map(|(_, pdu)| &*pdu.sender).collect()
+	} else {
+		BTreeSet::new()
+	};
+	let lazy_member = |pdu: &PduEvent| {
+		if pdu.kind != TimelineEventType::RoomMember {
+			return None;
+		}
+
+		pdu.state_key
+			.as_deref()
+			.and_then(|state_key| UserId::parse(state_key).ok())
+			.filter(|user_id| lazy_senders.contains(&**user_id))
+	};
+
+	if !initial && !state_changed {
+		// The client has the rest of the state already
+		state.retain(|pdu| {
+			lazy_member(pdu).is_some_and(|user_id| !lazy_members.contains(&user_id))
+				|| matches!(
+					services().rooms.timeline.get_pdu_count(&pdu.event_id),
+					Ok(Some(PduCount::Normal(count))) if count > roomsince
+				)
+		});
+	}
+
+	lazy_members.extend(state.iter().filter_map(|pdu| lazy_member(pdu)));
+
+	let notifications_changed = services()
+		.rooms
+		.user
+		.last_notification_read(sender_user, room_id)?
+		> roomsince;
+
+	if !initial && timeline_pdus.is_empty() && state.is_empty() && !notifications_changed {
+		return Ok(None);
+	}
+
+	let prev_batch = timeline_pdus
+		.first()
+		.map(|(pdu_count, _)| match pdu_count {
+			PduCount::Backfilled(_) => {
+				error!("timeline in backfill state?!");
+				"0".to_owned()
+			},
+			PduCount::Normal(c) => c.to_string(),
+		})
+		.or_else(|| (!initial).then(|| roomsince.to_string()));
+
+	let num_live = if globalsince == 0 {
+		0
+	} else {
+		timeline_pdus
+			.iter()
+			.filter(|(pdu_count, _)| *pdu_count > PduCount::Normal(globalsince))
+			.count()
+	};
+
+	let name = services().rooms.state_accessor.get_name(room_id)?;
+	let heroes = (initial && name.is_none()).then(|| heroes(sender_user, room_id));
+	let avatar = match services().rooms.state_accessor.get_avatar(room_id)? {
+		JsOption::Some(avatar) => JsOption::from_option(avatar.url),
+		JsOption::Null => JsOption::Null,
+		JsOption::Undefined => JsOption::Undefined,
+	};
+
+	let joined_count = services()
+		.rooms
+		.state_cache
+		.room_joined_count(room_id)?
+		.unwrap_or(0);
+	let invited_count = services()
+		.rooms
+		.state_cache
+		.room_invited_count(room_id)?
+		.unwrap_or(0);
+
+	let mut room = api::Room {
+		name,
+		avatar,
+		initial: Some(initial),
+		timeline: timeline_pdus
+			.iter()
+			.map(|(_, pdu)| pdu.to_sync_room_event())
+			.collect(),
+		required_state: state.iter().map(|pdu| pdu.to_sync_state_event()).collect(),
+		prev_batch,
+		limited,
+		joined_count: joined_count.try_into().ok(),
+		invited_count: invited_count.try_into().ok(),
+		num_live: num_live.try_into().ok(),
+		heroes,
+		..Default::default()
+	};
+
+	room.unread_notifications.highlight_count = services()
+		.rooms
+		.user
+		.highlight_count(sender_user, room_id)?
+		.try_into()
+		.ok();
+	room.unread_notifications.notification_count = services()
+		.rooms
+		.user
+		.notification_count(sender_user, room_id)?
+		.try_into()
+		.ok();
+
+	Ok(Some(room))
+}
+
+/// Resolves the requested state of a room: `*` matches any event type or
+/// state key, `$LAZY` the members of the timeline senders and `$ME` the
+/// sender.
+async fn required_state_events(
+	sender_user: &UserId, room_id: &RoomId, required_state: &RequiredState, timeline_pdus: &[(PduCount, PduEvent)],
+) -> Result<Vec<Arc<PduEvent>>> {
+	let is_wildcard = |event_type: &StateEventType| event_type.to_string() == "*";
+
+	let full_state = if required_state
+		.iter()
+		.any(|(event_type, state_key)| is_wildcard(event_type) || state_key == "*")
+	{
+		Some(
+			services()
+				.rooms
+				.state_accessor
+				.room_state_full(room_id)
+				.await?,
+		)
+	} else {
+		None
+	};
+
+	let mut events = BTreeMap::new();
+	for (event_type, state_key) in required_state {
+		if let Some(full_state) = full_state
+			.as_ref()
+			.filter(|_| is_wildcard(event_type) || state_key == "*")
+		{
+			for ((full_type, full_key), pdu) in full_state {
+				if (is_wildcard(event_type) || full_type == event_type) && (state_key == "*" || full_key == state_key) {
+					events.insert((full_type.clone(), full_key.clone()), Arc::clone(pdu));
+				}
+			}
+			continue;
+		}
+
+		let state_keys: Vec<String> = match state_key.as_str() {
+			"$LAZY" => timeline_pdus
+				.iter()
+				.map(|(_, pdu)| pdu.sender.to_string())
+				.collect(),
+			"$ME" => vec![sender_user.to_string()],
+			_ => vec![state_key.clone()],
+		};
+
+		for state_key in state_keys {
+			if let Some(pdu) = services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, event_type, &state_key)?
+			{
+				events.insert((event_type.clone(), state_key), pdu);
+			}
+		}
+	}
+
+	Ok(events.into_values().collect())
+}
+
+fn matches_filters(room_id: &RoomId, filters: &api::ListFilters, is_invite: bool, is_dm: bool) -> Result<bool> {
+	if filters.is_invite.is_some_and(|filter| filter != is_invite)
+		|| filters.is_dm.is_some_and(|filter| filter != is_dm)
+	{
+		return Ok(false);
+	}
+
+	if let Some(is_encrypted) = filters.is_encrypted {
+		let encrypted = services()
+			.rooms
+			.state_accessor
+			.room_state_get(room_id, &StateEventType::RoomEncryption, "")?
+			.is_some();
+		if encrypted != is_encrypted {
+			return Ok(false);
+		}
+	}
+
+	if !filters.room_types.is_empty() || !filters.not_room_types.is_empty() {
+		let room_type = services()
+			.rooms
+			.state_accessor
+			.room_state_get(room_id, &StateEventType::RoomCreate, "")?
+			.and_then(|pdu| serde_json::from_str::<RoomCreateEventContent>(pdu.content.get()).ok())
+			.and_then(|content| content.room_type);
+
+		if (!filters.room_types.is_empty() && !filters.room_types.contains(&room_type))
+			|| filters.not_room_types.contains(&room_type)
+		{
+			return Ok(false);
+		}
+	}
+
+	Ok(true)
+}
+
+/// Position of a room in the recency order: the count of its latest event
+fn bump_stamp(sender_user: &UserId, room_id: &RoomId) -> Result<u64> {
+	Ok(
+		match services()
+			.rooms
+			.timeline
+			.last_timeline_count(sender_user, room_id)?
+		{
+			PduCount::Normal(count) => count,
+			PduCount::Backfilled(_) => 0,
+		},
+	)
+}
+
+/// Rooms marked as direct chats in the `m.direct` account data of the user
+fn direct_rooms(sender_user: &UserId) -> Result<HashSet<OwnedRoomId>> {
+	Ok(services()
+		.account_data
+		.get(None, sender_user, GlobalAccountDataEventType::Direct.to_string().into())?
+		.and_then(|event| serde_json::from_str::<DirectEvent>(event.get()).ok())
+		.map(|event| event.content.0.into_values().flatten().collect())
+		.unwrap_or_default())
+}
+
+fn heroes(sender_user: &UserId, room_id: &RoomId) -> Vec<api::Hero> {
+	services()
+		.rooms
+		.state_cache
+		.room_members(room_id)
+		.filter_map(Result::ok)
+		.filter(|member| member != sender_user)
+		.take(HEROES_LIMIT)
+		.map(|member| {
+			let member_event = services()
+				.rooms
+				.state_accessor
+				.get_member(room_id, &member)
+				.ok()
+				.flatten();
+
+			api::Hero {
+				displayname: member_event
+					.as_ref()
+					.and_then(|event| event.displayname.clone()),
+				avatar_url: member_event.and_then(|event| event.avatar_url),
+				user_id: member,
+			}
+		})
+		.collect()
+}
+
+async fn load_extensions(
+	sender_user: &UserId, sender_device: &DeviceId, config: &api::Extensions, joined_rooms: &[OwnedRoomId],
+	extension_rooms: &[(OwnedRoomId, u64)], globalsince: u64,
+) -> Result<api::ExtensionsResponse> {
+	let mut extensions = api::ExtensionsResponse::default();
+
+	if config.to_device.enabled.unwrap_or(false) {
+		extensions.to_device = Some(to_device(sender_user, sender_device, &config.to_device)?);
+	}
+
+	if config.e2ee.enabled.unwrap_or(false) {
+		let (changed, left) = sliding_sync_device_lists(sender_user, joined_rooms, globalsince).await?;
+		extensions.e2ee = Some(api::E2ee {
+			device_lists: DeviceLists {
+				changed: changed.into_iter().collect(),
+				left: left.into_iter().collect(),
+			},
+			device_one_time_keys_count: services()
+				.users
+				.count_one_time_keys(sender_user, sender_device)?,
+			device_unused_fallback_key_types: Vec::new(),
+		});
+	}
+
+	if config.account_data.enabled.unwrap_or(false) {
+		let global = services()
+			.account_data
+			.changes_since(None, sender_user, globalsince)?
+			.into_iter()
+			.filter_map(|(_, v)| {
+				serde_json::from_str(v.json().get())
+					.map_err(|_| Error::bad_database("Invalid account event in database."))
+					.ok()
+			})
+			.collect();
+
+		let mut rooms = BTreeMap::new();
+		for (room_id, since) in extension_rooms {
+			let events: Vec<_> = services()
+				.account_data
+				.changes_since(Some(room_id), sender_user, *since)?
+				.into_iter()
+				.filter_map(|(_, v)| {
+					serde_json::from_str(v.json().get())
+						.map_err(|_| Error::bad_database("Invalid account event in database."))
+						.ok()
+				})
+				.collect();
+			if !events.is_empty() {
+				rooms.insert(room_id.clone(), events);
+			}
+		}
+
+		extensions.account_data = Some(api::AccountData {
+			global,
+			rooms,
+		});
+	}
+
+	if config.receipts.enabled.unwrap_or(false) {
+		let mut rooms = BTreeMap::new();
+		for (room_id, since) in extension_rooms {
+			if let Some(receipts) = receipts(room_id, *since) {
+				rooms.insert(room_id.clone(), receipts);
+			}
+		}
+
+		extensions.receipts = Some(api::Receipts {
+			rooms,
+		});
+	}
+
+	if config.typing.enabled.unwrap_or(false) {
+		let mut rooms = BTreeMap::new();
+		for (room_id, since) in extension_rooms {
+			if services().rooms.typing.last_typing_update(room_id).await? > *since {
+				let typing = services().rooms.typing.typings_all(room_id).await?;
+				rooms.insert(room_id.clone(), Raw::new(&typing).expect("event is valid, we just created it"));
+			}
+		}
+
+		extensions.typing = Some(api::Typing {
+			rooms,
+		});
+	}
+
+	Ok(extensions)
+}
+
+/// To-device events of the device a batch at a time, deleting those up to
+/// `since` which the client received.
+fn to_device(sender_user: &UserId, sender_device: &DeviceId, config: &api::ToDeviceConfig) -> Result<api::ToDevice> {
+	let since: Option<u64> = config
+		.since
+		.as_deref()
+		.map(str::parse)
+		.transpose()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid to_device since."))?;
+
+	if let Some(since) = since {
+		services()
+			.users
+			.remove_to_device_events(sender_user, sender_device, since)?;
+	}
+
+	let limit = config.limit.map_or(TO_DEVICE_LIMIT_DEFAULT, |limit| {
+		usize::try_from(u64::from(limit)).unwrap_or(usize::MAX)
+	});
+	let events = services()
+		.users
+		.get_to_device_events_with_count(sender_user, sender_device, limit)?;
+
+	let next_batch = events
+		.last()
+		.map(|(count, _)| *count)
+		.or(since)
+		.unwrap_or(0)
+		.to_string();
+
+	Ok(api::ToDevice {
+		next_batch,
+		events: events.into_iter().map(|(_, event)| event).collect(),
+	})
+}
+
+/// Read receipts of a room since `since`, merged into one event
+fn receipts(room_id: &RoomId, since: u64) -> Option<Raw<SyncReceiptEvent>> {
+	let mut content = ReceiptEventContent(BTreeMap::new());
+	for (_, _, event) in services()
+		.rooms
+		.read_receipt
+		.readreceipts_since(room_id, since)
+		.filter_map(Result::ok)
+	{
+		let Ok(event) = event.deserialize_as::<SyncReceiptEvent>() else {
+			continue;
+		};
+
+		for (event_id, receipts) in event.content.0 {
+			let event_receipts = content.0.entry(event_id).or_default();
+			for (receipt_type, user_receipts) in receipts {
+				event_receipts
+					.entry(receipt_type)
+					.or_default()
+					.extend(user_receipts);
+			}
+		}
+	}
+
+	(!content.0.is_empty()).then(|| {
+		Raw::new(&SyncReceiptEvent {
+			content,
+		})
+		.expect("event is valid, we just created it")
+	})
+}
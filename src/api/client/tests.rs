@@ -0,0 +1,154 @@
+#![cfg(test)]
+
+use std::net::IpAddr;
+
+use axum_client_ip::InsecureClientIp;
+use ruma::{
+	api::client::{
+		membership::invite_user::{self, v3::InvitationRecipient},
+		message::send_message_event,
+		profile::{set_avatar_url, set_display_name},
+		state::send_state_event,
+	},
+	device_id,
+	events::room::{message::RoomMessageEventContent, topic::RoomTopicEventContent},
+	owned_mxc_uri, room_id, user_id, TransactionId, UserId,
+};
+use service::tests::services;
+
+use super::{
+	invite_user_route, send_message_event_route, send_state_event_for_key_route, set_avatar_url_route,
+	set_displayname_route,
+};
+use crate::Ruma;
+
+const CLIENT: [u8; 4] = [192, 0, 2, 1];
+
+fn request<T>(user_id: &UserId, body: T) -> Ruma<T> {
+	Ruma {
+		body,
+		origin: None,
+		sender_user: Some(user_id.to_owned()),
+		sender_device: Some(device_id!("SHADOW").to_owned()),
+		appservice_info: None,
+		json_body: None,
+		client: IpAddr::from(CLIENT),
+	}
+}
+
+async fn shadow_banned(user_id: &UserId) {
+	let users = &services().await.users;
+	users.create(user_id, Some("password")).unwrap();
+	users.set_shadow_banned(user_id, true).unwrap();
+}
+
+#[tokio::test]
+async fn shadow_banned_messages_are_dropped() {
+	let services = services().await;
+	let user_id = user_id!("@shadow-message:example.com");
+	shadow_banned(user_id).await;
+
+	let txn_id = TransactionId::new();
+	let send = || {
+		send_message_event_route(request(
+			user_id,
+			send_message_event::v3::Request::new(
+				room_id!("!shadow-message:example.com").to_owned(),
+				txn_id.clone(),
+				&RoomMessageEventContent::text_plain("Buy now"),
+			)
+			.unwrap(),
+		))
+	};
+
+	let event_id = send().await.unwrap().event_id;
+	assert!(services
+		.rooms
+		.timeline
+		.get_pdu(&event_id)
+		.unwrap()
+		.is_none());
+	assert_eq!(send().await.unwrap().event_id, event_id, "retries get the same event ID");
+}
+
+#[tokio::test]
+async fn shadow_banned_state_events_are_dropped() {
+	let services = services().await;
+	let user_id = user_id!("@shadow-state:example.com");
+	shadow_banned(user_id).await;
+
+	let response = send_state_event_for_key_route(request(
+		user_id,
+		send_state_event::v3::Request::new(
+			room_id!("!shadow-state:example.com").to_owned(),
+			String::new(),
+			&RoomTopicEventContent::new("Buy now".to_owned()),
+		)
+		.unwrap(),
+	))
+	.await
+	.unwrap();
+	assert!(services
+		.rooms
+		.timeline
+		.get_pdu(&response.event_id)
+		.unwrap()
+		.is_none());
+}
+
+#[tokio::test]
+async fn shadow_banned_invites_are_dropped() {
+	let services = services().await;
+	let user_id = user_id!("@shadow-inviter:example.com");
+	let invitee = user_id!("@shadow-invitee:example.com");
+	let room_id = room_id!("!shadow-invite:example.com");
+	shadow_banned(user_id).await;
+
+	invite_user_route(
+		InsecureClientIp(IpAddr::from(CLIENT)),
+		request(
+			user_id,
+			invite_user::v3::Request::new(
+				room_id.to_owned(),
+				InvitationRecipient::UserId {
+					user_id: invitee.to_owned(),
+				},
+			),
+		),
+	)
+	.await
+	.unwrap();
+	assert!(!services
+		.rooms
+		.state_cache
+		.is_invited(invitee, room_id)
+		.unwrap());
+}
+
+#[tokio::test]
+async fn shadow_banned_profile_changes_are_dropped() {
+	let services = services().await;
+	let user_id = user_id!("@shadow-profile:example.com");
+	shadow_banned(user_id).await;
+	services
+		.users
+		.set_displayname(user_id, Some("Before".to_owned()))
+		.await
+		.unwrap();
+
+	set_displayname_route(request(
+		user_id,
+		set_display_name::v3::Request::new(user_id.to_owned(), Some("Buy now".to_owned())),
+	))
+	.await
+	.unwrap();
+	set_avatar_url_route(request(
+		user_id,
+		set_avatar_url::v3::Request::new(user_id.to_owned(), Some(owned_mxc_uri!("mxc://example.com/ad"))),
+	))
+	.await
+	.unwrap();
+
+	assert_eq!(services.users.displayname(user_id).unwrap().as_deref(), Some("Before"));
+	assert!(services.users.avatar_url(user_id).unwrap().is_none());
+}
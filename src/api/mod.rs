@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod client;
 mod router;
 pub mod routes;
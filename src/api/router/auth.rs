@@ -6,9 +6,32 @@ use axum_extra::{
 	typed_header::TypedHeaderRejectionReason,
 	TypedHeader,
 };
-use http::uri::PathAndQuery;
+use http::{uri::PathAndQuery, Method};
 use ruma::{
-	api::{client::error::ErrorKind, AuthScheme, Metadata},
+	api::{
+		client::{
+			account::{change_password, deactivate},
+			backup::{
+				add_backup_keys, add_backup_keys_for_room, add_backup_keys_for_session, create_backup_version,
+				delete_backup_keys, delete_backup_keys_for_room, delete_backup_keys_for_session, delete_backup_version,
+				update_backup_version,
+			},
+			config::{set_global_account_data, set_room_account_data},
+			device::{delete_device, delete_devices, update_device},
+			directory::get_public_rooms_filtered,
+			error::ErrorKind,
+			filter::create_filter,
+			keys::{claim_keys, get_keys, upload_keys, upload_signatures, upload_signing_keys},
+			membership::{forget_room, leave_room},
+			push::{delete_pushrule, set_pusher, set_pushrule, set_pushrule_actions, set_pushrule_enabled},
+			search::search_events,
+			session::{logout, logout_all},
+			tag::{create_tag, delete_tag},
+			to_device::send_event_to_device,
+			user_directory::search_users,
+		},
+		AuthScheme, IncomingRequest, Metadata,
+	},
 	CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 };
 use tracing::warn;
@@ -82,7 +105,7 @@ pub(super) async fn auth(
 			},
 			"Unknown access token.",
 		)),
-		(AuthScheme::AccessToken, Token::Appservice(info)) => Ok(auth_appservice(request, info)?),
+		(AuthScheme::AccessToken, Token::Appservice(info)) => Ok(auth_appservice(request, metadata, info)?),
 		(AuthScheme::None | AuthScheme::AccessTokenOptional | AuthScheme::AppserviceToken, Token::Appservice(info)) => {
 			Ok(Auth {
 				origin: None,
@@ -91,6 +114,18 @@ pub(super) async fn auth(
 				appservice_info: Some(*info),
 			})
 		},
+		// password reset by email, told apart by the matched route rather than
+		// the path so no other endpoint can be reached without a token
+		(AuthScheme::AccessToken, Token::None)
+			if is_route::<change_password::v3::Request>(metadata) && services().threepid.password_reset_enabled() =>
+		{
+			Ok(Auth {
+				origin: None,
+				sender_user: None,
+				sender_device: None,
+				appservice_info: None,
+			})
+		},
 		(AuthScheme::AccessToken, Token::None) => match request.parts.uri.path() {
 			// TODO: can we check this better?
 			"/_matrix/client/v3/voip/turnServer" | "/_matrix/client/r0/voip/turnServer" => {
@@ -110,12 +145,16 @@ pub(super) async fn auth(
 		(
 			AuthScheme::AccessToken | AuthScheme::AccessTokenOptional | AuthScheme::None,
 			Token::User((user_id, device_id)),
-		) => Ok(Auth {
-			origin: None,
-			sender_user: Some(user_id),
-			sender_device: Some(device_id),
-			appservice_info: None,
-		}),
+		) => {
+			check_account_restrictions(metadata, &user_id)?;
+
+			Ok(Auth {
+				origin: None,
+				sender_user: Some(user_id),
+				sender_device: Some(device_id),
+				appservice_info: None,
+			})
+		},
 		(AuthScheme::ServerSignatures, Token::None) => Ok(auth_server(request, json_body).await?),
 		(AuthScheme::None | AuthScheme::AppserviceToken | AuthScheme::AccessTokenOptional, Token::None) => Ok(Auth {
 			sender_user: None,
@@ -134,7 +173,92 @@ pub(super) async fn auth(
 	}
 }
 
-fn auth_appservice(request: &Request, info: Box<RegistrationInfo>) -> Result<Auth> {
+/// Whether the matched route is the endpoint of `R`.
+fn is_route<R: IncomingRequest>(metadata: &Metadata) -> bool {
+	metadata.method == R::METADATA.method
+		&& metadata
+			.history
+			.all_paths()
+			.eq(R::METADATA.history.all_paths())
+}
+
+/// Refuses every request of locked users but logging out, and the requests of
+/// suspended users but reads and those in `ALLOWED_WHILE_SUSPENDED`.
+fn check_account_restrictions(metadata: &Metadata, user_id: &UserId) -> Result<()> {
+	let is_logout = is_route::<logout::v3::Request>(metadata) || is_route::<logout_all::v3::Request>(metadata);
+
+	check_restrictions(user_id, is_logout, || is_allowed_while_suspended(metadata))
+}
+
+/// Refuses the requests of locked users unless `allowed_while_locked`, and
+/// those of suspended users unless `allowed_while_suspended`. Also used by
+/// the extractors of routes which aren't ruma requests.
+pub(crate) fn check_restrictions(
+	user_id: &UserId, allowed_while_locked: bool, allowed_while_suspended: impl FnOnce() -> bool,
+) -> Result<()> {
+	if !allowed_while_locked && services().users.account_lock(user_id)?.is_some() {
+		return Err(Error::UserLocked("This account has been locked."));
+	}
+
+	if services().users.suspension(user_id)?.is_some() && !allowed_while_suspended() {
+		return Err(Error::UserSuspended("This account has been suspended."));
+	}
+
+	Ok(())
+}
+
+/// Endpoints changing something which suspended users may still use: leaving
+/// rooms, and managing their session, devices, keys, account data and push
+/// settings, none of which is seen by other users.
+const ALLOWED_WHILE_SUSPENDED: &[fn(&Metadata) -> bool] = &[
+	is_route::<logout::v3::Request>,
+	is_route::<logout_all::v3::Request>,
+	is_route::<deactivate::v3::Request>,
+	is_route::<change_password::v3::Request>,
+	is_route::<leave_room::v3::Request>,
+	is_route::<forget_room::v3::Request>,
+	is_route::<update_device::v3::Request>,
+	is_route::<delete_device::v3::Request>,
+	is_route::<delete_devices::v3::Request>,
+	is_route::<upload_keys::v3::Request>,
+	is_route::<get_keys::v3::Request>,
+	is_route::<claim_keys::v3::Request>,
+	is_route::<upload_signatures::v3::Request>,
+	is_route::<upload_signing_keys::v3::Request>,
+	is_route::<send_event_to_device::v3::Request>,
+	is_route::<create_backup_version::v3::Request>,
+	is_route::<update_backup_version::v3::Request>,
+	is_route::<delete_backup_version::v3::Request>,
+	is_route::<add_backup_keys::v3::Request>,
+	is_route::<add_backup_keys_for_room::v3::Request>,
+	is_route::<add_backup_keys_for_session::v3::Request>,
+	is_route::<delete_backup_keys::v3::Request>,
+	is_route::<delete_backup_keys_for_room::v3::Request>,
+	is_route::<delete_backup_keys_for_session::v3::Request>,
+	is_route::<set_global_account_data::v3::Request>,
+	is_route::<set_room_account_data::v3::Request>,
+	is_route::<create_tag::v3::Request>,
+	is_route::<delete_tag::v3::Request>,
+	is_route::<create_filter::v3::Request>,
+	is_route::<set_pusher::v3::Request>,
+	is_route::<set_pushrule::v3::Request>,
+	is_route::<delete_pushrule::v3::Request>,
+	is_route::<set_pushrule_enabled::v3::Request>,
+	is_route::<set_pushrule_actions::v3::Request>,
+	// reads sent as POST for their filters
+	is_route::<get_public_rooms_filtered::v3::Request>,
+	is_route::<search_events::v3::Request>,
+	is_route::<search_users::v3::Request>,
+];
+
+fn is_allowed_while_suspended(metadata: &Metadata) -> bool {
+	metadata.method == Method::GET
+		|| ALLOWED_WHILE_SUSPENDED
+			.iter()
+			.any(|is_route| is_route(metadata))
+}
+
+fn auth_appservice(request: &Request, metadata: &Metadata, info: Box<RegistrationInfo>) -> Result<Auth> {
 	let user_id = request
 		.query
 		.user_id
@@ -158,6 +282,9 @@ fn auth_appservice(request: &Request, info: Box<RegistrationInfo>) -> Result<Aut
 		return Err(Error::BadRequest(ErrorKind::forbidden(), "User does not exist."));
 	}
 
+	// masquerading doesn't get around the restrictions of the user
+	check_account_restrictions(metadata, &user_id)?;
+
 	Ok(Auth {
 		origin: None,
 		sender_user: Some(user_id),
@@ -259,3 +386,100 @@ async fn auth_server(request: &mut Request, json_body: &Option<CanonicalJsonValu
 		},
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use ruma::{
+		api::{
+			client::{
+				account::deactivate,
+				directory::{get_public_rooms_filtered, set_room_visibility},
+				keys::upload_keys,
+				knock::knock_room,
+				media::create_content,
+				membership::{invite_user, join_room_by_id, join_room_by_id_or_alias, leave_room},
+				message::{get_message_events, send_message_event},
+				profile::set_display_name,
+				receipt::create_receipt,
+				redact::redact_event,
+				room::{create_room, upgrade_room},
+				session::logout,
+				state::send_state_event,
+				sync::sync_events,
+				typing::create_typing_event,
+			},
+			IncomingRequest,
+		},
+		user_id,
+	};
+	use service::tests::services;
+
+	use super::{check_account_restrictions, is_allowed_while_suspended};
+	use crate::Error;
+
+	fn allowed<R: IncomingRequest>() -> bool { is_allowed_while_suspended(&R::METADATA) }
+
+	#[test]
+	fn suspended_users_can_read() {
+		assert!(allowed::<sync_events::v3::Request>());
+		assert!(allowed::<get_message_events::v3::Request>());
+		assert!(
+			allowed::<get_public_rooms_filtered::v3::Request>(),
+			"filtering the directory is a read"
+		);
+	}
+
+	#[test]
+	fn suspended_users_can_leave_and_manage_their_account() {
+		assert!(allowed::<leave_room::v3::Request>());
+		assert!(allowed::<logout::v3::Request>());
+		assert!(allowed::<deactivate::v3::Request>());
+		assert!(allowed::<upload_keys::v3::Request>());
+	}
+
+	#[test]
+	fn suspended_users_cannot_send() {
+		assert!(!allowed::<send_message_event::v3::Request>());
+		assert!(!allowed::<send_state_event::v3::Request>());
+		assert!(!allowed::<redact_event::v3::Request>());
+		assert!(!allowed::<create_typing_event::v3::Request>());
+		assert!(!allowed::<create_receipt::v3::Request>());
+	}
+
+	#[test]
+	fn suspended_users_cannot_join_or_invite() {
+		assert!(!allowed::<join_room_by_id::v3::Request>());
+		assert!(!allowed::<join_room_by_id_or_alias::v3::Request>());
+		assert!(!allowed::<knock_room::v3::Request>());
+		assert!(!allowed::<invite_user::v3::Request>());
+	}
+
+	#[test]
+	fn suspended_users_cannot_create_or_publish() {
+		assert!(!allowed::<create_room::v3::Request>());
+		assert!(!allowed::<upgrade_room::v3::Request>());
+		assert!(!allowed::<set_room_visibility::v3::Request>());
+		assert!(!allowed::<create_content::v3::Request>());
+		assert!(!allowed::<set_display_name::v3::Request>());
+	}
+
+	#[tokio::test]
+	async fn restrictions_are_checked() {
+		let users = &services().await.users;
+		let (suspended, locked) = (user_id!("@suspended:example.com"), user_id!("@locked:example.com"));
+		users.suspend(suspended, None).unwrap();
+		users.lock_account(locked, None).unwrap();
+
+		assert!(check_account_restrictions(&sync_events::v3::Request::METADATA, suspended).is_ok());
+		assert!(matches!(
+			check_account_restrictions(&send_message_event::v3::Request::METADATA, suspended),
+			Err(Error::UserSuspended(_))
+		));
+
+		assert!(matches!(
+			check_account_restrictions(&sync_events::v3::Request::METADATA, locked),
+			Err(Error::UserLocked(_))
+		));
+		assert!(check_account_restrictions(&logout::v3::Request::METADATA, locked).is_ok());
+	}
+}
@@ -3,9 +3,17 @@ mod handler;
 mod request;
 mod xmatrix;
 
-use std::{mem, ops::Deref};
+use std::{
+	mem,
+	net::{IpAddr, Ipv4Addr, SocketAddr},
+	ops::Deref,
+};
 
-use axum::{async_trait, body::Body, extract::FromRequest};
+use axum::{
+	async_trait,
+	body::Body,
+	extract::{ConnectInfo, FromRequest},
+};
 use bytes::{BufMut, BytesMut};
 pub(super) use conduit::error::RumaResponse;
 use conduit::{debug, debug_warn, trace, warn};
@@ -14,6 +22,7 @@ use ruma::{
 	CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 };
 
+pub(crate) use self::auth::check_restrictions;
 pub(super) use self::handler::RouterExt;
 use self::{auth::Auth, request::Request};
 use crate::{service::appservice::RegistrationInfo, services, Error, Result};
@@ -42,6 +51,10 @@ pub(crate) struct Ruma<T> {
 	/// Parsed JSON content.
 	/// None when body is not a valid string
 	pub(crate) json_body: Option<CanonicalJsonValue>,
+
+	/// Client address: the connecting peer, or the client it forwarded the
+	/// request for when it's a trusted proxy.
+	pub(crate) client: IpAddr,
 }
 
 #[async_trait]
@@ -53,8 +66,20 @@ where
 
 	async fn from_request(request: hyper::Request<Body>, _: &S) -> Result<Self, Self::Rejection> {
 		let mut request = request::from(request).await?;
+		let remote = request
+			.parts
+			.extensions
+			.get::<ConnectInfo<SocketAddr>>()
+			.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ConnectInfo(addr)| addr.ip());
+		let client = services()
+			.ratelimit
+			.client_ip(remote, &request.parts.headers);
 		let mut json_body = serde_json::from_slice::<CanonicalJsonValue>(&request.body).ok();
 		let auth = auth::auth(&mut request, &json_body, &T::METADATA).await?;
+		if let Some(origin) = &auth.origin {
+			services().ratelimit.check_server(origin)?;
+		}
+
 		Ok(Self {
 			body: make_body::<T>(&mut request, &mut json_body, &auth)?,
 			origin: auth.origin,
@@ -62,6 +87,7 @@ where
 			sender_device: auth.sender_device,
 			appservice_info: auth.appservice_info,
 			json_body,
+			client,
 		})
 	}
 }
@@ -7,7 +7,7 @@ use conduit::{Error, Server};
 use http::Uri;
 use ruma::api::client::error::ErrorKind;
 
-use crate::{client, router::RouterExt, server};
+use crate::{admin, client, router::RouterExt, server};
 
 pub fn build(router: Router, server: &Server) -> Router {
 	let config = &server.config;
@@ -17,6 +17,8 @@ pub fn build(router: Router, server: &Server) -> Router {
 		.ruma_route(client::register_route)
 		.ruma_route(client::get_login_types_route)
 		.ruma_route(client::login_route)
+		.ruma_route(client::sso_login_route)
+		.ruma_route(client::sso_login_with_provider_route)
 		.ruma_route(client::whoami_route)
 		.ruma_route(client::logout_route)
 		.ruma_route(client::logout_all_route)
@@ -25,6 +27,10 @@ pub fn build(router: Router, server: &Server) -> Router {
 		.ruma_route(client::third_party_route)
 		.ruma_route(client::request_3pid_management_token_via_email_route)
 		.ruma_route(client::request_3pid_management_token_via_msisdn_route)
+		.ruma_route(client::add_3pid_route)
+		.ruma_route(client::delete_3pid_route)
+		.ruma_route(client::request_password_change_token_via_email_route)
+		.ruma_route(client::request_password_change_token_via_msisdn_route)
 		.ruma_route(client::check_registration_token_validity)
 		.ruma_route(client::get_capabilities_route)
 		.ruma_route(client::get_pushrules_all_route)
@@ -73,11 +79,14 @@ pub fn build(router: Router, server: &Server) -> Router {
 		.ruma_route(client::create_room_route)
 		.ruma_route(client::redact_event_route)
 		.ruma_route(client::report_event_route)
+		.ruma_route(client::report_room_route)
+		.ruma_route(client::report_user_route)
 		.ruma_route(client::create_alias_route)
 		.ruma_route(client::delete_alias_route)
 		.ruma_route(client::get_alias_route)
 		.ruma_route(client::join_room_by_id_route)
 		.ruma_route(client::join_room_by_id_or_alias_route)
+		.ruma_route(client::knock_room_route)
 		.ruma_route(client::joined_members_route)
 		.ruma_route(client::leave_room_route)
 		.ruma_route(client::forget_room_route)
@@ -122,6 +131,7 @@ pub fn build(router: Router, server: &Server) -> Router {
 		)
 		.ruma_route(client::sync_events_route)
 		.ruma_route(client::sync_events_v4_route)
+		.ruma_route(client::sync_events_v5_route)
 		.ruma_route(client::get_context_route)
 		.ruma_route(client::get_message_events_route)
 		.ruma_route(client::search_events_route)
@@ -158,11 +168,20 @@ pub fn build(router: Router, server: &Server) -> Router {
 		.ruma_route(client::get_content_route)
 		.ruma_route(client::get_content_as_filename_route)
 		.ruma_route(client::get_content_thumbnail_route)
+		.ruma_route(client::get_media_config_auth_route)
+		.ruma_route(client::get_media_preview_auth_route)
+		.ruma_route(client::get_content_auth_route)
+		.ruma_route(client::get_content_as_filename_auth_route)
+		.ruma_route(client::get_content_thumbnail_auth_route)
 		.ruma_route(client::get_devices_route)
 		.ruma_route(client::get_device_route)
 		.ruma_route(client::update_device_route)
 		.ruma_route(client::delete_device_route)
 		.ruma_route(client::delete_devices_route)
+		.ruma_route(client::put_dehydrated_device_route)
+		.ruma_route(client::get_dehydrated_device_route)
+		.ruma_route(client::delete_dehydrated_device_route)
+		.ruma_route(client::get_dehydrated_events_route)
 		.ruma_route(client::get_tags_route)
 		.ruma_route(client::update_tag_route)
 		.ruma_route(client::delete_tag_route)
@@ -184,7 +203,21 @@ pub fn build(router: Router, server: &Server) -> Router {
         .route("/_conduwuit/server_version", get(client::conduwuit_server_version))
 		.route("/_matrix/client/r0/rooms/:room_id/initialSync", get(initial_sync))
 		.route("/_matrix/client/v3/rooms/:room_id/initialSync", get(initial_sync))
-		.route("/client/server.json", get(client::syncv3_client_server_json));
+		.route("/client/server.json", get(client::syncv3_client_server_json))
+		.route("/_conduwuit/client/oidc/callback", get(client::oidc_callback_route))
+		.route("/_matrix/static/client/login/", get(client::login_fallback_route))
+		.route("/_synapse/admin/v1/registration_tokens", get(admin::list_registration_tokens_route))
+		.route("/_synapse/admin/v1/registration_tokens/new", post(admin::create_registration_token_route))
+		.route(
+			"/_synapse/admin/v1/registration_tokens/:token",
+			get(admin::get_registration_token_route)
+				.put(admin::update_registration_token_route)
+				.delete(admin::delete_registration_token_route),
+		)
+		.route(
+			"/_conduwuit/client/3pid/submit_token",
+			get(client::submit_3pid_token_link_route).post(client::submit_3pid_token_route),
+		);
 
 	if config.allow_federation {
 		router
@@ -206,6 +239,8 @@ pub fn build(router: Router, server: &Server) -> Router {
 			.ruma_route(server::create_join_event_template_route)
 			.ruma_route(server::create_join_event_v1_route)
 			.ruma_route(server::create_join_event_v2_route)
+			.ruma_route(server::create_knock_event_template_route)
+			.ruma_route(server::create_knock_event_v1_route)
 			.ruma_route(server::create_invite_route)
 			.ruma_route(server::get_devices_route)
 			.ruma_route(server::get_room_information_route)
@@ -213,6 +248,8 @@ pub fn build(router: Router, server: &Server) -> Router {
 			.ruma_route(server::get_keys_route)
 			.ruma_route(server::claim_keys_route)
 			.ruma_route(server::get_hierarchy_route)
+			.ruma_route(server::get_content_route)
+			.ruma_route(server::get_content_thumbnail_route)
 			.ruma_route(server::well_known_server)
 			.route("/_conduwuit/local_user_count", get(client::conduwuit_local_user_count))
 	} else {
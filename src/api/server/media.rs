@@ -0,0 +1,75 @@
+use ruma::api::{
+	client::error::ErrorKind,
+	federation::authenticated_media::{get_content, get_content_thumbnail, Content, ContentMetadata, FileOrLocation},
+};
+
+use crate::{
+	service::media::FileMeta, services, utils::content_disposition::make_content_disposition, Error, Result, Ruma,
+};
+
+/// # `GET /_matrix/federation/v1/media/download/{mediaId}`
+///
+/// Load media from our server for another server.
+pub(crate) async fn get_content_route(body: Ruma<get_content::v1::Request>) -> Result<get_content::v1::Response> {
+	let mxc = format!("mxc://{}/{}", services().globals.server_name(), body.media_id);
+
+	let Some(FileMeta {
+		content_type,
+		file,
+		content_disposition,
+	}) = services().media.get(&mxc).await?
+	else {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	};
+
+	let content_disposition = make_content_disposition(&content_type, content_disposition, None);
+
+	Ok(get_content::v1::Response {
+		metadata: ContentMetadata::new(),
+		content: FileOrLocation::File(Content {
+			file,
+			content_type,
+			content_disposition: content_disposition.parse().ok(),
+		}),
+	})
+}
+
+/// # `GET /_matrix/federation/v1/media/thumbnail/{mediaId}`
+///
+/// Load media thumbnail from our server for another server.
+pub(crate) async fn get_content_thumbnail_route(
+	body: Ruma<get_content_thumbnail::v1::Request>,
+) -> Result<get_content_thumbnail::v1::Response> {
+	let mxc = format!("mxc://{}/{}", services().globals.server_name(), body.media_id);
+
+	let Some(FileMeta {
+		content_type,
+		file,
+		content_disposition,
+	}) = services()
+		.media
+		.get_thumbnail(
+			&mxc,
+			body.width
+				.try_into()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Width is invalid."))?,
+			body.height
+				.try_into()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Height is invalid."))?,
+		)
+		.await?
+	else {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	};
+
+	let content_disposition = make_content_disposition(&content_type, content_disposition, None);
+
+	Ok(get_content_thumbnail::v1::Response {
+		metadata: ContentMetadata::new(),
+		content: FileOrLocation::File(Content {
+			file,
+			content_type,
+			content_disposition: content_disposition.parse().ok(),
+		}),
+	})
+}
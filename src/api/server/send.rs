@@ -13,18 +13,23 @@ use ruma::{
 			send_transaction_message,
 		},
 	},
-	events::receipt::{ReceiptEvent, ReceiptEventContent, ReceiptType},
+	events::{
+		receipt::{ReceiptEvent, ReceiptEventContent, ReceiptType},
+		room::member::{MembershipState, RoomMemberEventContent},
+		TimelineEventType,
+	},
+	serde::Base64,
 	to_device::DeviceIdOrAllDevices,
-	OwnedEventId, ServerName,
+	CanonicalJsonObject, EventId, OwnedEventId, RoomId, ServerName, UserId,
 };
 use tokio::sync::RwLock;
 use tracing::{debug, error, trace, warn};
 
 use crate::{
-	service::rooms::event_handler::parse_incoming_pdu,
+	service::{rooms::event_handler::parse_incoming_pdu, user_is_local},
 	services,
 	utils::{self},
-	Error, Result, Ruma,
+	Error, PduEvent, Result, Ruma,
 };
 
 type ResolvedMap = BTreeMap<OwnedEventId, Result<(), Error>>;
@@ -131,15 +136,17 @@ async fn handle_pdus(
 			.roomid_mutex_federation
 			.lock(&room_id)
 			.await;
-		resolved_map.insert(
-			event_id.clone(),
+		let result = if services().rooms.metadata.exists(&room_id)? {
 			services()
 				.rooms
 				.event_handler
 				.handle_incoming_pdu(origin, &room_id, &event_id, value, true, &pub_key_map)
 				.await
-				.map(|_| ()),
-		);
+				.map(|_| ())
+		} else {
+			handle_knock_rejection(origin, &room_id, &event_id, value, &pub_key_map).await
+		};
+		resolved_map.insert(event_id.clone(), result);
 		drop(mutex_lock);
 
 		debug!(
@@ -160,6 +167,71 @@ async fn handle_pdus(
 	Ok(resolved_map)
 }
 
+/// A room we aren't in only sends us the event rejecting one of our users'
+/// knocks on it. Anything else for an unknown room is refused like before.
+async fn handle_knock_rejection(
+	origin: &ServerName, room_id: &RoomId, event_id: &EventId, mut value: CanonicalJsonObject,
+	pub_key_map: &RwLock<BTreeMap<String, BTreeMap<String, Base64>>>,
+) -> Result<()> {
+	let unknown_room = || Error::BadRequest(ErrorKind::NotFound, "Room is unknown to this server");
+
+	value.remove("unsigned");
+	let pdu = PduEvent::from_id_val(event_id, value.clone()).map_err(|_| unknown_room())?;
+	if pdu.kind != TimelineEventType::RoomMember || pdu.sender.server_name() != origin {
+		return Err(unknown_room());
+	}
+
+	let Some(user_id) = pdu
+		.state_key
+		.as_deref()
+		.and_then(|state_key| UserId::parse(state_key).ok())
+	else {
+		return Err(unknown_room());
+	};
+
+	if !user_is_local(&user_id) || !services().rooms.state_cache.is_knocked(&user_id, room_id)? {
+		return Err(unknown_room());
+	}
+
+	let content = serde_json::from_str::<RoomMemberEventContent>(pdu.content.get())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid member event content."))?;
+	if content.membership != MembershipState::Leave {
+		return Err(unknown_room());
+	}
+
+	// Like any incoming PDU, the event must be signed by the origin and match
+	// its hashes, in the version of the room we knocked on
+	let Some(room_version_id) = services()
+		.rooms
+		.state_cache
+		.knocked_room_version(&user_id, room_id)?
+	else {
+		debug_warn!("Unknown version of room {room_id}, can't verify the rejection of {user_id}'s knock");
+		return Err(unknown_room());
+	};
+	match ruma::signatures::verify_event(&*pub_key_map.read().await, &value, &room_version_id) {
+		Ok(ruma::signatures::Verified::All) => {},
+		Ok(ruma::signatures::Verified::Signatures) => {
+			warn!("Dropping knock rejection {event_id} with content not matching its hashes");
+			return Err(Error::BadRequest(
+				ErrorKind::InvalidParam,
+				"Event hashes don't match its content",
+			));
+		},
+		Err(e) => {
+			warn!("Dropping knock rejection {event_id} with bad signatures: {e}");
+			return Err(Error::BadRequest(ErrorKind::InvalidParam, "Signature verification failed"));
+		},
+	}
+
+	debug!("Knock of {user_id} on {room_id} was rejected by {}", pdu.sender);
+	services().rooms.outlier.add_pdu_outlier(event_id, &value)?;
+	services()
+		.rooms
+		.state_cache
+		.update_membership(room_id, &user_id, content, &pdu.sender, None, None, false)
+}
+
 async fn handle_edus(
 	client: &IpAddr, body: &Ruma<send_transaction_message::v1::Request>, origin: &ServerName,
 ) -> Result<()> {
@@ -448,3 +520,117 @@ async fn handle_edu_signing_key_update(
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		net::IpAddr,
+		time::{Duration, Instant, SystemTime},
+	};
+
+	use ruma::{
+		api::federation::{
+			discovery::{ServerSigningKeys, VerifyKey},
+			transactions::send_transaction_message,
+		},
+		events::room::member::{MembershipState, RoomMemberEventContent},
+		room_id,
+		serde::{Base64, Raw},
+		server_name,
+		signatures::Ed25519KeyPair,
+		user_id, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, RoomVersionId,
+	};
+	use serde_json::{json, value::to_raw_value};
+	use service::tests::services;
+
+	use super::handle_pdus;
+	use crate::Ruma;
+
+	#[tokio::test]
+	async fn rejected_knock_on_unknown_room() {
+		let services = services().await;
+		let origin = server_name!("remote.test");
+		let room_id = room_id!("!knocked:remote.test");
+		let user_id = user_id!("@knocker:example.com");
+		let sender = user_id!("@moderator:remote.test");
+
+		let keypair = Ed25519KeyPair::from_der(&Ed25519KeyPair::generate().unwrap(), "key".to_owned()).unwrap();
+		let valid_until = SystemTime::now() + Duration::from_secs(3600);
+		let mut keys = ServerSigningKeys::new(
+			origin.to_owned(),
+			MilliSecondsSinceUnixEpoch::from_system_time(valid_until).unwrap(),
+		);
+		keys.verify_keys.insert(
+			"ed25519:key".try_into().unwrap(),
+			VerifyKey::new(Base64::new(keypair.public_key().to_vec())),
+		);
+		services.globals.add_signing_key(origin, keys).unwrap();
+
+		let create = json!({
+			"type": "m.room.create",
+			"state_key": "",
+			"sender": sender,
+			"content": { "room_version": "10" },
+		});
+		services
+			.rooms
+			.state_cache
+			.update_membership(
+				room_id,
+				user_id,
+				RoomMemberEventContent::new(MembershipState::Knock),
+				user_id,
+				Some(vec![Raw::from_json(to_raw_value(&create).unwrap())]),
+				None,
+				false,
+			)
+			.unwrap();
+		assert!(!services.rooms.metadata.exists(room_id).unwrap(), "only knocked on");
+
+		let mut leave: CanonicalJsonObject = serde_json::from_value(json!({
+			"type": "m.room.member",
+			"room_id": room_id,
+			"sender": sender,
+			"state_key": user_id,
+			"content": { "membership": "leave" },
+			"origin_server_ts": 1,
+			"depth": 5,
+			"auth_events": [],
+			"prev_events": [],
+		}))
+		.unwrap();
+		ruma::signatures::hash_and_sign_event(origin.as_str(), &keypair, &mut leave, &RoomVersionId::V10).unwrap();
+
+		let mut request = send_transaction_message::v1::Request::new(
+			"txn".into(),
+			origin.to_owned(),
+			MilliSecondsSinceUnixEpoch::now(),
+		);
+		request.pdus = vec![to_raw_value(&leave).unwrap()];
+		let body = Ruma {
+			body: request,
+			origin: Some(origin.to_owned()),
+			sender_user: None,
+			sender_device: None,
+			appservice_info: None,
+			json_body: None,
+			client: IpAddr::from([192, 0, 2, 1]),
+		};
+
+		let resolved = handle_pdus(&body.client, &body, origin, &Instant::now())
+			.await
+			.unwrap();
+		assert_eq!(resolved.len(), 1, "the PDU is parsed in the knocked room's version");
+		assert!(resolved.values().all(Result::is_ok), "rejection is accepted: {resolved:?}");
+		assert!(!services
+			.rooms
+			.state_cache
+			.is_knocked(user_id, room_id)
+			.unwrap());
+		assert!(services
+			.rooms
+			.state_cache
+			.is_left(user_id, room_id)
+			.unwrap());
+	}
+}
@@ -7,3 +7,7 @@ pub fn memory_stats() -> String { String::default() }
 /// Always returns the empty string
 #[must_use]
 pub fn memory_usage() -> String { String::default() }
+
+/// Always returns no statistics
+#[must_use]
+pub fn memory_metrics() -> Vec<(&'static str, usize)> { Vec::new() }
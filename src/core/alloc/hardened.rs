@@ -10,3 +10,7 @@ pub fn memory_usage() -> String {
 
 #[must_use]
 pub fn memory_stats() -> String { "Extended statistics are not available from hardened_malloc.".to_owned() }
+
+/// hardened_malloc doesn't expose statistics
+#[must_use]
+pub fn memory_metrics() -> Vec<(&'static str, usize)> { Vec::new() }
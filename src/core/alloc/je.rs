@@ -24,6 +24,24 @@ pub fn memory_usage() -> String {
 	)
 }
 
+/// Allocator statistics in bytes, for metrics
+#[must_use]
+pub fn memory_metrics() -> Vec<(&'static str, usize)> {
+	use mallctl::{epoch, stats};
+
+	// statistics are cached until the epoch is advanced
+	_ = epoch::advance();
+
+	vec![
+		("allocated", stats::allocated::read().unwrap_or_default()),
+		("active", stats::active::read().unwrap_or_default()),
+		("mapped", stats::mapped::read().unwrap_or_default()),
+		("metadata", stats::metadata::read().unwrap_or_default()),
+		("resident", stats::resident::read().unwrap_or_default()),
+		("retained", stats::retained::read().unwrap_or_default()),
+	]
+}
+
 #[must_use]
 pub fn memory_stats() -> String {
 	const MAX_LENGTH: usize = 65536 - 4096;
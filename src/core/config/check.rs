@@ -1,8 +1,10 @@
 #[cfg(unix)]
 use std::path::Path; // not unix specific, just only for UNIX sockets stuff and *nix container checks
 
+use itertools::Itertools;
 use tracing::{debug, error, info, warn};
 
+use super::MediaBackend;
 use crate::{error::Error, Config};
 
 pub fn check(config: &Config) -> Result<(), Error> {
@@ -97,14 +99,23 @@ pub fn check(config: &Config) -> Result<(), Error> {
 		}
 	}
 
+	for cidr in &config.rate_limit.trusted_proxies {
+		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
+			error!("Error parsing specified trusted proxy range {cidr}: {e}");
+			return Err(Error::bad_config("Error parsing \"rate_limit.trusted_proxies\" IP CIDR ranges"));
+		}
+	}
+
 	if config.allow_registration
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
+		&& !config.registration_requires_token
 	{
 		return Err(Error::bad_config(
 			"!! You have `allow_registration` enabled without a token configured in your config which means you are \
 			 allowing ANYONE to register on your conduwuit instance without any 2nd-step (e.g. registration token).\n
-If this is not the intended behaviour, please set a registration token with the `registration_token` config option.\n
+If this is not the intended behaviour, please set a registration token with the `registration_token` config option, or \
+			 set `registration_requires_token` and create tokens with the `registration-tokens` admin commands.\n
 For security and safety reasons, conduwuit will shut down. If you are extra sure this is the desired behaviour you \
 			 want, please set the following config option to true:
 `yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse`",
@@ -114,6 +125,7 @@ For security and safety reasons, conduwuit will shut down. If you are extra sure
 	if config.allow_registration
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
+		&& !config.registration_requires_token
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -123,6 +135,104 @@ For security and safety reasons, conduwuit will shut down. If you are extra sure
 		);
 	}
 
+	if config.threepid.smtp_url.is_some() && config.threepid.email_from.is_none() {
+		return Err(Error::bad_config("Sending emails needs \"threepid.email_from\" to be set."));
+	}
+
+	if (config.threepid.smtp_url.is_some() || config.threepid.sms_webhook_url.is_some())
+		&& config.threepid.public_base_url.is_none()
+		&& config.well_known.client.is_none()
+	{
+		return Err(Error::bad_config(
+			"Verifying email addresses and phone numbers needs the public URL of this server. Please set \
+			 \"threepid.public_base_url\" or \"well_known.client\".",
+		));
+	}
+
+	if config.metrics.address.is_some() && !config.metrics.enable {
+		warn!("\"metrics.address\" is set, but metrics are not enabled with \"metrics.enable\".");
+	}
+
+	if config.metrics.enable && config.metrics.address.is_none() && config.metrics.token.is_none() {
+		return Err(Error::bad_config(
+			"Metrics served on the main listeners must be protected with a token. Please set \"metrics.token\", or \
+			 serve them on a separate listener with \"metrics.address\".",
+		));
+	}
+
+	if config.retention_enabled && config.retention_check_interval_s == 0 {
+		return Err(Error::bad_config("\"retention_check_interval_s\" must be greater than 0."));
+	}
+
+	let storage = &config.media_storage;
+	if (storage.backend == MediaBackend::S3 || storage.s3_bucket.is_some())
+		&& (storage.s3_endpoint.is_none()
+			|| storage.s3_bucket.is_none()
+			|| storage.s3_access_key.is_none()
+			|| storage.s3_secret_key.is_none())
+	{
+		return Err(Error::bad_config(
+			"The S3 media backend needs \"media_storage.s3_endpoint\", \"media_storage.s3_bucket\", \
+			 \"media_storage.s3_access_key\" and \"media_storage.s3_secret_key\" to be set.",
+		));
+	}
+
+	if storage.cache_path.is_some() && storage.backend == MediaBackend::Filesystem {
+		warn!("\"media_storage.cache_path\" is set, but the filesystem media backend is not cached.");
+	}
+
+	if config.media_janitor_enabled && config.media_janitor_interval_s == 0 {
+		return Err(Error::bad_config("\"media_janitor_interval_s\" must be greater than 0."));
+	}
+
+	if config.media_janitor_enabled
+		&& config.media_remote_cache_max_age_s.is_none()
+		&& config.media_remote_cache_max_size.is_none()
+		&& config.media_local_retention_s.is_none()
+	{
+		warn!(
+			"The media janitor is enabled, but none of \"media_remote_cache_max_age_s\", \
+			 \"media_remote_cache_max_size\" or \"media_local_retention_s\" is set, so it won't delete anything."
+		);
+	}
+
+	if config.ldap.enable && (config.ldap.uri.is_none() || config.ldap.base_dn.is_empty()) {
+		return Err(Error::bad_config(
+			"LDAP login needs \"ldap.uri\" and \"ldap.base_dn\" to be set.",
+		));
+	}
+
+	if !config.sso.providers.is_empty() {
+		if config.sso.public_base_url.is_none() && config.well_known.client.is_none() {
+			return Err(Error::bad_config(
+				"SSO identity providers need the public URL of this server to redirect back to. Please set \
+				 \"sso.public_base_url\" or \"well_known.client\".",
+			));
+		}
+
+		if !config
+			.sso
+			.providers
+			.iter()
+			.map(|provider| &provider.id)
+			.all_unique()
+		{
+			return Err(Error::bad_config("SSO identity provider IDs must be unique."));
+		}
+	}
+
+	let well_known = &config.well_known;
+	if (config.media_quota_max_bytes.is_some() || config.media_quota_max_files.is_some())
+		&& well_known.support_page.is_none()
+		&& well_known.support_email.is_none()
+		&& well_known.support_mxid.is_none()
+	{
+		return Err(Error::bad_config(
+			"Media quotas need a contact users can ask for more storage. Please set \"well_known.support_page\", \
+			 \"well_known.support_email\" or \"well_known.support_mxid\".",
+		));
+	}
+
 	if config.allow_outgoing_presence && !config.allow_local_presence {
 		return Err(Error::bad_config(
 			"Outgoing presence requires allowing local presence. Please enable \"allow_local_presence\".",
@@ -8,9 +8,9 @@ use ruma::{
 	api::{
 		client::{
 			error::ErrorKind::{
-				Forbidden, GuestAccessForbidden, LimitExceeded, MissingToken, NotFound, ThreepidAuthFailed,
-				ThreepidDenied, TooLarge, Unauthorized, Unknown, UnknownToken, Unrecognized, UserDeactivated,
-				WrongRoomKeysVersion,
+				Forbidden, GuestAccessForbidden, LimitExceeded, MissingToken, NotFound, ResourceLimitExceeded,
+				ThreepidAuthFailed, ThreepidDenied, TooLarge, Unauthorized, Unknown, UnknownToken, Unrecognized,
+				UserDeactivated, WrongRoomKeysVersion,
 			},
 			uiaa::{UiaaInfo, UiaaResponse},
 		},
@@ -74,6 +74,12 @@ pub enum Error {
 	Conflict(&'static str), // This is only needed for when a room alias already exists
 	#[error("uiaa")]
 	Uiaa(UiaaInfo),
+	/// The user may read but not send, join or upload (MSC3823)
+	#[error("{0}")]
+	UserSuspended(&'static str),
+	/// Every request of the user is refused until they log out (MSC3939)
+	#[error("{0}")]
+	UserLocked(&'static str),
 
 	// unique / untyped
 	#[error("{0}")]
@@ -153,6 +159,30 @@ impl Error {
 			return RumaResponse(UiaaResponse::MatrixError(error));
 		}
 
+		// Our ruma doesn't know these error codes yet
+		match self {
+			Self::UserSuspended(message) => {
+				return RumaResponse(UiaaResponse::MatrixError(RumaError {
+					body: ErrorBody::Json(serde_json::json!({
+						"errcode": "M_USER_SUSPENDED",
+						"error": message,
+					})),
+					status_code: StatusCode::FORBIDDEN,
+				}));
+			},
+			Self::UserLocked(message) => {
+				return RumaResponse(UiaaResponse::MatrixError(RumaError {
+					body: ErrorBody::Json(serde_json::json!({
+						"errcode": "M_USER_LOCKED",
+						"error": message,
+						"soft_logout": true,
+					})),
+					status_code: StatusCode::UNAUTHORIZED,
+				}));
+			},
+			_ => {},
+		}
+
 		let message = format!("{self}");
 		let (kind, status_code) = match self {
 			Self::BadRequest(kind, _) => (
@@ -165,6 +195,9 @@ impl Error {
 						..
 					}
 					| GuestAccessForbidden
+					| ResourceLimitExceeded {
+						..
+					}
 					| ThreepidAuthFailed
 					| UserDeactivated
 					| ThreepidDenied => StatusCode::FORBIDDEN,
@@ -0,0 +1,216 @@
+//! Prometheus metrics
+
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+use prometheus::{
+	Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{alloc, Error, Result};
+
+/// Request latency buckets in seconds; sync long-polls run up to 30 seconds.
+const REQUEST_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Metrics exported on the `/metrics` endpoint. Counters and histograms are
+/// updated as things happen; gauges of other services' state are refreshed
+/// when the endpoint is scraped.
+pub struct Metrics {
+	registry: Registry,
+
+	requests: HistogramVec,
+	cache_lookups: IntCounterVec,
+	sync_long_polls: IntGaugeVec,
+	sync_long_polls_total: IntCounterVec,
+	allocator: IntGaugeVec,
+
+	/// Entries in an in-memory cache, by `cache`
+	pub cache_entries: IntGaugeVec,
+	/// Maximum entries of an in-memory cache, by `cache`
+	pub cache_capacity: IntGaugeVec,
+	/// Destinations the sender is sending to or backing off from, by `state`
+	pub sender_destinations: IntGaugeVec,
+	/// Requests waiting in the sender's database queue, by `state`
+	pub sender_requests: IntGaugeVec,
+	/// Requests dispatched to the sender not yet picked up
+	pub sender_channel: IntGauge,
+	/// RocksDB properties of the whole database, by `property`
+	pub db_properties: IntGaugeVec,
+	/// RocksDB properties of a column family, by `cf` and `property`
+	pub db_cf_properties: IntGaugeVec,
+	/// RocksDB block and row cache usage in bytes, by `cache`
+	pub db_cache_usage: IntGaugeVec,
+}
+
+/// Counts a sync request waiting for new events until dropped.
+pub struct LongPoll(IntGauge);
+
+impl Metrics {
+	#[must_use]
+	pub fn new() -> Self {
+		let registry = Registry::new();
+
+		let requests = HistogramVec::new(
+			HistogramOpts::new("conduwuit_http_request_duration_seconds", "Time taken to handle requests")
+				.buckets(REQUEST_BUCKETS.to_vec()),
+			&["method", "route", "status"],
+		)
+		.expect("valid metric");
+		let cache_lookups = IntCounterVec::new(
+			Opts::new("conduwuit_cache_lookups_total", "Lookups in in-memory caches"),
+			&["cache", "result"],
+		)
+		.expect("valid metric");
+		let sync_long_polls = IntGaugeVec::new(
+			Opts::new("conduwuit_sync_long_polls", "Sync requests currently waiting for new events"),
+			&["version"],
+		)
+		.expect("valid metric");
+		let sync_long_polls_total = IntCounterVec::new(
+			Opts::new("conduwuit_sync_long_polls_total", "Sync requests that waited for new events"),
+			&["version"],
+		)
+		.expect("valid metric");
+		let allocator = IntGaugeVec::new(
+			Opts::new("conduwuit_allocator_bytes", "Memory statistics of the allocator"),
+			&["stat"],
+		)
+		.expect("valid metric");
+		let cache_entries =
+			IntGaugeVec::new(Opts::new("conduwuit_cache_entries", "Entries in in-memory caches"), &["cache"])
+				.expect("valid metric");
+		let cache_capacity = IntGaugeVec::new(
+			Opts::new("conduwuit_cache_capacity", "Maximum entries of in-memory caches"),
+			&["cache"],
+		)
+		.expect("valid metric");
+		let sender_destinations = IntGaugeVec::new(
+			Opts::new(
+				"conduwuit_sender_destinations",
+				"Destinations the sender is sending to, retrying, or backing off from",
+			),
+			&["state"],
+		)
+		.expect("valid metric");
+		let sender_requests = IntGaugeVec::new(
+			Opts::new("conduwuit_sender_requests", "Requests in the sender's queue"),
+			&["state"],
+		)
+		.expect("valid metric");
+		let sender_channel = IntGauge::new(
+			"conduwuit_sender_channel_depth",
+			"Requests dispatched to the sender not yet picked up",
+		)
+		.expect("valid metric");
+		let db_properties = IntGaugeVec::new(
+			Opts::new("conduwuit_rocksdb_property", "RocksDB properties of the database"),
+			&["property"],
+		)
+		.expect("valid metric");
+		let db_cf_properties = IntGaugeVec::new(
+			Opts::new("conduwuit_rocksdb_cf_property", "RocksDB properties of column families"),
+			&["cf", "property"],
+		)
+		.expect("valid metric");
+		let db_cache_usage = IntGaugeVec::new(
+			Opts::new("conduwuit_rocksdb_cache_usage_bytes", "Memory used by RocksDB caches"),
+			&["cache"],
+		)
+		.expect("valid metric");
+
+		registry
+			.register(Box::new(requests.clone()))
+			.and_then(|()| registry.register(Box::new(cache_lookups.clone())))
+			.and_then(|()| registry.register(Box::new(sync_long_polls.clone())))
+			.and_then(|()| registry.register(Box::new(sync_long_polls_total.clone())))
+			.and_then(|()| registry.register(Box::new(allocator.clone())))
+			.and_then(|()| registry.register(Box::new(cache_entries.clone())))
+			.and_then(|()| registry.register(Box::new(cache_capacity.clone())))
+			.and_then(|()| registry.register(Box::new(sender_destinations.clone())))
+			.and_then(|()| registry.register(Box::new(sender_requests.clone())))
+			.and_then(|()| registry.register(Box::new(sender_channel.clone())))
+			.and_then(|()| registry.register(Box::new(db_properties.clone())))
+			.and_then(|()| registry.register(Box::new(db_cf_properties.clone())))
+			.and_then(|()| registry.register(Box::new(db_cache_usage.clone())))
+			.expect("metric names are unique");
+
+		#[cfg(target_os = "linux")]
+		registry
+			.register(Box::new(prometheus::process_collector::ProcessCollector::for_self()))
+			.expect("metric names are unique");
+
+		Self {
+			registry,
+			requests,
+			cache_lookups,
+			sync_long_polls,
+			sync_long_polls_total,
+			allocator,
+			cache_entries,
+			cache_capacity,
+			sender_destinations,
+			sender_requests,
+			sender_channel,
+			db_properties,
+			db_cf_properties,
+			db_cache_usage,
+		}
+	}
+
+	/// Records a handled request. `route` is the matched route pattern, not the
+	/// request path, to keep the number of series bounded.
+	pub fn request(&self, method: &Method, route: &str, status: StatusCode, elapsed: Duration) {
+		self.requests
+			.with_label_values(&[method.as_str(), route, status.as_str()])
+			.observe(elapsed.as_secs_f64());
+	}
+
+	/// Records a lookup in an in-memory cache.
+	pub fn cache_lookup(&self, cache: &str, hit: bool) {
+		let result = if hit {
+			"hit"
+		} else {
+			"miss"
+		};
+
+		self.cache_lookups.with_label_values(&[cache, result]).inc();
+	}
+
+	/// Counts a sync request of the given version which found nothing new and
+	/// waits for events, until the returned guard is dropped.
+	#[must_use]
+	pub fn sync_long_poll(&self, version: &str) -> LongPoll {
+		self.sync_long_polls_total
+			.with_label_values(&[version])
+			.inc();
+
+		let active = self.sync_long_polls.with_label_values(&[version]);
+		active.inc();
+
+		LongPoll(active)
+	}
+
+	/// Encodes all metrics in the Prometheus text format.
+	pub fn encode(&self) -> Result<String> {
+		for (stat, bytes) in alloc::memory_metrics() {
+			self.allocator
+				.with_label_values(&[stat])
+				.set(i64::try_from(bytes).unwrap_or(i64::MAX));
+		}
+
+		let mut buf = Vec::new();
+		TextEncoder::new()
+			.encode(&self.registry.gather(), &mut buf)
+			.map_err(|e| Error::Err(format!("Failed to encode metrics: {e}")))?;
+
+		String::from_utf8(buf).map_err(|e| Error::Err(format!("Failed to encode metrics: {e}")))
+	}
+}
+
+impl Default for Metrics {
+	fn default() -> Self { Self::new() }
+}
+
+impl Drop for LongPoll {
+	fn drop(&mut self) { self.0.dec(); }
+}
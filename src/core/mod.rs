@@ -3,6 +3,7 @@ pub mod config;
 pub mod debug;
 pub mod error;
 pub mod log;
+pub mod metrics;
 pub mod mods;
 pub mod pducount;
 pub mod server;
@@ -11,6 +12,7 @@ pub mod version;
 
 pub use config::Config;
 pub use error::{Error, RumaResponse};
+pub use metrics::Metrics;
 pub use pducount::PduCount;
 pub use server::Server;
 pub use version::version;
@@ -5,7 +5,7 @@ use std::{
 
 use tokio::{runtime, sync::broadcast};
 
-use crate::{config::Config, log, Error, Result};
+use crate::{config::Config, log, metrics::Metrics, Error, Result};
 
 /// Server runtime state; public portion
 pub struct Server {
@@ -35,6 +35,9 @@ pub struct Server {
 	/// Logging subsystem state
 	pub log: log::Log,
 
+	/// Prometheus metrics
+	pub metrics: Metrics,
+
 	/// TODO: move stats
 	pub requests_spawn_active: AtomicU32,
 	pub requests_spawn_finished: AtomicU32,
@@ -55,6 +58,7 @@ impl Server {
 			runtime,
 			signal: broadcast::channel::<&'static str>(1).0,
 			log,
+			metrics: Metrics::new(),
 			requests_spawn_active: AtomicU32::new(0),
 			requests_spawn_finished: AtomicU32::new(0),
 			requests_handle_active: AtomicU32::new(0),
@@ -35,3 +35,11 @@ fn increment_wrap() {
 	let res = u64::from_be_bytes(bytes);
 	assert_eq!(res, 0);
 }
+
+#[test]
+fn rfc2822_from_millis() {
+	use utils::time::rfc2822_from_millis;
+
+	assert_eq!(rfc2822_from_millis(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+	assert_eq!(rfc2822_from_millis(1_700_000_000_999), "Tue, 14 Nov 2023 22:13:20 +0000");
+}
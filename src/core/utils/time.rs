@@ -18,3 +18,35 @@ pub fn rfc2822_from_seconds(epoch: i64) -> String {
 		.unwrap_or_default()
 		.to_rfc2822()
 }
+
+/// Formats milliseconds since the unix epoch, as stored in the database
+#[must_use]
+pub fn rfc2822_from_millis(millis: u64) -> String {
+	use chrono::{DateTime, Utc};
+
+	DateTime::<Utc>::from_timestamp_millis(i64::try_from(millis).unwrap_or(i64::MAX))
+		.unwrap_or_default()
+		.to_rfc2822()
+}
+
+/// Formats a time in UTC with a chrono format string
+#[must_use]
+pub fn format(ts: SystemTime, str: &str) -> String {
+	use chrono::{DateTime, Utc};
+
+	let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+	DateTime::<Utc>::from_timestamp(
+		i64::try_from(since_epoch.as_secs()).unwrap_or(i64::MAX),
+		since_epoch.subsec_nanos(),
+	)
+	.unwrap_or_default()
+	.format(str)
+	.to_string()
+}
+
+/// Parses an RFC 2822 date, as found in HTTP headers like `Last-Modified`
+#[must_use]
+pub fn parse_rfc2822(date: &str) -> Option<SystemTime> {
+	let secs = chrono::DateTime::parse_from_rfc2822(date).ok()?.timestamp();
+	UNIX_EPOCH.checked_add(std::time::Duration::from_secs(u64::try_from(secs).ok()?))
+}
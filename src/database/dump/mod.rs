@@ -0,0 +1,257 @@
+//! Portable dumps of the database, independent of the storage engine.
+//!
+//! A dump starts with [`MAGIC`] and the format [`VERSION`] as a big-endian
+//! u32, followed by a zstd stream of records:
+//! - `M`, the name length as a big-endian u16 and the name: starts a column
+//!   family
+//! - `E`, the key length as a big-endian u32, the key, the value length as a
+//!   big-endian u32 and the value: an entry of the current column family
+//! - `Z` and the SHA-256 of every record before it, including this tag: ends
+//!   the dump
+
+mod tests;
+
+use std::io::{self, Read, Write};
+
+use conduit::{Error, Result};
+use sha2::{Digest, Sha256};
+
+use crate::Database;
+
+pub const MAGIC: &[u8] = b"conduwuit-dump\n";
+pub const VERSION: u32 = 1;
+
+const MAP: u8 = b'M';
+const ENTRY: u8 = b'E';
+const END: u8 = b'Z';
+
+/// What a dump holds.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+	pub maps: usize,
+	pub entries: u64,
+}
+
+/// Streams the column families into a dump, all of them if none are given
+pub fn export<W: Write>(db: &Database, maps: &[String], writer: W) -> Result<Summary> {
+	let mut dump = Writer::new(writer)?;
+	for (name, map) in &db.map {
+		if !maps.is_empty() && !maps.contains(name) {
+			continue;
+		}
+
+		dump.map(name)?;
+		for (key, value) in map.iter() {
+			dump.entry(&key, &value)?;
+		}
+	}
+
+	dump.finish()
+}
+
+/// Restores a dump into an empty database. The dump should be verified against
+/// the database first, since entries are written before reaching its checksum.
+pub fn import<R: Read>(db: &Database, reader: R) -> Result<Summary> {
+	if let Some((name, _)) = db.map.iter().find(|(_, map)| map.iter().next().is_some()) {
+		return Err(Error::Err(format!(
+			"Refusing to import into a database which isn't empty, column family {name} has entries."
+		)));
+	}
+
+	let _cork = db.cork_and_sync();
+	read(reader, |name, key, value| {
+		db.map
+			.get(name)
+			.ok_or_else(|| Error::Err(format!("Dump has unknown column family {name}.")))?
+			.insert(key, value)
+	})
+}
+
+/// Reads a whole dump, checking its format, its checksum and that the
+/// database has all of its column families
+pub fn verify<R: Read>(db: &Database, reader: R) -> Result<Summary> { check(reader, |name| db.map.contains_key(name)) }
+
+/// Reads a whole dump, checking its format, its checksum and that every
+/// column family is known
+pub(crate) fn check<R, K>(reader: R, known: K) -> Result<Summary>
+where
+	R: Read,
+	K: Fn(&str) -> bool,
+{
+	read_records(
+		reader,
+		|name| {
+			if known(name) {
+				Ok(())
+			} else {
+				Err(Error::Err(format!("Dump has unknown column family {name}.")))
+			}
+		},
+		|_, _, _| Ok(()),
+	)
+}
+
+/// Reads a dump, passing each entry with the name of its column family
+pub(crate) fn read<R, F>(reader: R, entry: F) -> Result<Summary>
+where
+	R: Read,
+	F: FnMut(&str, &[u8], &[u8]) -> Result<()>,
+{
+	read_records(reader, |_| Ok(()), entry)
+}
+
+/// Reads a dump, passing the name of each column family and each entry
+fn read_records<R, M, F>(mut reader: R, mut map_start: M, mut entry: F) -> Result<Summary>
+where
+	R: Read,
+	M: FnMut(&str) -> Result<()>,
+	F: FnMut(&str, &[u8], &[u8]) -> Result<()>,
+{
+	let mut magic = vec![0; MAGIC.len()];
+	reader.read_exact(&mut magic)?;
+	if magic != MAGIC {
+		return Err(Error::Err("Not a conduwuit database dump.".to_owned()));
+	}
+
+	let version = u32::from_be_bytes(read_array(&mut reader)?);
+	if version != VERSION {
+		return Err(Error::Err(format!(
+			"Unsupported dump format version {version}, expected {VERSION}."
+		)));
+	}
+
+	let mut reader = HashingReader {
+		inner: zstd::Decoder::new(reader)?,
+		hasher: Sha256::new(),
+	};
+	let mut summary = Summary::default();
+	let mut map: Option<String> = None;
+	loop {
+		let [tag] = read_array(&mut reader)?;
+		match tag {
+			MAP => {
+				let len = u16::from_be_bytes(read_array(&mut reader)?);
+				let name = String::from_utf8(read_vec(&mut reader, len.into())?)?;
+				map_start(&name)?;
+				summary.maps = summary.maps.saturating_add(1);
+				map = Some(name);
+			},
+			ENTRY => {
+				let name = map
+					.as_deref()
+					.ok_or_else(|| Error::Err("Dump has an entry outside of any column family.".to_owned()))?;
+				let len = u32::from_be_bytes(read_array(&mut reader)?);
+				let key = read_vec(&mut reader, len)?;
+				let len = u32::from_be_bytes(read_array(&mut reader)?);
+				let value = read_vec(&mut reader, len)?;
+				entry(name, &key, &value)?;
+				summary.entries = summary.entries.saturating_add(1);
+			},
+			END => {
+				let expected = reader.hasher.clone().finalize();
+				let checksum: [u8; 32] = read_array(&mut reader.inner)?;
+				if expected.as_slice() != checksum {
+					return Err(Error::Err("Dump checksum mismatch, it is corrupted.".to_owned()));
+				}
+
+				if reader.inner.read(&mut [0])? != 0 {
+					return Err(Error::Err("Dump has data after its end.".to_owned()));
+				}
+
+				return Ok(summary);
+			},
+			_ => return Err(Error::Err(format!("Dump has an invalid record tag {tag:#x}."))),
+		}
+	}
+}
+
+/// Writes the records of a dump, hashing them as they go.
+pub(crate) struct Writer<W: Write> {
+	encoder: zstd::Encoder<'static, W>,
+	hasher: Sha256,
+	summary: Summary,
+}
+
+impl<W: Write> Writer<W> {
+	pub(crate) fn new(mut writer: W) -> Result<Self> {
+		writer.write_all(MAGIC)?;
+		writer.write_all(&VERSION.to_be_bytes())?;
+
+		Ok(Self {
+			encoder: zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+			hasher: Sha256::new(),
+			summary: Summary::default(),
+		})
+	}
+
+	pub(crate) fn map(&mut self, name: &str) -> Result<()> {
+		let len = u16::try_from(name.len()).map_err(|_| Error::Err(format!("Column family name {name} too long.")))?;
+		self.write(&[MAP])?;
+		self.write(&len.to_be_bytes())?;
+		self.write(name.as_bytes())?;
+		self.summary.maps = self.summary.maps.saturating_add(1);
+
+		Ok(())
+	}
+
+	pub(crate) fn entry(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+		self.write(&[ENTRY])?;
+		self.write(&length(key)?.to_be_bytes())?;
+		self.write(key)?;
+		self.write(&length(value)?.to_be_bytes())?;
+		self.write(value)?;
+		self.summary.entries = self.summary.entries.saturating_add(1);
+
+		Ok(())
+	}
+
+	pub(crate) fn finish(mut self) -> Result<Summary> {
+		self.write(&[END])?;
+		let checksum = self.hasher.finalize();
+		self.encoder.write_all(&checksum)?;
+		self.encoder.finish()?.flush()?;
+
+		Ok(self.summary)
+	}
+
+	fn write(&mut self, bytes: &[u8]) -> Result<()> {
+		self.hasher.update(bytes);
+		self.encoder.write_all(bytes)?;
+
+		Ok(())
+	}
+}
+
+/// Hashes what is read from the inner reader.
+struct HashingReader<R> {
+	inner: R,
+	hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let len = self.inner.read(buf)?;
+		self.hasher.update(&buf[..len]);
+		Ok(len)
+	}
+}
+
+fn length(bytes: &[u8]) -> Result<u32> {
+	u32::try_from(bytes.len()).map_err(|_| Error::Err("Entry too large for a dump.".to_owned()))
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+	let mut buf = [0; N];
+	reader.read_exact(&mut buf)?;
+	Ok(buf)
+}
+
+fn read_vec<R: Read>(reader: &mut R, len: u32) -> Result<Vec<u8>> {
+	let mut buf = Vec::new();
+	reader.by_ref().take(len.into()).read_to_end(&mut buf)?;
+	if u32::try_from(buf.len()).map_or(true, |read| read != len) {
+		return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+	}
+
+	Ok(buf)
+}
@@ -0,0 +1,78 @@
+#![cfg(test)]
+
+use super::{check, read, Summary, Writer};
+
+fn dump() -> Vec<u8> {
+	let mut buf = Vec::new();
+	let mut writer = Writer::new(&mut buf).unwrap();
+	writer.map("global").unwrap();
+	writer.entry(b"version", &13_u64.to_be_bytes()).unwrap();
+	writer.map("userid_displayname").unwrap();
+	writer.map("userid_password").unwrap();
+	writer.entry(b"@alice:example.com", b"").unwrap();
+	writer.entry(b"@bob:example.com", b"hash").unwrap();
+	let summary = writer.finish().unwrap();
+
+	assert_eq!(
+		summary,
+		Summary {
+			maps: 3,
+			entries: 3
+		}
+	);
+
+	buf
+}
+
+#[test]
+fn round_trip() {
+	let mut entries = Vec::new();
+	let summary = read(dump().as_slice(), |name, key, value| {
+		entries.push((name.to_owned(), key.to_vec(), value.to_vec()));
+		Ok(())
+	})
+	.unwrap();
+
+	assert_eq!(
+		summary,
+		Summary {
+			maps: 3,
+			entries: 3
+		}
+	);
+	assert_eq!(
+		entries,
+		[
+			("global".to_owned(), b"version".to_vec(), 13_u64.to_be_bytes().to_vec()),
+			("userid_password".to_owned(), b"@alice:example.com".to_vec(), Vec::new()),
+			("userid_password".to_owned(), b"@bob:example.com".to_vec(), b"hash".to_vec()),
+		]
+	);
+}
+
+#[test]
+fn rejects_damaged_dumps() {
+	let dump = dump();
+
+	let mut bad_magic = dump.clone();
+	bad_magic[0] = b'C';
+	assert!(check(bad_magic.as_slice(), |_| true).is_err());
+
+	let truncated = &dump[..dump.len().saturating_sub(8)];
+	assert!(check(truncated, |_| true).is_err());
+
+	let mut trailing = dump.clone();
+	trailing.extend_from_slice(&dump[super::MAGIC.len().saturating_add(4)..]);
+	assert!(check(trailing.as_slice(), |_| true).is_err());
+
+	assert!(check(dump.as_slice(), |_| true).is_ok());
+}
+
+#[test]
+fn rejects_unknown_column_families() {
+	let known = |name: &str| name == "global" || name == "userid_password";
+	assert!(check(dump().as_slice(), known).is_err());
+
+	let known = |name: &str| known(name) || name == "userid_displayname";
+	assert!(check(dump().as_slice(), known).is_ok());
+}
@@ -155,6 +155,61 @@ impl Engine {
 		Ok(res)
 	}
 
+	/// Refreshes the RocksDB metrics: database-wide and per column family
+	/// properties, and cache usage.
+	pub fn update_metrics(&self) {
+		const DB_PROPERTIES: &[&str] = &[
+			"rocksdb.cur-size-all-mem-tables",
+			"rocksdb.size-all-mem-tables",
+			"rocksdb.estimate-table-readers-mem",
+			"rocksdb.num-running-compactions",
+			"rocksdb.num-running-flushes",
+			"rocksdb.estimate-pending-compaction-bytes",
+			"rocksdb.background-errors",
+			"rocksdb.total-sst-files-size",
+			"rocksdb.live-sst-files-size",
+		];
+		const CF_PROPERTIES: &[&str] = &["rocksdb.estimate-num-keys", "rocksdb.total-sst-files-size"];
+
+		let metrics = &self.server.metrics;
+		let gauge = |value: u64| i64::try_from(value).unwrap_or(i64::MAX);
+
+		for property in DB_PROPERTIES {
+			if let Ok(Some(value)) = self.db.property_int_value(property) {
+				metrics
+					.db_properties
+					.with_label_values(&[property])
+					.set(gauge(value));
+			}
+		}
+
+		for name in &*self.cfs.lock().expect("locked") {
+			let Some(cf) = self.db.cf_handle(name) else {
+				continue;
+			};
+
+			for property in CF_PROPERTIES {
+				if let Ok(Some(value)) = self.db.property_int_value_cf(&cf, property) {
+					metrics
+						.db_cf_properties
+						.with_label_values(&[name, property])
+						.set(gauge(value));
+				}
+			}
+		}
+
+		metrics
+			.db_cache_usage
+			.with_label_values(&["row"])
+			.set(i64::try_from(self.row_cache.get_usage()).unwrap_or(i64::MAX));
+		for (name, cache) in &*self.col_cache.read().expect("locked") {
+			metrics
+				.db_cache_usage
+				.with_label_values(&[name])
+				.set(i64::try_from(cache.get_usage()).unwrap_or(i64::MAX));
+		}
+	}
+
 	pub fn cleanup(&self) -> Result<()> {
 		debug!("Running flush_opt");
 		let flushoptions = rocksdb::FlushOptions::default();
@@ -19,6 +19,7 @@ pub const MAPS: &[&str] = &[
 	"alias_roomid",
 	"alias_userid",
 	"aliasid_alias",
+	"authenticatedmediaids",
 	"backupid_algorithm",
 	"backupid_etag",
 	"backupkeyid_backup",
@@ -33,13 +34,18 @@ pub const MAPS: &[&str] = &[
 	"keyid_key",
 	"lazyloadedids",
 	"mediaid_file",
+	"mediaid_lastaccess",
 	"mediaid_user",
+	"oidcsubject_userid",
 	"onetimekeyid_onetimekeys",
 	"pduid_pdu",
 	"presenceid_presence",
 	"publicroomids",
 	"readreceiptid_readreceipt",
 	"referencedevents",
+	"referencedmediaids",
+	"registrationtoken_info",
+	"reportid_report",
 	"roomid_invitedcount",
 	"roomid_inviteviaservers",
 	"roomid_joinedcount",
@@ -51,6 +57,7 @@ pub const MAPS: &[&str] = &[
 	"roomuserdataid_accountdata",
 	"roomuserid_invitecount",
 	"roomuserid_joined",
+	"roomuserid_knockedcount",
 	"roomuserid_lastprivatereadupdate",
 	"roomuserid_leftcount",
 	"roomuserid_privateread",
@@ -59,6 +66,7 @@ pub const MAPS: &[&str] = &[
 	"senderkey_pusher",
 	"server_signingkeys",
 	"servercurrentevent_data",
+	"servername_backoff",
 	"servername_educount",
 	"servernameevent_data",
 	"serverroomids",
@@ -71,29 +79,44 @@ pub const MAPS: &[&str] = &[
 	"statehash_shortstatehash",
 	"statekey_shortstatekey",
 	"threadid_userids",
+	"threepid_userid",
+	"threepidsessionid_session",
 	"todeviceid_events",
 	"tofrom_relation",
 	"token_userdeviceid",
 	"tokenids",
 	"url_previews",
+	"userdeviceconnid_slidingsync",
+	"userdeviceconnroomid_slidingsyncroom",
 	"userdeviceid_metadata",
 	"userdeviceid_token",
 	"userdevicesessionid_uiaainfo",
 	"userdevicetxnid_response",
+	"userdirectory_termuserid",
 	"userfilterid_filter",
 	"userid_avatarurl",
 	"userid_blurhash",
+	"userid_dehydrateddevice",
 	"userid_devicelistversion",
 	"userid_displayname",
 	"userid_lastonetimekeyupdate",
+	"userid_locked",
 	"userid_masterkeyid",
+	"userid_mediaquota",
+	"userid_mediausage",
 	"userid_password",
 	"userid_presenceid",
 	"userid_selfsigningkeyid",
+	"userid_servernoticeroom",
+	"userid_shadowbanned",
+	"userid_suspended",
+	"userid_userdirectory",
 	"userid_usersigningkeyid",
 	"userroomid_highlightcount",
 	"userroomid_invitestate",
 	"userroomid_joined",
+	"userroomid_knockedstate",
 	"userroomid_leftstate",
 	"userroomid_notificationcount",
+	"userthreepid_data",
 ];
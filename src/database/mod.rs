@@ -1,5 +1,6 @@
 mod cork;
 mod database;
+pub mod dump;
 mod engine;
 mod handle;
 mod iter;
@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Commandline arguments
 #[derive(Parser, Debug)]
@@ -11,6 +11,30 @@ pub(crate) struct Args {
 	#[arg(short, long)]
 	/// Optional argument to the path of a conduwuit config TOML file
 	pub(crate) config: Option<PathBuf>,
+
+	#[command(subcommand)]
+	/// Offline database maintenance to run instead of the server
+	pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub(crate) enum Command {
+	/// Export the database into a portable dump file, while the server is
+	/// stopped
+	Export {
+		/// Path of the dump file to create
+		path: PathBuf,
+
+		#[arg(short, long = "map")]
+		/// Only export these column families, which is useful to debug them
+		maps: Vec<String>,
+	},
+
+	/// Import a dump file into the empty database, after verifying it
+	Import {
+		/// Path of the dump file to import
+		path: PathBuf,
+	},
 }
 
 /// Parse commandline arguments into structured data
@@ -0,0 +1,55 @@
+//! Offline export and import of the database as portable dumps
+
+use std::{
+	fs::File,
+	io::{BufReader, BufWriter, Write},
+	path::Path,
+	sync::Arc,
+};
+
+use conduit::{info, Error, Result};
+use database::{dump, Database};
+
+use crate::clap::Command;
+
+/// Opens the database alone, without the services, and runs the command on it
+pub(crate) async fn run(server: &Arc<conduit::Server>, command: Command) -> Result<()> {
+	let db = Database::open(server).await?;
+
+	match command {
+		Command::Export {
+			path,
+			maps,
+		} => export(&db, &path, &maps),
+		Command::Import {
+			path,
+		} => import(&db, &path),
+	}
+}
+
+fn export(db: &Database, path: &Path, maps: &[String]) -> Result<()> {
+	if let Some(name) = maps.iter().find(|name| !db.map.contains_key(*name)) {
+		return Err(Error::Err(format!("Unknown column family {name}.")));
+	}
+
+	info!("Exporting the database to {}", path.display());
+	let mut file = BufWriter::new(File::options().write(true).create_new(true).open(path)?);
+	let summary = dump::export(db, maps, &mut file)?;
+	file.flush()?;
+	file.get_ref().sync_all()?;
+
+	info!("Exported {} entries of {} column families", summary.entries, summary.maps);
+	Ok(())
+}
+
+fn import(db: &Database, path: &Path) -> Result<()> {
+	info!("Verifying the dump {}", path.display());
+	let summary = dump::verify(db, BufReader::new(File::open(path)?))?;
+	info!("Importing {} entries of {} column families", summary.entries, summary.maps);
+
+	dump::import(db, BufReader::new(File::open(path)?))?;
+	db.db.sync()?;
+
+	info!("Finished importing the dump");
+	Ok(())
+}
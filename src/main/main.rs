@@ -1,4 +1,5 @@
 pub(crate) mod clap;
+mod dump;
 mod mods;
 mod restart;
 mod sentry;
@@ -7,6 +8,7 @@ mod signal;
 mod tracing;
 
 extern crate conduit_core as conduit;
+extern crate conduit_database as database;
 
 use std::{
 	cmp,
@@ -33,7 +35,12 @@ fn main() -> Result<(), Error> {
 		.build()
 		.expect("built runtime");
 
+	let command = args.command.clone();
 	let server: Arc<Server> = Server::build(args, Some(runtime.handle()))?;
+	if let Some(command) = command {
+		return runtime.block_on(dump::run(&server.server, command));
+	}
+
 	runtime.spawn(signal::signal(server.clone()));
 	runtime.block_on(async_main(&server))?;
 
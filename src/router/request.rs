@@ -1,6 +1,12 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+	sync::{atomic::Ordering, Arc},
+	time::Instant,
+};
 
-use axum::{extract::State, response::IntoResponse};
+use axum::{
+	extract::{MatchedPath, State},
+	response::IntoResponse,
+};
 use conduit::{debug_error, debug_warn, defer, Result, RumaResponse, Server};
 use http::{Method, StatusCode, Uri};
 use ruma::api::client::{
@@ -57,7 +63,18 @@ pub(crate) async fn handle(
 
 	let method = req.method().clone();
 	let uri = req.uri().clone();
+	let route = req
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or("unknown", MatchedPath::as_str)
+		.to_owned();
+
+	let started = Instant::now();
 	let result = next.run(req).await;
+	server
+		.metrics
+		.request(&method, &route, result.status(), started.elapsed());
+
 	handle_result(&method, &uri, result)
 }
 
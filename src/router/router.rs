@@ -1,21 +1,61 @@
 use std::sync::Arc;
 
 use axum::{response::IntoResponse, routing::get, Router};
-use conduit::{Error, Server};
+use conduit::{Error, Result, Server};
 use conduit_service as service;
-use http::Uri;
+use http::{header, HeaderMap, Uri};
 use ruma::api::client::error::ErrorKind;
+use subtle::ConstantTimeEq;
 
+extern crate conduit_admin as admin;
 extern crate conduit_api as api;
 
 pub(crate) fn build(server: &Arc<Server>) -> Router {
 	let state = service::services();
-	let router = Router::new()
-		.route("/", get(it_works))
-		.fallback(not_found)
-		.with_state(state);
+	let mut router = Router::new().route("/", get(it_works)).fallback(not_found);
 
-	api::routes::build(router, server)
+	let config = &server.config;
+	if config.metrics.enable && config.metrics.address.is_none() {
+		router = router.route("/metrics", get(metrics));
+	}
+
+	let router = api::routes::build(router.with_state(state), server);
+	admin::routes(router)
+}
+
+/// Router of the separate metrics listener
+pub(crate) fn build_metrics() -> Router { Router::new().route("/metrics", get(metrics)) }
+
+/// # `GET /metrics`
+///
+/// Prometheus metrics of this server.
+///
+/// - Requires the configured bearer token if there is one
+async fn metrics(headers: HeaderMap) -> Result<impl IntoResponse> {
+	let services = service::services();
+	if let Some(token) = &services.server.config.metrics.token {
+		let bearer = headers
+			.get(header::AUTHORIZATION)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.strip_prefix("Bearer "));
+
+		// compared in constant time so the token can't be guessed byte by byte
+		let is_valid = bearer.is_some_and(|bearer| bool::from(bearer.as_bytes().ct_eq(token.as_bytes())));
+		if !is_valid {
+			return Err(Error::BadRequest(
+				ErrorKind::UnknownToken {
+					soft_logout: false,
+				},
+				"Missing or invalid metrics token.",
+			));
+		}
+	}
+	services.update_metrics().await;
+
+	Ok((
+		[(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+		services.server.metrics.encode()?,
+	))
 }
 
 async fn not_found(_uri: Uri) -> impl IntoResponse {
@@ -0,0 +1,18 @@
+use std::net::SocketAddr;
+
+use axum_server::{bind, Handle as ServerHandle};
+use conduit::Result;
+use tracing::info;
+
+use crate::router;
+
+/// Serve metrics on their own listener, which can be kept off the public
+/// network.
+pub(super) async fn serve(handle: ServerHandle, addr: SocketAddr) -> Result<()> {
+	let app = router::build_metrics().into_make_service();
+
+	info!("Serving metrics on {addr}");
+	bind(addr).handle(handle).serve(app).await?;
+
+	Ok(())
+}
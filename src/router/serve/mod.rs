@@ -1,3 +1,4 @@
+mod metrics;
 mod plain;
 mod tls;
 mod unix;
@@ -8,6 +9,7 @@ use axum::Router;
 use axum_server::Handle as ServerHandle;
 use conduit::{Error, Result, Server};
 use tokio::sync::broadcast;
+use tracing::error;
 
 /// Serve clients
 pub(super) async fn serve(
@@ -16,11 +18,26 @@ pub(super) async fn serve(
 	let config = &server.config;
 	let addrs = config.get_bind_addrs();
 
-	if cfg!(unix) && config.unix_socket_path.is_some() {
+	let metrics = config
+		.metrics
+		.address
+		.filter(|_| config.metrics.enable)
+		.map(|addr| server.runtime().spawn(metrics::serve(handle.clone(), addr)));
+
+	let res = if cfg!(unix) && config.unix_socket_path.is_some() {
 		unix::serve(server, app, shutdown).await
 	} else if config.tls.is_some() {
 		tls::serve(server, app, handle, addrs).await
 	} else {
 		plain::serve(server, app, handle, addrs).await
+	};
+
+	if let Some(metrics) = metrics {
+		metrics.abort();
+		if let Ok(Err(e)) = metrics.await {
+			error!("Metrics listener failed: {e}");
+		}
 	}
+
+	res
 }
@@ -12,6 +12,9 @@ pub struct Client {
 	pub sender: reqwest::Client,
 	pub appservice: reqwest::Client,
 	pub pusher: reqwest::Client,
+	/// Follows no redirects, so each hop can be checked against the
+	/// `ip_range_denylist` before requesting it
+	pub media: reqwest::Client,
 }
 
 impl Client {
@@ -83,6 +86,13 @@ impl Client {
 				.redirect(redirect::Policy::limited(2))
 				.build()
 				.unwrap(),
+
+			media: Self::base(config)
+				.unwrap()
+				.dns_resolver(resolver.clone())
+				.redirect(redirect::Policy::none())
+				.build()
+				.unwrap(),
 		}
 	}
 
@@ -9,7 +9,7 @@ use std::{
 	time::Instant,
 };
 
-use conduit::{debug, debug_info, debug_warn, error, info, utils, warn, Config, Error, Result};
+use conduit::{config::MediaBackend, debug, debug_info, debug_warn, error, info, utils, warn, Config, Error, Result};
 use database::Database;
 use itertools::Itertools;
 use ruma::{
@@ -18,7 +18,7 @@ use ruma::{
 	EventId, OwnedRoomId, RoomId, UserId,
 };
 
-use crate::services;
+use crate::{services, user_directory::DirectoryEntry, user_is_local};
 
 /// The current schema version.
 /// - If database is opened at greater version we reject with error. The
@@ -54,6 +54,9 @@ async fn fresh(db: &Arc<Database>, config: &Config) -> Result<()> {
 
 	db["global"].insert(b"fix_bad_double_separator_in_state_cache", &[])?;
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", &[])?;
+	db["global"].insert(b"populate_user_directory", &[])?;
+	db["global"].insert(b"feat_media_usage", &[])?;
+	db["global"].insert(b"feat_media_references", &[])?;
 
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room().await?;
@@ -124,7 +127,7 @@ async fn migrate(db: &Arc<Database>, config: &Config) -> Result<()> {
 
 	if db["global"].get(b"feat_sha256_media")?.is_none() {
 		migrate_sha256_media(db, config).await?;
-	} else if config.media_startup_check {
+	} else if config.media_startup_check && config.media_storage.backend == MediaBackend::Filesystem {
 		checkup_sha256_media(db, config).await?;
 	}
 
@@ -142,6 +145,18 @@ async fn migrate(db: &Arc<Database>, config: &Config) -> Result<()> {
 		retroactively_fix_bad_data_from_roomuserid_joined(db, config).await?;
 	}
 
+	if db["global"].get(b"populate_user_directory")?.is_none() {
+		populate_user_directory(db, config).await?;
+	}
+
+	if db["global"].get(b"feat_media_usage")?.is_none() {
+		account_media_usage(db, config).await?;
+	}
+
+	if db["global"].get(b"feat_media_references")?.is_none() {
+		index_media_references(db, config).await?;
+	}
+
 	assert_eq!(
 		services().globals.database_version().unwrap(),
 		DATABASE_VERSION,
@@ -936,3 +951,82 @@ async fn retroactively_fix_bad_data_from_roomuserid_joined(db: &Arc<Database>, _
 	info!("Finished fixing");
 	Ok(())
 }
+
+async fn populate_user_directory(db: &Arc<Database>, _config: &Config) -> Result<()> {
+	warn!("Populating the user directory index");
+	let _cork = db.cork_and_sync();
+
+	for user_id in services()
+		.users
+		.iter()
+		.filter_map(Result::ok)
+		.filter(|user_id| user_is_local(user_id))
+	{
+		services().user_directory.update_local_user(&user_id)?;
+	}
+
+	for room_id in services().rooms.metadata.iter_ids().filter_map(Result::ok) {
+		for user_id in services()
+			.rooms
+			.state_cache
+			.room_members(&room_id)
+			.filter_map(Result::ok)
+			.filter(|user_id| !user_is_local(user_id))
+		{
+			if let Some(member) = services()
+				.rooms
+				.state_accessor
+				.get_member(&room_id, &user_id)?
+			{
+				services().user_directory.update(
+					&user_id,
+					DirectoryEntry {
+						display_name: member.displayname,
+						avatar_url: member.avatar_url,
+					},
+				)?;
+			}
+		}
+	}
+
+	db["global"].insert(b"populate_user_directory", &[])?;
+
+	info!("Finished populating the user directory");
+	Ok(())
+}
+
+/// Records the size of existing media and how much each user stored, for the
+/// media quotas
+async fn account_media_usage(db: &Arc<Database>, _config: &Config) -> Result<()> {
+	warn!("Accounting the storage used by existing media, this may take a while");
+	services().media.account_existing_media().await?;
+
+	db["global"].insert(b"feat_media_usage", &[])?;
+
+	info!("Finished accounting media usage");
+	Ok(())
+}
+
+/// Records the local media referred to by existing events and profiles, which
+/// the media janitor keeps
+async fn index_media_references(db: &Arc<Database>, _config: &Config) -> Result<()> {
+	warn!("Indexing the media referred to by events, this may take a while");
+	let _cork = db.cork_and_sync();
+
+	for (_, pdu) in db["pduid_pdu"].iter() {
+		services()
+			.media
+			.reference_media(&String::from_utf8_lossy(&pdu))?;
+	}
+
+	for user_id in services().users.iter().filter_map(Result::ok) {
+		if let Some(avatar_url) = services().users.avatar_url(&user_id)? {
+			services().media.reference_media(avatar_url.as_str())?;
+		}
+	}
+
+	db["global"].insert(b"feat_media_references", &[])?;
+
+	info!("Finished indexing media references");
+	Ok(())
+}
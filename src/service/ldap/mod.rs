@@ -0,0 +1,252 @@
+mod tests;
+
+use std::sync::Arc;
+
+#[cfg(feature = "ldap")]
+use conduit::debug_info;
+use conduit::{config::LdapConfig, Error, Result, Server};
+use database::Database;
+#[cfg(feature = "ldap")]
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, Scope, SearchEntry};
+use ruma::{api::client::error::ErrorKind, OwnedUserId, UserId};
+#[cfg(feature = "ldap")]
+use tracing::warn;
+
+use crate::{services, user_is_local};
+
+pub struct Service {
+	config: LdapConfig,
+}
+
+/// The directory entry of a user whose password was verified.
+#[cfg_attr(not(feature = "ldap"), allow(dead_code))]
+struct Entry {
+	uid: String,
+	displayname: Option<String>,
+	/// Whether the user matches `admin_filter`, if set
+	is_admin: Option<bool>,
+}
+
+/// A user who logged in with their directory password.
+pub struct Login {
+	pub user_id: OwnedUserId,
+	/// Whether the account was created by this login
+	pub created: bool,
+	/// Whether the user matches `admin_filter`, if set, which their admin
+	/// status is synced to
+	pub is_admin: Option<bool>,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, _db: &Arc<Database>) -> Result<Self> {
+		let config = server.config.ldap.clone();
+		if config.enable && !cfg!(feature = "ldap") {
+			return Err(Error::bad_config(
+				"LDAP login is enabled, but conduwuit was built without the \"ldap\" feature.",
+			));
+		}
+
+		Ok(Self {
+			config,
+		})
+	}
+
+	/// Whether password logins of this user are checked against the directory
+	/// instead of the local password.
+	pub fn handles(&self, user_id: &UserId) -> bool {
+		self.config.enable && user_is_local(user_id) && !self.config.local_users.iter().any(|local| local == user_id)
+	}
+
+	/// Checks a user's password against the directory and returns the local
+	/// user, whose account is created on their first login.
+	pub async fn login(&self, user_id: &UserId, password: &str) -> Result<Login> {
+		// an empty password makes an unauthenticated bind, which succeeds
+		if password.is_empty() {
+			return Err(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."));
+		}
+
+		let entry = self
+			.authenticate(user_id.localpart(), password)
+			.await?
+			.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."))?;
+
+		self.account(entry).await
+	}
+
+	/// Finds the local user of a directory entry, creating the account if
+	/// allowed.
+	async fn account(&self, entry: Entry) -> Result<Login> {
+		let user_id = UserId::parse_with_server_name(entry.uid.to_lowercase(), services().globals.server_name())
+			.ok()
+			.filter(|user_id| !user_id.is_historical() && user_is_local(user_id))
+			.ok_or(Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
+
+		let created = !services().users.exists(&user_id)?;
+		if created {
+			if !self.config.create_users {
+				return Err(Error::BadRequest(ErrorKind::forbidden(), "This account does not exist."));
+			}
+
+			services()
+				.users
+				.create_external(&user_id, entry.displayname, "LDAP")
+				.await?;
+		} else if services().users.is_deactivated(&user_id)? {
+			return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+		}
+
+		Ok(Login {
+			user_id,
+			created,
+			is_admin: entry.is_admin,
+		})
+	}
+
+	/// Checks a user's password against the directory, without creating the
+	/// account or syncing admin status.
+	pub async fn check_password(&self, user_id: &UserId, password: &str) -> Result<bool> {
+		if password.is_empty() {
+			return Ok(false);
+		}
+
+		Ok(self
+			.authenticate(user_id.localpart(), password)
+			.await?
+			.is_some())
+	}
+
+	/// Binds as the user with their password and looks up their entry.
+	/// Returns `None` if the user doesn't exist or the password is wrong.
+	#[cfg(feature = "ldap")]
+	async fn authenticate(&self, login: &str, password: &str) -> Result<Option<Entry>> {
+		let uri = self.config.uri.as_ref().expect("checked on startup");
+		let (conn, mut ldap) = LdapConnAsync::new(uri.as_str()).await.map_err(ldap_error)?;
+		ldap3::drive!(conn);
+
+		// bind_dn with a {username} placeholder means users bind directly, otherwise
+		// we search for their DN first
+		let user_dn = self
+			.config
+			.bind_dn
+			.as_ref()
+			.filter(|bind_dn| bind_dn.contains("{username}"))
+			.map(|bind_dn| bind_dn.replace("{username}", &dn_escape(login)));
+
+		if let Some(user_dn) = &user_dn {
+			if !bind(&mut ldap, user_dn, password).await? {
+				return Ok(None);
+			}
+		} else if let Some(bind_dn) = &self.config.bind_dn {
+			let bind_password = self.config.bind_password.as_deref().unwrap_or_default();
+			if !bind(&mut ldap, bind_dn, bind_password).await? {
+				return Err(Error::bad_config("Could not bind to the LDAP server with ldap.bind_dn."));
+			}
+		}
+
+		let filter = self.user_filter(login);
+		let mut entries = search(&mut ldap, &self.config.base_dn, &filter, &self.config).await?;
+		if entries.len() != 1 {
+			debug_info!("Found {} LDAP entries for {login}", entries.len());
+			return Ok(None);
+		}
+
+		let entry = entries.remove(0);
+		let uid = first_attr(&entry, &self.config.uid_attribute).unwrap_or_else(|| login.to_owned());
+
+		let is_admin = match &self.config.admin_filter {
+			Some(admin_filter) => {
+				let filter = self.admin_filter(admin_filter, &uid);
+				let base_dn = self
+					.config
+					.admin_base_dn
+					.as_ref()
+					.unwrap_or(&self.config.base_dn);
+
+				Some(
+					!search(&mut ldap, base_dn, &filter, &self.config)
+						.await?
+						.is_empty(),
+				)
+			},
+			None => None,
+		};
+
+		if user_dn.is_none() && !bind(&mut ldap, &entry.dn, password).await? {
+			return Ok(None);
+		}
+
+		ldap.unbind().await.map_err(ldap_error)?;
+
+		Ok(Some(Entry {
+			displayname: first_attr(&entry, &self.config.name_attribute),
+			uid,
+			is_admin,
+		}))
+	}
+
+	/// Search filter for the entry of a user logging in.
+	#[cfg(feature = "ldap")]
+	fn user_filter(&self, login: &str) -> String {
+		format!(
+			"(&{}({}={}))",
+			self.config.filter,
+			self.config.uid_attribute,
+			ldap_escape(login)
+		)
+	}
+
+	/// Search filter matching a user if they are an admin.
+	#[cfg(feature = "ldap")]
+	fn admin_filter(&self, admin_filter: &str, uid: &str) -> String {
+		format!("(&{admin_filter}({}={}))", self.config.uid_attribute, ldap_escape(uid))
+	}
+
+	#[cfg(not(feature = "ldap"))]
+	#[allow(clippy::unused_async)]
+	async fn authenticate(&self, _login: &str, _password: &str) -> Result<Option<Entry>> {
+		Err(Error::Err("conduwuit was built without LDAP support.".to_owned()))
+	}
+}
+
+/// Binds with a DN and password. Returns whether the credentials were
+/// accepted.
+#[cfg(feature = "ldap")]
+async fn bind(ldap: &mut Ldap, dn: &str, password: &str) -> Result<bool> {
+	const INVALID_CREDENTIALS: u32 = 49;
+
+	let result = ldap.simple_bind(dn, password).await.map_err(ldap_error)?;
+
+	match result.rc {
+		0 => Ok(true),
+		INVALID_CREDENTIALS => Ok(false),
+		rc => Err(ldap_error(format!("bind failed with result code {rc}: {}", result.text))),
+	}
+}
+
+#[cfg(feature = "ldap")]
+async fn search(ldap: &mut Ldap, base_dn: &str, filter: &str, config: &LdapConfig) -> Result<Vec<SearchEntry>> {
+	let attrs = vec![config.uid_attribute.as_str(), config.name_attribute.as_str()];
+	let (entries, _) = ldap
+		.search(base_dn, Scope::Subtree, filter, attrs)
+		.await
+		.and_then(ldap3::SearchResult::success)
+		.map_err(ldap_error)?;
+
+	Ok(entries.into_iter().map(SearchEntry::construct).collect())
+}
+
+#[cfg(feature = "ldap")]
+fn first_attr(entry: &SearchEntry, attr: &str) -> Option<String> {
+	entry
+		.attrs
+		.get(attr)
+		.and_then(|values| values.first())
+		.filter(|value| !value.is_empty())
+		.cloned()
+}
+
+#[cfg(feature = "ldap")]
+fn ldap_error(e: impl std::fmt::Display) -> Error {
+	warn!("LDAP error: {e}");
+	Error::BadServerResponse("Failed to check the password with the LDAP server.")
+}
@@ -0,0 +1,131 @@
+#![cfg(test)]
+
+use conduit::config::LdapConfig;
+use ruma::user_id;
+
+use super::{Entry, Service};
+use crate::tests::services;
+
+fn ldap(config: LdapConfig) -> Service {
+	Service {
+		config,
+	}
+}
+
+fn entry(uid: &str) -> Entry {
+	Entry {
+		uid: uid.to_owned(),
+		displayname: Some("Directory Name".to_owned()),
+		is_admin: Some(false),
+	}
+}
+
+#[tokio::test]
+async fn handles_local_users_unless_exempt() {
+	services().await;
+	let service = ldap(LdapConfig {
+		enable: true,
+		local_users: vec![user_id!("@ldap-exempt:example.com").to_owned()],
+		..LdapConfig::default()
+	});
+
+	assert!(service.handles(user_id!("@ldap-user:example.com")));
+	assert!(!service.handles(user_id!("@ldap-exempt:example.com")), "local password");
+	assert!(!service.handles(user_id!("@ldap-user:remote.test")), "remote user");
+	assert!(
+		!ldap(LdapConfig::default()).handles(user_id!("@ldap-user:example.com")),
+		"disabled"
+	);
+}
+
+#[tokio::test]
+async fn account_created_on_first_login() {
+	let services = services().await;
+	let service = ldap(LdapConfig::default());
+
+	let login = service.account(entry("LDAP-First")).await.unwrap();
+	assert_eq!(login.user_id, user_id!("@ldap-first:example.com"), "uid is lowercased");
+	assert!(login.created);
+	assert_eq!(login.is_admin, Some(false));
+	assert!(
+		services
+			.users
+			.displayname(&login.user_id)
+			.unwrap()
+			.is_some_and(|displayname| displayname.starts_with("Directory Name")),
+		"display name from the directory"
+	);
+	assert!(
+		!services.users.is_deactivated(&login.user_id).unwrap(),
+		"random password can't be empty"
+	);
+
+	let again = service.account(entry("ldap-first")).await.unwrap();
+	assert_eq!(again.user_id, login.user_id);
+	assert!(!again.created);
+}
+
+#[tokio::test]
+async fn account_refused() {
+	let services = services().await;
+
+	let service = ldap(LdapConfig {
+		create_users: false,
+		..LdapConfig::default()
+	});
+	assert!(service.account(entry("ldap-new")).await.is_err(), "creating is disabled");
+	assert!(!services
+		.users
+		.exists(user_id!("@ldap-new:example.com"))
+		.unwrap());
+
+	let service = ldap(LdapConfig::default());
+	assert!(service.account(entry("not a localpart")).await.is_err());
+
+	let user_id = user_id!("@ldap-gone:example.com");
+	services.users.create(user_id, Some("password")).unwrap();
+	services.users.deactivate_account(user_id).unwrap();
+	assert!(service.account(entry("ldap-gone")).await.is_err(), "deactivated");
+}
+
+#[tokio::test]
+async fn login_without_password() {
+	let service = ldap(LdapConfig {
+		enable: true,
+		..LdapConfig::default()
+	});
+
+	assert!(
+		service
+			.login(user_id!("@ldap-user:example.com"), "")
+			.await
+			.is_err(),
+		"empty passwords bind anonymously"
+	);
+	assert!(!service
+		.check_password(user_id!("@ldap-user:example.com"), "")
+		.await
+		.unwrap());
+}
+
+#[cfg(feature = "ldap")]
+#[test]
+fn search_filters_escape_logins() {
+	let service = ldap(LdapConfig {
+		filter: "(memberOf=cn=matrix,dc=example,dc=com)".to_owned(),
+		..LdapConfig::default()
+	});
+
+	assert_eq!(
+		service.user_filter("alice"),
+		"(&(memberOf=cn=matrix,dc=example,dc=com)(uid=alice))"
+	);
+	assert_eq!(
+		service.user_filter("*)(uid=*"),
+		r"(&(memberOf=cn=matrix,dc=example,dc=com)(uid=\2a\29\28uid=\2a))"
+	);
+	assert_eq!(
+		service.admin_filter("(memberOf=cn=admins,dc=example,dc=com)", "alice"),
+		"(&(memberOf=cn=admins,dc=example,dc=com)(uid=alice))"
+	);
+}
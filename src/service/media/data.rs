@@ -1,30 +1,53 @@
-use std::sync::Arc;
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
 
-use conduit::{debug, debug_info, Error, Result};
+use conduit::{debug, debug_info, utils, Error, Result};
 use database::{Database, Map};
-use ruma::api::client::error::ErrorKind;
+use ruma::{api::client::error::ErrorKind, OwnedUserId, UserId};
 
-use crate::{media::UrlPreviewData, utils::string_from_bytes};
+use crate::{
+	media::{Quota, UrlPreviewData, Usage},
+	utils::string_from_bytes,
+};
 
 pub(crate) struct Data {
+	authenticatedmediaids: Arc<Map>,
 	mediaid_file: Arc<Map>,
+	mediaid_lastaccess: Arc<Map>,
 	mediaid_user: Arc<Map>,
+	referencedmediaids: Arc<Map>,
 	url_previews: Arc<Map>,
+	userid_mediaquota: Arc<Map>,
+	userid_mediausage: Arc<Map>,
+	/// Serialises the read-modify-write updates of the usage counters
+	usage_lock: Mutex<()>,
 }
 
 impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
+			authenticatedmediaids: db["authenticatedmediaids"].clone(),
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_lastaccess: db["mediaid_lastaccess"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
+			referencedmediaids: db["referencedmediaids"].clone(),
 			url_previews: db["url_previews"].clone(),
+			userid_mediaquota: db["userid_mediaquota"].clone(),
+			userid_mediausage: db["userid_mediausage"].clone(),
+			usage_lock: Mutex::new(()),
 		}
 	}
 
+	/// Records the metadata and size of a file. The usage of the sender must
+	/// have been reserved with [`Self::reserve_usage`] for originals.
+	#[allow(clippy::too_many_arguments)]
 	pub(super) fn create_file_metadata(
 		&self, sender_user: Option<&str>, mxc: &str, width: u32, height: u32, content_disposition: Option<&str>,
-		content_type: Option<&str>,
+		content_type: Option<&str>, size: usize,
 	) -> Result<Vec<u8>> {
+		let size = u64::try_from(size).unwrap_or(u64::MAX);
 		let mut key = mxc.as_bytes().to_vec();
 		key.push(0xFF);
 		key.extend_from_slice(&width.to_be_bytes());
@@ -44,7 +67,7 @@ impl Data {
 				.unwrap_or_default(),
 		);
 
-		self.mediaid_file.insert(&key, &[])?;
+		self.mediaid_file.insert(&key, &size.to_be_bytes())?;
 
 		if let Some(user) = sender_user {
 			let key = mxc.as_bytes().to_vec();
@@ -63,7 +86,12 @@ impl Data {
 
 		debug!("MXC db prefix: {prefix:?}");
 
-		for (key, _) in self.mediaid_file.scan_prefix(prefix) {
+		let mut original = None;
+		for (key, value) in self.mediaid_file.scan_prefix(prefix.clone()) {
+			if key.get(prefix.len()..prefix.len().saturating_add(8)) == Some(&[0; 8]) {
+				original = Some(file_size(&value));
+			}
+
 			debug!("Deleting key: {:?}", key);
 			self.mediaid_file.remove(&key)?;
 		}
@@ -74,12 +102,90 @@ impl Data {
 
 				debug_info!("Deleting key \"{key:?}\" which was uploaded by user {user}");
 				self.mediaid_user.remove(&key)?;
+
+				if let Some(size) = original {
+					self.update_usage(&value, |usage| Usage {
+						bytes: usage.bytes.saturating_sub(size),
+						files: usage.files.saturating_sub(1),
+					})?;
+				}
 			}
 		}
 
+		self.authenticatedmediaids.remove(mxc.as_bytes())?;
+		for (key, _) in self.authenticatedmediaids.scan_prefix(prefix) {
+			self.authenticatedmediaids.remove(&key)?;
+		}
+		self.mediaid_lastaccess.remove(mxc.as_bytes())?;
+
 		Ok(())
 	}
 
+	/// Records when a media was last stored or downloaded, in milliseconds
+	/// since the unix epoch
+	pub(super) fn set_last_access(&self, mxc: &str, timestamp: u64) -> Result<()> {
+		self.mediaid_lastaccess
+			.insert(mxc.as_bytes(), &timestamp.to_be_bytes())
+	}
+
+	pub(super) fn last_access(&self, mxc: &str) -> Result<Option<u64>> {
+		self.mediaid_lastaccess
+			.get(mxc.as_bytes())?
+			.map(|bytes| {
+				utils::u64_from_bytes(&bytes)
+					.map_err(|_| Error::bad_database("Invalid timestamp in mediaid_lastaccess."))
+			})
+			.transpose()
+	}
+
+	/// Records that an event or profile refers to a media
+	pub(super) fn set_referenced(&self, mxc: &str) -> Result<()> { self.referencedmediaids.insert(mxc.as_bytes(), &[]) }
+
+	pub(super) fn is_referenced(&self, mxc: &str) -> Result<bool> {
+		Ok(self.referencedmediaids.get(mxc.as_bytes())?.is_some())
+	}
+
+	pub(super) fn has_uploader(&self, mxc: &str) -> Result<bool> {
+		Ok(self.mediaid_user.get(mxc.as_bytes())?.is_some())
+	}
+
+	/// Every MXC with the total size of its files, including thumbnails
+	pub(super) fn media_sizes(&self) -> Result<Vec<(String, u64)>> {
+		let mut sizes: Vec<(String, u64)> = Vec::new();
+		for (key, value) in self.mediaid_file.iter() {
+			let mxc = key.split(|&b| b == 0xFF).next().unwrap_or_default();
+			let mxc =
+				string_from_bytes(mxc).map_err(|_| Error::bad_database("MXC in mediaid_file is invalid unicode."))?;
+
+			// Files of the same MXC are next to each other
+			match sizes.last_mut() {
+				Some((last, size)) if *last == mxc => *size = size.saturating_add(file_size(&value)),
+				_ => sizes.push((mxc, file_size(&value))),
+			}
+		}
+
+		Ok(sizes)
+	}
+
+	/// Marks a file as only retrievable through the authenticated endpoints.
+	/// Width, height = 0 for the original file, which also covers its
+	/// thumbnails.
+	pub(super) fn set_authenticated_only(&self, mxc: &str, width: u32, height: u32) -> Result<()> {
+		self.authenticatedmediaids
+			.insert(&authenticated_id(mxc, width, height), &[])
+	}
+
+	pub(super) fn is_authenticated_only(&self, mxc: &str, width: u32, height: u32) -> Result<bool> {
+		Ok(self
+			.authenticatedmediaids
+			.get(&authenticated_id(mxc, width, height))?
+			.is_some())
+	}
+
+	pub(super) fn file_exists(&self, mxc: &str, width: u32, height: u32) -> bool {
+		self.search_file_metadata(mxc, width, height).is_ok()
+	}
+
 	/// Searches for all files with the given MXC
 	pub(super) fn search_mxc_metadata_prefix(&self, mxc: &str) -> Result<Vec<Vec<u8>>> {
 		debug!("MXC URI: {:?}", mxc);
@@ -148,6 +254,125 @@ impl Data {
 	/// associated with it such as width, height, content-type, etc)
 	pub(crate) fn get_all_media_keys(&self) -> Vec<Vec<u8>> { self.mediaid_file.iter().map(|(key, _)| key).collect() }
 
+	/// Keys of the files stored before their size was recorded
+	pub(super) fn unsized_media_keys(&self) -> Vec<Vec<u8>> {
+		self.mediaid_file
+			.iter()
+			.filter(|(_, value)| value.is_empty())
+			.map(|(key, _)| key)
+			.collect()
+	}
+
+	pub(super) fn set_file_size(&self, key: &[u8], size: u64) -> Result<()> {
+		self.mediaid_file.insert(key, &size.to_be_bytes())
+	}
+
+	/// Size of all the files in the database, including remote media and
+	/// thumbnails
+	pub(super) fn total_usage(&self) -> Usage {
+		self.mediaid_file
+			.iter()
+			.fold(Usage::default(), |usage, (_, value)| Usage {
+				bytes: usage.bytes.saturating_add(file_size(&value)),
+				files: usage.files.saturating_add(1),
+			})
+	}
+
+	pub(super) fn usage(&self, user_id: &UserId) -> Result<Usage> {
+		self.userid_mediausage
+			.get(user_id.as_bytes())?
+			.map_or_else(|| Ok(Usage::default()), |bytes| Usage::from_bytes(&bytes))
+	}
+
+	pub(super) fn all_usage(&self) -> impl Iterator<Item = Result<(OwnedUserId, Usage)>> + '_ {
+		self.userid_mediausage.iter().map(|(key, value)| {
+			let user_id = UserId::parse(
+				string_from_bytes(&key).map_err(|_| Error::bad_database("User ID in userid_mediausage is invalid."))?,
+			)
+			.map_err(|_| Error::bad_database("User ID in userid_mediausage is invalid."))?;
+
+			Ok((user_id, Usage::from_bytes(&value)?))
+		})
+	}
+
+	/// Recomputes the usage of every user from the media they uploaded
+	pub(super) fn recount_usage(&self) -> Result<()> {
+		let _lock = self.usage_lock.lock().expect("locked");
+
+		let mut usages = HashMap::<Vec<u8>, Usage>::new();
+		for (mxc, user) in self.mediaid_user.iter() {
+			let mut prefix = mxc;
+			prefix.push(0xFF);
+			prefix.extend_from_slice(&[0; 8]);
+			prefix.push(0xFF);
+
+			if let Some((_, value)) = self.mediaid_file.scan_prefix(prefix).next() {
+				let usage = usages.entry(user).or_default();
+				usage.bytes = usage.bytes.saturating_add(file_size(&value));
+				usage.files = usage.files.saturating_add(1);
+			}
+		}
+
+		for (key, _) in self.userid_mediausage.iter() {
+			self.userid_mediausage.remove(&key)?;
+		}
+		for (user, usage) in usages {
+			self.userid_mediausage.insert(&user, &usage.to_bytes())?;
+		}
+
+		Ok(())
+	}
+
+	/// Counts a file towards the usage of a user unless it would exceed their
+	/// quota, checking and updating the usage at once so concurrent uploads
+	/// can't exceed it together. Returns whether the file was counted.
+	pub(super) fn reserve_usage(&self, user_id: &UserId, size: u64, quota: &Quota) -> Result<bool> {
+		let _lock = self.usage_lock.lock().expect("locked");
+
+		let usage = self.usage(user_id)?;
+		if usage.exceeds(quota, size) {
+			return Ok(false);
+		}
+
+		let usage = Usage {
+			bytes: usage.bytes.saturating_add(size),
+			files: usage.files.saturating_add(1),
+		};
+		self.userid_mediausage
+			.insert(user_id.as_bytes(), &usage.to_bytes())?;
+
+		Ok(true)
+	}
+
+	fn update_usage(&self, user: &[u8], f: impl FnOnce(Usage) -> Usage) -> Result<()> {
+		let _lock = self.usage_lock.lock().expect("locked");
+
+		let usage = self
+			.userid_mediausage
+			.get(user)?
+			.map_or_else(|| Ok(Usage::default()), |bytes| Usage::from_bytes(&bytes))?;
+
+		self.userid_mediausage.insert(user, &f(usage).to_bytes())
+	}
+
+	pub(super) fn quota(&self, user_id: &UserId) -> Result<Option<Quota>> {
+		self.userid_mediaquota
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid quota in userid_mediaquota."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set_quota(&self, user_id: &UserId, quota: Option<&Quota>) -> Result<()> {
+		match quota {
+			Some(quota) => self
+				.userid_mediaquota
+				.insert(user_id.as_bytes(), &serde_json::to_vec(quota).expect("Quota can be serialized")),
+			None => self.userid_mediaquota.remove(user_id.as_bytes()),
+		}
+	}
+
 	pub(super) fn remove_url_preview(&self, url: &str) -> Result<()> { self.url_previews.remove(url.as_bytes()) }
 
 	pub(super) fn set_url_preview(
@@ -251,3 +476,18 @@ impl Data {
 		})
 	}
 }
+
+/// Size recorded for a file, 0 for files stored before sizes were recorded
+fn file_size(value: &[u8]) -> u64 { utils::u64_from_bytes(value).unwrap_or(0) }
+
+/// Originals are marked by their MXC, thumbnails by their MXC and dimensions
+fn authenticated_id(mxc: &str, width: u32, height: u32) -> Vec<u8> {
+	let mut id = mxc.as_bytes().to_vec();
+	if (width, height) != (0, 0) {
+		id.push(0xFF);
+		id.extend_from_slice(&width.to_be_bytes());
+		id.extend_from_slice(&height.to_be_bytes());
+	}
+
+	id
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use conduit::{debug, debug_warn, info, utils, warn, Result};
+use ruma::{OwnedMxcUri, ServerName};
+use tokio::{task::JoinHandle, time::interval};
+
+use super::Service;
+use crate::services;
+
+impl Service {
+	/// Records that a media was stored or downloaded, which keeps it from
+	/// being evicted. This is only bookkeeping and can't fail the request.
+	pub(super) fn record_access(&self, mxc: &str) {
+		if let Err(e) = self
+			.db
+			.set_last_access(mxc, utils::millis_since_unix_epoch())
+		{
+			debug_warn!(%mxc, "Failed to record media access: {e}");
+		}
+	}
+
+	/// Records the local media referred to anywhere in a text, such as the
+	/// JSON content of an event, which local media retention then keeps.
+	pub fn reference_media(&self, text: &str) -> Result<()> {
+		for mxc in local_mxcs(text, &self.server.config.server_name) {
+			self.db.set_referenced(mxc)?;
+		}
+
+		Ok(())
+	}
+
+	/// Evicts remote media not downloaded for too long or above the cache
+	/// size, least recently downloaded first, and expires the local media
+	/// not referenced by any event after the retention period. Thumbnails go
+	/// with their media. Returns how many media were deleted.
+	pub async fn clean_up(&self) -> Result<usize> {
+		let config = &self.server.config;
+		let now = utils::millis_since_unix_epoch();
+		let cutoff = |secs: u64| now.saturating_sub(secs.saturating_mul(1000));
+
+		let mut expired = Vec::new();
+		let mut cached = Vec::new();
+		for (mxc, size) in self.db.media_sizes()? {
+			// Media stored before accesses were recorded starts counting now
+			let last_access = match self.db.last_access(&mxc)? {
+				Some(last_access) => last_access,
+				None => {
+					self.db.set_last_access(&mxc, now)?;
+					now
+				},
+			};
+
+			if OwnedMxcUri::from(mxc.clone()).server_name() == Ok(&*config.server_name) {
+				// Local media without uploader are URL preview images, which the
+				// previews still refer to
+				if config
+					.media_local_retention_s
+					.is_some_and(|retention| last_access < cutoff(retention))
+					&& self.db.has_uploader(&mxc)?
+					&& !self.db.is_referenced(&mxc)?
+				{
+					expired.push(mxc);
+				}
+			} else if config
+				.media_remote_cache_max_age_s
+				.is_some_and(|max_age| last_access < cutoff(max_age))
+			{
+				expired.push(mxc);
+			} else {
+				cached.push((last_access, size, mxc));
+			}
+		}
+
+		if let Some(max_size) = config.media_remote_cache_max_size {
+			let mut size = cached
+				.iter()
+				.fold(0_u64, |total, (_, size, _)| total.saturating_add(*size));
+
+			cached.sort_unstable();
+			for (_, len, mxc) in cached {
+				if size <= max_size {
+					break;
+				}
+
+				size = size.saturating_sub(len);
+				expired.push(mxc);
+			}
+		}
+
+		let mut deleted: usize = 0;
+		for mxc in expired {
+			debug!(%mxc, "Deleting expired media");
+			match self.delete(&mxc).await {
+				Ok(()) => deleted = deleted.saturating_add(1),
+				Err(e) => warn!(%mxc, "Failed to delete expired media: {e}"),
+			}
+		}
+
+		Ok(deleted)
+	}
+}
+
+/// The MXC URIs of a server found anywhere in a text
+pub(super) fn local_mxcs<'a>(text: &'a str, server_name: &ServerName) -> Vec<&'a str> {
+	let prefix = format!("mxc://{server_name}/");
+
+	let mut mxcs = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find(&prefix) {
+		let uri = &rest[start..];
+		let media_id_len = uri[prefix.len()..]
+			.find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-')
+			.unwrap_or(uri.len().saturating_sub(prefix.len()));
+		let end = prefix.len().saturating_add(media_id_len);
+
+		if media_id_len > 0 {
+			mxcs.push(&uri[..end]);
+		}
+		rest = &uri[end..];
+	}
+
+	mxcs
+}
+
+/// Starts the task which periodically evicts remote media from the cache and
+/// expires unreferenced local media.
+#[tracing::instrument]
+pub fn start_janitor_task() -> JoinHandle<()> {
+	let timer_interval = Duration::from_secs(services().globals.config.media_janitor_interval_s);
+
+	services().server.runtime().spawn(async move {
+		let mut i = interval(timer_interval);
+
+		loop {
+			i.tick().await;
+
+			match services().media.clean_up().await {
+				Ok(deleted) if deleted > 0 => info!("Media janitor deleted {deleted} expired media"),
+				Ok(_) => {},
+				Err(e) => warn!("Media janitor failed: {e}"),
+			}
+		}
+	})
+}
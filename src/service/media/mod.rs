@@ -1,10 +1,14 @@
 mod data;
+pub mod janitor;
+mod quota;
+mod remote;
+mod storage;
 mod tests;
 
 use std::{collections::HashMap, io::Cursor, path::PathBuf, sync::Arc, time::SystemTime};
 
 use base64::{engine::general_purpose, Engine as _};
-use conduit::{debug, debug_error, error, utils, Error, Result, Server};
+use conduit::{config::MediaBackend, debug, error, utils, Error, Result, Server};
 use data::Data;
 use database::Database;
 use image::imageops::FilterType;
@@ -12,10 +16,12 @@ use ruma::{OwnedMxcUri, OwnedUserId};
 use serde::Serialize;
 use tokio::{
 	fs,
-	io::{AsyncReadExt, AsyncWriteExt, BufReader},
 	sync::{Mutex, RwLock},
+	task::JoinHandle,
 };
 
+pub use self::quota::{Quota, Usage};
+use self::storage::Storage;
 use crate::services;
 
 #[derive(Debug)]
@@ -45,44 +51,91 @@ pub struct UrlPreviewData {
 pub struct Service {
 	server: Arc<Server>,
 	pub(crate) db: Data,
+	storage: Box<dyn Storage>,
+	/// Client of the object store, which may be on the local network unlike
+	/// the servers our other clients request
+	storage_client: reqwest::Client,
 	pub url_preview_mutex: RwLock<HashMap<String, Arc<Mutex<()>>>>,
+	pub janitor_handle: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Service {
 	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		let storage_client = reqwest::Client::new();
+
 		Ok(Self {
 			server: server.clone(),
 			db: Data::new(db),
+			storage: storage::build(&server.config, &storage_client)?,
+			storage_client,
 			url_preview_mutex: RwLock::new(HashMap::new()),
+			janitor_handle: Mutex::new(None),
 		})
 	}
 
-	/// Uploads a file.
+	/// Uploads a file, counting it towards the quota of its sender.
 	pub async fn create(
 		&self, sender_user: Option<OwnedUserId>, mxc: &str, content_disposition: Option<&str>,
 		content_type: Option<&str>, file: &[u8],
 	) -> Result<()> {
 		// Width, Height = 0 if it's not a thumbnail
 		let key = if let Some(user) = sender_user {
-			self.db
-				.create_file_metadata(Some(user.as_str()), mxc, 0, 0, content_disposition, content_type)?
+			self.reserve_quota(&user, file.len())?;
+			self.db.create_file_metadata(
+				Some(user.as_str()),
+				mxc,
+				0,
+				0,
+				content_disposition,
+				content_type,
+				file.len(),
+			)?
 		} else {
 			self.db
-				.create_file_metadata(None, mxc, 0, 0, content_disposition, content_type)?
+				.create_file_metadata(None, mxc, 0, 0, content_disposition, content_type, file.len())?
 		};
 
-		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
+		if self.server.config.freeze_legacy_media {
+			self.db.set_authenticated_only(mxc, 0, 0)?;
+		}
+
+		self.record_access(mxc);
+
+		if let Err(e) = self.storage.put(&key, file).await {
+			// Neither keep the metadata of a missing file nor count it towards the quota
+			self.db.delete_file_mxc(mxc)?;
+			return Err(e);
+		}
 
 		Ok(())
 	}
 
-	/// Deletes a file in the database and from the media directory via an MXC
+	/// Whether the unauthenticated media endpoints must not serve the media:
+	/// it was stored while `freeze_legacy_media` was enabled, or would be
+	/// stored now by fetching it over federation.
+	pub fn is_authenticated_only(&self, mxc: &str) -> Result<bool> {
+		Ok(self.db.is_authenticated_only(mxc, 0, 0)?
+			|| (self.server.config.freeze_legacy_media && !self.db.file_exists(mxc, 0, 0)))
+	}
+
+	/// Whether the unauthenticated media endpoints must not serve a thumbnail
+	/// of the requested size, like [`Self::is_authenticated_only`] for
+	/// thumbnails generated or fetched while `freeze_legacy_media` is enabled.
+	pub fn is_thumbnail_authenticated_only(&self, mxc: &str, width: u32, height: u32) -> Result<bool> {
+		let Some((width, height, _)) = self.thumbnail_properties(width, height) else {
+			return self.is_authenticated_only(mxc);
+		};
+
+		Ok(self.db.is_authenticated_only(mxc, 0, 0)?
+			|| self.db.is_authenticated_only(mxc, width, height)?
+			|| (self.server.config.freeze_legacy_media && !self.db.file_exists(mxc, width, height)))
+	}
+
+	/// Deletes a file in the database and from the storage via an MXC
 	pub async fn delete(&self, mxc: &str) -> Result<()> {
 		if let Ok(keys) = self.db.search_mxc_metadata_prefix(mxc) {
 			for key in keys {
-				self.remove_media_file(&key).await?;
+				self.storage.delete(&key).await?;
 
 				debug!("Deleting MXC {mxc} from database");
 				self.db.delete_file_mxc(mxc)?;
@@ -104,16 +157,31 @@ impl Service {
 		content_type: Option<&str>, width: u32, height: u32, file: &[u8],
 	) -> Result<()> {
 		let key = if let Some(user) = sender_user {
-			self.db
-				.create_file_metadata(Some(user.as_str()), mxc, width, height, content_disposition, content_type)?
+			self.db.create_file_metadata(
+				Some(user.as_str()),
+				mxc,
+				width,
+				height,
+				content_disposition,
+				content_type,
+				file.len(),
+			)?
 		} else {
 			self.db
-				.create_file_metadata(None, mxc, width, height, content_disposition, content_type)?
+				.create_file_metadata(None, mxc, width, height, content_disposition, content_type, file.len())?
 		};
 
-		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
+		if self.server.config.freeze_legacy_media {
+			self.db.set_authenticated_only(mxc, width, height)?;
+		}
+
+		self.record_access(mxc);
+
+		if let Err(e) = self.storage.put(&key, file).await {
+			// Neither keep the metadata of a missing file nor count it towards the quota
+			self.db.delete_file_mxc(mxc)?;
+			return Err(e);
+		}
 
 		Ok(())
 	}
@@ -121,11 +189,8 @@ impl Service {
 	/// Downloads a file.
 	pub async fn get(&self, mxc: &str) -> Result<Option<FileMeta>> {
 		if let Ok((content_disposition, content_type, key)) = self.db.search_file_metadata(mxc, 0, 0) {
-			let mut file = Vec::new();
-			let path = self.get_media_file(&key);
-			BufReader::new(fs::File::open(path).await?)
-				.read_to_end(&mut file)
-				.await?;
+			let file = self.get_file(&key).await?;
+			self.record_access(mxc);
 
 			Ok(Some(FileMeta {
 				content_disposition,
@@ -190,24 +255,21 @@ impl Service {
 				continue;
 			}
 
-			let path = self.get_media_file(&key);
-			debug!("MXC path: {path:?}");
-
-			let file_metadata = fs::metadata(path.clone()).await?;
-			debug!("File metadata: {file_metadata:?}");
-
-			let file_created_at = match file_metadata.created() {
-				Ok(value) => value,
-				Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
-					debug!("btime is unsupported, using mtime instead");
-					file_metadata.modified()?
+			let file_created_at = match self.storage.created(&key).await {
+				Ok(Some(value)) => value,
+				Ok(None) => {
+					if force {
+						error!("Could not delete MXC {mxc}: {}. Skipping...", storage::missing(&key));
+						continue;
+					}
+					return Err(storage::missing(&key));
 				},
 				Err(err) => {
 					if force {
-						error!("Could not delete MXC path {:?}: {:?}. Skipping...", path, err);
+						error!("Could not delete MXC {mxc}: {err}. Skipping...");
 						continue;
 					}
-					return Err(err.into());
+					return Err(err);
 				},
 			};
 			debug!("File created at: {:?}", file_created_at);
@@ -272,9 +334,8 @@ impl Service {
 
 		if let Ok((content_disposition, content_type, key)) = self.db.search_file_metadata(mxc, width, height) {
 			// Using saved thumbnail
-			let mut file = Vec::new();
-			let path = self.get_media_file(&key);
-			fs::File::open(path).await?.read_to_end(&mut file).await?;
+			let file = self.get_file(&key).await?;
+			self.record_access(mxc);
 
 			Ok(Some(FileMeta {
 				content_disposition,
@@ -283,9 +344,8 @@ impl Service {
 			}))
 		} else if let Ok((content_disposition, content_type, key)) = self.db.search_file_metadata(mxc, 0, 0) {
 			// Generate a thumbnail
-			let mut file = Vec::new();
-			let path = self.get_media_file(&key);
-			fs::File::open(path).await?.read_to_end(&mut file).await?;
+			let file = self.get_file(&key).await?;
+			self.record_access(mxc);
 
 			if let Ok(image) = image::load_from_memory(&file) {
 				let original_width = image.width();
@@ -349,10 +409,14 @@ impl Service {
 					height,
 					content_disposition.as_deref(),
 					content_type.as_deref(),
+					thumbnail_bytes.len(),
 				)?;
 
-				let mut f = self.create_media_file(&thumbnail_key).await?;
-				f.write_all(&thumbnail_bytes).await?;
+				if self.server.config.freeze_legacy_media {
+					self.db.set_authenticated_only(mxc, width, height)?;
+				}
+
+				self.storage.put(&thumbnail_key, &thumbnail_bytes).await?;
 
 				Ok(Some(FileMeta {
 					content_disposition,
@@ -393,39 +457,38 @@ impl Service {
 		Ok(fs::create_dir_all(dir).await?)
 	}
 
-	async fn remove_media_file(&self, key: &[u8]) -> Result<()> {
-		let path = self.get_media_file(key);
-		let legacy = self.get_media_file_b64(key);
-		debug!(?key, ?path, ?legacy, "Removing media file");
-
-		let file_rm = fs::remove_file(&path);
-		let legacy_rm = fs::remove_file(&legacy);
-		let (file_rm, legacy_rm) = tokio::join!(file_rm, legacy_rm);
-		if let Err(e) = legacy_rm {
-			if self.server.config.media_compat_file_link {
-				debug_error!(?key, ?legacy, "Failed to remove legacy media symlink: {e}");
+	/// Copies the media files missing from a backend into another one, for
+	/// all media known to the database. Returns the number of files copied and
+	/// the number of files missing from the source backend.
+	pub async fn migrate_storage(&self, from: MediaBackend, to: MediaBackend) -> Result<(usize, usize)> {
+		let config = &self.server.config;
+		let from = storage::backend(config, from, &self.storage_client)?;
+		let to = storage::backend(config, to, &self.storage_client)?;
+
+		let (mut copied, mut missing) = (0_usize, 0_usize);
+		for key in self.db.get_all_media_keys() {
+			if to.created(&key).await?.is_some() {
+				continue;
 			}
-		}
 
-		Ok(file_rm?)
-	}
+			let Some(file) = from.get(&key).await? else {
+				debug!(key = ?encode_key(&key), "Media file missing from the source backend");
+				missing = missing.saturating_add(1);
+				continue;
+			};
 
-	async fn create_media_file(&self, key: &[u8]) -> Result<fs::File> {
-		let path = self.get_media_file(key);
-		debug!(?key, ?path, "Creating media file");
-
-		let file = fs::File::create(&path).await?;
-		if self.server.config.media_compat_file_link {
-			let legacy = self.get_media_file_b64(key);
-			if let Err(e) = fs::symlink(&path, &legacy).await {
-				debug_error!(
-					key = ?encode_key(key), ?path, ?legacy,
-					"Failed to create legacy media symlink: {e}"
-				);
-			}
+			to.put(&key, &file).await?;
+			copied = copied.saturating_add(1);
 		}
 
-		Ok(file)
+		Ok((copied, missing))
+	}
+
+	async fn get_file(&self, key: &[u8]) -> Result<Vec<u8>> {
+		self.storage
+			.get(key)
+			.await?
+			.ok_or_else(|| storage::missing(key))
 	}
 
 	#[inline]
@@ -435,12 +498,7 @@ impl Service {
 	/// SHA256 hash of the base64 key as the file name
 	pub fn get_media_file_sha256(&self, key: &[u8]) -> PathBuf {
 		let mut r = self.get_media_dir();
-		// Using the hash of the base64 key as the filename
-		// This is to prevent the total length of the path from exceeding the maximum
-		// length in most filesystems
-		let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
-		let encoded = encode_key(&digest);
-		r.push(encoded);
+		r.push(storage::file_name(key));
 		r
 	}
 
@@ -454,12 +512,7 @@ impl Service {
 		r
 	}
 
-	pub fn get_media_dir(&self) -> PathBuf {
-		let mut r = PathBuf::new();
-		r.push(self.server.config.database_path.clone());
-		r.push("media");
-		r
-	}
+	pub fn get_media_dir(&self) -> PathBuf { storage::media_dir(&self.server.config) }
 }
 
 #[inline]
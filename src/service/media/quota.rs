@@ -0,0 +1,138 @@
+use conduit::{debug_info, utils, warn, Config, Error, Result};
+use ruma::{api::client::error::ErrorKind, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+
+use super::Service;
+
+/// Storage used by the media of a user or of the whole server
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Usage {
+	pub bytes: u64,
+	pub files: u64,
+}
+
+/// Limits of the media a user may store, None for no limit
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Quota {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_bytes: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_files: Option<u64>,
+}
+
+impl Usage {
+	pub(super) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+		if bytes.len() != 16 {
+			return Err(Error::bad_database("Invalid usage in userid_mediausage."));
+		}
+
+		Ok(Self {
+			bytes: utils::u64_from_u8(&bytes[..8]),
+			files: utils::u64_from_u8(&bytes[8..]),
+		})
+	}
+
+	pub(super) fn to_bytes(self) -> [u8; 16] {
+		let mut bytes = [0; 16];
+		bytes[..8].copy_from_slice(&self.bytes.to_be_bytes());
+		bytes[8..].copy_from_slice(&self.files.to_be_bytes());
+		bytes
+	}
+
+	/// Whether storing another file of a size would exceed a quota
+	#[must_use]
+	pub fn exceeds(&self, quota: &Quota, size: u64) -> bool {
+		quota
+			.max_bytes
+			.is_some_and(|max| self.bytes.saturating_add(size) > max)
+			|| quota
+				.max_files
+				.is_some_and(|max| self.files.saturating_add(1) > max)
+	}
+}
+
+impl Service {
+	/// Storage used by the media a user uploaded
+	pub fn usage(&self, user_id: &UserId) -> Result<Usage> { self.db.usage(user_id) }
+
+	/// Storage used by all the media of the server, including remote media and
+	/// thumbnails
+	#[must_use]
+	pub fn total_usage(&self) -> Usage { self.db.total_usage() }
+
+	/// The users storing the most media, biggest first
+	pub fn top_uploaders(&self, limit: usize) -> Result<Vec<(OwnedUserId, Usage)>> {
+		let mut uploaders = self.db.all_usage().collect::<Result<Vec<_>>>()?;
+
+		uploaders.retain(|(_, usage)| usage.files > 0);
+		uploaders.sort_unstable_by(|(a_user, a), (b_user, b)| b.bytes.cmp(&a.bytes).then_with(|| a_user.cmp(b_user)));
+		uploaders.truncate(limit);
+
+		Ok(uploaders)
+	}
+
+	/// The quota of a user: its override if an admin set one, else the
+	/// configured default
+	pub fn quota(&self, user_id: &UserId) -> Result<Quota> {
+		Ok(self.quota_override(user_id)?.unwrap_or(Quota {
+			max_bytes: self.server.config.media_quota_max_bytes,
+			max_files: self.server.config.media_quota_max_files,
+		}))
+	}
+
+	pub fn quota_override(&self, user_id: &UserId) -> Result<Option<Quota>> { self.db.quota(user_id) }
+
+	/// Overrides the configured quota of a user, or restores it with None
+	pub fn set_quota_override(&self, user_id: &UserId, quota: Option<&Quota>) -> Result<()> {
+		self.db.set_quota(user_id, quota)
+	}
+
+	/// Counts an upload towards the usage of a user, refusing it if it would
+	/// exceed their quota
+	pub(super) fn reserve_quota(&self, user_id: &UserId, size: usize) -> Result<()> {
+		let size = u64::try_from(size).unwrap_or(u64::MAX);
+		let quota = self.quota(user_id)?;
+		if self.db.reserve_usage(user_id, size, &quota)? {
+			return Ok(());
+		}
+
+		debug_info!(%user_id, size, ?quota, "Refusing upload exceeding the media quota");
+		let kind = match admin_contact(&self.server.config) {
+			Some(admin_contact) => ErrorKind::ResourceLimitExceeded {
+				admin_contact,
+			},
+			None => ErrorKind::forbidden(),
+		};
+
+		Err(Error::BadRequest(kind, "This upload would exceed your media storage quota."))
+	}
+
+	/// Records the size of the files stored before sizes were, then recounts
+	/// the usage of every user.
+	pub(crate) async fn account_existing_media(&self) -> Result<()> {
+		for key in self.db.unsized_media_keys() {
+			match self.storage.size(&key).await {
+				Ok(size) => self.db.set_file_size(&key, size.unwrap_or(0))?,
+				Err(e) => warn!("Failed to get the size of a media file, not counting it: {e}"),
+			}
+		}
+
+		self.db.recount_usage()
+	}
+}
+
+/// How users reach the admins to raise their quota, from the well-known
+/// support contacts
+fn admin_contact(config: &Config) -> Option<String> {
+	let config = &config.well_known;
+	if let Some(page) = &config.support_page {
+		Some(page.to_string())
+	} else if let Some(email) = &config.support_email {
+		Some(format!("mailto:{email}"))
+	} else {
+		config
+			.support_mxid
+			.as_ref()
+			.map(|mxid| mxid.matrix_to_uri().to_string())
+	}
+}
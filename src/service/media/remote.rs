@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use conduit::{debug_warn, utils::content_disposition::make_content_disposition, Error, Result};
+use ipaddress::IPAddress;
+use ruma::{
+	api::{
+		client::{error::ErrorKind, media},
+		federation::authenticated_media::{self, FileOrLocation},
+	},
+	media::Method,
+	ServerName, UInt,
+};
+use tokio::net::lookup_host;
+use url::Url;
+
+use super::{FileMeta, Service};
+use crate::{sending::resolve::validate_ip, services};
+
+/// Redirects followed from the location a remote server sends us to
+const MAX_LOCATION_REDIRECTS: usize = 3;
+
+impl Service {
+	/// Fetches remote media over federation and caches it locally. The
+	/// authenticated (MSC3916) federation endpoint is preferred; servers which
+	/// don't implement it yet are retried on the legacy unauthenticated one.
+	pub async fn fetch_remote_content(
+		&self, mxc: &str, server_name: &ServerName, media_id: &str, timeout_ms: Duration,
+	) -> Result<FileMeta> {
+		check_fetch_allowed(mxc, server_name)?;
+
+		let request = authenticated_media::get_content::v1::Request {
+			media_id: media_id.to_owned(),
+			timeout_ms,
+		};
+
+		let content = match services()
+			.sending
+			.send_federation_request(server_name, request)
+			.await
+		{
+			Ok(response) => file_or_location(response.content).await?,
+			Err(e) => {
+				debug_warn!("Authenticated media download of {mxc} failed, trying legacy endpoint: {e}");
+				let response = services()
+					.sending
+					.send_federation_request(
+						server_name,
+						media::get_content::v3::Request {
+							allow_remote: true,
+							server_name: server_name.to_owned(),
+							media_id: media_id.to_owned(),
+							timeout_ms,
+							allow_redirect: true,
+						},
+					)
+					.await?;
+
+				FileMeta {
+					content_disposition: response.content_disposition,
+					content_type: response.content_type,
+					file: response.file,
+				}
+			},
+		};
+
+		let content_disposition = make_content_disposition(&content.content_type, content.content_disposition, None);
+
+		self.create(
+			None,
+			mxc,
+			Some(&content_disposition),
+			content.content_type.as_deref(),
+			&content.file,
+		)
+		.await?;
+
+		Ok(FileMeta {
+			content_disposition: Some(content_disposition),
+			..content
+		})
+	}
+
+	/// Fetches a remote thumbnail over federation and caches it locally,
+	/// preferring the authenticated endpoint like `fetch_remote_content`.
+	#[allow(clippy::too_many_arguments)]
+	pub async fn fetch_remote_thumbnail(
+		&self, mxc: &str, server_name: &ServerName, media_id: &str, width: UInt, height: UInt, method: Option<Method>,
+		animated: Option<bool>, timeout_ms: Duration,
+	) -> Result<FileMeta> {
+		check_fetch_allowed(mxc, server_name)?;
+
+		let request = authenticated_media::get_content_thumbnail::v1::Request {
+			media_id: media_id.to_owned(),
+			method: method.clone(),
+			width,
+			height,
+			timeout_ms,
+			animated,
+		};
+
+		let content = match services()
+			.sending
+			.send_federation_request(server_name, request)
+			.await
+		{
+			Ok(response) => file_or_location(response.content).await?,
+			Err(e) => {
+				debug_warn!("Authenticated thumbnail download of {mxc} failed, trying legacy endpoint: {e}");
+				let response = services()
+					.sending
+					.send_federation_request(
+						server_name,
+						media::get_content_thumbnail::v3::Request {
+							allow_remote: true,
+							height,
+							width,
+							method,
+							server_name: server_name.to_owned(),
+							media_id: media_id.to_owned(),
+							timeout_ms,
+							allow_redirect: true,
+							animated,
+						},
+					)
+					.await?;
+
+				FileMeta {
+					content_disposition: response.content_disposition,
+					content_type: response.content_type,
+					file: response.file,
+				}
+			},
+		};
+
+		self.upload_thumbnail(
+			None,
+			mxc,
+			None,
+			content.content_type.as_deref(),
+			width.try_into().expect("all UInts are valid u32s"),
+			height.try_into().expect("all UInts are valid u32s"),
+			&content.file,
+		)
+		.await?;
+
+		let content_disposition = make_content_disposition(&content.content_type, content.content_disposition, None);
+
+		Ok(FileMeta {
+			content_disposition: Some(content_disposition),
+			..content
+		})
+	}
+}
+
+fn check_fetch_allowed(mxc: &str, server_name: &ServerName) -> Result<()> {
+	if services()
+		.globals
+		.prevent_media_downloads_from()
+		.iter()
+		.any(|blocked| blocked == server_name)
+	{
+		// we'll lie to the client and say the blocked server's media was not found and
+		// log. the client has no way of telling anyways so this is a security bonus.
+		debug_warn!("Received request for media `{mxc}` on blocklisted server");
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Media not found."));
+	}
+
+	Ok(())
+}
+
+/// Remote servers may answer with the file itself or redirect us to a
+/// location to fetch it from.
+async fn file_or_location(content: FileOrLocation) -> Result<FileMeta> {
+	match content {
+		FileOrLocation::File(content) => Ok(FileMeta {
+			content_disposition: content.content_disposition.map(|cd| cd.to_string()),
+			content_type: content.content_type,
+			file: content.file,
+		}),
+		FileOrLocation::Location(location) => {
+			let mut location = Url::parse(&location).map_err(|_| Error::BadServerResponse("Invalid media location"))?;
+
+			// redirects are followed here rather than by the client, so every hop is
+			// checked before we request it
+			let mut redirects: usize = 0;
+			let response = loop {
+				check_location_allowed(&location).await?;
+
+				let response = services()
+					.globals
+					.client
+					.media
+					.get(location.clone())
+					.send()
+					.await?;
+
+				// the name may have resolved differently for the client than for our check
+				if let Some(remote_addr) = response.remote_addr() {
+					if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
+						validate_ip(&ip)?;
+					}
+				}
+
+				if !response.status().is_redirection() {
+					break response.error_for_status()?;
+				}
+
+				if redirects >= MAX_LOCATION_REDIRECTS {
+					return Err(Error::BadServerResponse("Too many redirects from media location"));
+				}
+				redirects = redirects.saturating_add(1);
+
+				location = response
+					.headers()
+					.get(reqwest::header::LOCATION)
+					.and_then(|value| value.to_str().ok())
+					.and_then(|value| location.join(value).ok())
+					.ok_or(Error::BadServerResponse("Invalid redirect from media location"))?;
+			};
+
+			let content_type = response
+				.headers()
+				.get(reqwest::header::CONTENT_TYPE)
+				.and_then(|value| value.to_str().ok())
+				.map(ToOwned::to_owned);
+
+			let content_disposition = response
+				.headers()
+				.get(reqwest::header::CONTENT_DISPOSITION)
+				.and_then(|value| value.to_str().ok())
+				.map(ToOwned::to_owned);
+
+			Ok(FileMeta {
+				content_disposition,
+				content_type,
+				file: response.bytes().await?.to_vec(),
+			})
+		},
+	}
+}
+
+/// The location and its redirects are supplied by the remote server, so they
+/// must not be used to make us request anything within the
+/// `ip_range_denylist`.
+async fn check_location_allowed(url: &Url) -> Result<()> {
+	let host = url
+		.host_str()
+		.ok_or(Error::BadServerResponse("Invalid media location"))?;
+
+	if let Ok(ip) = IPAddress::parse(host) {
+		return validate_ip(&ip);
+	}
+
+	let port = url.port_or_known_default().unwrap_or(443);
+	for addr in lookup_host((host, port)).await? {
+		if let Ok(ip) = IPAddress::parse(addr.ip().to_string()) {
+			validate_ip(&ip)?;
+		}
+	}
+
+	Ok(())
+}
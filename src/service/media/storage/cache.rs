@@ -0,0 +1,145 @@
+use std::{
+	sync::atomic::{AtomicU64, Ordering},
+	time::SystemTime,
+};
+
+use async_trait::async_trait;
+use conduit::{debug, debug_warn, Result};
+use tokio::{fs, sync::Mutex};
+
+use super::{fs::Filesystem, Storage};
+
+/// Keeps the files recently stored or downloaded from a remote backend on
+/// local disk, evicting the least recently used ones above a size.
+pub(super) struct Cache {
+	cache: Filesystem,
+	inner: Box<dyn Storage>,
+	max_size: u64,
+	size: AtomicU64,
+	evicting: Mutex<()>,
+}
+
+impl Cache {
+	pub(super) fn new(cache: Filesystem, inner: Box<dyn Storage>, max_size: u64) -> Result<Self> {
+		std::fs::create_dir_all(cache.dir())?;
+
+		let mut size: u64 = 0;
+		for entry in std::fs::read_dir(cache.dir())? {
+			size = size.saturating_add(entry?.metadata()?.len());
+		}
+
+		Ok(Self {
+			cache,
+			inner,
+			max_size,
+			size: AtomicU64::new(size),
+			evicting: Mutex::new(()),
+		})
+	}
+
+	/// Caches a file, which is only an optimisation and can't fail
+	async fn store(&self, key: &[u8], file: &[u8]) {
+		// A file cached already is replaced rather than added to the size
+		let replaced = self.cache.size(key).await.ok().flatten().unwrap_or(0);
+		if let Err(e) = self.cache.put(key, file).await {
+			debug_warn!("Failed to cache media file: {e}");
+			return;
+		}
+
+		self.shrink(replaced);
+		let len = u64::try_from(file.len()).unwrap_or(u64::MAX);
+		let size = self
+			.size
+			.fetch_add(len, Ordering::Relaxed)
+			.saturating_add(len);
+		if size > self.max_size {
+			if let Err(e) = self.evict().await {
+				debug_warn!("Failed to evict media files from the cache: {e}");
+			}
+		}
+	}
+
+	/// Subtracts the length of a file no longer cached from the size
+	fn shrink(&self, len: u64) {
+		#[allow(clippy::let_underscore_must_use)]
+		{
+			_ = self
+				.size
+				.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| Some(size.saturating_sub(len)));
+		}
+	}
+
+	/// Removes the least recently used files until the cache is a tenth under
+	/// its size
+	async fn evict(&self) -> Result<()> {
+		let Ok(_lock) = self.evicting.try_lock() else {
+			return Ok(());
+		};
+
+		let mut files = Vec::new();
+		let mut entries = fs::read_dir(self.cache.dir()).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			let metadata = entry.metadata().await?;
+			let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+			files.push((modified, metadata.len(), entry.path()));
+		}
+		files.sort_unstable();
+
+		let target = self.max_size.saturating_sub(self.max_size / 10);
+		let mut size = files.iter().map(|(_, len, _)| len).sum::<u64>();
+		for (_, len, path) in files {
+			if size <= target {
+				break;
+			}
+
+			fs::remove_file(&path).await?;
+			size = size.saturating_sub(len);
+		}
+
+		debug!(size, "Evicted media files from the cache");
+		self.size.store(size, Ordering::Relaxed);
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Storage for Cache {
+	async fn put(&self, key: &[u8], file: &[u8]) -> Result<()> {
+		self.inner.put(key, file).await?;
+		self.store(key, file).await;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		if let Some(file) = self.cache.get(key).await? {
+			// Eviction goes by modification time, which is thus the time of the last use
+			if let Err(e) = self.cache.touch(key).await {
+				debug_warn!("Failed to touch cached media file: {e}");
+			}
+			return Ok(Some(file));
+		}
+
+		let file = self.inner.get(key).await?;
+		if let Some(file) = &file {
+			self.store(key, file).await;
+		}
+
+		Ok(file)
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result<()> {
+		self.inner.delete(key).await?;
+
+		let cached = self.cache.size(key).await?.unwrap_or(0);
+		self.cache.delete(key).await?;
+		self.shrink(cached);
+
+		Ok(())
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<Option<SystemTime>> { self.inner.created(key).await }
+
+	async fn size(&self, key: &[u8]) -> Result<Option<u64>> { self.inner.size(key).await }
+}
@@ -0,0 +1,143 @@
+use std::{
+	io,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use async_trait::async_trait;
+use conduit::{debug, debug_error, utils, Result};
+use tokio::{fs, io::AsyncWriteExt};
+
+use super::{file_name, Storage};
+use crate::media::encode_key;
+
+/// Length of the random suffix of files being written
+const TMP_SUFFIX_LENGTH: usize = 8;
+
+/// Stores media files in a directory, named by [`file_name`].
+pub(super) struct Filesystem {
+	dir: PathBuf,
+	/// Link the legacy base64 file names to the files
+	compat_file_link: bool,
+}
+
+impl Filesystem {
+	pub(super) fn new(dir: PathBuf, compat_file_link: bool) -> Self {
+		Self {
+			dir,
+			compat_file_link,
+		}
+	}
+
+	pub(super) fn dir(&self) -> &PathBuf { &self.dir }
+
+	fn path(&self, key: &[u8]) -> PathBuf { self.dir.join(file_name(key)) }
+
+	fn legacy_path(&self, key: &[u8]) -> PathBuf { self.dir.join(encode_key(key)) }
+
+	/// Sets the modification time of a file to now
+	pub(super) async fn touch(&self, key: &[u8]) -> Result<()> {
+		let file = fs::File::options()
+			.write(true)
+			.open(self.path(key))
+			.await?
+			.into_std()
+			.await;
+		file.set_modified(SystemTime::now())?;
+
+		Ok(())
+	}
+}
+
+async fn write(path: &Path, file: &[u8]) -> Result<()> {
+	let mut f = fs::File::create(path).await?;
+	f.write_all(file).await?;
+	f.sync_all().await?;
+
+	Ok(())
+}
+
+#[async_trait]
+impl Storage for Filesystem {
+	async fn put(&self, key: &[u8], file: &[u8]) -> Result<()> {
+		let path = self.path(key);
+		debug!(?key, ?path, "Creating media file");
+
+		// Written aside and renamed over, so a file is never read half written
+		let tmp = self
+			.dir
+			.join(format!("{}.{}.tmp", file_name(key), utils::random_string(TMP_SUFFIX_LENGTH)));
+		if let Err(e) = write(&tmp, file).await {
+			if let Err(rm) = fs::remove_file(&tmp).await {
+				debug_error!(?tmp, "Failed to remove partly written media file: {rm}");
+			}
+			return Err(e);
+		}
+		fs::rename(&tmp, &path).await?;
+
+		if self.compat_file_link {
+			let legacy = self.legacy_path(key);
+			if let Err(e) = fs::symlink(&path, &legacy).await {
+				debug_error!(
+					key = ?encode_key(key), ?path, ?legacy,
+					"Failed to create legacy media symlink: {e}"
+				);
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		match fs::read(self.path(key)).await {
+			Ok(file) => Ok(Some(file)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result<()> {
+		let path = self.path(key);
+		let legacy = self.legacy_path(key);
+		debug!(?key, ?path, ?legacy, "Removing media file");
+
+		let file_rm = fs::remove_file(&path);
+		let legacy_rm = fs::remove_file(&legacy);
+		let (file_rm, legacy_rm) = tokio::join!(file_rm, legacy_rm);
+		if let Err(e) = legacy_rm {
+			if self.compat_file_link {
+				debug_error!(?key, ?legacy, "Failed to remove legacy media symlink: {e}");
+			}
+		}
+
+		match file_rm {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<Option<SystemTime>> {
+		let metadata = match fs::metadata(self.path(key)).await {
+			Ok(metadata) => metadata,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		match metadata.created() {
+			Ok(created) => Ok(Some(created)),
+			Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+				debug!("btime is unsupported, using mtime instead");
+				Ok(Some(metadata.modified()?))
+			},
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn size(&self, key: &[u8]) -> Result<Option<u64>> {
+		match fs::metadata(self.path(key)).await {
+			Ok(metadata) => Ok(Some(metadata.len())),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+}
@@ -0,0 +1,69 @@
+mod cache;
+mod fs;
+mod s3;
+mod tests;
+
+use std::{path::PathBuf, time::SystemTime};
+
+use async_trait::async_trait;
+use conduit::{config::MediaBackend, Config, Error, Result};
+
+use self::{cache::Cache, fs::Filesystem, s3::S3};
+use super::encode_key;
+
+/// Where the media files are stored, by their media key.
+#[async_trait]
+pub trait Storage: Send + Sync {
+	async fn put(&self, key: &[u8], file: &[u8]) -> Result<()>;
+
+	/// The file, or None if it's missing from the storage
+	async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+	async fn delete(&self, key: &[u8]) -> Result<()>;
+
+	/// When the file was stored, or None if it's missing from the storage
+	async fn created(&self, key: &[u8]) -> Result<Option<SystemTime>>;
+
+	/// Size of the file in bytes, or None if it's missing from the storage
+	async fn size(&self, key: &[u8]) -> Result<Option<u64>>;
+}
+
+/// Builds the configured backend, with its cache if any
+pub(super) fn build(config: &Config, client: &reqwest::Client) -> Result<Box<dyn Storage>> {
+	let storage = backend(config, config.media_storage.backend, client)?;
+	let storage = match (&config.media_storage.cache_path, config.media_storage.backend) {
+		(Some(path), MediaBackend::S3) => Box::new(Cache::new(
+			Filesystem::new(path.clone(), false),
+			storage,
+			config.media_storage.cache_max_size,
+		)?),
+		_ => storage,
+	};
+
+	Ok(storage)
+}
+
+/// Builds a backend without cache, e.g. to migrate media from or to it
+pub(super) fn backend(config: &Config, backend: MediaBackend, client: &reqwest::Client) -> Result<Box<dyn Storage>> {
+	Ok(match backend {
+		MediaBackend::Filesystem => Box::new(Filesystem::new(media_dir(config), config.media_compat_file_link)),
+		MediaBackend::S3 => Box::new(S3::new(&config.media_storage, client.clone())?),
+	})
+}
+
+pub(super) fn media_dir(config: &Config) -> PathBuf {
+	let mut r = PathBuf::new();
+	r.push(config.database_path.clone());
+	r.push("media");
+	r
+}
+
+/// Name of the file of a media key: the base64 of its SHA-256, which keeps
+/// paths shorter than most filesystems' limit
+#[must_use]
+pub fn file_name(key: &[u8]) -> String {
+	let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
+	encode_key(&digest)
+}
+
+pub(super) fn missing(key: &[u8]) -> Error { Error::Err(format!("Media file {} is missing.", file_name(key))) }
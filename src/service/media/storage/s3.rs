@@ -0,0 +1,233 @@
+//! S3-compatible object store, signing requests with AWS Signature Version 4.
+
+use std::{fmt::Write as _, time::SystemTime};
+
+use async_trait::async_trait;
+use conduit::{config::MediaStorageConfig, debug, utils, Error, Result};
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::{file_name, Storage};
+
+pub(super) struct S3 {
+	client: reqwest::Client,
+	endpoint: Url,
+	region: String,
+	bucket: String,
+	prefix: String,
+	access_key: String,
+	secret_key: String,
+	path_style: bool,
+}
+
+impl S3 {
+	pub(super) fn new(config: &MediaStorageConfig, client: reqwest::Client) -> Result<Self> {
+		let setting = |value: &Option<String>, name: &str| {
+			value
+				.clone()
+				.ok_or_else(|| Error::bad_config(&format!("\"media_storage.{name}\" must be set for S3 media.")))
+		};
+
+		Ok(Self {
+			client,
+			endpoint: config
+				.s3_endpoint
+				.clone()
+				.ok_or_else(|| Error::bad_config("\"media_storage.s3_endpoint\" must be set for S3 media."))?,
+			region: config.s3_region.clone(),
+			bucket: setting(&config.s3_bucket, "s3_bucket")?,
+			prefix: config.s3_prefix.clone(),
+			access_key: setting(&config.s3_access_key, "s3_access_key")?,
+			secret_key: setting(&config.s3_secret_key, "s3_secret_key")?,
+			path_style: config.s3_path_style,
+		})
+	}
+
+	/// URL of the object of a media key
+	pub(super) fn url(&self, key: &[u8]) -> Result<Url> {
+		let object = format!("{}{}", self.prefix, file_name(key));
+		let mut url = self.endpoint.clone();
+		if self.path_style {
+			url.path_segments_mut()
+				.map_err(|()| Error::bad_config("Invalid \"media_storage.s3_endpoint\"."))?
+				.pop_if_empty()
+				.push(&self.bucket)
+				.extend(object.split('/'));
+		} else {
+			let host = url
+				.host_str()
+				.ok_or_else(|| Error::bad_config("Invalid \"media_storage.s3_endpoint\"."))?;
+			url.set_host(Some(&format!("{}.{host}", self.bucket)))
+				.map_err(|_| Error::bad_config("Invalid \"media_storage.s3_bucket\"."))?;
+			url.path_segments_mut()
+				.map_err(|()| Error::bad_config("Invalid \"media_storage.s3_endpoint\"."))?
+				.pop_if_empty()
+				.extend(object.split('/'));
+		}
+
+		Ok(url)
+	}
+
+	/// Sends a signed request for the object of a media key
+	async fn send(&self, method: Method, key: &[u8], body: Option<&[u8]>) -> Result<reqwest::Response> {
+		let url = self.url(key)?;
+		let payload_hash = format!("{:x}", Sha256::digest(body.unwrap_or_default()));
+		let date = utils::time::format(SystemTime::now(), "%Y%m%dT%H%M%SZ");
+		let host = match url.port() {
+			Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+			None => url.host_str().unwrap_or_default().to_owned(),
+		};
+
+		let headers = [
+			("host", host.as_str()),
+			("x-amz-content-sha256", payload_hash.as_str()),
+			("x-amz-date", date.as_str()),
+		];
+		let (signed_headers, signature) = signature(
+			&self.secret_key,
+			&self.region,
+			&date,
+			method.as_str(),
+			url.path(),
+			&headers,
+			&payload_hash,
+		);
+		let authorization = format!(
+			"AWS4-HMAC-SHA256 Credential={}/{}/{}/s3/aws4_request, SignedHeaders={signed_headers}, \
+			 Signature={signature}",
+			self.access_key,
+			&date[..8],
+			self.region,
+		);
+
+		debug!(%method, %url, "Sending S3 request");
+		let mut request = self
+			.client
+			.request(method, url)
+			.header("x-amz-content-sha256", payload_hash)
+			.header("x-amz-date", date)
+			.header("authorization", authorization);
+		if let Some(body) = body {
+			request = request.body(body.to_vec());
+		}
+
+		Ok(request.send().await?)
+	}
+}
+
+#[async_trait]
+impl Storage for S3 {
+	async fn put(&self, key: &[u8], file: &[u8]) -> Result<()> {
+		let response = self.send(Method::PUT, key, Some(file)).await?;
+		check(response).await?;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		let response = self.send(Method::GET, key, None).await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		Ok(Some(check(response).await?.bytes().await?.to_vec()))
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result<()> {
+		// Deleting a missing object succeeds
+		let response = self.send(Method::DELETE, key, None).await?;
+		check(response).await?;
+
+		Ok(())
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<Option<SystemTime>> {
+		let response = self.send(Method::HEAD, key, None).await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		let response = check(response).await?;
+		let created = response
+			.headers()
+			.get(reqwest::header::LAST_MODIFIED)
+			.and_then(|date| date.to_str().ok())
+			.and_then(utils::time::parse_rfc2822)
+			.ok_or_else(|| Error::Err("S3 object has no valid Last-Modified header.".to_owned()))?;
+
+		Ok(Some(created))
+	}
+
+	async fn size(&self, key: &[u8]) -> Result<Option<u64>> {
+		let response = self.send(Method::HEAD, key, None).await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		let response = check(response).await?;
+		let size = response
+			.headers()
+			.get(reqwest::header::CONTENT_LENGTH)
+			.and_then(|len| len.to_str().ok())
+			.and_then(|len| len.parse().ok())
+			.ok_or_else(|| Error::Err("S3 object has no valid Content-Length header.".to_owned()))?;
+
+		Ok(Some(size))
+	}
+}
+
+/// Turns error responses into errors, with the message of the object store
+async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+
+	let body = response.text().await.unwrap_or_default();
+	Err(Error::Err(format!("S3 request failed with {status}: {body}")))
+}
+
+/// The signed headers and signature of a request. Headers must be lowercase
+/// and sorted, and the date formatted as `%Y%m%dT%H%M%SZ`.
+pub(super) fn signature(
+	secret_key: &str, region: &str, date: &str, method: &str, path: &str, headers: &[(&str, &str)], payload_hash: &str,
+) -> (String, String) {
+	let signed_headers = headers
+		.iter()
+		.map(|(name, _)| *name)
+		.collect::<Vec<_>>()
+		.join(";");
+	let mut canonical_request = format!("{method}\n{path}\n\n");
+	for (name, value) in headers {
+		writeln!(canonical_request, "{name}:{}", value.trim()).expect("should be able to write to string buffer");
+	}
+	write!(canonical_request, "\n{signed_headers}\n{payload_hash}").expect("should be able to write to string buffer");
+
+	let day = &date[..8];
+	let scope = format!("{day}/{region}/s3/aws4_request");
+	let string_to_sign = format!(
+		"AWS4-HMAC-SHA256\n{date}\n{scope}\n{:x}",
+		Sha256::digest(canonical_request.as_bytes())
+	);
+
+	let key = hmac(format!("AWS4{secret_key}").as_bytes(), day.as_bytes());
+	let key = hmac(&key, region.as_bytes());
+	let key = hmac(&key, b"s3");
+	let key = hmac(&key, b"aws4_request");
+	let signature = hmac(&key, string_to_sign.as_bytes())
+		.iter()
+		.fold(String::new(), |mut hex, byte| {
+			write!(hex, "{byte:02x}").expect("should be able to write to string buffer");
+			hex
+		});
+
+	(signed_headers, signature)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+	let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+	mac.update(data);
+	mac.finalize().into_bytes().to_vec()
+}
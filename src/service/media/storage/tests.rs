@@ -0,0 +1,118 @@
+#![cfg(test)]
+
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process,
+	time::{Duration, SystemTime},
+};
+
+use conduit::config::MediaStorageConfig;
+
+use super::{cache::Cache, file_name, fs::Filesystem, s3, Storage};
+
+fn test_dir(name: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("conduwuit-media-{name}-{}", process::id()));
+	fs::create_dir_all(&dir).expect("created test directory");
+	dir
+}
+
+fn set_modified(dir: &Path, key: &[u8], ago: Duration) {
+	fs::File::options()
+		.write(true)
+		.open(dir.join(file_name(key)))
+		.unwrap()
+		.set_modified(SystemTime::now().checked_sub(ago).unwrap())
+		.unwrap();
+}
+
+#[test]
+fn s3_signature() {
+	// Example of signing a GET object request in the AWS documentation
+	let payload_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+	let (signed_headers, signature) = s3::signature(
+		"wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+		"us-east-1",
+		"20130524T000000Z",
+		"GET",
+		"/test.txt",
+		&[
+			("host", "examplebucket.s3.amazonaws.com"),
+			("range", "bytes=0-9"),
+			("x-amz-content-sha256", payload_hash),
+			("x-amz-date", "20130524T000000Z"),
+		],
+		payload_hash,
+	);
+
+	assert_eq!(signed_headers, "host;range;x-amz-content-sha256;x-amz-date");
+	assert_eq!(signature, "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41");
+}
+
+#[test]
+fn s3_object_urls() {
+	let key = b"mxc://example.com/media";
+	let mut config = MediaStorageConfig {
+		s3_endpoint: Some("http://127.0.0.1:9000".parse().unwrap()),
+		s3_bucket: Some("media".to_owned()),
+		s3_prefix: "conduwuit/".to_owned(),
+		s3_access_key: Some("access".to_owned()),
+		s3_secret_key: Some("secret".to_owned()),
+		s3_path_style: true,
+		..Default::default()
+	};
+
+	let storage = s3::S3::new(&config, reqwest::Client::new()).unwrap();
+	assert_eq!(
+		storage.url(key).unwrap().as_str(),
+		format!("http://127.0.0.1:9000/media/conduwuit/{}", file_name(key))
+	);
+
+	config.s3_endpoint = Some("https://s3.eu-west-1.amazonaws.com".parse().unwrap());
+	config.s3_prefix = String::new();
+	config.s3_path_style = false;
+	let storage = s3::S3::new(&config, reqwest::Client::new()).unwrap();
+	assert_eq!(
+		storage.url(key).unwrap().as_str(),
+		format!("https://media.s3.eu-west-1.amazonaws.com/{}", file_name(key))
+	);
+}
+
+#[tokio::test]
+async fn files_are_replaced_whole() {
+	let dir = test_dir("replace");
+	let storage = Filesystem::new(dir.clone(), false);
+	let key = b"mxc://example.com/replaced";
+
+	storage.put(key, b"first version").await.unwrap();
+	storage.put(key, b"second").await.unwrap();
+
+	assert_eq!(storage.get(key).await.unwrap().as_deref(), Some(&b"second"[..]));
+	let names: Vec<_> = fs::read_dir(&dir)
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name())
+		.collect();
+	assert_eq!(names, [file_name(key)], "no temporary file is left behind");
+}
+
+#[tokio::test]
+async fn cache_evicts_least_recently_used() {
+	let dir = test_dir("cache");
+	let inner = Filesystem::new(test_dir("cache-inner"), false);
+	let cache = Cache::new(Filesystem::new(dir.clone(), false), Box::new(inner), 100).unwrap();
+	let (used, unused, new) = (b"mxc://example.com/used", b"mxc://example.com/unused", b"mxc://example.com/new");
+
+	cache.put(used, &[0; 40]).await.unwrap();
+	cache.put(unused, &[0; 40]).await.unwrap();
+	set_modified(&dir, used, Duration::from_secs(120));
+	set_modified(&dir, unused, Duration::from_secs(60));
+
+	// reading the older file makes it the most recently used
+	assert!(cache.get(used).await.unwrap().is_some());
+	cache.put(new, &[0; 40]).await.unwrap();
+
+	assert!(dir.join(file_name(used)).exists());
+	assert!(!dir.join(file_name(unused)).exists(), "the least recently used file is evicted");
+	assert!(dir.join(file_name(new)).exists());
+	assert!(cache.get(unused).await.unwrap().is_some(), "evicted files are still stored");
+}
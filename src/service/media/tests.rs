@@ -83,3 +83,219 @@ async fn long_file_names_works() {
 		r.to_str().unwrap().len()
 	);
 }
+
+#[test]
+fn usage_round_trip() {
+	use super::Usage;
+
+	let usage = Usage {
+		bytes: 5_000_000_000,
+		files: 42,
+	};
+
+	assert_eq!(Usage::from_bytes(&usage.to_bytes()).unwrap(), usage);
+	assert!(Usage::from_bytes(&usage.to_bytes()[..8]).is_err());
+}
+
+#[test]
+fn quota_exceeded() {
+	use super::{Quota, Usage};
+
+	let usage = Usage {
+		bytes: 900,
+		files: 9,
+	};
+
+	assert!(!usage.exceeds(&Quota::default(), u64::MAX));
+	assert!(!usage.exceeds(
+		&Quota {
+			max_bytes: Some(1000),
+			max_files: Some(10),
+		},
+		100
+	));
+	assert!(usage.exceeds(
+		&Quota {
+			max_bytes: Some(1000),
+			max_files: None,
+		},
+		101
+	));
+	assert!(usage.exceeds(
+		&Quota {
+			max_bytes: None,
+			max_files: Some(9),
+		},
+		0
+	));
+}
+
+#[test]
+fn local_mxcs_in_content() {
+	use ruma::server_name;
+
+	use super::janitor::local_mxcs;
+
+	let content = r#"{"body":"cat.png","url":"mxc://example.com/abc_DEF-123","info":{"thumbnail_url":"mxc://other.example.com/xyz"},"formatted_body":"<img src=\"mxc://example.com/inline\"> mxc://example.com/"}"#;
+
+	assert_eq!(
+		local_mxcs(content, server_name!("example.com")),
+		["mxc://example.com/abc_DEF-123", "mxc://example.com/inline"]
+	);
+	assert!(local_mxcs(content, server_name!("example.org")).is_empty());
+}
+
+#[tokio::test]
+async fn uploads_count_towards_the_quota() {
+	use ruma::user_id;
+
+	use super::{Quota, Usage};
+	use crate::tests::services;
+
+	let media = &services().await.media;
+	let user_id = user_id!("@uploader:example.com");
+	media
+		.set_quota_override(
+			user_id,
+			Some(&Quota {
+				max_bytes: Some(100),
+				max_files: None,
+			}),
+		)
+		.unwrap();
+
+	let mxc = "mxc://example.com/quota-counted";
+	media
+		.create(Some(user_id.to_owned()), mxc, None, None, &[0; 60])
+		.await
+		.unwrap();
+	assert_eq!(
+		media.usage(user_id).unwrap(),
+		Usage {
+			bytes: 60,
+			files: 1
+		}
+	);
+
+	let refused = media
+		.create(
+			Some(user_id.to_owned()),
+			"mxc://example.com/quota-refused",
+			None,
+			None,
+			&[0; 60],
+		)
+		.await;
+	assert!(refused.is_err(), "the upload would exceed the quota");
+	assert_eq!(media.usage(user_id).unwrap().bytes, 60, "refused uploads aren't counted");
+
+	media.delete(mxc).await.unwrap();
+	assert_eq!(media.usage(user_id).unwrap(), Usage::default(), "deleting releases the quota");
+}
+
+#[tokio::test]
+async fn failed_uploads_release_the_quota() {
+	use std::{collections::HashMap, time::SystemTime};
+
+	use async_trait::async_trait;
+	use ruma::user_id;
+	use tokio::sync::{Mutex, RwLock};
+
+	use super::{storage::Storage, Data, Service, Usage};
+	use crate::tests::{database, services};
+
+	struct FullStorage;
+
+	#[async_trait]
+	impl Storage for FullStorage {
+		async fn put(&self, _key: &[u8], _file: &[u8]) -> conduit::Result<()> {
+			Err(conduit::Error::Err("No space left.".to_owned()))
+		}
+
+		async fn get(&self, _key: &[u8]) -> conduit::Result<Option<Vec<u8>>> { Ok(None) }
+
+		async fn delete(&self, _key: &[u8]) -> conduit::Result<()> { Ok(()) }
+
+		async fn created(&self, _key: &[u8]) -> conduit::Result<Option<SystemTime>> { Ok(None) }
+
+		async fn size(&self, _key: &[u8]) -> conduit::Result<Option<u64>> { Ok(None) }
+	}
+
+	let media = Service {
+		server: services().await.media.server.clone(),
+		db: Data::new(&database().await),
+		storage: Box::new(FullStorage),
+		storage_client: reqwest::Client::new(),
+		url_preview_mutex: RwLock::new(HashMap::new()),
+		janitor_handle: Mutex::new(None),
+	};
+	let user_id = user_id!("@unlucky:example.com");
+	let mxc = "mxc://example.com/not-stored";
+
+	assert!(media
+		.create(Some(user_id.to_owned()), mxc, None, None, &[0; 60])
+		.await
+		.is_err());
+	assert_eq!(media.usage(user_id).unwrap(), Usage::default());
+	assert!(
+		media.db.search_mxc_metadata_prefix(mxc).is_err(),
+		"no metadata is left for the missing file"
+	);
+}
+
+#[tokio::test]
+async fn clean_up_selects_expired_media() {
+	use std::sync::Arc;
+
+	use conduit::utils;
+	use database::Database;
+	use ruma::user_id;
+
+	use super::Service;
+	use crate::tests::server_with_config;
+
+	const HOUR_MS: u64 = 3_600_000;
+
+	let server = server_with_config(
+		"media_local_retention_s = 3600\nmedia_remote_cache_max_age_s = 3600\nmedia_remote_cache_max_size = 100\n",
+	);
+	let db = Arc::new(Database::open(&server).await.unwrap());
+	let media = Service::build(&server, &db).unwrap();
+	media.create_media_dir().await.unwrap();
+
+	let uploader = user_id!("@janitor:example.com");
+	let now = utils::millis_since_unix_epoch();
+	let media_at = |mxc: &'static str, uploaded: bool, size: usize, last_access: u64| {
+		let media = &media;
+		async move {
+			media
+				.create(uploaded.then(|| uploader.to_owned()), mxc, None, None, &vec![0; size])
+				.await
+				.unwrap();
+			media.db.set_last_access(mxc, last_access).unwrap();
+		}
+	};
+
+	media_at("mxc://example.com/old", true, 10, now - 2 * HOUR_MS).await;
+	media_at("mxc://example.com/referenced", true, 10, now - 2 * HOUR_MS).await;
+	media
+		.reference_media(r#"{"url":"mxc://example.com/referenced"}"#)
+		.unwrap();
+	media_at("mxc://example.com/preview", false, 10, now - 2 * HOUR_MS).await;
+	media_at("mxc://example.com/recent", true, 10, now).await;
+	media_at("mxc://remote.example/old", false, 10, now - 2 * HOUR_MS).await;
+	// together above the remote cache size, so the least recently used goes
+	media_at("mxc://remote.example/less-recent", false, 60, now - HOUR_MS / 2).await;
+	media_at("mxc://remote.example/recent", false, 60, now).await;
+
+	assert_eq!(media.clean_up().await.unwrap(), 3);
+
+	let kept = |mxc| media.db.search_mxc_metadata_prefix(mxc).is_ok();
+	assert!(!kept("mxc://example.com/old"), "unreferenced local media expires");
+	assert!(kept("mxc://example.com/referenced"));
+	assert!(kept("mxc://example.com/preview"), "URL preview images stay");
+	assert!(kept("mxc://example.com/recent"));
+	assert!(!kept("mxc://remote.example/old"), "remote media expires");
+	assert!(!kept("mxc://remote.example/less-recent"));
+	assert!(kept("mxc://remote.example/recent"));
+}
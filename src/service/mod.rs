@@ -1,18 +1,28 @@
 pub mod pdu;
 pub mod services;
+#[cfg(any(test, feature = "testing"))]
+pub mod tests;
 
 pub mod account_data;
 pub mod admin;
 pub mod appservice;
 pub mod globals;
 pub mod key_backups;
+pub mod ldap;
 pub mod media;
 pub mod presence;
 pub mod pusher;
+pub mod ratelimit;
+pub mod registration_tokens;
+pub mod reports;
 pub mod rooms;
 pub mod sending;
+pub mod server_notices;
+pub mod sso;
+pub mod threepid;
 pub mod transaction_ids;
 pub mod uiaa;
+pub mod user_directory;
 pub mod users;
 
 extern crate conduit_core as conduit;
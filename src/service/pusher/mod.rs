@@ -17,9 +17,10 @@ use ruma::{
 		IncomingResponse, MatrixVersion, OutgoingRequest, SendAccessToken,
 	},
 	events::{
-		room::power_levels::RoomPowerLevelsEventContent, AnySyncTimelineEvent, StateEventType, TimelineEventType,
+		push_rules::PushRulesEvent, room::power_levels::RoomPowerLevelsEventContent, AnySyncTimelineEvent,
+		GlobalAccountDataEventType, StateEventType, TimelineEventType,
 	},
-	push::{Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, PushFormat, Ruleset, Tweak},
+	push::{Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, PushFormat, RoomVersionFeature, Ruleset, Tweak},
 	serde::Raw,
 	uint, RoomId, UInt, UserId,
 };
@@ -183,11 +184,44 @@ impl Service {
 		Ok(())
 	}
 
+	/// Gets the push rules of a user, adding server-default rules which were
+	/// introduced after they were stored, like the intentional mention rules.
+	pub fn get_ruleset(&self, user: &UserId) -> Result<Ruleset> {
+		let Some(event) =
+			services()
+				.account_data
+				.get(None, user, GlobalAccountDataEventType::PushRules.to_string().into())?
+		else {
+			return Ok(Ruleset::server_default(user));
+		};
+
+		let mut ruleset = serde_json::from_str::<PushRulesEvent>(event.get())
+			.map_err(|e| {
+				warn!("Invalid push rules event in db for user ID {user}: {e}");
+				Error::bad_database("Invalid push rules event in db.")
+			})?
+			.content
+			.global;
+		ruleset.update_with_server_default(Ruleset::server_default(user));
+
+		Ok(ruleset)
+	}
+
 	#[tracing::instrument(skip(self, user, ruleset, pdu))]
 	pub fn get_actions<'a>(
 		&self, user: &UserId, ruleset: &'a Ruleset, power_levels: &RoomPowerLevelsEventContent,
 		pdu: &Raw<AnySyncTimelineEvent>, room_id: &RoomId,
 	) -> Result<&'a [Action]> {
+		let ctx = self.push_context(user, power_levels, room_id)?;
+
+		Ok(ruleset.get_actions(pdu, &ctx))
+	}
+
+	/// Gets what push rules are evaluated against for the events of a room,
+	/// to be reused for several events of the same room.
+	pub fn push_context(
+		&self, user: &UserId, power_levels: &RoomPowerLevelsEventContent, room_id: &RoomId,
+	) -> Result<PushConditionRoomCtx> {
 		let power_levels = PushConditionPowerLevelsCtx {
 			users: power_levels.users.clone(),
 			users_default: power_levels.users_default,
@@ -210,9 +244,12 @@ impl Service {
 				.displayname(user)?
 				.unwrap_or_else(|| user.localpart().to_owned()),
 			power_levels: Some(power_levels),
+			supported_features: RoomVersionFeature::list_for_room_version(
+				&services().rooms.state.get_room_version(room_id)?,
+			),
 		};
 
-		Ok(ruleset.get_actions(pdu, &ctx))
+		Ok(ctx)
 	}
 
 	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
@@ -0,0 +1,288 @@
+mod tests;
+
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduit::{
+	config::{RateLimit, RateLimitConfig},
+	debug_info, debug_warn, Error, Result, Server,
+};
+use database::Database;
+use http::HeaderMap;
+use ipaddress::IPAddress;
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedServerName, OwnedUserId, ServerName, UserId,
+};
+
+use crate::services;
+
+/// How often buckets which have refilled completely are dropped, so clients
+/// that went away don't keep their bucket around forever.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Service {
+	config: RateLimitConfig,
+	trusted_proxies: Vec<IPAddress>,
+	buckets: Mutex<Buckets>,
+}
+
+/// The actions which are rate limited separately.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+	Message,
+	Registration,
+	Login,
+	Join,
+	MediaUpload,
+	ThreepidValidation,
+}
+
+#[derive(Eq, Hash, PartialEq)]
+enum Key {
+	User(Limit, OwnedUserId),
+	Ip(Limit, IpAddr),
+	Server(OwnedServerName),
+}
+
+struct Buckets {
+	map: HashMap<Key, Bucket>,
+	pruned: Instant,
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, _db: &Arc<Database>) -> Result<Self> {
+		Ok(Self {
+			config: server.config.rate_limit.clone(),
+			trusted_proxies: server
+				.config
+				.rate_limit
+				.trusted_proxies
+				.iter()
+				.map(|cidr| IPAddress::parse(cidr).expect("valid cidr range"))
+				.collect(),
+			buckets: Mutex::new(Buckets {
+				map: HashMap::new(),
+				pruned: Instant::now(),
+			}),
+		})
+	}
+
+	/// Rate limits an action by a local user. Server admins are exempt.
+	pub fn check_user(&self, limit: Limit, user_id: &UserId) -> Result<()> {
+		match self.take(Key::User(limit, user_id.to_owned()), limit.of(&self.config)) {
+			Err(_) if services().users.is_admin(user_id)? => Ok(()),
+			result => result,
+		}
+	}
+
+	/// Rate limits an unauthenticated action by the client's IP address.
+	pub fn check_ip(&self, limit: Limit, ip: IpAddr) -> Result<()> {
+		self.take(Key::Ip(limit, ip), limit.of(&self.config))
+	}
+
+	/// Finds the address a request is limited by: the connecting peer, or the
+	/// client it forwarded the request for when it's a trusted proxy.
+	///
+	/// Proxies append the address they received the request from to
+	/// `X-Forwarded-For`, so the rightmost address which isn't a trusted proxy
+	/// is the client; anything left of it may be made up.
+	pub fn client_ip(&self, remote: IpAddr, headers: &HeaderMap) -> IpAddr {
+		if !self.is_trusted_proxy(remote) {
+			return remote;
+		}
+
+		let forwarded: Vec<_> = headers
+			.get_all("x-forwarded-for")
+			.iter()
+			.filter_map(|value| value.to_str().ok())
+			.flat_map(|value| value.split(','))
+			.collect();
+
+		for ip in forwarded.iter().rev() {
+			let Ok(ip) = ip.trim().parse::<IpAddr>() else {
+				return remote;
+			};
+
+			if !self.is_trusted_proxy(ip) {
+				return ip;
+			}
+		}
+
+		headers
+			.get("x-real-ip")
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.trim().parse().ok())
+			.unwrap_or(remote)
+	}
+
+	/// Connections over the UNIX socket have no peer address and come from a
+	/// local proxy.
+	fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+		if ip.is_unspecified() {
+			return true;
+		}
+
+		let Ok(ip) = IPAddress::parse(ip.to_canonical().to_string()) else {
+			return false;
+		};
+
+		self.trusted_proxies.iter().any(|cidr| cidr.includes(&ip))
+	}
+
+	/// Rate limits requests from a remote server.
+	pub fn check_server(&self, origin: &ServerName) -> Result<()> {
+		self.take(Key::Server(origin.to_owned()), self.config.federation)
+	}
+
+	fn take(&self, key: Key, limit: RateLimit) -> Result<()> {
+		if !self.config.enabled || limit.per_second <= 0.0 {
+			return Ok(());
+		}
+
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().expect("locked");
+		if now.saturating_duration_since(buckets.pruned) >= PRUNE_INTERVAL {
+			buckets.prune(&self.config, now);
+		}
+
+		if buckets.map.len() >= self.config.max_buckets && !buckets.map.contains_key(&key) {
+			buckets.prune(&self.config, now);
+			buckets.evict(&self.config, now);
+		}
+
+		buckets
+			.map
+			.entry(key)
+			.or_insert_with(|| Bucket::new(limit, now))
+			.take(limit, now)
+			.map_err(|retry_after| {
+				Error::BadRequest(
+					ErrorKind::LimitExceeded {
+						retry_after: Some(RetryAfter::Delay(retry_after)),
+					},
+					"Too many requests, slow down.",
+				)
+			})
+	}
+}
+
+impl Limit {
+	fn of(self, config: &RateLimitConfig) -> RateLimit {
+		match self {
+			Self::Message => config.message,
+			Self::Registration => config.registration,
+			Self::Login => config.login,
+			Self::Join => config.join,
+			Self::MediaUpload => config.media_upload,
+			Self::ThreepidValidation => config.threepid_validation,
+		}
+	}
+}
+
+impl Buckets {
+	fn prune(&mut self, config: &RateLimitConfig, now: Instant) {
+		let before = self.map.len();
+		self.map
+			.retain(|key, bucket| !bucket.is_full(key.limit(config), now));
+
+		self.pruned = now;
+		if self.map.len() < before {
+			debug_info!("Pruned {} full rate limit buckets", before.saturating_sub(self.map.len()));
+		}
+	}
+
+	/// Drops the buckets closest to full until a tenth of `max_buckets` is free
+	/// again, so a flood of new clients can't grow the map without bound.
+	fn evict(&mut self, config: &RateLimitConfig, now: Instant) {
+		let keep = config.max_buckets.saturating_sub(config.max_buckets / 10);
+		let mut excess = self.map.len().saturating_sub(keep);
+		if excess == 0 {
+			return;
+		}
+
+		let mut fills: Vec<f64> = self
+			.map
+			.iter_mut()
+			.map(|(key, bucket)| bucket.fill(key.limit(config), now))
+			.collect();
+		fills.sort_unstable_by(|a, b| b.total_cmp(a));
+		let Some(&threshold) = fills.get(excess.saturating_sub(1)) else {
+			return;
+		};
+
+		let before = self.map.len();
+		self.map.retain(|key, bucket| {
+			if excess > 0 && bucket.fill(key.limit(config), now) >= threshold {
+				excess = excess.saturating_sub(1);
+				return false;
+			}
+
+			true
+		});
+
+		debug_warn!(
+			"Too many rate limit buckets, dropped {} of {before}",
+			before.saturating_sub(self.map.len())
+		);
+	}
+}
+
+impl Key {
+	fn limit(&self, config: &RateLimitConfig) -> RateLimit {
+		match self {
+			Self::User(limit, _) | Self::Ip(limit, _) => limit.of(config),
+			Self::Server(_) => config.federation,
+		}
+	}
+}
+
+impl Bucket {
+	fn new(limit: RateLimit, now: Instant) -> Self {
+		Self {
+			tokens: f64::from(limit.burst_count),
+			updated: now,
+		}
+	}
+
+	fn refill(&mut self, limit: RateLimit, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+		self.tokens = elapsed
+			.mul_add(limit.per_second, self.tokens)
+			.min(f64::from(limit.burst_count));
+		self.updated = now;
+	}
+
+	/// Takes a token for one request, or returns how long until one is
+	/// available.
+	fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+		self.refill(limit, now);
+		if self.tokens >= 1.0 {
+			self.tokens -= 1.0;
+			return Ok(());
+		}
+
+		let wait = (1.0 - self.tokens) / limit.per_second;
+		Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+	}
+
+	fn is_full(&mut self, limit: RateLimit, now: Instant) -> bool {
+		self.refill(limit, now);
+		self.tokens >= f64::from(limit.burst_count)
+	}
+
+	/// How full the bucket is, from 0 to 1.
+	fn fill(&mut self, limit: RateLimit, now: Instant) -> f64 {
+		self.refill(limit, now);
+		self.tokens / f64::from(limit.burst_count.max(1))
+	}
+}
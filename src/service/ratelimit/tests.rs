@@ -0,0 +1,143 @@
+#![cfg(test)]
+
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use conduit::config::{RateLimit, RateLimitConfig};
+use http::{HeaderMap, HeaderValue};
+use ipaddress::IPAddress;
+
+use super::{Bucket, Buckets, Key, Limit, Service};
+
+fn service(trusted_proxies: &[&str]) -> Service {
+	Service {
+		config: RateLimitConfig::default(),
+		trusted_proxies: trusted_proxies
+			.iter()
+			.map(|cidr| IPAddress::parse(*cidr).unwrap())
+			.collect(),
+		buckets: Mutex::new(Buckets {
+			map: HashMap::new(),
+			pruned: Instant::now(),
+		}),
+	}
+}
+
+fn ip(ip: &str) -> IpAddr { ip.parse().unwrap() }
+
+fn forwarded_for(value: &str) -> HeaderMap {
+	let mut headers = HeaderMap::new();
+	headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+	headers
+}
+
+#[test]
+fn burst_then_limited() {
+	let limit = RateLimit::new(0.5, 3);
+	let now = Instant::now();
+	let mut bucket = Bucket::new(limit, now);
+
+	for _ in 0..3 {
+		assert!(bucket.take(limit, now).is_ok(), "requests within the burst are allowed");
+	}
+
+	let retry_after = bucket.take(limit, now).expect_err("burst is used up");
+	assert_eq!(retry_after, Duration::from_secs(2));
+}
+
+#[test]
+fn refills_over_time() {
+	let limit = RateLimit::new(1.0, 2);
+	let start = Instant::now();
+	let mut bucket = Bucket::new(limit, start);
+	bucket.take(limit, start).unwrap();
+	bucket.take(limit, start).unwrap();
+	assert!(bucket.take(limit, start).is_err());
+
+	let later = start + Duration::from_millis(1500);
+	assert!(bucket.take(limit, later).is_ok(), "one token refilled");
+	assert!(bucket.take(limit, later).is_err(), "only half of the next one");
+
+	let much_later = later + Duration::from_secs(60);
+	assert!(bucket.is_full(limit, much_later), "refill stops at the burst count");
+}
+
+#[test]
+fn forwarded_headers_only_from_trusted_proxies() {
+	let service = service(&["10.0.0.0/8"]);
+	let headers = forwarded_for("198.51.100.7");
+
+	assert_eq!(
+		service.client_ip(ip("203.0.113.1"), &headers),
+		ip("203.0.113.1"),
+		"untrusted peer"
+	);
+	assert_eq!(service.client_ip(ip("10.0.0.1"), &headers), ip("198.51.100.7"), "trusted proxy");
+	assert_eq!(
+		service.client_ip(ip("0.0.0.0"), &headers),
+		ip("198.51.100.7"),
+		"UNIX socket connections come from a proxy"
+	);
+	assert_eq!(
+		service.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+		ip("10.0.0.1"),
+		"no header"
+	);
+}
+
+#[test]
+fn forwarded_for_rightmost_untrusted() {
+	let service = service(&["10.0.0.0/8"]);
+
+	let headers = forwarded_for("192.0.2.1, 198.51.100.7, 10.0.0.2");
+	assert_eq!(
+		service.client_ip(ip("10.0.0.1"), &headers),
+		ip("198.51.100.7"),
+		"addresses left of the client can be made up by it"
+	);
+
+	let headers = forwarded_for("198.51.100.7, garbage");
+	assert_eq!(service.client_ip(ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+
+	let mut headers = HeaderMap::new();
+	headers.insert("x-real-ip", HeaderValue::from_static("198.51.100.8"));
+	assert_eq!(service.client_ip(ip("10.0.0.1"), &headers), ip("198.51.100.8"));
+}
+
+#[test]
+fn evicts_fullest_buckets() {
+	let config = RateLimitConfig {
+		max_buckets: 10,
+		..RateLimitConfig::default()
+	};
+	let limit = Limit::Login.of(&config);
+	let now = Instant::now();
+	let mut buckets = Buckets {
+		map: HashMap::new(),
+		pruned: now,
+	};
+
+	for i in 0..10_u8 {
+		let mut bucket = Bucket::new(limit, now);
+		if i < 5 {
+			bucket.take(limit, now).unwrap();
+			bucket.take(limit, now).unwrap();
+		}
+		buckets
+			.map
+			.insert(Key::Ip(Limit::Login, IpAddr::from([192, 0, 2, i])), bucket);
+	}
+
+	buckets.evict(&config, now);
+	assert_eq!(buckets.map.len(), 9, "a tenth of the capacity is freed");
+	assert!(
+		(0..5_u8).all(|i| buckets
+			.map
+			.contains_key(&Key::Ip(Limit::Login, IpAddr::from([192, 0, 2, i])))),
+		"buckets in use are kept"
+	);
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use serde::{Deserialize, Serialize};
+
+pub(super) struct Data {
+	registrationtoken_info: Arc<Map>,
+}
+
+/// Limits and usage of a token. Registrations in progress aren't persisted.
+#[derive(Deserialize, Serialize)]
+pub(super) struct TokenInfo {
+	pub(super) uses_allowed: Option<u32>,
+	pub(super) completed: u32,
+	pub(super) expiry_time: Option<u64>,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			registrationtoken_info: db["registrationtoken_info"].clone(),
+		}
+	}
+
+	pub(super) fn get(&self, token: &str) -> Result<Option<TokenInfo>> {
+		self.registrationtoken_info
+			.get(token.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid token info in registrationtoken_info."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set(&self, token: &str, info: &TokenInfo) -> Result<()> {
+		self.registrationtoken_info.insert(
+			token.as_bytes(),
+			&serde_json::to_vec(info).expect("TokenInfo::to_vec always works"),
+		)
+	}
+
+	pub(super) fn remove(&self, token: &str) -> Result<()> { self.registrationtoken_info.remove(token.as_bytes()) }
+
+	pub(super) fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(String, TokenInfo)>> + 'a> {
+		Box::new(self.registrationtoken_info.iter().map(|(key, value)| {
+			let token = utils::string_from_bytes(&key)
+				.map_err(|_| Error::bad_database("Token in registrationtoken_info is invalid unicode."))?;
+			let info = serde_json::from_slice(&value)
+				.map_err(|_| Error::bad_database("Invalid token info in registrationtoken_info."))?;
+
+			Ok((token, info))
+		}))
+	}
+}
@@ -0,0 +1,257 @@
+mod data;
+mod tests;
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduit::{utils, Error, Result, Server};
+use data::{Data, TokenInfo};
+use database::Database;
+use ruma::api::client::error::ErrorKind;
+use serde::Serialize;
+
+/// Length of generated tokens if none is given
+const DEFAULT_TOKEN_LENGTH: usize = 16;
+
+const MAX_TOKEN_LENGTH: usize = 64;
+
+/// How long a registration counts towards `pending` without completing
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+pub struct Service {
+	db: Data,
+	config_token: Option<String>,
+	requires_token: bool,
+	/// Registrations which passed the token stage and haven't completed yet,
+	/// by UIAA session
+	pending: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+/// A registration token and its usage, in the shape of the Synapse admin API.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegistrationToken {
+	pub token: String,
+	/// How many registrations may use the token, unlimited if `None`
+	pub uses_allowed: Option<u32>,
+	/// Registrations using the token in progress
+	pub pending: u32,
+	/// Registrations completed with the token
+	pub completed: u32,
+	/// When the token expires, in milliseconds since the unix epoch
+	pub expiry_time: Option<u64>,
+}
+
+impl RegistrationToken {
+	/// Whether the token can be used for another registration at `now`
+	#[must_use]
+	pub fn is_valid(&self, now: u64) -> bool {
+		self.expiry_time
+			.map_or(true, |expiry_time| expiry_time > now)
+			&& self
+				.uses_allowed
+				.map_or(true, |uses_allowed| self.pending.saturating_add(self.completed) < uses_allowed)
+	}
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		let config = &server.config;
+
+		Ok(Self {
+			db: Data::new(db),
+			config_token: config.registration_token.clone(),
+			requires_token: config.registration_token.is_some() || config.registration_requires_token,
+			pending: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// Whether registering needs a token
+	pub fn required(&self) -> bool { self.requires_token }
+
+	/// Creates a token, with a random one of `length` characters if `token` is
+	/// not given.
+	pub fn create(
+		&self, token: Option<String>, length: Option<usize>, uses_allowed: Option<u32>, expiry_time: Option<u64>,
+	) -> Result<RegistrationToken> {
+		let token = match token {
+			Some(token) => {
+				if !is_valid_token(&token) {
+					return Err(Error::BadRequest(
+						ErrorKind::InvalidParam,
+						"Tokens must be 1 to 64 characters from A-Z, a-z, 0-9, '.', '_', '~' and '-'.",
+					));
+				}
+
+				token
+			},
+			None => {
+				let length = length.unwrap_or(DEFAULT_TOKEN_LENGTH);
+				if length == 0 || length > MAX_TOKEN_LENGTH {
+					return Err(Error::BadRequest(
+						ErrorKind::InvalidParam,
+						"Token length must be between 1 and 64.",
+					));
+				}
+
+				utils::random_string(length)
+			},
+		};
+
+		if expiry_time.is_some_and(|expiry_time| expiry_time <= utils::millis_since_unix_epoch()) {
+			return Err(Error::BadRequest(ErrorKind::InvalidParam, "Expiry time is in the past."));
+		}
+
+		let _pending = self.pending.lock().expect("locked");
+		if self.config_token.as_ref() == Some(&token) || self.db.get(&token)?.is_some() {
+			return Err(Error::BadRequest(ErrorKind::InvalidParam, "Token already exists."));
+		}
+
+		let info = TokenInfo {
+			uses_allowed,
+			completed: 0,
+			expiry_time,
+		};
+		self.db.set(&token, &info)?;
+
+		Ok(RegistrationToken {
+			token,
+			uses_allowed,
+			pending: 0,
+			completed: 0,
+			expiry_time,
+		})
+	}
+
+	pub fn get(&self, token: &str) -> Result<Option<RegistrationToken>> {
+		let pending = self.pending.lock().expect("locked");
+
+		Ok(self
+			.db
+			.get(token)?
+			.map(|info| registration_token(token.to_owned(), info, &pending)))
+	}
+
+	/// Returns all tokens created by admins. The token from the config is not
+	/// included.
+	pub fn list(&self) -> Result<Vec<RegistrationToken>> {
+		let pending = self.pending.lock().expect("locked");
+
+		self.db
+			.iter()
+			.map(|result| result.map(|(token, info)| registration_token(token, info, &pending)))
+			.collect()
+	}
+
+	/// Changes the limits of a token. `None` leaves a limit unchanged,
+	/// `Some(None)` removes it.
+	pub fn update(
+		&self, token: &str, uses_allowed: Option<Option<u32>>, expiry_time: Option<Option<u64>>,
+	) -> Result<RegistrationToken> {
+		let pending = self.pending.lock().expect("locked");
+
+		let mut info = self
+			.db
+			.get(token)?
+			.ok_or(Error::BadRequest(ErrorKind::NotFound, "Registration token not found."))?;
+
+		if let Some(uses_allowed) = uses_allowed {
+			info.uses_allowed = uses_allowed;
+		}
+		if let Some(expiry_time) = expiry_time {
+			info.expiry_time = expiry_time;
+		}
+		self.db.set(token, &info)?;
+
+		Ok(registration_token(token.to_owned(), info, &pending))
+	}
+
+	/// Deletes a token. Returns whether it existed.
+	pub fn revoke(&self, token: &str) -> Result<bool> {
+		let _pending = self.pending.lock().expect("locked");
+		if self.db.get(token)?.is_none() {
+			return Ok(false);
+		}
+
+		self.db.remove(token)?;
+
+		Ok(true)
+	}
+
+	/// Whether a token can be used to register now.
+	pub fn is_valid(&self, token: &str) -> Result<bool> {
+		if self.config_token.as_deref() == Some(token) {
+			return Ok(true);
+		}
+
+		Ok(self
+			.get(token)?
+			.is_some_and(|token| token.is_valid(utils::millis_since_unix_epoch())))
+	}
+
+	/// Counts a registration in the UIAA `session` as pending on the token, if
+	/// it can be used. Returns whether the token was accepted.
+	pub fn reserve(&self, token: &str, session: &str) -> Result<bool> {
+		if self.config_token.as_deref() == Some(token) {
+			return Ok(true);
+		}
+
+		let mut pending = self.pending.lock().expect("locked");
+		pending.retain(|_, (_, reserved)| reserved.elapsed() < PENDING_TIMEOUT);
+		pending.remove(session);
+
+		let valid = self.db.get(token)?.is_some_and(|info| {
+			registration_token(token.to_owned(), info, &pending).is_valid(utils::millis_since_unix_epoch())
+		});
+
+		if valid {
+			pending.insert(session.to_owned(), (token.to_owned(), Instant::now()));
+		}
+
+		Ok(valid)
+	}
+
+	/// Counts the token reserved by the UIAA `session` as used, once the
+	/// account was created.
+	pub fn complete(&self, session: &str) -> Result<()> {
+		let mut pending = self.pending.lock().expect("locked");
+		let Some((token, _)) = pending.remove(session) else {
+			return Ok(());
+		};
+
+		if let Some(mut info) = self.db.get(&token)? {
+			info.completed = info.completed.saturating_add(1);
+			self.db.set(&token, &info)?;
+		}
+
+		Ok(())
+	}
+}
+
+fn registration_token(
+	token: String, info: TokenInfo, pending: &HashMap<String, (String, Instant)>,
+) -> RegistrationToken {
+	let pending = pending
+		.values()
+		.filter(|(pending, reserved)| *pending == token && reserved.elapsed() < PENDING_TIMEOUT)
+		.count();
+
+	RegistrationToken {
+		token,
+		uses_allowed: info.uses_allowed,
+		pending: u32::try_from(pending).unwrap_or(u32::MAX),
+		completed: info.completed,
+		expiry_time: info.expiry_time,
+	}
+}
+
+/// Tokens are limited to the characters allowed by the spec.
+fn is_valid_token(token: &str) -> bool {
+	!token.is_empty()
+		&& token.len() <= MAX_TOKEN_LENGTH
+		&& token
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '~' | '-'))
+}
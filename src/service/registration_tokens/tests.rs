@@ -0,0 +1,31 @@
+#![cfg(test)]
+
+use super::{is_valid_token, RegistrationToken};
+
+fn token(uses_allowed: Option<u32>, pending: u32, completed: u32, expiry_time: Option<u64>) -> RegistrationToken {
+	RegistrationToken {
+		token: "abcd".to_owned(),
+		uses_allowed,
+		pending,
+		completed,
+		expiry_time,
+	}
+}
+
+#[test]
+fn token_characters() {
+	assert!(is_valid_token("Abc-123_.~"));
+	assert!(!is_valid_token(""));
+	assert!(!is_valid_token("has space"));
+	assert!(!is_valid_token("ünicode"));
+	assert!(!is_valid_token(&"a".repeat(65)));
+}
+
+#[test]
+fn token_validity() {
+	assert!(token(None, 5, 100, None).is_valid(1000));
+	assert!(token(Some(1), 0, 0, Some(1001)).is_valid(1000));
+	assert!(!token(Some(1), 1, 0, None).is_valid(1000));
+	assert!(!token(Some(2), 1, 1, None).is_valid(1000));
+	assert!(!token(None, 0, 0, Some(1000)).is_valid(1000));
+}
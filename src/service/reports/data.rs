@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use conduit::{Error, Result};
+use database::{Database, Map};
+
+use super::Report;
+
+pub(super) struct Data {
+	reportid_report: Arc<Map>,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			reportid_report: db["reportid_report"].clone(),
+		}
+	}
+
+	pub(super) fn get(&self, id: u64) -> Result<Option<Report>> {
+		self.reportid_report
+			.get(&id.to_be_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid report in reportid_report."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set(&self, report: &Report) -> Result<()> {
+		self.reportid_report.insert(
+			&report.id.to_be_bytes(),
+			&serde_json::to_vec(report).expect("Report::to_vec always works"),
+		)
+	}
+
+	/// Iterates over all reports, oldest first
+	pub(super) fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Report>> + 'a> {
+		Box::new(self.reportid_report.iter().map(|(_, value)| {
+			serde_json::from_slice(&value).map_err(|_| Error::bad_database("Invalid report in reportid_report."))
+		}))
+	}
+}
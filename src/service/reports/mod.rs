@@ -0,0 +1,215 @@
+mod data;
+mod tests;
+
+use std::{fmt, sync::Arc};
+
+use conduit::{utils, Error, Result, Server};
+use data::Data;
+use database::Database;
+use ruma::{api::client::error::ErrorKind, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::services;
+
+/// Stores reports of events, rooms and users for the server admins to handle.
+pub struct Service {
+	db: Data,
+}
+
+/// A report by a user, and how an admin handled it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	pub id: u64,
+	pub reporter: OwnedUserId,
+	pub target: ReportTarget,
+	pub reason: Option<String>,
+	/// Score from -100 (most offensive) to 0, for event reports
+	pub score: Option<i64>,
+	/// When the report was received, in milliseconds since the unix epoch
+	pub received_at: u64,
+	pub state: ReportState,
+	/// When the report was resolved or dismissed, in milliseconds since the
+	/// unix epoch
+	pub handled_at: Option<u64>,
+	/// Note of the admin handling the report
+	pub note: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportTarget {
+	Event {
+		room_id: OwnedRoomId,
+		event_id: OwnedEventId,
+		sender: OwnedUserId,
+	},
+	Room {
+		room_id: OwnedRoomId,
+	},
+	User {
+		user_id: OwnedUserId,
+	},
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportState {
+	Open,
+	Resolved,
+	Dismissed,
+}
+
+/// Selects reports to list or handle in bulk; unset fields match any report.
+#[derive(Debug, Default)]
+pub struct ReportFilter {
+	pub state: Option<ReportState>,
+	/// Reported room, or room of the reported event
+	pub room_id: Option<OwnedRoomId>,
+	/// Reported user, or sender of the reported event
+	pub user_id: Option<OwnedUserId>,
+	pub reporter: Option<OwnedUserId>,
+}
+
+impl Report {
+	/// The reported room, or the room of the reported event
+	#[must_use]
+	pub fn room_id(&self) -> Option<&RoomId> {
+		match &self.target {
+			ReportTarget::Event {
+				room_id,
+				..
+			}
+			| ReportTarget::Room {
+				room_id,
+			} => Some(room_id),
+			ReportTarget::User {
+				..
+			} => None,
+		}
+	}
+
+	/// The reported user, or the sender of the reported event
+	#[must_use]
+	pub fn user_id(&self) -> Option<&UserId> {
+		match &self.target {
+			ReportTarget::Event {
+				sender,
+				..
+			} => Some(sender),
+			ReportTarget::User {
+				user_id,
+			} => Some(user_id),
+			ReportTarget::Room {
+				..
+			} => None,
+		}
+	}
+}
+
+impl fmt::Display for ReportTarget {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Event {
+				room_id,
+				event_id,
+				sender,
+			} => write!(f, "event {event_id} by {sender} in {room_id}"),
+			Self::Room {
+				room_id,
+			} => write!(f, "room {room_id}"),
+			Self::User {
+				user_id,
+			} => write!(f, "user {user_id}"),
+		}
+	}
+}
+
+impl fmt::Display for ReportState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Open => "open",
+			Self::Resolved => "resolved",
+			Self::Dismissed => "dismissed",
+		})
+	}
+}
+
+impl ReportFilter {
+	/// Whether the filter selects the report
+	#[must_use]
+	pub fn matches(&self, report: &Report) -> bool {
+		self.state.map_or(true, |state| report.state == state)
+			&& self
+				.room_id
+				.as_deref()
+				.map_or(true, |room_id| report.room_id() == Some(room_id))
+			&& self
+				.user_id
+				.as_deref()
+				.map_or(true, |user_id| report.user_id() == Some(user_id))
+			&& self
+				.reporter
+				.as_ref()
+				.map_or(true, |reporter| report.reporter == *reporter)
+	}
+}
+
+impl Service {
+	pub fn build(_server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		Ok(Self {
+			db: Data::new(db),
+		})
+	}
+
+	/// Stores a new open report
+	pub fn add(
+		&self, reporter: &UserId, target: ReportTarget, reason: Option<String>, score: Option<i64>,
+	) -> Result<Report> {
+		let report = Report {
+			id: services().globals.next_count()?,
+			reporter: reporter.to_owned(),
+			target,
+			reason,
+			score,
+			received_at: utils::millis_since_unix_epoch(),
+			state: ReportState::Open,
+			handled_at: None,
+			note: None,
+		};
+		self.db.set(&report)?;
+
+		Ok(report)
+	}
+
+	pub fn get(&self, id: u64) -> Result<Option<Report>> { self.db.get(id) }
+
+	/// Reports selected by the filter, oldest first
+	pub fn list(&self, filter: &ReportFilter) -> Result<Vec<Report>> {
+		self.db
+			.iter()
+			.filter(|report| {
+				report
+					.as_ref()
+					.map_or(true, |report| filter.matches(report))
+			})
+			.collect()
+	}
+
+	/// Resolves, dismisses or reopens a report, keeping the previous note if
+	/// none is given
+	pub fn set_state(&self, id: u64, state: ReportState, note: Option<String>) -> Result<Report> {
+		let mut report = self
+			.db
+			.get(id)?
+			.ok_or(Error::BadRequest(ErrorKind::NotFound, "Report not found."))?;
+
+		report.state = state;
+		report.handled_at = (state != ReportState::Open).then(utils::millis_since_unix_epoch);
+		if note.is_some() {
+			report.note = note;
+		}
+		self.db.set(&report)?;
+
+		Ok(report)
+	}
+}
@@ -0,0 +1,67 @@
+#![cfg(test)]
+
+use ruma::{owned_event_id, owned_room_id, owned_user_id};
+
+use super::{Report, ReportFilter, ReportState, ReportTarget};
+
+fn report(target: ReportTarget, state: ReportState) -> Report {
+	Report {
+		id: 1,
+		reporter: owned_user_id!("@reporter:example.com"),
+		target,
+		reason: None,
+		score: None,
+		received_at: 0,
+		state,
+		handled_at: None,
+		note: None,
+	}
+}
+
+#[test]
+fn filter_matches() {
+	let event = report(
+		ReportTarget::Event {
+			room_id: owned_room_id!("!room:example.com"),
+			event_id: owned_event_id!("$event"),
+			sender: owned_user_id!("@spammer:example.com"),
+		},
+		ReportState::Open,
+	);
+	let room = report(
+		ReportTarget::Room {
+			room_id: owned_room_id!("!room:example.com"),
+		},
+		ReportState::Resolved,
+	);
+	let user = report(
+		ReportTarget::User {
+			user_id: owned_user_id!("@spammer:example.com"),
+		},
+		ReportState::Open,
+	);
+
+	let by_room = ReportFilter {
+		room_id: Some(owned_room_id!("!room:example.com")),
+		..Default::default()
+	};
+	assert!(by_room.matches(&event));
+	assert!(by_room.matches(&room));
+	assert!(!by_room.matches(&user));
+
+	let open_by_user = ReportFilter {
+		state: Some(ReportState::Open),
+		user_id: Some(owned_user_id!("@spammer:example.com")),
+		..Default::default()
+	};
+	assert!(open_by_user.matches(&event));
+	assert!(!open_by_user.matches(&room));
+	assert!(open_by_user.matches(&user));
+
+	let by_reporter = ReportFilter {
+		reporter: Some(owned_user_id!("@other:example.com")),
+		..Default::default()
+	};
+	assert!(!by_reporter.matches(&event));
+	assert!(ReportFilter::default().matches(&event));
+}
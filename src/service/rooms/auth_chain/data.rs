@@ -7,6 +7,8 @@ use conduit::{utils, Result, Server};
 use database::{Database, Map};
 use lru_cache::LruCache;
 
+use crate::services;
+
 pub(super) struct Data {
 	shorteventid_authchain: Arc<Map>,
 	pub(super) auth_chain_cache: Mutex<LruCache<Vec<u64>, Arc<[u64]>>>,
@@ -26,8 +28,10 @@ impl Data {
 	pub(super) fn get_cached_eventid_authchain(&self, key: &[u64]) -> Result<Option<Arc<[u64]>>> {
 		// Check RAM cache
 		if let Some(result) = self.auth_chain_cache.lock().unwrap().get_mut(key) {
+			services().server.metrics.cache_lookup("auth_chain", true);
 			return Ok(Some(Arc::clone(result)));
 		}
+		services().server.metrics.cache_lookup("auth_chain", false);
 
 		// We only save auth chains for single events in the db
 		if key.len() == 1 {
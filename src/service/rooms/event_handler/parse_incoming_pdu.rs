@@ -1,9 +1,11 @@
 use conduit::{Error, Result};
-use ruma::{api::client::error::ErrorKind, CanonicalJsonObject, OwnedEventId, OwnedRoomId, RoomId};
+use ruma::{
+	api::client::error::ErrorKind, CanonicalJsonObject, OwnedEventId, OwnedRoomId, RoomId, RoomVersionId, UserId,
+};
 use serde_json::value::RawValue as RawJsonValue;
 use tracing::warn;
 
-use crate::{pdu::gen_event_id_canonical_json, services};
+use crate::{pdu::gen_event_id_canonical_json, services, user_is_local};
 
 pub fn parse_incoming_pdu(pdu: &RawJsonValue) -> Result<(OwnedEventId, CanonicalJsonObject, OwnedRoomId)> {
 	let value: CanonicalJsonObject = serde_json::from_str(pdu.get()).map_err(|e| {
@@ -16,8 +18,10 @@ pub fn parse_incoming_pdu(pdu: &RawJsonValue) -> Result<(OwnedEventId, Canonical
 		.and_then(|id| RoomId::parse(id.as_str()?).ok())
 		.ok_or(Error::BadRequest(ErrorKind::InvalidParam, "Invalid room id in pdu"))?;
 
-	let Ok(room_version_id) = services().rooms.state.get_room_version(&room_id) else {
-		return Err(Error::Err(format!("Server is not in room {room_id}")));
+	let room_version_id = match services().rooms.state.get_room_version(&room_id) {
+		Ok(room_version_id) => room_version_id,
+		Err(_) => knocked_room_version(&value, &room_id)?
+			.ok_or_else(|| Error::Err(format!("Server is not in room {room_id}")))?,
 	};
 
 	let Ok((event_id, value)) = gen_event_id_canonical_json(pdu, &room_version_id) else {
@@ -30,3 +34,23 @@ pub fn parse_incoming_pdu(pdu: &RawJsonValue) -> Result<(OwnedEventId, Canonical
 
 	Ok((event_id, value, room_id))
 }
+
+/// A room we only knocked on isn't known, but sends us the rejection of the
+/// knock. Its version is in the stripped state we got back when knocking.
+fn knocked_room_version(value: &CanonicalJsonObject, room_id: &RoomId) -> Result<Option<RoomVersionId>> {
+	let Some(user_id) = value
+		.get("state_key")
+		.and_then(|state_key| UserId::parse(state_key.as_str()?).ok())
+	else {
+		return Ok(None);
+	};
+
+	if !user_is_local(&user_id) || !services().rooms.state_cache.is_knocked(&user_id, room_id)? {
+		return Ok(None);
+	}
+
+	services()
+		.rooms
+		.state_cache
+		.knocked_room_version(&user_id, room_id)
+}
@@ -6,6 +6,7 @@ pub mod lazy_loading;
 pub mod metadata;
 pub mod outlier;
 pub mod pdu_metadata;
+pub mod purge;
 pub mod read_receipt;
 pub mod search;
 pub mod short;
@@ -28,6 +29,7 @@ pub struct Service {
 	pub metadata: metadata::Service,
 	pub outlier: outlier::Service,
 	pub pdu_metadata: pdu_metadata::Service,
+	pub purge: purge::Service,
 	pub read_receipt: read_receipt::Service,
 	pub search: search::Service,
 	pub short: short::Service,
@@ -31,6 +31,27 @@ impl Data {
 		Ok(())
 	}
 
+	/// Removes relations pointing at `target` and the relation from `target`
+	/// to the event it relates to, if any.
+	pub(super) fn remove_relations(&self, target: u64, relates_to: Option<u64>) -> Result<()> {
+		let keys: Vec<_> = self
+			.tofrom_relation
+			.scan_prefix(target.to_be_bytes().to_vec())
+			.map(|(key, _)| key)
+			.collect();
+
+		self.tofrom_relation
+			.remove_batch(keys.iter().map(Vec::as_slice))?;
+
+		if let Some(to) = relates_to {
+			let mut key = to.to_be_bytes().to_vec();
+			key.extend_from_slice(&target.to_be_bytes());
+			self.tofrom_relation.remove(&key)?;
+		}
+
+		Ok(())
+	}
+
 	pub(super) fn relations_until<'a>(
 		&'a self, user_id: &'a UserId, shortroomid: u64, target: u64, until: PduCount,
 	) -> Result<PdusIterator<'a>> {
@@ -47,6 +47,23 @@ impl Service {
 		}
 	}
 
+	/// Removes the relations of a pdu which is being deleted: those of other
+	/// pdus to it and its own relation to `relates_to`.
+	#[tracing::instrument(skip(self))]
+	pub fn remove_relations(&self, count: PduCount, relates_to: Option<PduCount>) -> Result<()> {
+		let PduCount::Normal(count) = count else {
+			// relations with backfilled pdus are never added
+			return Ok(());
+		};
+
+		let relates_to = match relates_to {
+			Some(PduCount::Normal(to)) => Some(to),
+			_ => None,
+		};
+
+		self.db.remove_relations(count, relates_to)
+	}
+
 	#[allow(clippy::too_many_arguments)]
 	pub fn paginate_relations_with_filter(
 		&self, sender_user: &UserId, room_id: &RoomId, target: &EventId, filter_event_type: &Option<TimelineEventType>,
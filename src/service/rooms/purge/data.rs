@@ -0,0 +1,385 @@
+use std::{
+	collections::{BTreeSet, HashSet},
+	mem::size_of,
+	sync::Arc,
+};
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use ruma::{OwnedEventId, OwnedRoomId, RoomId};
+use serde::Deserialize;
+
+use crate::{rooms::timeline::pdu_count, PduCount, PduEvent};
+
+pub(super) struct Data {
+	db: Arc<Database>,
+	aliasid_alias: Arc<Map>,
+	alias_roomid: Arc<Map>,
+	alias_userid: Arc<Map>,
+	eventid_outlierpdu: Arc<Map>,
+	eventid_pduid: Arc<Map>,
+	eventid_shorteventid: Arc<Map>,
+	lazyloadedids: Arc<Map>,
+	pduid_pdu: Arc<Map>,
+	publicroomids: Arc<Map>,
+	readreceiptid_readreceipt: Arc<Map>,
+	referencedevents: Arc<Map>,
+	roomid_invitedcount: Arc<Map>,
+	roomid_inviteviaservers: Arc<Map>,
+	roomid_joinedcount: Arc<Map>,
+	roomid_pduleaves: Arc<Map>,
+	roomid_shortroomid: Arc<Map>,
+	roomid_shortstatehash: Arc<Map>,
+	roomserverids: Arc<Map>,
+	roomsynctoken_shortstatehash: Arc<Map>,
+	roomuserdataid_accountdata: Arc<Map>,
+	roomuserid_invitecount: Arc<Map>,
+	roomuserid_knockedcount: Arc<Map>,
+	roomuserid_joined: Arc<Map>,
+	roomuserid_lastprivatereadupdate: Arc<Map>,
+	roomuserid_leftcount: Arc<Map>,
+	roomuserid_privateread: Arc<Map>,
+	roomuseroncejoinedids: Arc<Map>,
+	roomusertype_roomuserdataid: Arc<Map>,
+	serverroomids: Arc<Map>,
+	shorteventid_authchain: Arc<Map>,
+	shorteventid_eventid: Arc<Map>,
+	shorteventid_shortstatehash: Arc<Map>,
+	shortstatehash_statediff: Arc<Map>,
+	softfailedeventids: Arc<Map>,
+	statehash_shortstatehash: Arc<Map>,
+	threadid_userids: Arc<Map>,
+	tofrom_relation: Arc<Map>,
+	tokenids: Arc<Map>,
+	userroomid_highlightcount: Arc<Map>,
+	userroomid_invitestate: Arc<Map>,
+	userroomid_knockedstate: Arc<Map>,
+	userroomid_joined: Arc<Map>,
+	userroomid_leftstate: Arc<Map>,
+	userroomid_notificationcount: Arc<Map>,
+}
+
+#[derive(Deserialize)]
+struct ExtractRoomId {
+	room_id: OwnedRoomId,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			db: db.clone(),
+			aliasid_alias: db["aliasid_alias"].clone(),
+			alias_roomid: db["alias_roomid"].clone(),
+			alias_userid: db["alias_userid"].clone(),
+			eventid_outlierpdu: db["eventid_outlierpdu"].clone(),
+			eventid_pduid: db["eventid_pduid"].clone(),
+			eventid_shorteventid: db["eventid_shorteventid"].clone(),
+			lazyloadedids: db["lazyloadedids"].clone(),
+			pduid_pdu: db["pduid_pdu"].clone(),
+			publicroomids: db["publicroomids"].clone(),
+			readreceiptid_readreceipt: db["readreceiptid_readreceipt"].clone(),
+			referencedevents: db["referencedevents"].clone(),
+			roomid_invitedcount: db["roomid_invitedcount"].clone(),
+			roomid_inviteviaservers: db["roomid_inviteviaservers"].clone(),
+			roomid_joinedcount: db["roomid_joinedcount"].clone(),
+			roomid_pduleaves: db["roomid_pduleaves"].clone(),
+			roomid_shortroomid: db["roomid_shortroomid"].clone(),
+			roomid_shortstatehash: db["roomid_shortstatehash"].clone(),
+			roomserverids: db["roomserverids"].clone(),
+			roomsynctoken_shortstatehash: db["roomsynctoken_shortstatehash"].clone(),
+			roomuserdataid_accountdata: db["roomuserdataid_accountdata"].clone(),
+			roomuserid_invitecount: db["roomuserid_invitecount"].clone(),
+			roomuserid_knockedcount: db["roomuserid_knockedcount"].clone(),
+			roomuserid_joined: db["roomuserid_joined"].clone(),
+			roomuserid_lastprivatereadupdate: db["roomuserid_lastprivatereadupdate"].clone(),
+			roomuserid_leftcount: db["roomuserid_leftcount"].clone(),
+			roomuserid_privateread: db["roomuserid_privateread"].clone(),
+			roomuseroncejoinedids: db["roomuseroncejoinedids"].clone(),
+			roomusertype_roomuserdataid: db["roomusertype_roomuserdataid"].clone(),
+			serverroomids: db["serverroomids"].clone(),
+			shorteventid_authchain: db["shorteventid_authchain"].clone(),
+			shorteventid_eventid: db["shorteventid_eventid"].clone(),
+			shorteventid_shortstatehash: db["shorteventid_shortstatehash"].clone(),
+			shortstatehash_statediff: db["shortstatehash_statediff"].clone(),
+			softfailedeventids: db["softfailedeventids"].clone(),
+			statehash_shortstatehash: db["statehash_shortstatehash"].clone(),
+			threadid_userids: db["threadid_userids"].clone(),
+			tofrom_relation: db["tofrom_relation"].clone(),
+			tokenids: db["tokenids"].clone(),
+			userroomid_highlightcount: db["userroomid_highlightcount"].clone(),
+			userroomid_invitestate: db["userroomid_invitestate"].clone(),
+			userroomid_knockedstate: db["userroomid_knockedstate"].clone(),
+			userroomid_joined: db["userroomid_joined"].clone(),
+			userroomid_leftstate: db["userroomid_leftstate"].clone(),
+			userroomid_notificationcount: db["userroomid_notificationcount"].clone(),
+		}
+	}
+
+	/// Calls `f` for every pdu in the room's timeline.
+	pub(super) fn for_each_timeline_pdu<F>(&self, shortroomid: u64, mut f: F) -> Result<()>
+	where
+		F: FnMut(PduEvent),
+	{
+		for (_, value) in self
+			.pduid_pdu
+			.scan_prefix(shortroomid.to_be_bytes().to_vec())
+		{
+			f(serde_json::from_slice(&value).map_err(|_| Error::bad_database("Invalid PDU in db."))?);
+		}
+
+		Ok(())
+	}
+
+	/// Calls `f` for every outlier belonging to the room. Outliers aren't keyed
+	/// by room so this has to look at all of them.
+	pub(super) fn for_each_outlier_pdu<F>(&self, room_id: &RoomId, mut f: F) -> Result<()>
+	where
+		F: FnMut(PduEvent),
+	{
+		for (_, value) in self.eventid_outlierpdu.iter() {
+			let Ok(ExtractRoomId {
+				room_id: outlier_room_id,
+			}) = serde_json::from_slice(&value)
+			else {
+				continue;
+			};
+
+			if outlier_room_id == room_id {
+				f(serde_json::from_slice(&value).map_err(|_| Error::bad_database("Invalid PDU in db."))?);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Removes the timeline and everything keyed by the room's pdu ids: the
+	/// search index, thread roots and relations.
+	pub(super) fn remove_timeline(&self, shortroomid: u64) -> Result<()> {
+		let _cork = self.db.cork_and_flush();
+		let prefix = shortroomid.to_be_bytes().to_vec();
+
+		let pdu_ids: Vec<_> = self
+			.pduid_pdu
+			.scan_prefix(prefix.clone())
+			.map(|(key, _)| key)
+			.collect();
+
+		for pdu_id in &pdu_ids {
+			// relations aren't recorded for backfilled pdus
+			if let Ok(PduCount::Normal(count)) = pdu_count(pdu_id) {
+				remove_prefix(&self.tofrom_relation, count.to_be_bytes().to_vec())?;
+			}
+		}
+
+		self.pduid_pdu
+			.remove_batch(pdu_ids.iter().map(Vec::as_slice))?;
+		remove_prefix(&self.tokenids, prefix.clone())?;
+		remove_prefix(&self.threadid_userids, prefix)
+	}
+
+	/// Removes the event id mappings of the room's events and their short
+	/// event ids. Returns the state hashes which were attached to the events.
+	pub(super) fn remove_events(&self, event_ids: &[OwnedEventId]) -> Result<HashSet<u64>> {
+		let _cork = self.db.cork_and_flush();
+		let mut shortstatehashes = HashSet::new();
+
+		for event_id in event_ids {
+			let key = event_id.as_bytes();
+			self.eventid_pduid.remove(key)?;
+			self.eventid_outlierpdu.remove(key)?;
+			self.softfailedeventids.remove(key)?;
+
+			let Some(shorteventid) = self.eventid_shorteventid.get(key)? else {
+				continue;
+			};
+
+			let shorteventid = shorteventid.to_vec();
+			if let Some(shortstatehash) = self.shorteventid_shortstatehash.get(&shorteventid)? {
+				shortstatehashes.insert(
+					utils::u64_from_bytes(&shortstatehash)
+						.map_err(|_| Error::bad_database("Invalid shortstatehash in db."))?,
+				);
+			}
+
+			self.shorteventid_shortstatehash.remove(&shorteventid)?;
+			self.shorteventid_authchain.remove(&shorteventid)?;
+			self.shorteventid_eventid.remove(&shorteventid)?;
+			self.eventid_shorteventid.remove(key)?;
+		}
+
+		Ok(shortstatehashes)
+	}
+
+	/// Removes every state snapshot of the room. `shortstatehashes` are the
+	/// ones referenced by events; the room's current state, the sync token
+	/// snapshots and all of their parents are found here. Returns the number of
+	/// snapshots removed.
+	pub(super) fn remove_state(
+		&self, room_id: &RoomId, shortroomid: Option<u64>, mut shortstatehashes: HashSet<u64>,
+	) -> Result<usize> {
+		let _cork = self.db.cork_and_flush();
+
+		if let Some(current) = self.roomid_shortstatehash.get(room_id.as_bytes())? {
+			shortstatehashes.insert(
+				utils::u64_from_bytes(&current).map_err(|_| Error::bad_database("Invalid shortstatehash in db."))?,
+			);
+		}
+
+		if let Some(shortroomid) = shortroomid {
+			let prefix = shortroomid.to_be_bytes().to_vec();
+			for (_, value) in self
+				.roomsynctoken_shortstatehash
+				.scan_prefix(prefix.clone())
+			{
+				shortstatehashes.insert(
+					utils::u64_from_bytes(&value).map_err(|_| Error::bad_database("Invalid shortstatehash in db."))?,
+				);
+			}
+
+			remove_prefix(&self.roomsynctoken_shortstatehash, prefix)?;
+		}
+
+		// follow every snapshot's chain of parents to the room's first state
+		let mut pending: Vec<_> = shortstatehashes.iter().copied().collect();
+		let mut removed = BTreeSet::new();
+		while let Some(shortstatehash) = pending.pop() {
+			if !removed.insert(shortstatehash) {
+				continue;
+			}
+
+			let key = shortstatehash.to_be_bytes();
+			let Some(diff) = self.shortstatehash_statediff.get(&key)? else {
+				continue;
+			};
+
+			let parent = diff
+				.get(..size_of::<u64>())
+				.map(utils::u64_from_bytes)
+				.transpose()
+				.map_err(|_| Error::bad_database("Invalid parent in shortstatehash_statediff."))?
+				.filter(|&parent| parent != 0);
+
+			pending.extend(parent);
+			self.shortstatehash_statediff.remove(&key)?;
+		}
+
+		// the state hash itself can't be derived from the short id so the
+		// reverse mapping has to be searched
+		let statehashes: Vec<_> = self
+			.statehash_shortstatehash
+			.iter()
+			.filter(|(_, value)| {
+				utils::u64_from_bytes(value).is_ok_and(|shortstatehash| removed.contains(&shortstatehash))
+			})
+			.map(|(key, _)| key)
+			.collect();
+
+		self.statehash_shortstatehash
+			.remove_batch(statehashes.iter().map(Vec::as_slice))?;
+		self.roomid_shortstatehash.remove(room_id.as_bytes())?;
+		remove_prefix(&self.roomid_pduleaves, room_prefix(room_id))?;
+
+		Ok(removed.len())
+	}
+
+	/// Removes membership, receipts, account data, aliases and everything else
+	/// keyed by the room id.
+	pub(super) fn remove_room_data(&self, room_id: &RoomId) -> Result<()> {
+		let _cork = self.db.cork_and_flush();
+		let prefix = room_prefix(room_id);
+
+		for map in [
+			&self.readreceiptid_readreceipt,
+			&self.roomuserid_privateread,
+			&self.roomuserid_lastprivatereadupdate,
+			&self.roomuserid_joined,
+			&self.roomuserid_invitecount,
+			&self.roomuserid_knockedcount,
+			&self.roomuserid_leftcount,
+			&self.roomserverids,
+			&self.roomuserdataid_accountdata,
+			&self.roomusertype_roomuserdataid,
+		] {
+			remove_prefix(map, prefix.clone())?;
+		}
+
+		// keys starting with another id and ending with the room id
+		let mut suffix = vec![0xFF];
+		suffix.extend_from_slice(room_id.as_bytes());
+		for map in [
+			&self.userroomid_joined,
+			&self.userroomid_invitestate,
+			&self.userroomid_knockedstate,
+			&self.userroomid_leftstate,
+			&self.userroomid_notificationcount,
+			&self.userroomid_highlightcount,
+			&self.roomuseroncejoinedids,
+			&self.serverroomids,
+		] {
+			remove_where(map, |key| key.ends_with(&suffix))?;
+		}
+
+		// userid 0xFF deviceid 0xFF roomid 0xFF lazyloadeduserid
+		remove_where(&self.lazyloadedids, |key| {
+			key.split(|&b| b == 0xFF).nth(2) == Some(room_id.as_bytes())
+		})?;
+
+		// roomid eventid, without a separator; event ids always start with `$`
+		let mut referenced = room_id.as_bytes().to_vec();
+		referenced.push(b'$');
+		remove_prefix(&self.referencedevents, referenced)?;
+
+		for (key, alias) in self.aliasid_alias.scan_prefix(prefix.clone()) {
+			if let Some((localpart, _)) = alias.get(1..).and_then(|alias| {
+				alias
+					.iter()
+					.position(|&b| b == b':')
+					.map(|pos| alias.split_at(pos))
+			}) {
+				self.alias_roomid.remove(localpart)?;
+				self.alias_userid.remove(localpart)?;
+			}
+
+			self.aliasid_alias.remove(&key)?;
+		}
+
+		for map in [
+			&self.roomid_joinedcount,
+			&self.roomid_invitedcount,
+			&self.roomid_inviteviaservers,
+			&self.publicroomids,
+		] {
+			map.remove(room_id.as_bytes())?;
+		}
+
+		Ok(())
+	}
+
+	pub(super) fn remove_shortroomid(&self, room_id: &RoomId) -> Result<()> {
+		self.roomid_shortroomid.remove(room_id.as_bytes())
+	}
+}
+
+fn room_prefix(room_id: &RoomId) -> Vec<u8> {
+	let mut prefix = room_id.as_bytes().to_vec();
+	prefix.push(0xFF);
+	prefix
+}
+
+fn remove_prefix(map: &Map, prefix: Vec<u8>) -> Result<()> {
+	let keys: Vec<_> = map.scan_prefix(prefix).map(|(key, _)| key).collect();
+	map.remove_batch(keys.iter().map(Vec::as_slice))
+}
+
+fn remove_where<F>(map: &Map, filter: F) -> Result<()>
+where
+	F: Fn(&[u8]) -> bool,
+{
+	let keys: Vec<_> = map
+		.iter()
+		.map(|(key, _)| key)
+		.filter(|key| filter(key))
+		.collect();
+
+	map.remove_batch(keys.iter().map(Vec::as_slice))
+}
@@ -0,0 +1,207 @@
+mod data;
+mod tests;
+
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use conduit::{debug_warn, Error, Result, Server};
+use data::Data;
+use database::Database;
+use ruma::{events::TimelineEventType, OwnedMxcUri, OwnedRoomId, RoomId};
+use serde::Deserialize;
+
+use crate::{server_is_ours, services, PduEvent};
+
+pub struct Service {
+	server: Arc<Server>,
+	db: Arc<Data>,
+}
+
+#[derive(Default)]
+pub struct PurgeSummary {
+	pub events: usize,
+	pub state_snapshots: usize,
+	pub media: usize,
+}
+
+#[derive(Deserialize)]
+struct ExtractMedia {
+	url: Option<OwnedMxcUri>,
+	file: Option<ExtractUrl>,
+	info: Option<ExtractInfo>,
+}
+
+#[derive(Deserialize)]
+struct ExtractUrl {
+	url: OwnedMxcUri,
+}
+
+#[derive(Deserialize)]
+struct ExtractInfo {
+	thumbnail_url: Option<OwnedMxcUri>,
+	thumbnail_file: Option<ExtractUrl>,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		Ok(Self {
+			server: server.clone(),
+			db: Arc::new(Data::new(db)),
+		})
+	}
+
+	/// Deletes everything stored about a room: its timeline and outliers,
+	/// state snapshots, search index, relations, receipts, membership, account
+	/// data, aliases and finally its short room id. With `delete_media`,
+	/// media on our server referenced by the room's events is deleted too.
+	///
+	/// Bans and disabled federation are kept so the room isn't joined or
+	/// backfilled again. Short state keys are shared by all rooms and stay.
+	/// `progress` is called with a description after each step.
+	pub async fn purge_room<F, Fut>(&self, room_id: &RoomId, delete_media: bool, progress: F) -> Result<PurgeSummary>
+	where
+		F: Fn(String) -> Fut,
+		Fut: Future<Output = ()>,
+	{
+		let _federation_lock = services()
+			.globals
+			.roomid_mutex_federation
+			.lock(room_id)
+			.await;
+		let _state_lock = services().globals.roomid_mutex_state.lock(room_id).await;
+		let _insert_lock = services().globals.roomid_mutex_insert.lock(room_id).await;
+
+		let shortroomid = services().rooms.short.get_shortroomid(room_id)?;
+		let mut summary = PurgeSummary::default();
+
+		let (event_ids, media) = self
+			.blocking(room_id, move |db, room_id| {
+				let mut event_ids = Vec::new();
+				let mut media = BTreeSet::new();
+				let mut collect = |pdu: PduEvent| {
+					if delete_media {
+						media.extend(local_media(&pdu));
+					}
+
+					event_ids.push(pdu.event_id.as_ref().to_owned());
+				};
+
+				if let Some(shortroomid) = shortroomid {
+					db.for_each_timeline_pdu(shortroomid, &mut collect)?;
+				}
+				db.for_each_outlier_pdu(room_id, &mut collect)?;
+
+				Ok((event_ids, media))
+			})
+			.await?;
+		progress(format!("Found {} events in {room_id}.", event_ids.len())).await;
+
+		summary.events = event_ids.len();
+		let shortstatehashes = self
+			.blocking(room_id, move |db, _| {
+				if let Some(shortroomid) = shortroomid {
+					db.remove_timeline(shortroomid)?;
+				}
+
+				db.remove_events(&event_ids)
+			})
+			.await?;
+		progress(format!("Removed {} events and their short ids.", summary.events)).await;
+
+		summary.state_snapshots = self
+			.blocking(room_id, move |db, room_id| {
+				db.remove_state(room_id, shortroomid, shortstatehashes)
+			})
+			.await?;
+		progress(format!("Removed {} state snapshots.", summary.state_snapshots)).await;
+
+		self.blocking(room_id, |db, room_id| db.remove_room_data(room_id))
+			.await?;
+		progress("Removed membership, receipts, account data and aliases.".to_owned()).await;
+
+		for mxc in &media {
+			match services().media.delete(mxc.as_str()).await {
+				Ok(()) => summary.media = summary.media.saturating_add(1),
+				Err(e) => debug_warn!("Failed to delete media {mxc} from purged room {room_id}: {e}"),
+			}
+		}
+		if delete_media {
+			progress(format!(
+				"Deleted {} of {} referenced local media files.",
+				summary.media,
+				media.len()
+			))
+			.await;
+		}
+
+		self.db.remove_shortroomid(room_id)?;
+		clear_caches(room_id).await;
+
+		Ok(summary)
+	}
+
+	/// Runs a step of the purge on the blocking thread pool, as most of them
+	/// scan entire column families.
+	async fn blocking<T, F>(&self, room_id: &RoomId, f: F) -> Result<T>
+	where
+		F: FnOnce(&Data, &RoomId) -> Result<T> + Send + 'static,
+		T: Send + 'static,
+	{
+		let db = self.db.clone();
+		let room_id: OwnedRoomId = room_id.to_owned();
+		self.server
+			.runtime()
+			.spawn_blocking(move || f(&db, &room_id))
+			.await
+			.map_err(|e| Error::Err(format!("Purge task failed: {e}")))?
+	}
+}
+
+/// Media on our server referenced by a message or room avatar. Member events
+/// are skipped since their avatars are users' profile pictures.
+fn local_media(pdu: &PduEvent) -> Vec<OwnedMxcUri> {
+	if pdu.kind == TimelineEventType::RoomMember {
+		return Vec::new();
+	}
+
+	let Ok(content) = serde_json::from_str::<ExtractMedia>(pdu.content.get()) else {
+		return Vec::new();
+	};
+
+	let (thumbnail_url, thumbnail_file) = content
+		.info
+		.map_or((None, None), |info| (info.thumbnail_url, info.thumbnail_file));
+
+	[
+		content.url,
+		content.file.map(|file| file.url),
+		thumbnail_url,
+		thumbnail_file.map(|file| file.url),
+	]
+	.into_iter()
+	.flatten()
+	.filter(|mxc| mxc.server_name().is_ok_and(server_is_ours))
+	.collect()
+}
+
+async fn clear_caches(room_id: &RoomId) {
+	services().rooms.timeline.clear_lasttimelinecount_cache();
+	services().rooms.auth_chain.clear_cache();
+	services()
+		.rooms
+		.state_cache
+		.clear_appservice_in_room_cache();
+	services()
+		.rooms
+		.state_compressor
+		.stateinfo_cache
+		.lock()
+		.expect("locked")
+		.clear();
+	services()
+		.rooms
+		.spaces
+		.roomid_spacehierarchy_cache
+		.lock()
+		.await
+		.pop(room_id);
+}
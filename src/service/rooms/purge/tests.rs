@@ -0,0 +1,106 @@
+#![cfg(test)]
+
+use std::collections::HashSet;
+
+use database::Database;
+use ruma::room_id;
+
+use super::data::Data;
+use crate::tests::database;
+
+fn key(parts: &[&str]) -> Vec<u8> {
+	let parts: Vec<&[u8]> = parts.iter().map(|part| part.as_bytes()).collect();
+	parts.join(&0xFF)
+}
+
+fn exists(db: &Database, map: &str, key: &[u8]) -> bool { db[map].get(key).unwrap().is_some() }
+
+#[tokio::test]
+async fn remove_room_data_keeps_other_rooms() {
+	let db = database().await;
+	let data = Data::new(&db);
+
+	let mut keys = Vec::new();
+	for room in ["!purged:example.com", "!kept:example.com"] {
+		let room_keys = [
+			("roomuserid_joined", key(&[room, "@alice:example.com"])),
+			("readreceiptid_readreceipt", key(&[room, "1", "@alice:example.com"])),
+			("userroomid_joined", key(&["@alice:example.com", room])),
+			("userroomid_notificationcount", key(&["@alice:example.com", room])),
+			(
+				"lazyloadedids",
+				key(&["@alice:example.com", "DEVICE", room, "@bob:example.com"]),
+			),
+			("referencedevents", format!("{room}$event").into_bytes()),
+			("roomid_joinedcount", room.as_bytes().to_vec()),
+		];
+
+		for (map, key) in &room_keys {
+			db[*map].insert(key, b"").unwrap();
+		}
+		keys.push(room_keys);
+	}
+
+	data.remove_room_data(room_id!("!purged:example.com"))
+		.unwrap();
+
+	for (map, key) in &keys[0] {
+		assert!(!exists(&db, map, key), "{map} of the purged room is removed");
+	}
+	for (map, key) in &keys[1] {
+		assert!(exists(&db, map, key), "{map} of another room is kept");
+	}
+}
+
+#[tokio::test]
+async fn remove_timeline_and_events() {
+	let db = database().await;
+	let data = Data::new(&db);
+
+	let (purged, kept) = (1_u64, 2_u64);
+	for (shortroomid, count) in [(purged, 1_u64), (purged, 2), (kept, 3)] {
+		let mut pdu_id = shortroomid.to_be_bytes().to_vec();
+		pdu_id.extend_from_slice(&count.to_be_bytes());
+		db["pduid_pdu"].insert(&pdu_id, b"{}").unwrap();
+
+		let mut token = shortroomid.to_be_bytes().to_vec();
+		token.extend_from_slice(b"word");
+		token.push(0xFF);
+		token.extend_from_slice(&count.to_be_bytes());
+		db["tokenids"].insert(&token, b"").unwrap();
+	}
+
+	data.remove_timeline(purged).unwrap();
+
+	let remaining: Vec<_> = db["pduid_pdu"].iter().map(|(key, _)| key).collect();
+	assert_eq!(remaining.len(), 1);
+	assert!(remaining[0].starts_with(&kept.to_be_bytes()));
+	assert!(db["tokenids"]
+		.iter()
+		.all(|(key, _)| key.starts_with(&kept.to_be_bytes())));
+
+	// the event's short id, its state and auth chain go with it
+	let shorteventid = 7_u64.to_be_bytes();
+	let shortstatehash = 9_u64.to_be_bytes();
+	db["eventid_pduid"].insert(b"$event", b"").unwrap();
+	db["eventid_shorteventid"]
+		.insert(b"$event", &shorteventid)
+		.unwrap();
+	db["shorteventid_eventid"]
+		.insert(&shorteventid, b"$event")
+		.unwrap();
+	db["shorteventid_shortstatehash"]
+		.insert(&shorteventid, &shortstatehash)
+		.unwrap();
+	db["shorteventid_authchain"]
+		.insert(&shorteventid, b"")
+		.unwrap();
+
+	let shortstatehashes = data.remove_events(&["$event".try_into().unwrap()]).unwrap();
+	assert_eq!(shortstatehashes, HashSet::from([9]));
+	assert!(!exists(&db, "eventid_pduid", b"$event"));
+	assert!(!exists(&db, "eventid_shorteventid", b"$event"));
+	for map in ["shorteventid_eventid", "shorteventid_shortstatehash", "shorteventid_authchain"] {
+		assert!(!exists(&db, map, &shorteventid), "{map} is removed");
+	}
+}
@@ -2,11 +2,14 @@ use std::sync::Arc;
 
 use conduit::{utils, Result};
 use database::{Database, Map};
-use ruma::RoomId;
 
-use crate::services;
+use super::{query::Query, tokenize};
 
-type SearchPdusResult<'a> = Result<Option<(Box<dyn Iterator<Item = Vec<u8>> + 'a>, Vec<String>)>>;
+/// Number of postings a single query prefix may expand to. Prefixes matching
+/// many words would otherwise load most of a room's index into memory.
+const MAX_PREFIX_POSTINGS: usize = 10_000;
+
+type PduIdIter<'a> = Box<dyn Iterator<Item = Vec<u8>> + Send + 'a>;
 
 pub(super) struct Data {
 	tokenids: Arc<Map>,
@@ -50,50 +53,74 @@ impl Data {
 		Ok(())
 	}
 
-	pub(super) fn search_pdus<'a>(&'a self, room_id: &RoomId, search_string: &str) -> SearchPdusResult<'a> {
-		let prefix = services()
-			.rooms
-			.short
-			.get_shortroomid(room_id)?
-			.expect("room exists")
-			.to_be_bytes()
-			.to_vec();
+	/// Candidate pdu ids in one room containing every word and a token for
+	/// every prefix of the query, newest first. Phrase adjacency is not checked
+	/// here.
+	pub(super) fn search_pdus<'a>(&'a self, shortroomid: u64, query: &Query) -> Option<PduIdIter<'a>> {
+		let words = query.words().map(|word| self.word_pdus(shortroomid, word));
 
-		let words: Vec<_> = tokenize(search_string).collect();
+		let prefixes = query
+			.prefixes()
+			.map(|prefix| self.prefix_pdus(shortroomid, prefix));
 
-		let iterators = words.clone().into_iter().map(move |word| {
-			let mut prefix2 = prefix.clone();
-			prefix2.extend_from_slice(word.as_bytes());
-			prefix2.push(0xFF);
-			let prefix3 = prefix2.clone();
+		let iterators: Vec<PduIdIter<'a>> = words.chain(prefixes).collect();
+		let common_elements = utils::common_elements(iterators.into_iter(), |a, b| {
+			// We compare b with a because we reversed the iterator earlier
+			b.cmp(a)
+		})?;
 
-			let mut last_possible_id = prefix2.clone();
-			last_possible_id.extend_from_slice(&u64::MAX.to_be_bytes());
+		Some(Box::new(common_elements))
+	}
 
+	fn word_pdus<'a>(&'a self, shortroomid: u64, word: &str) -> PduIdIter<'a> {
+		let mut prefix = shortroomid.to_be_bytes().to_vec();
+		prefix.extend_from_slice(word.as_bytes());
+		prefix.push(0xFF);
+		let prefix_len = prefix.len();
+
+		let mut last_possible_id = prefix.clone();
+		last_possible_id.extend_from_slice(&u64::MAX.to_be_bytes());
+
+		Box::new(
 			self.tokenids
 				.iter_from(&last_possible_id, true) // Newest pdus first
-				.take_while(move |(k, _)| k.starts_with(&prefix2))
-				.map(move |(key, _)| key[prefix3.len()..].to_vec())
-		});
+				.take_while(move |(k, _)| k.starts_with(&prefix))
+				.map(move |(key, _)| key[prefix_len..].to_vec()),
+		)
+	}
 
-		let Some(common_elements) = utils::common_elements(iterators, |a, b| {
-			// We compare b with a because we reversed the iterator earlier
-			b.cmp(a)
-		}) else {
-			return Ok(None);
-		};
+	/// A prefix spans several words whose postings are each sorted on their
+	/// own, so they are merged and sorted here before intersecting. At most
+	/// [`MAX_PREFIX_POSTINGS`] are expanded, newest first within each word.
+	fn prefix_pdus<'a>(&'a self, shortroomid: u64, word_prefix: &str) -> PduIdIter<'a> {
+		let mut prefix = shortroomid.to_be_bytes().to_vec();
+		prefix.extend_from_slice(word_prefix.as_bytes());
+
+		// utf-8 never contains 0xFF so this sorts after every word with the prefix
+		let mut last_possible_id = prefix.clone();
+		last_possible_id.push(0xFF);
+		last_possible_id.extend_from_slice(&u64::MAX.to_be_bytes());
+
+		let mut pdu_ids: Vec<_> = self
+			.tokenids
+			.iter_from(&last_possible_id, true)
+			.take_while(|(k, _)| k.starts_with(&prefix))
+			.take(MAX_PREFIX_POSTINGS)
+			.filter_map(|(key, _)| {
+				// the first 0xFF terminates the word
+				let separator = key
+					.iter()
+					.skip(8)
+					.position(|&b| b == 0xFF)?
+					.saturating_add(9);
+
+				key.get(separator..).map(<[u8]>::to_vec)
+			})
+			.collect();
 
-		Ok(Some((Box::new(common_elements), words)))
-	}
-}
+		pdu_ids.sort_unstable_by(|a, b| b.cmp(a));
+		pdu_ids.dedup();
 
-/// Splits a string into tokens used as keys in the search inverted index
-///
-/// This may be used to tokenize both message bodies (for indexing) or search
-/// queries (for querying).
-fn tokenize(body: &str) -> impl Iterator<Item = String> + '_ {
-	body.split_terminator(|c: char| !c.is_alphanumeric())
-		.filter(|s| !s.is_empty())
-		.filter(|word| word.len() <= 50)
-		.map(str::to_lowercase)
+		Box::new(pdu_ids.into_iter())
+	}
 }
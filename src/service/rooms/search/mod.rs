@@ -1,16 +1,62 @@
 mod data;
+mod query;
+mod tests;
 
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 
-use conduit::{Result, Server};
+use conduit::{PduCount, Result, Server};
 use data::Data;
 use database::Database;
-use ruma::RoomId;
+use itertools::Itertools;
+use ruma::{
+	api::client::search::search_events::v3::{Criteria, OrderBy},
+	OwnedRoomId, UserId,
+};
+
+use self::query::{term_frequency, Query, Term};
+use crate::{
+	rooms::timeline::{pdu_count, ExtractBody},
+	services, PduEvent,
+};
+
+/// Number of the most recent matches which are scored when ordering by rank.
+/// Scoring requires loading every candidate so this bounds the cost of a
+/// search for common words across many rooms.
+const RANK_WINDOW: usize = 1000;
+
+/// BM25 term frequency saturation.
+const BM25_K1: f64 = 1.2;
+
+/// BM25 document length normalization.
+const BM25_B: f64 = 0.75;
 
 pub struct Service {
 	db: Data,
 }
 
+pub struct RoomQuery<'a> {
+	pub rooms: &'a [OwnedRoomId],
+	pub user_id: &'a UserId,
+	pub criteria: &'a Criteria,
+	pub limit: usize,
+	pub skip: usize,
+}
+
+#[derive(Default)]
+pub struct SearchResults {
+	/// Number of matches found; for rank ordering this is bounded by the
+	/// scored window.
+	pub count: usize,
+	pub results: Vec<(PduEvent, Option<f64>)>,
+	pub highlights: Vec<String>,
+	pub more: bool,
+}
+
+struct Match {
+	pdu: PduEvent,
+	tokens: Vec<String>,
+}
+
 impl Service {
 	pub fn build(_server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
 		Ok(Self {
@@ -28,10 +74,156 @@ impl Service {
 		self.db.deindex_pdu(shortroomid, pdu_id, message_body)
 	}
 
-	#[tracing::instrument(skip(self))]
-	pub fn search_pdus<'a>(
-		&'a self, room_id: &RoomId, search_string: &str,
-	) -> Result<Option<(impl Iterator<Item = Vec<u8>> + 'a, Vec<String>)>> {
-		self.db.search_pdus(room_id, search_string)
+	/// Searches all of the query's rooms at once. Matches are merged newest
+	/// first across rooms, checked for phrase adjacency and visibility to the
+	/// user, then either paginated directly or ranked by BM25.
+	#[tracing::instrument(skip_all, fields(rooms = query.rooms.len()))]
+	pub fn search_pdus(&self, query: &RoomQuery<'_>) -> Result<SearchResults> {
+		let parsed = Query::parse(&query.criteria.search_term);
+		if parsed.is_empty() {
+			return Ok(SearchResults::default());
+		}
+
+		let mut candidates = Vec::with_capacity(query.rooms.len());
+		for room_id in query.rooms {
+			let Some(shortroomid) = services().rooms.short.get_shortroomid(room_id)? else {
+				continue;
+			};
+
+			candidates.extend(self.db.search_pdus(shortroomid, &parsed));
+		}
+
+		// pdu ids from different rooms only compare meaningfully by their count
+		let mut matches = candidates
+			.into_iter()
+			.kmerge_by(|a, b| recency(a) > recency(b))
+			.filter_map(|pdu_id| load_match(&pdu_id, &parsed, query.user_id));
+
+		let (count, page, more) = if matches!(query.criteria.order_by, Some(OrderBy::Rank)) {
+			let ranked = rank(&parsed, matches.take(RANK_WINDOW).collect());
+			let count = ranked.len();
+			let more = count > query.skip.saturating_add(query.limit);
+			let page: Vec<_> = ranked
+				.into_iter()
+				.skip(query.skip)
+				.take(query.limit)
+				.map(|(m, score)| (m, Some(score)))
+				.collect();
+
+			(count, page, more)
+		} else {
+			let page: Vec<_> = matches
+				.by_ref()
+				.skip(query.skip)
+				.take(query.limit)
+				.map(|m| (m, None))
+				.collect();
+
+			let more = matches.next().is_some();
+			(page.len(), page, more)
+		};
+
+		let highlights = highlights(&parsed, page.iter().map(|(m, _)| m));
+		let results = page.into_iter().map(|(m, score)| (m.pdu, score)).collect();
+
+		Ok(SearchResults {
+			count,
+			results,
+			highlights,
+			more,
+		})
+	}
+}
+
+fn recency(pdu_id: &[u8]) -> PduCount { pdu_count(pdu_id).unwrap_or_else(|_| PduCount::min()) }
+
+/// Loads a candidate and confirms it against the full query; the index can't
+/// answer phrase adjacency, redactions or history visibility.
+fn load_match(pdu_id: &[u8], query: &Query, user_id: &UserId) -> Option<Match> {
+	let pdu = services().rooms.timeline.get_pdu_from_id(pdu_id).ok()??;
+	if pdu.is_redacted() {
+		return None;
+	}
+
+	let body = serde_json::from_str::<ExtractBody>(pdu.content.get())
+		.ok()?
+		.body?;
+
+	let tokens: Vec<_> = tokenize(&body).collect();
+	if !query.matches(&tokens) {
+		return None;
+	}
+
+	services()
+		.rooms
+		.state_accessor
+		.user_can_see_event(user_id, &pdu.room_id, &pdu.event_id)
+		.unwrap_or(false)
+		.then_some(Match {
+			pdu,
+			tokens,
+		})
+}
+
+/// Scores matches with BM25 and sorts them best first. Every match contains
+/// every term, so inverse document frequency is the same for all of them and
+/// is left out.
+fn rank(query: &Query, matches: Vec<Match>) -> Vec<(Match, f64)> {
+	let total_len: usize = matches.iter().map(|m| m.tokens.len()).sum();
+	let avg_len = (total_len as f64 / matches.len().max(1) as f64).max(1.0);
+
+	let mut ranked: Vec<_> = matches
+		.into_iter()
+		.map(|m| {
+			let score = bm25(&query.terms, &m.tokens, avg_len);
+			(m, score)
+		})
+		.collect();
+
+	// ties keep their newest-first order
+	ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+	ranked
+}
+
+fn bm25(terms: &[Term], tokens: &[String], avg_len: f64) -> f64 {
+	let len_norm = BM25_B.mul_add(tokens.len() as f64 / avg_len, 1.0 - BM25_B);
+
+	terms
+		.iter()
+		.map(|term| {
+			let tf = term_frequency(term, tokens) as f64;
+			tf * (BM25_K1 + 1.0) / BM25_K1.mul_add(len_norm, tf)
+		})
+		.sum()
+}
+
+/// Words for the client to highlight. Prefixes are expanded to the words they
+/// matched in the returned events.
+fn highlights<'a, I>(query: &Query, matches: I) -> Vec<String>
+where
+	I: Iterator<Item = &'a Match>,
+{
+	let mut highlights: BTreeSet<String> = query.words().map(ToOwned::to_owned).collect();
+	let prefixes: Vec<_> = query.prefixes().collect();
+	if !prefixes.is_empty() {
+		highlights.extend(
+			matches
+				.flat_map(|m| m.tokens.iter())
+				.filter(|token| prefixes.iter().any(|prefix| token.starts_with(prefix)))
+				.cloned(),
+		);
 	}
+
+	highlights.into_iter().collect()
+}
+
+/// Splits a string into tokens used as keys in the search inverted index
+///
+/// This may be used to tokenize both message bodies (for indexing) or search
+/// queries (for querying).
+fn tokenize(body: &str) -> impl Iterator<Item = String> + '_ {
+	body.split_terminator(|c: char| !c.is_alphanumeric())
+		.filter(|s| !s.is_empty())
+		.filter(|word| word.len() <= 50)
+		.map(str::to_lowercase)
 }
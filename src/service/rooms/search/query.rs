@@ -0,0 +1,119 @@
+use super::tokenize;
+
+/// Shortest word accepted as a prefix. Shorter ones are searched as plain
+/// words since they would expand to most of the index.
+const MIN_PREFIX_LEN: usize = 3;
+
+/// A single clause of a parsed search query. Every clause of a query must
+/// match for an event to be a result.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum Term {
+	/// A single word which must be present as a token.
+	Word(String),
+
+	/// A word ending in `*`; any token starting with it matches.
+	Prefix(String),
+
+	/// A quoted sequence of words which must appear adjacent and in order.
+	Phrase(Vec<String>),
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Query {
+	pub(super) terms: Vec<Term>,
+}
+
+impl Query {
+	/// Parses a client search term. Quoted sections become phrases, words
+	/// ending in `*` become prefixes and everything else is tokenized into
+	/// plain words the same way message bodies are indexed.
+	pub(super) fn parse(search_term: &str) -> Self {
+		let mut terms = Vec::new();
+		for (i, section) in search_term.split('"').enumerate() {
+			// odd sections are within quotes; an unterminated quote extends to the end
+			if i % 2 == 1 {
+				let words: Vec<_> = tokenize(section).collect();
+				match words.len() {
+					0 => {},
+					1 => terms.extend(words.into_iter().map(Term::Word)),
+					_ => terms.push(Term::Phrase(words)),
+				}
+
+				continue;
+			}
+
+			for raw in section.split_whitespace() {
+				let mut words: Vec<_> = tokenize(raw).collect();
+				let prefix = raw
+					.strip_suffix('*')
+					.filter(|raw| raw.ends_with(char::is_alphanumeric))
+					.and_then(|_| words.pop());
+
+				terms.extend(words.into_iter().map(Term::Word));
+				terms.extend(prefix.map(|prefix| {
+					if prefix.chars().count() < MIN_PREFIX_LEN {
+						Term::Word(prefix)
+					} else {
+						Term::Prefix(prefix)
+					}
+				}));
+			}
+		}
+
+		terms.dedup();
+		Self {
+			terms,
+		}
+	}
+
+	pub(super) fn is_empty(&self) -> bool { self.terms.is_empty() }
+
+	/// All distinct words which must exist in the index for a match. Prefixes
+	/// are excluded since they expand to many words.
+	pub(super) fn words(&self) -> impl Iterator<Item = &str> + '_ {
+		let mut words: Vec<&str> = self
+			.terms
+			.iter()
+			.flat_map(|term| match term {
+				Term::Word(word) => std::slice::from_ref(word),
+				Term::Phrase(words) => words.as_slice(),
+				Term::Prefix(_) => &[][..],
+			})
+			.map(String::as_str)
+			.collect();
+
+		words.sort_unstable();
+		words.dedup();
+		words.into_iter()
+	}
+
+	pub(super) fn prefixes(&self) -> impl Iterator<Item = &str> + '_ {
+		self.terms.iter().filter_map(|term| match term {
+			Term::Prefix(prefix) => Some(prefix.as_str()),
+			_ => None,
+		})
+	}
+
+	/// Whether the tokenized body satisfies every term, including phrase
+	/// adjacency which the index alone cannot answer.
+	pub(super) fn matches(&self, tokens: &[String]) -> bool {
+		self.terms
+			.iter()
+			.all(|term| term_frequency(term, tokens) > 0)
+	}
+}
+
+/// Number of times the term occurs in the tokenized body.
+pub(super) fn term_frequency(term: &Term, tokens: &[String]) -> usize {
+	match term {
+		Term::Word(word) => tokens.iter().filter(|token| *token == word).count(),
+		Term::Prefix(prefix) => tokens
+			.iter()
+			.filter(|token| token.starts_with(prefix.as_str()))
+			.count(),
+		Term::Phrase(words) => tokens
+			.windows(words.len())
+			.filter(|window| window == words)
+			.count(),
+	}
+}
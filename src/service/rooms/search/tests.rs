@@ -0,0 +1,82 @@
+#![cfg(test)]
+
+use super::{
+	query::{term_frequency, Query, Term},
+	tokenize,
+};
+
+fn tokens(body: &str) -> Vec<String> { tokenize(body).collect() }
+
+#[test]
+fn parse_words() {
+	let query = Query::parse("Hello, World");
+	assert_eq!(
+		query.terms,
+		vec![Term::Word("hello".to_owned()), Term::Word("world".to_owned())]
+	);
+}
+
+#[test]
+fn parse_phrase_and_prefix() {
+	let query = Query::parse("\"big red\" dog cat* \"single\"");
+	assert_eq!(
+		query.terms,
+		vec![
+			Term::Phrase(vec!["big".to_owned(), "red".to_owned()]),
+			Term::Word("dog".to_owned()),
+			Term::Prefix("cat".to_owned()),
+			Term::Word("single".to_owned()),
+		]
+	);
+	assert_eq!(query.words().collect::<Vec<_>>(), vec!["big", "dog", "red", "single"]);
+	assert_eq!(query.prefixes().collect::<Vec<_>>(), vec!["cat"]);
+}
+
+#[test]
+fn parse_unterminated_quote() {
+	let query = Query::parse("foo \"bar baz");
+	assert_eq!(
+		query.terms,
+		vec![
+			Term::Word("foo".to_owned()),
+			Term::Phrase(vec!["bar".to_owned(), "baz".to_owned()]),
+		]
+	);
+}
+
+#[test]
+fn parse_bare_wildcard_is_ignored() {
+	let query = Query::parse("* foo-bar*");
+	assert_eq!(query.terms, vec![Term::Word("foo".to_owned()), Term::Prefix("bar".to_owned())]);
+}
+
+#[test]
+fn parse_short_prefix_is_a_word() {
+	let query = Query::parse("a* ca* cat*");
+	assert_eq!(
+		query.terms,
+		vec![
+			Term::Word("a".to_owned()),
+			Term::Word("ca".to_owned()),
+			Term::Prefix("cat".to_owned()),
+		]
+	);
+}
+
+#[test]
+fn phrase_requires_adjacency() {
+	let query = Query::parse("\"red dog\"");
+	assert!(query.matches(&tokens("the red dog barked")), "adjacent phrase matches");
+	assert!(!query.matches(&tokens("the dog was red")), "reordered words don't match");
+}
+
+#[test]
+fn frequencies() {
+	let body = tokens("cats and a cat, catalogued; red dog red dog");
+	assert_eq!(term_frequency(&Term::Word("cat".to_owned()), &body), 1);
+	assert_eq!(term_frequency(&Term::Prefix("cat".to_owned()), &body), 3);
+	assert_eq!(
+		term_frequency(&Term::Phrase(vec!["red".to_owned(), "dog".to_owned()]), &body),
+		2
+	);
+}
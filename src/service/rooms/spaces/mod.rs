@@ -230,6 +230,10 @@ impl Service {
 			.get_mut(&current_room.to_owned())
 			.as_ref()
 		{
+			services()
+				.server
+				.metrics
+				.cache_lookup("roomid_spacehierarchy", true);
 			return Ok(if let Some(cached) = cached {
 				if is_accessable_child(
 					current_room,
@@ -245,6 +249,10 @@ impl Service {
 				None
 			});
 		}
+		services()
+			.server
+			.metrics
+			.cache_lookup("roomid_spacehierarchy", false);
 
 		Ok(
 			if let Some(children_pdus) = get_stripped_space_child_events(current_room).await? {
@@ -25,7 +25,7 @@ use ruma::{
 		StateEventType,
 	},
 	space::SpaceRoomJoinRule,
-	EventId, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+	EventId, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
 };
 use serde_json::value::to_raw_value;
 
@@ -119,8 +119,16 @@ impl Service {
 			.unwrap()
 			.get_mut(&(origin.to_owned(), shortstatehash))
 		{
+			services()
+				.server
+				.metrics
+				.cache_lookup("server_visibility", true);
 			return Ok(*visibility);
 		}
+		services()
+			.server
+			.metrics
+			.cache_lookup("server_visibility", false);
 
 		let history_visibility = self
 			.state_get(shortstatehash, &StateEventType::RoomHistoryVisibility, "")?
@@ -182,8 +190,16 @@ impl Service {
 			.unwrap()
 			.get_mut(&(user_id.to_owned(), shortstatehash))
 		{
+			services()
+				.server
+				.metrics
+				.cache_lookup("user_visibility", true);
 			return Ok(*visibility);
 		}
+		services()
+			.server
+			.metrics
+			.cache_lookup("user_visibility", false);
 
 		let currently_member = services().rooms.state_cache.is_joined(user_id, room_id)?;
 
@@ -436,6 +452,28 @@ impl Service {
 			.unwrap_or((SpaceRoomJoinRule::Invite, vec![])))
 	}
 
+	/// Whether users may knock on a room: its join rule has to be `knock` or
+	/// `knock_restricted`, which room versions before 7 don't have.
+	pub fn allows_knocking(&self, room_id: &RoomId) -> Result<bool> {
+		let room_version_id = services().rooms.state.get_room_version(room_id)?;
+		if matches!(
+			room_version_id,
+			RoomVersionId::V1
+				| RoomVersionId::V2
+				| RoomVersionId::V3
+				| RoomVersionId::V4
+				| RoomVersionId::V5
+				| RoomVersionId::V6
+		) {
+			return Ok(false);
+		}
+
+		Ok(matches!(
+			self.get_join_rule(room_id)?.0,
+			SpaceRoomJoinRule::Knock | SpaceRoomJoinRule::KnockRestricted
+		))
+	}
+
 	/// Returns an empty vec if not a restricted room
 	pub fn allowed_room_ids(&self, join_rule: JoinRule) -> Vec<OwnedRoomId> {
 		let mut room_ids = vec![];
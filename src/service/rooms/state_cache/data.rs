@@ -23,6 +23,8 @@ pub(super) struct Data {
 	roomuserid_joined: Arc<Map>,
 	userroomid_invitestate: Arc<Map>,
 	roomuserid_invitecount: Arc<Map>,
+	userroomid_knockedstate: Arc<Map>,
+	roomuserid_knockedcount: Arc<Map>,
 	userroomid_leftstate: Arc<Map>,
 	roomuserid_leftcount: Arc<Map>,
 	roomid_inviteviaservers: Arc<Map>,
@@ -41,6 +43,8 @@ impl Data {
 			roomuserid_joined: db["roomuserid_joined"].clone(),
 			userroomid_invitestate: db["userroomid_invitestate"].clone(),
 			roomuserid_invitecount: db["roomuserid_invitecount"].clone(),
+			userroomid_knockedstate: db["userroomid_knockedstate"].clone(),
+			roomuserid_knockedcount: db["roomuserid_knockedcount"].clone(),
 			userroomid_leftstate: db["userroomid_leftstate"].clone(),
 			roomuserid_leftcount: db["roomuserid_leftcount"].clone(),
 			roomid_inviteviaservers: db["roomid_inviteviaservers"].clone(),
@@ -75,6 +79,8 @@ impl Data {
 		self.roomuserid_joined.insert(&roomuser_id, &[])?;
 		self.userroomid_invitestate.remove(&userroom_id)?;
 		self.roomuserid_invitecount.remove(&roomuser_id)?;
+		self.userroomid_knockedstate.remove(&userroom_id)?;
+		self.roomuserid_knockedcount.remove(&roomuser_id)?;
 		self.userroomid_leftstate.remove(&userroom_id)?;
 		self.roomuserid_leftcount.remove(&roomuser_id)?;
 
@@ -103,6 +109,8 @@ impl Data {
 			.insert(&roomuser_id, &services().globals.next_count()?.to_be_bytes())?;
 		self.userroomid_joined.remove(&userroom_id)?;
 		self.roomuserid_joined.remove(&roomuser_id)?;
+		self.userroomid_knockedstate.remove(&userroom_id)?;
+		self.roomuserid_knockedcount.remove(&roomuser_id)?;
 		self.userroomid_leftstate.remove(&userroom_id)?;
 		self.roomuserid_leftcount.remove(&roomuser_id)?;
 
@@ -128,6 +136,33 @@ impl Data {
 		Ok(())
 	}
 
+	pub(super) fn mark_as_knocked(
+		&self, user_id: &UserId, room_id: &RoomId, knocked_state: Option<Vec<Raw<AnyStrippedStateEvent>>>,
+	) -> Result<()> {
+		let mut roomuser_id = room_id.as_bytes().to_vec();
+		roomuser_id.push(0xFF);
+		roomuser_id.extend_from_slice(user_id.as_bytes());
+
+		let mut userroom_id = user_id.as_bytes().to_vec();
+		userroom_id.push(0xFF);
+		userroom_id.extend_from_slice(room_id.as_bytes());
+
+		self.userroomid_knockedstate.insert(
+			&userroom_id,
+			&serde_json::to_vec(&knocked_state.unwrap_or_default()).expect("state to bytes always works"),
+		)?;
+		self.roomuserid_knockedcount
+			.insert(&roomuser_id, &services().globals.next_count()?.to_be_bytes())?;
+		self.userroomid_joined.remove(&userroom_id)?;
+		self.roomuserid_joined.remove(&roomuser_id)?;
+		self.userroomid_invitestate.remove(&userroom_id)?;
+		self.roomuserid_invitecount.remove(&roomuser_id)?;
+		self.userroomid_leftstate.remove(&userroom_id)?;
+		self.roomuserid_leftcount.remove(&roomuser_id)?;
+
+		Ok(())
+	}
+
 	pub(super) fn mark_as_left(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
 		let roomid = room_id.as_bytes().to_vec();
 
@@ -149,6 +184,8 @@ impl Data {
 		self.roomuserid_joined.remove(&roomuser_id)?;
 		self.userroomid_invitestate.remove(&userroom_id)?;
 		self.roomuserid_invitecount.remove(&roomuser_id)?;
+		self.userroomid_knockedstate.remove(&userroom_id)?;
+		self.roomuserid_knockedcount.remove(&roomuser_id)?;
 
 		self.roomid_inviteviaservers.remove(&roomid)?;
 
@@ -223,6 +260,11 @@ impl Data {
 			.and_then(|map| map.get(&appservice.registration.id))
 			.copied();
 
+		services()
+			.server
+			.metrics
+			.cache_lookup("appservice_in_room", maybe.is_some());
+
 		if let Some(b) = maybe {
 			Ok(b)
 		} else {
@@ -441,6 +483,18 @@ impl Data {
 			})
 	}
 
+	#[tracing::instrument(skip(self))]
+	pub(super) fn get_knock_count(&self, room_id: &RoomId, user_id: &UserId) -> Result<Option<u64>> {
+		let mut key = room_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(user_id.as_bytes());
+
+		self.roomuserid_knockedcount
+			.get(&key)?
+			.map(|bytes| utils::u64_from_bytes(&bytes).map_err(|_| Error::bad_database("Invalid knockedcount in db.")))
+			.transpose()
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub(super) fn get_left_count(&self, room_id: &RoomId, user_id: &UserId) -> Result<Option<u64>> {
 		let mut key = room_id.as_bytes().to_vec();
@@ -520,6 +574,53 @@ impl Data {
 			.transpose()
 	}
 
+	/// Returns an iterator over all rooms a user has knocked on.
+	#[tracing::instrument(skip(self))]
+	pub(super) fn rooms_knocked<'a>(&'a self, user_id: &UserId) -> StrippedStateEventIter<'a> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		Box::new(
+			self.userroomid_knockedstate
+				.scan_prefix(prefix)
+				.map(|(key, state)| {
+					let room_id = RoomId::parse(
+						utils::string_from_bytes(
+							key.rsplit(|&b| b == 0xFF)
+								.next()
+								.expect("rsplit always returns an element"),
+						)
+						.map_err(|_| Error::bad_database("Room ID in userroomid_knockedstate is invalid unicode."))?,
+					)
+					.map_err(|_| Error::bad_database("Room ID in userroomid_knockedstate is invalid."))?;
+
+					let state = serde_json::from_slice(&state)
+						.map_err(|_| Error::bad_database("Invalid state in userroomid_knockedstate."))?;
+
+					Ok((room_id, state))
+				}),
+		)
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub(super) fn knock_state(
+		&self, user_id: &UserId, room_id: &RoomId,
+	) -> Result<Option<Vec<Raw<AnyStrippedStateEvent>>>> {
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(room_id.as_bytes());
+
+		self.userroomid_knockedstate
+			.get(&key)?
+			.map(|state| {
+				let state = serde_json::from_slice(&state)
+					.map_err(|_| Error::bad_database("Invalid state in userroomid_knockedstate."))?;
+
+				Ok(state)
+			})
+			.transpose()
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub(super) fn left_state(
 		&self, user_id: &UserId, room_id: &RoomId,
@@ -594,6 +695,15 @@ impl Data {
 		Ok(self.userroomid_invitestate.get(&userroom_id)?.is_some())
 	}
 
+	#[tracing::instrument(skip(self))]
+	pub(super) fn is_knocked(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> {
+		let mut userroom_id = user_id.as_bytes().to_vec();
+		userroom_id.push(0xFF);
+		userroom_id.extend_from_slice(room_id.as_bytes());
+
+		Ok(self.userroomid_knockedstate.get(&userroom_id)?.is_some())
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub(super) fn is_left(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> {
 		let mut userroom_id = user_id.as_bytes().to_vec();
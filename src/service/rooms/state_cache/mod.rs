@@ -19,10 +19,11 @@ use ruma::{
 	},
 	int,
 	serde::Raw,
-	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
 };
+use serde::Deserialize;
 
-use crate::{appservice::RegistrationInfo, services, user_is_local};
+use crate::{appservice::RegistrationInfo, services, user_directory::DirectoryEntry, user_is_local};
 
 pub struct Service {
 	db: Data,
@@ -169,6 +170,17 @@ impl Service {
 				}
 
 				self.db.mark_as_joined(user_id, room_id)?;
+
+				// Local users are indexed from their profile instead
+				if !user_is_local(user_id) {
+					services().user_directory.update(
+						user_id,
+						DirectoryEntry {
+							display_name: membership_event.displayname.clone(),
+							avatar_url: membership_event.avatar_url.clone(),
+						},
+					)?;
+				}
 			},
 			MembershipState::Invite => {
 				// We want to know if the sender is ignored by the receiver
@@ -203,8 +215,20 @@ impl Service {
 				self.db
 					.mark_as_invited(user_id, room_id, last_state, invite_via)?;
 			},
+			MembershipState::Knock => {
+				self.db.mark_as_knocked(user_id, room_id, last_state)?;
+
+				// Servers we knocked through, to rescind the knock through them
+				if let Some(servers) = invite_via {
+					self.db.add_servers_invite_via(room_id, &servers)?;
+				}
+			},
 			MembershipState::Leave | MembershipState::Ban => {
 				self.db.mark_as_left(user_id, room_id)?;
+
+				if !user_is_local(user_id) && self.rooms_joined(user_id).next().is_none() {
+					services().user_directory.remove(user_id)?;
+				}
 			},
 			_ => {},
 		}
@@ -332,6 +356,11 @@ impl Service {
 		self.db.get_invite_count(room_id, user_id)
 	}
 
+	#[tracing::instrument(skip(self))]
+	pub fn get_knock_count(&self, room_id: &RoomId, user_id: &UserId) -> Result<Option<u64>> {
+		self.db.get_knock_count(room_id, user_id)
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub fn get_left_count(&self, room_id: &RoomId, user_id: &UserId) -> Result<Option<u64>> {
 		self.db.get_left_count(room_id, user_id)
@@ -356,6 +385,44 @@ impl Service {
 		self.db.invite_state(user_id, room_id)
 	}
 
+	/// Returns an iterator over all rooms a user has knocked on.
+	#[tracing::instrument(skip(self))]
+	pub fn rooms_knocked(
+		&self, user_id: &UserId,
+	) -> impl Iterator<Item = Result<(OwnedRoomId, Vec<Raw<AnyStrippedStateEvent>>)>> + '_ {
+		self.db.rooms_knocked(user_id)
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub fn knock_state(&self, user_id: &UserId, room_id: &RoomId) -> Result<Option<Vec<Raw<AnyStrippedStateEvent>>>> {
+		self.db.knock_state(user_id, room_id)
+	}
+
+	/// Version of a room a user knocked on, from the `m.room.create` event in
+	/// the stripped state the room sent back
+	#[tracing::instrument(skip(self))]
+	pub fn knocked_room_version(&self, user_id: &UserId, room_id: &RoomId) -> Result<Option<RoomVersionId>> {
+		#[derive(Deserialize)]
+		struct ExtractCreate {
+			#[serde(rename = "type")]
+			kind: StateEventType,
+			content: ExtractRoomVersion,
+		}
+
+		#[derive(Deserialize)]
+		struct ExtractRoomVersion {
+			room_version: Option<RoomVersionId>,
+		}
+
+		Ok(self
+			.knock_state(user_id, room_id)?
+			.unwrap_or_default()
+			.iter()
+			.filter_map(|event| event.deserialize_as::<ExtractCreate>().ok())
+			.find(|event| event.kind == StateEventType::RoomCreate)
+			.map(|create| create.content.room_version.unwrap_or(RoomVersionId::V1)))
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub fn left_state(&self, user_id: &UserId, room_id: &RoomId) -> Result<Option<Vec<Raw<AnyStrippedStateEvent>>>> {
 		self.db.left_state(user_id, room_id)
@@ -382,6 +449,11 @@ impl Service {
 		self.db.is_invited(user_id, room_id)
 	}
 
+	#[tracing::instrument(skip(self))]
+	pub fn is_knocked(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> {
+		self.db.is_knocked(user_id, room_id)
+	}
+
 	#[tracing::instrument(skip(self))]
 	pub fn is_left(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> { self.db.is_left(user_id, room_id) }
 
@@ -73,8 +73,10 @@ impl Service {
 			.unwrap()
 			.get_mut(&shortstatehash)
 		{
+			services().server.metrics.cache_lookup("stateinfo", true);
 			return Ok(r.clone());
 		}
+		services().server.metrics.cache_lookup("stateinfo", false);
 
 		let StateDiff {
 			parent,
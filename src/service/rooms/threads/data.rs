@@ -65,6 +65,8 @@ impl Data {
 		Ok(())
 	}
 
+	pub(super) fn remove_thread(&self, root_id: &[u8]) -> Result<()> { self.threadid_userids.remove(root_id) }
+
 	pub(super) fn get_participants(&self, root_id: &[u8]) -> Result<Option<Vec<OwnedUserId>>> {
 		if let Some(users) = self.threadid_userids.get(root_id)? {
 			Ok(Some(
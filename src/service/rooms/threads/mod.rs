@@ -31,6 +31,9 @@ impl Service {
 		self.db.threads_until(user_id, room_id, until, include)
 	}
 
+	/// Forgets a thread whose root pdu is being deleted.
+	pub fn remove_thread(&self, root_id: &[u8]) -> Result<()> { self.db.remove_thread(root_id) }
+
 	pub fn add_to_thread(&self, root_event_id: &EventId, pdu: &PduEvent) -> Result<()> {
 		let root_id = &services()
 			.rooms
@@ -43,6 +43,10 @@ impl Data {
 			.entry(room_id.to_owned())
 		{
 			hash_map::Entry::Vacant(v) => {
+				services()
+					.server
+					.metrics
+					.cache_lookup("lasttimelinecount", false);
 				if let Some(last_count) = self
 					.pdus_until(sender_user, room_id, PduCount::max())?
 					.find_map(|r| {
@@ -57,7 +61,13 @@ impl Data {
 					Ok(PduCount::Normal(0))
 				}
 			},
-			hash_map::Entry::Occupied(o) => Ok(*o.get()),
+			hash_map::Entry::Occupied(o) => {
+				services()
+					.server
+					.metrics
+					.cache_lookup("lasttimelinecount", true);
+				Ok(*o.get())
+			},
 		}
 	}
 
@@ -279,7 +289,7 @@ impl Data {
 }
 
 /// Returns the `count` of this pdu's id.
-pub(super) fn pdu_count(pdu_id: &[u8]) -> Result<PduCount> {
+pub(crate) fn pdu_count(pdu_id: &[u8]) -> Result<PduCount> {
 	let last_u64 = utils::u64_from_bytes(&pdu_id[pdu_id.len() - size_of::<u64>()..])
 		.map_err(|_| Error::bad_database("PDU has invalid count bytes."))?;
 	let second_last_u64 =
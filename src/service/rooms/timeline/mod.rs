@@ -1,4 +1,5 @@
 mod data;
+pub mod retention;
 
 use std::{
 	collections::{BTreeMap, HashMap, HashSet},
@@ -6,6 +7,7 @@ use std::{
 };
 
 use conduit::{debug, error, info, utils, utils::mutex_map, warn, Error, Result, Server};
+pub(crate) use data::pdu_count;
 use data::Data;
 use database::Database;
 use itertools::Itertools;
@@ -14,7 +16,6 @@ use ruma::{
 	api::{client::error::ErrorKind, federation},
 	canonical_json::to_canonical_value,
 	events::{
-		push_rules::PushRulesEvent,
 		room::{
 			create::RoomCreateEventContent,
 			encrypted::Relation,
@@ -22,9 +23,9 @@ use ruma::{
 			power_levels::RoomPowerLevelsEventContent,
 			redaction::RoomRedactionEventContent,
 		},
-		GlobalAccountDataEventType, StateEventType, TimelineEventType,
+		StateEventType, TimelineEventType,
 	},
-	push::{Action, Ruleset, Tweak},
+	push::{Action, Tweak},
 	serde::Base64,
 	state_res::{self, Event, RoomVersion},
 	uint, user_id, CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, OwnedRoomId, OwnedServerName,
@@ -32,7 +33,10 @@ use ruma::{
 };
 use serde::Deserialize;
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
-use tokio::sync::{Mutex, RwLock};
+use tokio::{
+	sync::{Mutex, RwLock},
+	task::JoinHandle,
+};
 
 use crate::{
 	admin,
@@ -60,14 +64,18 @@ struct ExtractRelatesToEventId {
 }
 
 #[derive(Deserialize)]
-struct ExtractBody {
-	body: Option<String>,
+pub(crate) struct ExtractBody {
+	pub(crate) body: Option<String>,
 }
 
 pub struct Service {
 	db: Data,
 
 	pub lasttimelinecount_cache: Mutex<HashMap<OwnedRoomId, PduCount>>,
+	pub retention_handle: Mutex<Option<JoinHandle<()>>>,
+	/// Where each room's next retention check continues from, after the
+	/// events it already deleted or kept
+	retention_cursors: Mutex<HashMap<OwnedRoomId, PduCount>>,
 }
 
 impl Service {
@@ -75,6 +83,8 @@ impl Service {
 		Ok(Self {
 			db: Data::new(db),
 			lasttimelinecount_cache: Mutex::new(HashMap::new()),
+			retention_handle: Mutex::new(None),
+			retention_cursors: Mutex::new(HashMap::new()),
 		})
 	}
 
@@ -337,17 +347,7 @@ impl Service {
 				continue;
 			}
 
-			let rules_for_user = services()
-				.account_data
-				.get(None, user, GlobalAccountDataEventType::PushRules.to_string().into())?
-				.map(|event| {
-					serde_json::from_str::<PushRulesEvent>(event.get()).map_err(|e| {
-						warn!("Invalid push rules event in db for user ID {user}: {e}");
-						Error::bad_database("Invalid push rules event in db.")
-					})
-				})
-				.transpose()?
-				.map_or_else(|| Ruleset::server_default(user), |ev: PushRulesEvent| ev.content.global);
+			let rules_for_user = services().pusher.get_ruleset(user)?;
 
 			let mut highlight = false;
 			let mut notify = false;
@@ -457,7 +457,7 @@ impl Service {
 					})?;
 
 					let invite_state = match content.membership {
-						MembershipState::Invite => {
+						MembershipState::Invite | MembershipState::Knock => {
 							let state = services().rooms.state.calculate_invite_state(pdu)?;
 							Some(state)
 						},
@@ -498,6 +498,8 @@ impl Service {
 			_ => {},
 		}
 
+		services().media.reference_media(pdu.content.get())?;
+
 		if let Ok(content) = serde_json::from_str::<ExtractRelatesToEventId>(pdu.content.get()) {
 			if let Some(related_pducount) = self.get_pdu_count(&content.relates_to.event_id)? {
 				services()
@@ -1236,6 +1238,7 @@ impl Service {
 					.index_pdu(shortroomid, &pdu_id, &body)?;
 			}
 		}
+		services().media.reference_media(pdu.content.get())?;
 		drop(mutex_lock);
 
 		debug!("Prepended backfill pdu");
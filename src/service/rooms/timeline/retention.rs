@@ -0,0 +1,327 @@
+use std::time::Duration;
+
+use conduit::{debug, info, utils, warn, Config, Error, Result};
+use ruma::{canonical_json::redact_content_in_place, events::StateEventType, RoomId, RoomVersionId};
+use serde::Deserialize;
+use serde_json::value::to_raw_value;
+use tokio::{task::JoinHandle, time::interval};
+
+use super::{ExtractBody, ExtractRelatesToEventId, Service};
+use crate::{services, PduCount, PduEvent};
+
+/// Most events redacted in one room per check, so a large backlog is worked
+/// through over several checks instead of holding up the other rooms.
+const EXPIRE_BATCH_SIZE: usize = 1000;
+
+/// Content of an `m.room.retention` state event (MSC1763). Lifetimes are in
+/// milliseconds.
+#[derive(Default, Deserialize)]
+struct RetentionPolicy {
+	min_lifetime: Option<u64>,
+	max_lifetime: Option<u64>,
+}
+
+/// The server's retention settings, in milliseconds
+#[derive(Clone, Copy, Default)]
+struct RetentionLimits {
+	default_max_lifetime: Option<u64>,
+	allowed_min_lifetime: Option<u64>,
+	allowed_max_lifetime: Option<u64>,
+}
+
+impl RetentionLimits {
+	fn from_config(config: &Config) -> Self {
+		let secs_to_millis = |secs: u64| secs.saturating_mul(1000);
+
+		Self {
+			default_max_lifetime: config.retention_default_max_lifetime_s.map(secs_to_millis),
+			allowed_min_lifetime: config.retention_allowed_min_lifetime_s.map(secs_to_millis),
+			allowed_max_lifetime: config.retention_allowed_max_lifetime_s.map(secs_to_millis),
+		}
+	}
+}
+
+impl Service {
+	/// Expires a pdu by redacting its content in place and removing its search
+	/// index entries, relations and thread. The event itself, its short ids
+	/// and the state at it are kept: later events may still cite it as a prev
+	/// or auth event, and a missing one would be fetched again over
+	/// federation.
+	#[tracing::instrument(skip(self, pdu), fields(event_id = %pdu.event_id))]
+	pub fn expire_pdu(&self, shortroomid: u64, count: PduCount, pdu: &PduEvent) -> Result<()> {
+		let Some(pdu_id) = self
+			.get_pdu_id(&pdu.event_id)?
+			.map(|pdu_id| pdu_id.to_vec())
+		else {
+			return Ok(());
+		};
+
+		if let Ok(ExtractBody {
+			body: Some(body),
+		}) = serde_json::from_str(pdu.content.get())
+		{
+			services()
+				.rooms
+				.search
+				.deindex_pdu(shortroomid, &pdu_id, &body)?;
+		}
+
+		let relates_to = match serde_json::from_str::<ExtractRelatesToEventId>(pdu.content.get()) {
+			Ok(content) => self.get_pdu_count(&content.relates_to.event_id)?,
+			Err(_) => None,
+		};
+
+		services()
+			.rooms
+			.pdu_metadata
+			.remove_relations(count, relates_to)?;
+		services().rooms.threads.remove_thread(&pdu_id)?;
+
+		let room_version_id = services().rooms.state.get_room_version(&pdu.room_id)?;
+		let pdu = expired(pdu, &room_version_id)?;
+		self.replace_pdu(
+			&pdu_id,
+			&utils::to_canonical_object(&pdu)
+				.map_err(|e| Error::Err(format!("Failed to convert PDU to canonical JSON: {e}")))?,
+			&pdu,
+		)
+	}
+}
+
+/// The pdu as it's kept once expired: its content redacted by the rules of
+/// the room version and without unsigned data.
+fn expired(pdu: &PduEvent, room_version_id: &RoomVersionId) -> Result<PduEvent> {
+	let mut content =
+		serde_json::from_str(pdu.content.get()).map_err(|_| Error::bad_database("PDU in db has invalid content."))?;
+	redact_content_in_place(&mut content, room_version_id, pdu.kind.to_string())
+		.map_err(|e| Error::Redaction(pdu.sender.server_name().to_owned(), e))?;
+
+	let mut pdu = pdu.clone();
+	pdu.unsigned = None;
+	pdu.content = to_raw_value(&content).expect("to string always works");
+
+	Ok(pdu)
+}
+
+/// Starts the task which periodically redacts events older than their room's
+/// retention policy allows.
+#[tracing::instrument]
+pub fn start_retention_task() -> JoinHandle<()> {
+	let timer_interval = Duration::from_secs(services().globals.config.retention_check_interval_s);
+
+	services().server.runtime().spawn(async move {
+		let mut i = interval(timer_interval);
+
+		loop {
+			i.tick().await;
+
+			let expired = expire_events().await;
+			if expired > 0 {
+				info!("Redacted {expired} events past their room's retention policy");
+			}
+		}
+	})
+}
+
+async fn expire_events() -> usize {
+	let limits = RetentionLimits::from_config(&services().globals.config);
+	let now = utils::millis_since_unix_epoch();
+	let room_ids: Vec<_> = services()
+		.rooms
+		.metadata
+		.iter_ids()
+		.filter_map(Result::ok)
+		.collect();
+
+	let mut expired: usize = 0;
+	for room_id in room_ids {
+		let policy = match room_policy(&room_id) {
+			Ok(policy) => policy,
+			Err(e) => {
+				warn!(%room_id, %e, "Failed to get retention policy");
+				continue;
+			},
+		};
+
+		let Some(max_lifetime) = max_lifetime(&policy, limits) else {
+			continue;
+		};
+
+		match expire_room_events(&room_id, now.saturating_sub(max_lifetime)).await {
+			Ok(count) => expired = expired.saturating_add(count),
+			Err(e) => warn!(%room_id, %e, "Failed to redact expired events"),
+		}
+	}
+
+	expired
+}
+
+fn room_policy(room_id: &RoomId) -> Result<RetentionPolicy> {
+	Ok(services()
+		.rooms
+		.state_accessor
+		.room_state_get(room_id, &StateEventType::from("m.room.retention".to_owned()), "")?
+		.and_then(|pdu| serde_json::from_str::<RetentionPolicy>(pdu.content.get()).ok())
+		.unwrap_or_default())
+}
+
+/// The lifetime in milliseconds after which events are redacted: the room's
+/// own policy or the server default, no shorter than the room's
+/// `min_lifetime`, and bounded by what the server allows.
+fn max_lifetime(policy: &RetentionPolicy, limits: RetentionLimits) -> Option<u64> {
+	let mut lifetime = policy.max_lifetime.or(limits.default_max_lifetime)?;
+
+	if let Some(min) = policy.min_lifetime {
+		lifetime = lifetime.max(min);
+	}
+
+	if let Some(min) = limits.allowed_min_lifetime {
+		lifetime = lifetime.max(min);
+	}
+
+	if let Some(max) = limits.allowed_max_lifetime {
+		lifetime = lifetime.min(max);
+	}
+
+	Some(lifetime)
+}
+
+/// Redacts the oldest events sent before `cutoff`. State events are needed
+/// for authorization and the forward extremities to send new events, so
+/// they're kept. Each check continues after the events the previous one
+/// went through instead of scanning the room from its first event again.
+async fn expire_room_events(room_id: &RoomId, cutoff: u64) -> Result<usize> {
+	let Some(shortroomid) = services().rooms.short.get_shortroomid(room_id)? else {
+		return Ok(0);
+	};
+
+	let _insert_lock = services().globals.roomid_mutex_insert.lock(room_id).await;
+
+	let timeline = &services().rooms.timeline;
+	let from = timeline
+		.retention_cursors
+		.lock()
+		.await
+		.get(room_id)
+		.copied()
+		.unwrap_or_else(PduCount::min);
+
+	let extremities = services().rooms.state.get_forward_extremities(room_id)?;
+	let mut cursor = None;
+	let mut expired = Vec::new();
+	for (count, pdu) in timeline
+		.pdus_after(&services().globals.server_user, room_id, from)?
+		.filter_map(Result::ok)
+	{
+		if u64::from(pdu.origin_server_ts) >= cutoff
+			|| extremities.contains(&pdu.event_id)
+			|| expired.len() >= EXPIRE_BATCH_SIZE
+		{
+			break;
+		}
+
+		cursor = Some(count);
+		if pdu.state_key.is_none() {
+			expired.push((count, pdu));
+		}
+	}
+
+	for (count, pdu) in &expired {
+		timeline
+			.expire_pdu(shortroomid, *count, pdu)
+			.map_err(|e| Error::Err(format!("Failed to redact {}: {e}", pdu.event_id)))?;
+	}
+
+	if let Some(cursor) = cursor {
+		timeline
+			.retention_cursors
+			.lock()
+			.await
+			.insert(room_id.to_owned(), cursor);
+	}
+
+	debug!(%room_id, "Redacted {} expired events", expired.len());
+	Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::RoomVersionId;
+	use serde_json::json;
+
+	use super::{expired, max_lifetime, RetentionLimits, RetentionPolicy};
+	use crate::PduEvent;
+
+	const DAY: u64 = 24 * 60 * 60 * 1000;
+
+	fn policy(min_lifetime: Option<u64>, max_lifetime: Option<u64>) -> RetentionPolicy {
+		RetentionPolicy {
+			min_lifetime,
+			max_lifetime,
+		}
+	}
+
+	#[test]
+	fn room_policy_or_server_default() {
+		let limits = RetentionLimits {
+			default_max_lifetime: Some(30 * DAY),
+			..Default::default()
+		};
+
+		assert_eq!(max_lifetime(&policy(None, Some(DAY)), limits), Some(DAY));
+		assert_eq!(max_lifetime(&policy(None, None), limits), Some(30 * DAY));
+		assert_eq!(max_lifetime(&policy(None, None), RetentionLimits::default()), None);
+	}
+
+	#[test]
+	fn kept_for_min_lifetime() {
+		assert_eq!(
+			max_lifetime(&policy(Some(7 * DAY), Some(DAY)), RetentionLimits::default()),
+			Some(7 * DAY)
+		);
+	}
+
+	#[test]
+	fn bounded_by_server() {
+		let limits = RetentionLimits {
+			default_max_lifetime: None,
+			allowed_min_lifetime: Some(DAY),
+			allowed_max_lifetime: Some(365 * DAY),
+		};
+
+		assert_eq!(max_lifetime(&policy(None, Some(1000)), limits), Some(DAY));
+		assert_eq!(max_lifetime(&policy(None, Some(1000 * DAY)), limits), Some(365 * DAY));
+		assert_eq!(
+			max_lifetime(&policy(Some(1000 * DAY), Some(DAY)), limits),
+			Some(365 * DAY),
+			"the server's maximum wins over the room's min_lifetime"
+		);
+	}
+
+	#[test]
+	fn expired_pdus_keep_their_skeleton() {
+		let pdu: PduEvent = serde_json::from_value(json!({
+			"event_id": "$expired",
+			"room_id": "!room:example.com",
+			"sender": "@alice:example.com",
+			"origin_server_ts": 1,
+			"type": "m.room.message",
+			"content": { "msgtype": "m.text", "body": "secret" },
+			"prev_events": ["$prev"],
+			"depth": 2,
+			"auth_events": ["$create"],
+			"unsigned": { "age": 1000 },
+			"hashes": { "sha256": "hash" },
+		}))
+		.unwrap();
+
+		let expired = expired(&pdu, &RoomVersionId::V10).unwrap();
+
+		assert_eq!(expired.content.get(), "{}");
+		assert!(expired.unsigned.is_none());
+		assert_eq!(expired.event_id, pdu.event_id);
+		assert_eq!(expired.prev_events, pdu.prev_events);
+		assert_eq!(expired.auth_events, pdu.auth_events);
+		assert_eq!(expired.hashes.sha256, pdu.hashes.sha256);
+	}
+}
@@ -44,6 +44,21 @@ impl Data {
 		Ok(())
 	}
 
+	pub(super) fn set_notification_counts(
+		&self, user_id: &UserId, room_id: &RoomId, notifications: u64, highlights: u64,
+	) -> Result<()> {
+		let mut userroom_id = user_id.as_bytes().to_vec();
+		userroom_id.push(0xFF);
+		userroom_id.extend_from_slice(room_id.as_bytes());
+
+		self.userroomid_notificationcount
+			.insert(&userroom_id, &notifications.to_be_bytes())?;
+		self.userroomid_highlightcount
+			.insert(&userroom_id, &highlights.to_be_bytes())?;
+
+		Ok(())
+	}
+
 	pub(super) fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
 		let mut userroom_id = user_id.as_bytes().to_vec();
 		userroom_id.push(0xFF);
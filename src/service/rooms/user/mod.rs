@@ -1,20 +1,38 @@
 mod data;
+mod tests;
 
-use std::sync::Arc;
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
 
-use conduit::{Result, Server};
+use conduit::{debug_warn, Error, PduCount, Result, Server};
 use data::Data;
 use database::Database;
-use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use ruma::{
+	events::{room::power_levels::RoomPowerLevelsEventContent, StateEventType},
+	push::{Action, Ruleset, Tweak},
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use tokio::task::JoinHandle;
+
+use crate::{services, PduEvent};
+
+/// Most unread events looked at when recounting notifications in a room
+const RECOUNT_LIMIT: usize = 1000;
 
 pub struct Service {
 	db: Data,
+	server: Arc<Server>,
+	recounts: Mutex<HashMap<OwnedUserId, JoinHandle<()>>>,
 }
 
 impl Service {
-	pub fn build(_server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
 		Ok(Self {
 			db: Data::new(db),
+			server: server.clone(),
+			recounts: Mutex::new(HashMap::new()),
 		})
 	}
 
@@ -22,6 +40,86 @@ impl Service {
 		self.db.reset_notification_counts(user_id, room_id)
 	}
 
+	/// Recounts the unread notifications of a user in the background after
+	/// their push rules changed. A recount still running for the user is
+	/// cancelled, as it would count with outdated rules.
+	pub fn schedule_recount(&self, user_id: &UserId) {
+		let mut recounts = self.recounts.lock().expect("locked");
+		recounts.retain(|_, recount| !recount.is_finished());
+		if let Some(recount) = recounts.remove(user_id) {
+			recount.abort();
+		}
+
+		let user_id = user_id.to_owned();
+		let recount = self.server.runtime().spawn({
+			let user_id = user_id.clone();
+			async move {
+				if let Err(e) = services().rooms.user.recount_notifications(&user_id).await {
+					debug_warn!(%user_id, "Failed to recount notifications: {e}");
+				}
+			}
+		});
+
+		recounts.insert(user_id, recount);
+	}
+
+	/// Recounts the unread notifications and highlights of a user in all their
+	/// joined rooms, yielding between rooms so a recount can be cancelled.
+	pub async fn recount_notifications(&self, user_id: &UserId) -> Result<()> {
+		let ruleset = services().pusher.get_ruleset(user_id)?;
+
+		let room_ids = services()
+			.rooms
+			.state_cache
+			.rooms_joined(user_id)
+			.collect::<Result<Vec<_>>>()?;
+
+		for room_id in room_ids {
+			// new events can't be counted in between, as the state lock is held while
+			// appending them
+			let state_lock = services().globals.roomid_mutex_state.lock(&room_id).await;
+			let (notifications, highlights) = self.count_unread(user_id, &room_id, &ruleset)?;
+			self.db
+				.set_notification_counts(user_id, &room_id, notifications, highlights)?;
+			drop(state_lock);
+
+			tokio::task::yield_now().await;
+		}
+
+		Ok(())
+	}
+
+	/// Evaluates the push rules for the events since the user last read the
+	/// room, the same way they are counted when the events arrive.
+	fn count_unread(&self, user_id: &UserId, room_id: &RoomId, ruleset: &Ruleset) -> Result<(u64, u64)> {
+		let last_read = PduCount::Normal(self.last_notification_read(user_id, room_id)?);
+
+		let power_levels: RoomPowerLevelsEventContent = services()
+			.rooms
+			.state_accessor
+			.room_state_get(room_id, &StateEventType::RoomPowerLevels, "")?
+			.map(|ev| {
+				serde_json::from_str(ev.content.get())
+					.map_err(|_| Error::bad_database("invalid m.room.power_levels event"))
+			})
+			.transpose()?
+			.unwrap_or_default();
+
+		let ctx = services()
+			.pusher
+			.push_context(user_id, &power_levels, room_id)?;
+
+		let pdus = services()
+			.rooms
+			.timeline
+			.pdus_until(user_id, room_id, PduCount::max())?
+			.take(RECOUNT_LIMIT);
+
+		count_unread_pdus(user_id, last_read, pdus, |pdu| {
+			Ok(ruleset.get_actions(&pdu.to_sync_room_event(), &ctx))
+		})
+	}
+
 	pub fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
 		self.db.notification_count(user_id, room_id)
 	}
@@ -47,3 +145,40 @@ impl Service {
 		self.db.get_shared_rooms(users)
 	}
 }
+
+/// Counts the notifications and highlights among the PDUs of a room, newest
+/// first, up to the last read one. The user's own PDUs don't count.
+fn count_unread_pdus<'a>(
+	user_id: &UserId, last_read: PduCount, pdus: impl Iterator<Item = Result<(PduCount, PduEvent)>>,
+	get_actions: impl Fn(&PduEvent) -> Result<&'a [Action]>,
+) -> Result<(u64, u64)> {
+	let mut notifications: u64 = 0;
+	let mut highlights: u64 = 0;
+	for result in pdus {
+		let (count, pdu) = result?;
+		if count <= last_read {
+			break;
+		}
+
+		if pdu.sender == user_id {
+			continue;
+		}
+
+		let actions = get_actions(&pdu)?;
+		if actions
+			.iter()
+			.any(|action| matches!(action, Action::Notify))
+		{
+			notifications = notifications.saturating_add(1);
+		}
+
+		if actions
+			.iter()
+			.any(|action| matches!(action, Action::SetTweak(Tweak::Highlight(true))))
+		{
+			highlights = highlights.saturating_add(1);
+		}
+	}
+
+	Ok((notifications, highlights))
+}
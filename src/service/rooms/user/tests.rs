@@ -0,0 +1,77 @@
+#![cfg(test)]
+
+use conduit::{PduCount, Result};
+use ruma::{
+	owned_room_id, owned_user_id,
+	push::{PushConditionRoomCtx, RuleKind, Ruleset},
+	uint, OwnedUserId, UserId,
+};
+use serde_json::json;
+
+use super::count_unread_pdus;
+use crate::PduEvent;
+
+fn pdu(count: u64, sender: &UserId, body: &str) -> Result<(PduCount, PduEvent)> {
+	let pdu = serde_json::from_value(json!({
+		"event_id": format!("$event{count}"),
+		"room_id": "!room:example.com",
+		"sender": sender,
+		"origin_server_ts": count,
+		"type": "m.room.message",
+		"content": { "msgtype": "m.text", "body": body },
+		"prev_events": [],
+		"depth": count,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid PDU");
+
+	Ok((PduCount::Normal(count), pdu))
+}
+
+fn count(user_id: &UserId, ruleset: &Ruleset, last_read: u64) -> (u64, u64) {
+	let bob: OwnedUserId = owned_user_id!("@bob:example.com");
+	let pdus = vec![
+		pdu(5, &bob, "Alice, are you there?"),
+		pdu(4, user_id, "Hello"),
+		pdu(3, &bob, "Hello"),
+		pdu(2, &bob, "Alice?"),
+	];
+
+	let ctx = PushConditionRoomCtx {
+		room_id: owned_room_id!("!room:example.com"),
+		member_count: uint!(2),
+		user_id: user_id.to_owned(),
+		user_display_name: "Alice".to_owned(),
+		power_levels: None,
+		supported_features: Vec::new(),
+	};
+
+	count_unread_pdus(user_id, PduCount::Normal(last_read), pdus.into_iter(), |pdu| {
+		Ok(ruleset.get_actions(&pdu.to_sync_room_event(), &ctx))
+	})
+	.expect("counted")
+}
+
+#[test]
+fn count_unread_since_last_read() {
+	let alice = owned_user_id!("@alice:example.com");
+	let ruleset = Ruleset::server_default(&alice);
+
+	// Own events don't count
+	assert_eq!(count(&alice, &ruleset, 1), (3, 2));
+	assert_eq!(count(&alice, &ruleset, 3), (1, 1));
+	assert_eq!(count(&alice, &ruleset, 5), (0, 0));
+}
+
+#[test]
+fn recount_with_changed_rules() {
+	let alice = owned_user_id!("@alice:example.com");
+	let mut ruleset = Ruleset::server_default(&alice);
+	assert_eq!(count(&alice, &ruleset, 1), (3, 2));
+
+	ruleset
+		.set_enabled(RuleKind::Override, ".m.rule.master", true)
+		.expect("master rule exists");
+	assert_eq!(count(&alice, &ruleset, 1), (0, 0));
+}
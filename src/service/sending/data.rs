@@ -2,9 +2,9 @@ use std::sync::Arc;
 
 use conduit::{utils, Error, Result};
 use database::{Database, Map};
-use ruma::{ServerName, UserId};
+use ruma::{OwnedServerName, ServerName, UserId};
 
-use super::{Destination, SendingEvent};
+use super::{Backoff, Destination, SendingEvent};
 use crate::services;
 
 type OutgoingSendingIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Destination, SendingEvent)>> + 'a>;
@@ -14,6 +14,7 @@ pub struct Data {
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
 	servername_educount: Arc<Map>,
+	servername_backoff: Arc<Map>,
 	_db: Arc<Database>,
 }
 
@@ -23,6 +24,7 @@ impl Data {
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
 			servername_educount: db["servername_educount"].clone(),
+			servername_backoff: db["servername_backoff"].clone(),
 			_db: db,
 		}
 	}
@@ -120,6 +122,14 @@ impl Data {
 		Ok(())
 	}
 
+	/// Number of requests waiting to be sent, and being sent.
+	pub(super) fn request_counts(&self) -> (usize, usize) {
+		(
+			self.servernameevent_data.iter().count(),
+			self.servercurrentevent_data.iter().count(),
+		)
+	}
+
 	pub(super) fn set_latest_educount(&self, server_name: &ServerName, last_count: u64) -> Result<()> {
 		self.servername_educount
 			.insert(server_name.as_bytes(), &last_count.to_be_bytes())
@@ -132,6 +142,40 @@ impl Data {
 				utils::u64_from_bytes(&bytes).map_err(|_| Error::bad_database("Invalid u64 in servername_educount."))
 			})
 	}
+
+	pub(super) fn set_backoff(&self, server_name: &ServerName, backoff: &Backoff) -> Result<()> {
+		self.servername_backoff.insert(
+			server_name.as_bytes(),
+			&serde_json::to_vec(backoff).expect("Backoff serializes to json"),
+		)
+	}
+
+	pub fn get_backoff(&self, server_name: &ServerName) -> Result<Option<Backoff>> {
+		self.servername_backoff
+			.get(server_name.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid backoff in servername_backoff."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn remove_backoff(&self, server_name: &ServerName) -> Result<()> {
+		self.servername_backoff.remove(server_name.as_bytes())
+	}
+
+	pub fn backoffs(&self) -> impl Iterator<Item = Result<(OwnedServerName, Backoff)>> + '_ {
+		self.servername_backoff.iter().map(|(key, value)| {
+			let server_name = utils::string_from_bytes(&key)
+				.map_err(|_| Error::bad_database("Invalid server name bytes in servername_backoff."))?
+				.try_into()
+				.map_err(|_| Error::bad_database("Invalid server name in servername_backoff."))?;
+			let backoff = serde_json::from_slice(&value)
+				.map_err(|_| Error::bad_database("Invalid backoff in servername_backoff."))?;
+
+			Ok((server_name, backoff))
+		})
+	}
 }
 
 #[tracing::instrument(skip(key))]
@@ -3,8 +3,9 @@ mod data;
 pub mod resolve;
 mod send;
 mod sender;
+mod tests;
 
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::HashSet, fmt::Debug, iter, sync::Arc};
 
 use conduit::{Error, Result, Server};
 use data::Data;
@@ -14,6 +15,7 @@ use ruma::{
 	api::{appservice::Registration, OutgoingRequest},
 	OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 };
+use serde::{Deserialize, Serialize};
 use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::{error, warn};
 
@@ -28,6 +30,9 @@ pub struct Service {
 	handler_join: Mutex<Option<JoinHandle<()>>>,
 	startup_netburst: bool,
 	startup_netburst_keep: i64,
+	/// Servers whose backoff an admin ended, so the sender retries them
+	/// without looking up each backoff in the database
+	cleared_backoffs: std::sync::Mutex<HashSet<OwnedServerName>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -52,6 +57,28 @@ pub enum SendingEvent {
 	Flush,        // none
 }
 
+/// Backoff of a server which transactions failed to be sent to, kept across
+/// restarts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Backoff {
+	/// Failed attempts in a row
+	pub failures: u32,
+	/// When the last attempt failed, in milliseconds since the unix epoch
+	pub last_failure: u64,
+	/// Why the last attempt failed
+	pub last_error: String,
+}
+
+impl Backoff {
+	/// When the server will be retried, in milliseconds since the unix epoch
+	#[must_use]
+	pub fn next_retry(&self) -> u64 {
+		let backoff = u64::try_from(sender::backoff_duration(self.failures).as_millis()).unwrap_or(u64::MAX);
+
+		self.last_failure.saturating_add(backoff)
+	}
+}
+
 impl Service {
 	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Arc<Self>> {
 		let config = &server.config;
@@ -63,6 +90,7 @@ impl Service {
 			handler_join: Mutex::new(None),
 			startup_netburst: config.startup_netburst,
 			startup_netburst_keep: config.startup_netburst_keep,
+			cleared_backoffs: std::sync::Mutex::new(HashSet::new()),
 		}))
 	}
 
@@ -81,6 +109,24 @@ impl Service {
 		}
 	}
 
+	/// Refreshes the metrics of the sender's queues.
+	pub fn update_metrics(&self) {
+		let metrics = &services().server.metrics;
+		metrics
+			.sender_channel
+			.set(i64::try_from(self.sender.len()).unwrap_or(i64::MAX));
+
+		let (queued, active) = self.db.request_counts();
+		metrics
+			.sender_requests
+			.with_label_values(&["queued"])
+			.set(i64::try_from(queued).unwrap_or(i64::MAX));
+		metrics
+			.sender_requests
+			.with_label_values(&["active"])
+			.set(i64::try_from(active).unwrap_or(i64::MAX));
+	}
+
 	#[tracing::instrument(skip(self, pdu_id, user, pushkey))]
 	pub fn send_pdu_push(&self, pdu_id: &[u8], user: &UserId, pushkey: String) -> Result<()> {
 		let dest = Destination::Push(user.to_owned(), pushkey);
@@ -219,6 +265,34 @@ impl Service {
 		Ok(())
 	}
 
+	/// Servers we are backing off from sending to, because sending failed
+	pub fn backoffs(&self) -> Result<Vec<(OwnedServerName, Backoff)>> { self.db.backoffs().collect() }
+
+	/// Ends the backoff from a server and retries sending the pending
+	/// transactions right away.
+	pub fn retry_server(&self, server: &ServerName) -> Result<()> {
+		self.clear_backoff(server)?;
+		self.flush_servers(iter::once(server.to_owned()))
+	}
+
+	/// Drops everything queued for a server and ends the backoff from it.
+	pub fn drop_server_queue(&self, server: &ServerName) -> Result<()> {
+		let _cork = services().db.cork();
+		self.db
+			.delete_all_requests_for(&Destination::Normal(server.to_owned()))?;
+		self.clear_backoff(server)
+	}
+
+	fn clear_backoff(&self, server: &ServerName) -> Result<()> {
+		self.db.remove_backoff(server)?;
+		self.cleared_backoffs
+			.lock()
+			.expect("locked")
+			.insert(server.to_owned());
+
+		Ok(())
+	}
+
 	#[tracing::instrument(skip(self, request), name = "request")]
 	pub async fn send_federation_request<T>(&self, dest: &ServerName, request: T) -> Result<T::IncomingResponse>
 	where
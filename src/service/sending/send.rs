@@ -52,7 +52,7 @@ async fn prepare<T>(dest: &ServerName, actual: &ActualDest, req: T) -> Result<Re
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	const VERSIONS: [MatrixVersion; 1] = [MatrixVersion::V1_5];
+	const VERSIONS: [MatrixVersion; 2] = [MatrixVersion::V1_5, MatrixVersion::V1_11];
 
 	trace!("Preparing request");
 
@@ -17,16 +17,21 @@ use ruma::{
 		},
 	},
 	device_id,
-	events::{push_rules::PushRulesEvent, receipt::ReceiptType, AnySyncEphemeralRoomEvent, GlobalAccountDataEventType},
+	events::{receipt::ReceiptType, AnySyncEphemeralRoomEvent},
 	push, uint, MilliSecondsSinceUnixEpoch, OwnedServerName, OwnedUserId, RoomId, ServerName, UInt,
 };
 use tracing::{debug, error, warn};
 
-use super::{appservice, send, Destination, Msg, SendingEvent, Service};
-use crate::{presence::Presence, services, user_is_local, utils::calculate_hash, Error, PduEvent, Result};
+use super::{appservice, send, Backoff, Destination, Msg, SendingEvent, Service};
+use crate::{
+	presence::Presence,
+	services, user_is_local,
+	utils::{self, calculate_hash},
+	Error, PduEvent, Result,
+};
 
 #[derive(Debug)]
-enum TransactionStatus {
+pub(super) enum TransactionStatus {
 	Running,
 	Failed(u32, Instant), // number of times failed, time of last failure
 	Retrying(u32),        // number of times failed
@@ -36,7 +41,7 @@ type SendingError = (Destination, Error);
 type SendingResult = Result<Destination, SendingError>;
 type SendingFuture<'a> = BoxFuture<'a, SendingResult>;
 type SendingFutures<'a> = FuturesUnordered<SendingFuture<'a>>;
-type CurTransactionStatus = HashMap<Destination, TransactionStatus>;
+pub(super) type CurTransactionStatus = HashMap<Destination, TransactionStatus>;
 
 const DEQUEUE_LIMIT: usize = 48;
 const SELECT_EDU_LIMIT: usize = 16;
@@ -61,15 +66,25 @@ impl Service {
 		let mut statuses: CurTransactionStatus = CurTransactionStatus::new();
 
 		self.initial_transactions(&futures, &mut statuses);
+		set_status_metrics(&statuses);
 		loop {
 			debug_assert!(!receiver.is_closed(), "channel error");
 			tokio::select! {
 				request = receiver.recv_async() => match request {
-					Ok(request) => self.handle_request(request, &futures, &mut statuses),
+					Ok(request) => {
+						let dest = request.dest.clone();
+						let before = status_state(statuses.get(&dest));
+						self.handle_request(request, &futures, &mut statuses);
+						update_status_metrics(before, status_state(statuses.get(&dest)));
+					},
 					Err(_) => return Ok(()),
 				},
 				Some(response) = futures.next() => {
+					let (Ok(dest) | Err((dest, _))) = &response;
+					let dest = dest.clone();
+					let before = status_state(statuses.get(&dest));
 					self.handle_response(response, &mut futures, &mut statuses);
+					update_status_metrics(before, status_state(statuses.get(&dest)));
 				},
 			}
 		}
@@ -80,24 +95,40 @@ impl Service {
 	) {
 		match response {
 			Ok(dest) => self.handle_response_ok(&dest, futures, statuses),
-			Err((dest, e)) => Self::handle_response_err(dest, futures, statuses, &e),
+			Err((dest, e)) => self.handle_response_err(dest, futures, statuses, &e),
 		};
 	}
 
 	fn handle_response_err(
-		dest: Destination, _futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus, e: &Error,
+		&self, dest: Destination, _futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus, e: &Error,
 	) {
 		debug!(dest = ?dest, "{e:?}");
-		statuses.entry(dest).and_modify(|e| {
-			*e = match e {
-				TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
-				TransactionStatus::Retrying(n) => TransactionStatus::Failed(*n + 1, Instant::now()),
+		let mut failures = None;
+		statuses.entry(dest.clone()).and_modify(|status| {
+			let tries = match status {
+				TransactionStatus::Running => 1,
+				TransactionStatus::Retrying(n) => *n + 1,
 				TransactionStatus::Failed(..) => panic!("Request that was not even running failed?!"),
-			}
+			};
+
+			*status = TransactionStatus::Failed(tries, Instant::now());
+			failures = Some(tries);
 		});
+
+		if let (Destination::Normal(server_name), Some(failures)) = (&dest, failures) {
+			let backoff = Backoff {
+				failures,
+				last_failure: utils::millis_since_unix_epoch(),
+				last_error: e.to_string(),
+			};
+
+			if let Err(e) = self.db.set_backoff(server_name, &backoff) {
+				error!("Failed to store backoff for {server_name}: {e}");
+			}
+		}
 	}
 
-	fn handle_response_ok(
+	pub(super) fn handle_response_ok(
 		&self, dest: &Destination, futures: &SendingFutures<'_>, statuses: &mut CurTransactionStatus,
 	) {
 		let _cork = services().db.cork();
@@ -105,6 +136,18 @@ impl Service {
 			.delete_all_active_requests_for(dest)
 			.expect("all active requests deleted");
 
+		// Only destinations which failed before have a backoff stored, and
+		// sending to them continues as if they never failed
+		if let Some(status @ (TransactionStatus::Retrying(_) | TransactionStatus::Failed(..))) = statuses.get_mut(dest)
+		{
+			*status = TransactionStatus::Running;
+			if let Destination::Normal(server_name) = dest {
+				if let Err(e) = self.db.remove_backoff(server_name) {
+					error!("Failed to remove backoff for {server_name}: {e}");
+				}
+			}
+		}
+
 		// Find events that have been added since starting the last request
 		let new_events = self
 			.db
@@ -151,11 +194,42 @@ impl Service {
 			}
 		}
 
+		// Servers which failed before the restart stay in backoff for the time
+		// remaining. Only the part of the elapsed time which counts towards the
+		// backoff is subtracted, as an Instant can't be before the system booted.
+		let now = utils::millis_since_unix_epoch();
+		for (server_name, backoff) in self.db.backoffs().filter_map(Result::ok) {
+			let elapsed =
+				Duration::from_millis(now.saturating_sub(backoff.last_failure)).min(backoff_duration(backoff.failures));
+			let time = Instant::now()
+				.checked_sub(elapsed)
+				.unwrap_or_else(Instant::now);
+
+			statuses.insert(
+				Destination::Normal(server_name),
+				TransactionStatus::Failed(backoff.failures, time),
+			);
+		}
+
 		for (dest, events) in txns {
-			if self.startup_netburst && !events.is_empty() {
-				statuses.insert(dest.clone(), TransactionStatus::Running);
-				futures.push(Box::pin(send_events(dest.clone(), events)));
+			if !self.startup_netburst || events.is_empty() {
+				continue;
 			}
+
+			let status = match statuses.get(&dest) {
+				Some(TransactionStatus::Failed(tries, time)) => {
+					if time.elapsed() < backoff_duration(*tries) {
+						debug!(?dest, "Not sending to destination in backoff");
+						continue;
+					}
+
+					TransactionStatus::Retrying(*tries)
+				},
+				_ => TransactionStatus::Running,
+			};
+
+			statuses.insert(dest.clone(), status);
+			futures.push(Box::pin(send_events(dest.clone(), events)));
 		}
 	}
 
@@ -166,7 +240,7 @@ impl Service {
 		new_events: Vec<(SendingEvent, Vec<u8>)>, // Events we want to send: event and full key
 		statuses: &mut CurTransactionStatus,
 	) -> Result<Option<Vec<SendingEvent>>> {
-		let (allow, retry) = self.select_events_current(dest.clone(), statuses)?;
+		let (allow, retry) = self.select_events_current(dest.clone(), statuses);
 
 		// Nothing can be done for this remote, bail out.
 		if !allow {
@@ -207,18 +281,25 @@ impl Service {
 	}
 
 	#[tracing::instrument(skip_all)]
-	fn select_events_current(&self, dest: Destination, statuses: &mut CurTransactionStatus) -> Result<(bool, bool)> {
+	pub(super) fn select_events_current(&self, dest: Destination, statuses: &mut CurTransactionStatus) -> (bool, bool) {
 		let (mut allow, mut retry) = (true, false);
+
+		// An admin may have ended the backoff to retry right away
+		let cleared = match &dest {
+			Destination::Normal(server_name) => self
+				.cleared_backoffs
+				.lock()
+				.expect("locked")
+				.remove(server_name),
+			_ => false,
+		};
+
 		statuses
 			.entry(dest)
 			.and_modify(|e| match e {
 				TransactionStatus::Failed(tries, time) => {
 					// Fail if a request has failed recently (exponential backoff)
-					let max_duration = Duration::from_secs(services().globals.config.sender_retry_backoff_limit);
-					let min_duration = Duration::from_secs(services().globals.config.sender_timeout);
-					let min_elapsed_duration = min_duration * (*tries) * (*tries);
-					let min_elapsed_duration = cmp::min(min_elapsed_duration, max_duration);
-					if time.elapsed() < min_elapsed_duration {
+					if !cleared && time.elapsed() < backoff_duration(*tries) {
 						allow = false;
 					} else {
 						retry = true;
@@ -231,7 +312,7 @@ impl Service {
 			})
 			.or_insert(TransactionStatus::Running);
 
-		Ok((allow, retry))
+		(allow, retry)
 	}
 
 	#[tracing::instrument(skip_all)]
@@ -394,6 +475,62 @@ fn select_edus_receipts(
 	Ok(true)
 }
 
+/// How long to wait before retrying a destination after `tries` failures in a
+/// row
+pub(super) fn backoff_duration(tries: u32) -> Duration {
+	let max_duration = Duration::from_secs(services().globals.config.sender_retry_backoff_limit);
+	let min_duration = Duration::from_secs(services().globals.config.sender_timeout);
+	let min_elapsed_duration = min_duration.saturating_mul(tries.saturating_mul(tries));
+
+	cmp::min(min_elapsed_duration, max_duration)
+}
+
+/// Exports how many destinations are being sent to, retried after failing, or
+/// backed off from. Only called on startup; afterwards the counts follow
+/// each status change in [`update_status_metrics`].
+fn set_status_metrics(statuses: &CurTransactionStatus) {
+	let (mut running, mut retrying, mut backoff) = (0, 0, 0);
+	for status in statuses.values() {
+		match status {
+			TransactionStatus::Running => running += 1,
+			TransactionStatus::Retrying(_) => retrying += 1,
+			TransactionStatus::Failed(..) => backoff += 1,
+		}
+	}
+
+	let destinations = &services().server.metrics.sender_destinations;
+	destinations.with_label_values(&["running"]).set(running);
+	destinations.with_label_values(&["retrying"]).set(retrying);
+	destinations.with_label_values(&["backoff"]).set(backoff);
+}
+
+/// Moves a destination between the counted states after handling a request
+/// or response changed its status.
+fn update_status_metrics(before: Option<&str>, after: Option<&str>) {
+	if before == after {
+		return;
+	}
+
+	let destinations = &services().server.metrics.sender_destinations;
+	if let Some(state) = before {
+		destinations.with_label_values(&[state]).dec();
+	}
+
+	if let Some(state) = after {
+		destinations.with_label_values(&[state]).inc();
+	}
+}
+
+/// The `state` a destination is counted under by the `sender_destinations`
+/// metric.
+fn status_state(status: Option<&TransactionStatus>) -> Option<&'static str> {
+	status.map(|status| match status {
+		TransactionStatus::Running => "running",
+		TransactionStatus::Retrying(_) => "retrying",
+		TransactionStatus::Failed(..) => "backoff",
+	})
+}
+
 async fn send_events(dest: Destination, events: Vec<SendingEvent>) -> SendingResult {
 	//debug_assert!(!events.is_empty(), "sending empty transaction");
 	match dest {
@@ -514,11 +651,9 @@ async fn send_events_dest_push(
 		};
 
 		let rules_for_user = services()
-			.account_data
-			.get(None, userid, GlobalAccountDataEventType::PushRules.to_string().into())
-			.unwrap_or_default()
-			.and_then(|event| serde_json::from_str::<PushRulesEvent>(event.get()).ok())
-			.map_or_else(|| push::Ruleset::server_default(userid), |ev: PushRulesEvent| ev.content.global);
+			.pusher
+			.get_ruleset(userid)
+			.unwrap_or_else(|_| push::Ruleset::server_default(userid));
 
 		let unread: UInt = services()
 			.rooms
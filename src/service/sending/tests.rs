@@ -0,0 +1,92 @@
+#![cfg(test)]
+
+use std::time::{Duration, Instant};
+
+use futures_util::stream::FuturesUnordered;
+use ruma::{server_name, ServerName};
+
+use super::{
+	sender::{CurTransactionStatus, TransactionStatus},
+	Backoff, Destination,
+};
+use crate::tests::services;
+
+fn failed(server_name: &ServerName, tries: u32, ago: Duration) -> (Destination, CurTransactionStatus) {
+	let dest = Destination::Normal(server_name.to_owned());
+	let statuses = CurTransactionStatus::from([(
+		dest.clone(),
+		TransactionStatus::Failed(tries, Instant::now().checked_sub(ago).unwrap()),
+	)]);
+
+	(dest, statuses)
+}
+
+#[tokio::test]
+async fn backoff_holds_back_transactions() {
+	let sending = &services().await.sending;
+
+	let (dest, mut statuses) = failed(server_name!("backoff.test"), 3, Duration::ZERO);
+	assert_eq!(sending.select_events_current(dest.clone(), &mut statuses), (false, false));
+	assert!(matches!(statuses[&dest], TransactionStatus::Failed(3, _)));
+
+	let (dest, mut statuses) = failed(server_name!("backoff-over.test"), 1, Duration::from_secs(24 * 60 * 60));
+	assert_eq!(
+		sending.select_events_current(dest.clone(), &mut statuses),
+		(true, true),
+		"retried once the backoff is over"
+	);
+	assert!(matches!(statuses[&dest], TransactionStatus::Retrying(1)));
+
+	let dest = Destination::Normal(server_name!("running.test").to_owned());
+	let mut statuses = CurTransactionStatus::new();
+	assert_eq!(sending.select_events_current(dest.clone(), &mut statuses), (true, false));
+	assert_eq!(
+		sending.select_events_current(dest, &mut statuses),
+		(false, false),
+		"one transaction at a time"
+	);
+}
+
+#[tokio::test]
+async fn cleared_backoff_retries_right_away() {
+	let sending = &services().await.sending;
+	let server_name = server_name!("cleared.test");
+
+	let (dest, mut statuses) = failed(server_name, 5, Duration::ZERO);
+	sending.retry_server(server_name).unwrap();
+	assert_eq!(sending.select_events_current(dest.clone(), &mut statuses), (true, true));
+	assert!(matches!(statuses[&dest], TransactionStatus::Retrying(5)));
+
+	// only the next transaction skips the backoff
+	let (dest, mut statuses) = failed(server_name, 6, Duration::ZERO);
+	assert_eq!(sending.select_events_current(dest, &mut statuses), (false, false));
+}
+
+#[tokio::test]
+async fn successful_retry_removes_backoff() {
+	let sending = &services().await.sending;
+	let server_name = server_name!("recovered.test");
+	let has_backoff = || {
+		sending
+			.db
+			.backoffs()
+			.filter_map(Result::ok)
+			.any(|(backoff_server, _)| *backoff_server == *server_name)
+	};
+
+	let backoff = Backoff {
+		failures: 2,
+		last_failure: 0,
+		last_error: "timed out".to_owned(),
+	};
+	sending.db.set_backoff(server_name, &backoff).unwrap();
+
+	let dest = Destination::Normal(server_name.to_owned());
+	let mut statuses = CurTransactionStatus::from([(dest.clone(), TransactionStatus::Running)]);
+	sending.handle_response_ok(&dest, &FuturesUnordered::new(), &mut statuses);
+	assert!(has_backoff(), "only retried destinations have their backoff looked at");
+
+	let mut statuses = CurTransactionStatus::from([(dest.clone(), TransactionStatus::Retrying(2))]);
+	sending.handle_response_ok(&dest, &FuturesUnordered::new(), &mut statuses);
+	assert!(!has_backoff());
+}
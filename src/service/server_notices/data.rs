@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use ruma::{OwnedRoomId, RoomId, UserId};
+
+pub(super) struct Data {
+	userid_servernoticeroom: Arc<Map>,
+	global: Arc<Map>,
+}
+
+/// Key in `global` of the account created to send server notices
+const NOTICES_USER_KEY: &[u8] = b"server_notices_user";
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			userid_servernoticeroom: db["userid_servernoticeroom"].clone(),
+			global: db["global"].clone(),
+		}
+	}
+
+	/// Room the user was last sent server notices in
+	pub(super) fn room(&self, user_id: &UserId) -> Result<Option<OwnedRoomId>> {
+		self.userid_servernoticeroom
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				utils::string_from_bytes(&bytes)
+					.ok()
+					.and_then(|room_id| RoomId::parse(room_id).ok())
+					.ok_or_else(|| Error::bad_database("Invalid room ID in userid_servernoticeroom."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set_room(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
+		self.userid_servernoticeroom
+			.insert(user_id.as_bytes(), room_id.as_bytes())
+	}
+
+	/// Whether the account was created to send server notices
+	pub(super) fn is_notices_user(&self, user_id: &UserId) -> Result<bool> {
+		Ok(self
+			.global
+			.get(NOTICES_USER_KEY)?
+			.is_some_and(|bytes| bytes == user_id.as_bytes()))
+	}
+
+	pub(super) fn set_notices_user(&self, user_id: &UserId) -> Result<()> {
+		self.global.insert(NOTICES_USER_KEY, user_id.as_bytes())
+	}
+}
@@ -0,0 +1,315 @@
+mod data;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduit::{Error, Result, Server};
+use data::Data;
+use database::Database;
+use ruma::{
+	api::client::error::ErrorKind,
+	events::{
+		room::{
+			create::RoomCreateEventContent,
+			guest_access::{GuestAccess, RoomGuestAccessEventContent},
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			name::RoomNameEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::{TagEvent, TagEventContent, TagInfo, TagName},
+		RoomAccountDataEventType, TimelineEventType,
+	},
+	EventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+};
+use serde_json::value::to_raw_value;
+use tracing::{info, warn};
+
+use crate::{pdu::PduBuilder, services, user_is_local};
+
+/// Sends server notices: messages from the server to its local users, each in
+/// a room of their own with the notices user, tagged `m.server_notice`.
+pub struct Service {
+	db: Data,
+	user: Option<OwnedUserId>,
+	displayname: String,
+	room_name: String,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		let config = &server.config.server_notices;
+		let user = config
+			.localpart
+			.as_ref()
+			.map(|localpart| {
+				UserId::parse_with_server_name(localpart.as_str(), &server.config.server_name)
+					.map_err(|e| Error::Err(format!("Invalid server notices localpart {localpart:?}: {e}")))
+			})
+			.transpose()?;
+
+		Ok(Self {
+			db: Data::new(db),
+			user,
+			displayname: config.displayname.clone(),
+			room_name: config.room_name.clone(),
+		})
+	}
+
+	/// The user sending server notices, if enabled
+	#[must_use]
+	pub fn user(&self) -> Option<&UserId> { self.user.as_deref() }
+
+	/// Whether the user ID is reserved for sending server notices
+	#[must_use]
+	pub fn is_notices_user(&self, user_id: &UserId) -> bool { self.user.as_deref() == Some(user_id) }
+
+	/// Sends a server notice to a local user, creating their server notices
+	/// room if they don't have one they are in.
+	pub async fn send(&self, user_id: &UserId, content: &RoomMessageEventContent) -> Result<Arc<EventId>> {
+		let notices_user = self.enabled_user()?;
+		if !user_is_local(user_id)
+			|| user_id == notices_user
+			|| user_id == services().globals.server_user
+			|| !services().users.exists(user_id)?
+			|| services().users.is_deactivated(user_id)?
+		{
+			return Err(Error::BadRequest(
+				ErrorKind::InvalidParam,
+				"Server notices can only be sent to active local users.",
+			));
+		}
+
+		let room_id = self.room(notices_user, user_id).await?;
+
+		let state_lock = services().globals.roomid_mutex_state.lock(&room_id).await;
+		services()
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: TimelineEventType::RoomMessage,
+					content: to_raw_value(content).expect("event is valid, we just created it"),
+					unsigned: None,
+					state_key: None,
+					redacts: None,
+				},
+				notices_user,
+				&room_id,
+				&state_lock,
+			)
+			.await
+	}
+
+	/// Sends a server notice to every active local user, returning how many
+	/// were sent.
+	pub async fn send_all(&self, content: &RoomMessageEventContent) -> Result<usize> {
+		self.enabled_user()?;
+
+		let users: Vec<_> = services()
+			.users
+			.iter()
+			.filter_map(Result::ok)
+			.filter(|user_id| {
+				user_is_local(user_id) && !self.is_notices_user(user_id) && *user_id != services().globals.server_user
+			})
+			.collect();
+
+		let mut sent: usize = 0;
+		for user_id in users {
+			if services().users.is_deactivated(&user_id)? {
+				continue;
+			}
+
+			match self.send(&user_id, content).await {
+				Ok(_) => sent = sent.saturating_add(1),
+				Err(e) => warn!("Failed to send server notice to {user_id}: {e}"),
+			}
+		}
+
+		Ok(sent)
+	}
+
+	/// Refuses to start when the configured notices localpart belongs to an
+	/// account which wasn't created to send notices, as notices would
+	/// otherwise be sent as that user.
+	pub fn check_user(&self) -> Result<()> {
+		let Some(user) = self.user.as_deref() else {
+			return Ok(());
+		};
+
+		if !services().users.exists(user)? || self.db.is_notices_user(user)? {
+			return Ok(());
+		}
+
+		Err(Error::bad_config(
+			"The \"server_notices.localpart\" is already taken by another account. Please choose a localpart which \
+			 isn't in use, server notices can't be sent as an existing user.",
+		))
+	}
+
+	fn enabled_user(&self) -> Result<&UserId> {
+		self.user.as_deref().ok_or_else(|| {
+			Error::Err("Server notices are disabled, set `server_notices.localpart` to enable them.".to_owned())
+		})
+	}
+
+	/// The user's server notices room, created and the user invited if they
+	/// left or never had one
+	async fn room(&self, notices_user: &UserId, user_id: &UserId) -> Result<OwnedRoomId> {
+		if let Some(room_id) = self.db.room(user_id)? {
+			if services().rooms.state_cache.is_joined(user_id, &room_id)?
+				|| services().rooms.state_cache.is_invited(user_id, &room_id)?
+			{
+				return Ok(room_id);
+			}
+		}
+
+		if !services().users.exists(notices_user)? {
+			services().users.create(notices_user, None)?;
+			self.db.set_notices_user(notices_user)?;
+			services()
+				.users
+				.set_displayname(notices_user, Some(self.displayname.clone()))
+				.await?;
+		} else if !self.db.is_notices_user(notices_user)? {
+			return Err(Error::Err(format!(
+				"{notices_user} is not the server notices account, refusing to send notices as them."
+			)));
+		}
+
+		let room_id = self.create_room(notices_user, user_id).await?;
+		self.db.set_room(user_id, &room_id)?;
+		info!("Created server notices room {room_id} for {user_id}");
+
+		Ok(room_id)
+	}
+
+	async fn create_room(&self, notices_user: &UserId, user_id: &UserId) -> Result<OwnedRoomId> {
+		let room_id = RoomId::new(services().globals.server_name());
+		services().rooms.short.get_or_create_shortroomid(&room_id)?;
+
+		let state_lock = services().globals.roomid_mutex_state.lock(&room_id).await;
+
+		let room_version = services().globals.default_room_version();
+		let mut create_content = match room_version {
+			RoomVersionId::V1
+			| RoomVersionId::V2
+			| RoomVersionId::V3
+			| RoomVersionId::V4
+			| RoomVersionId::V5
+			| RoomVersionId::V6
+			| RoomVersionId::V7
+			| RoomVersionId::V8
+			| RoomVersionId::V9
+			| RoomVersionId::V10 => RoomCreateEventContent::new_v1(notices_user.to_owned()),
+			RoomVersionId::V11 => RoomCreateEventContent::new_v11(),
+			_ => {
+				warn!("Unexpected or unsupported room version {}", room_version);
+				return Err(Error::BadRequest(
+					ErrorKind::BadJson,
+					"Unexpected or unsupported room version found",
+				));
+			},
+		};
+		create_content.federate = false;
+		create_content.room_version = room_version;
+
+		// Only the notices user may send messages
+		let mut users = BTreeMap::new();
+		users.insert(notices_user.to_owned(), 100.into());
+
+		let member = |membership| RoomMemberEventContent {
+			membership,
+			displayname: None,
+			avatar_url: None,
+			is_direct: None,
+			third_party_invite: None,
+			blurhash: None,
+			reason: None,
+			join_authorized_via_users_server: None,
+		};
+
+		let events = [
+			(TimelineEventType::RoomCreate, to_raw_value(&create_content), String::new()),
+			(
+				TimelineEventType::RoomMember,
+				to_raw_value(&member(MembershipState::Join)),
+				notices_user.to_string(),
+			),
+			(
+				TimelineEventType::RoomPowerLevels,
+				to_raw_value(&RoomPowerLevelsEventContent {
+					users,
+					events_default: 100.into(),
+					..Default::default()
+				}),
+				String::new(),
+			),
+			(
+				TimelineEventType::RoomJoinRules,
+				to_raw_value(&RoomJoinRulesEventContent::new(JoinRule::Invite)),
+				String::new(),
+			),
+			(
+				TimelineEventType::RoomHistoryVisibility,
+				to_raw_value(&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared)),
+				String::new(),
+			),
+			(
+				TimelineEventType::RoomGuestAccess,
+				to_raw_value(&RoomGuestAccessEventContent::new(GuestAccess::Forbidden)),
+				String::new(),
+			),
+			(
+				TimelineEventType::RoomName,
+				to_raw_value(&RoomNameEventContent::new(self.room_name.clone())),
+				String::new(),
+			),
+			(
+				TimelineEventType::RoomMember,
+				to_raw_value(&member(MembershipState::Invite)),
+				user_id.to_string(),
+			),
+		];
+
+		for (event_type, content, state_key) in events {
+			services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type,
+						content: content.expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: Some(state_key),
+						redacts: None,
+					},
+					notices_user,
+					&room_id,
+					&state_lock,
+				)
+				.await?;
+		}
+
+		// Clients show rooms with this tag as server notices
+		let mut tags = TagEvent {
+			content: TagEventContent {
+				tags: BTreeMap::new(),
+			},
+		};
+		tags.content
+			.tags
+			.insert(TagName::ServerNotice, TagInfo::new());
+
+		services().account_data.update(
+			Some(&room_id),
+			user_id,
+			RoomAccountDataEventType::Tag,
+			&serde_json::to_value(tags).expect("to json value always works"),
+		)?;
+
+		Ok(room_id)
+	}
+}
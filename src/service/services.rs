@@ -5,24 +5,33 @@ use database::Database;
 use tracing::{debug, info, trace};
 
 use crate::{
-	account_data, admin, appservice, globals, key_backups, media, presence, pusher, rooms, sending, transaction_ids,
-	uiaa, users,
+	account_data, admin, appservice, globals, key_backups, ldap, media, presence, pusher, ratelimit,
+	registration_tokens, reports, rooms, sending, server_notices, sso, threepid, transaction_ids, uiaa, user_directory,
+	users,
 };
 
 pub struct Services {
 	pub rooms: rooms::Service,
 	pub appservice: appservice::Service,
 	pub pusher: pusher::Service,
+	pub ratelimit: ratelimit::Service,
+	pub registration_tokens: registration_tokens::Service,
+	pub reports: reports::Service,
 	pub transaction_ids: transaction_ids::Service,
 	pub uiaa: uiaa::Service,
+	pub user_directory: user_directory::Service,
 	pub users: users::Service,
 	pub account_data: account_data::Service,
 	pub presence: Arc<presence::Service>,
 	pub admin: Arc<admin::Service>,
 	pub globals: globals::Service,
 	pub key_backups: key_backups::Service,
+	pub ldap: ldap::Service,
 	pub media: media::Service,
 	pub sending: Arc<sending::Service>,
+	pub server_notices: server_notices::Service,
+	pub sso: sso::Service,
+	pub threepid: threepid::Service,
 	pub server: Arc<Server>,
 	pub db: Arc<Database>,
 }
@@ -39,6 +48,7 @@ impl Services {
 				metadata: rooms::metadata::Service::build(&server, &db)?,
 				outlier: rooms::outlier::Service::build(&server, &db)?,
 				pdu_metadata: rooms::pdu_metadata::Service::build(&server, &db)?,
+				purge: rooms::purge::Service::build(&server, &db)?,
 				read_receipt: rooms::read_receipt::Service::build(&server, &db)?,
 				search: rooms::search::Service::build(&server, &db)?,
 				short: rooms::short::Service::build(&server, &db)?,
@@ -54,15 +64,23 @@ impl Services {
 			},
 			appservice: appservice::Service::build(&server, &db)?,
 			pusher: pusher::Service::build(&server, &db)?,
+			ratelimit: ratelimit::Service::build(&server, &db)?,
+			registration_tokens: registration_tokens::Service::build(&server, &db)?,
+			reports: reports::Service::build(&server, &db)?,
 			transaction_ids: transaction_ids::Service::build(&server, &db)?,
 			uiaa: uiaa::Service::build(&server, &db)?,
+			user_directory: user_directory::Service::build(&server, &db)?,
 			users: users::Service::build(&server, &db)?,
 			account_data: account_data::Service::build(&server, &db)?,
 			presence: presence::Service::build(&server, &db)?,
 			admin: admin::Service::build(&server, &db)?,
 			key_backups: key_backups::Service::build(&server, &db)?,
+			ldap: ldap::Service::build(&server, &db)?,
 			media: media::Service::build(&server, &db)?,
 			sending: sending::Service::build(&server, &db)?,
+			server_notices: server_notices::Service::build(&server, &db)?,
+			sso: sso::Service::build(&server, &db)?,
+			threepid: threepid::Service::build(&server, &db)?,
 			globals: globals::Service::build(&server, &db)?,
 			server,
 			db,
@@ -141,6 +159,65 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 		)
 	}
 
+	/// Refreshes the metrics gauges of caches, the sender and the database
+	/// before they are exported.
+	pub async fn update_metrics(&self) {
+		let metrics = &self.server.metrics;
+		let cache = |name: &str, entries: usize, capacity: Option<usize>| {
+			metrics
+				.cache_entries
+				.with_label_values(&[name])
+				.set(i64::try_from(entries).unwrap_or(i64::MAX));
+			if let Some(capacity) = capacity {
+				metrics
+					.cache_capacity
+					.with_label_values(&[name])
+					.set(i64::try_from(capacity).unwrap_or(i64::MAX));
+			}
+		};
+
+		let (auth_chain, auth_chain_capacity) = self.rooms.auth_chain.get_cache_usage();
+		cache("auth_chain", auth_chain, Some(auth_chain_capacity));
+		let (appservice_in_room, _) = self.rooms.state_cache.get_appservice_in_room_cache_usage();
+		cache("appservice_in_room", appservice_in_room, None);
+		let (lasttimelinecount, _) = self.rooms.timeline.get_lasttimelinecount_cache_usage();
+		cache("lasttimelinecount", lasttimelinecount, None);
+		{
+			let server_visibility = self
+				.rooms
+				.state_accessor
+				.server_visibility_cache
+				.lock()
+				.expect("locked");
+			cache("server_visibility", server_visibility.len(), Some(server_visibility.capacity()));
+		}
+		{
+			let user_visibility = self
+				.rooms
+				.state_accessor
+				.user_visibility_cache
+				.lock()
+				.expect("locked");
+			cache("user_visibility", user_visibility.len(), Some(user_visibility.capacity()));
+		}
+		{
+			let stateinfo = self
+				.rooms
+				.state_compressor
+				.stateinfo_cache
+				.lock()
+				.expect("locked");
+			cache("stateinfo", stateinfo.len(), Some(stateinfo.capacity()));
+		}
+		{
+			let spacehierarchy = self.rooms.spaces.roomid_spacehierarchy_cache.lock().await;
+			cache("roomid_spacehierarchy", spacehierarchy.len(), Some(spacehierarchy.capacity()));
+		}
+
+		self.sending.update_metrics();
+		self.db.db.update_metrics();
+	}
+
 	pub async fn clear_caches(&self, amount: u32) {
 		if amount > 0 {
 			self.rooms
@@ -224,6 +301,7 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 		self.media.create_media_dir().await?;
 		globals::migrations::migrations(&self.db, &self.globals.config).await?;
 		globals::emerg_access::init_emergency_access();
+		self.server_notices.check_user()?;
 
 		self.admin.start_handler().await;
 		self.sending.start_handler().await;
@@ -240,6 +318,41 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 			}
 		}
 
+		if self.globals.config.retention_enabled {
+			let handle = rooms::timeline::retention::start_retention_task();
+
+			#[allow(clippy::let_underscore_must_use)] // needed for shutdown
+			{
+				_ = self
+					.rooms
+					.timeline
+					.retention_handle
+					.lock()
+					.await
+					.insert(handle);
+			}
+		}
+
+		let expiry_handle = users::start_sliding_sync_expiry_task();
+		#[allow(clippy::let_underscore_must_use)] // needed for shutdown
+		{
+			_ = self
+				.users
+				.sliding_sync_expiry_handle
+				.lock()
+				.await
+				.insert(expiry_handle);
+		}
+
+		if self.globals.config.media_janitor_enabled {
+			let handle = media::janitor::start_janitor_task();
+
+			#[allow(clippy::let_underscore_must_use)] // needed for shutdown
+			{
+				_ = self.media.janitor_handle.lock().await.insert(handle);
+			}
+		}
+
 		debug_info!("Services startup complete.");
 		Ok(())
 	}
@@ -267,6 +380,36 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 			}
 		}
 
+		debug!("Waiting for retention worker...");
+		if let Some(retention_handle) = self.rooms.timeline.retention_handle.lock().await.take() {
+			retention_handle.abort();
+
+			#[allow(clippy::let_underscore_must_use)]
+			{
+				_ = retention_handle.await;
+			}
+		}
+
+		debug!("Waiting for media janitor...");
+		if let Some(janitor_handle) = self.media.janitor_handle.lock().await.take() {
+			janitor_handle.abort();
+
+			#[allow(clippy::let_underscore_must_use)]
+			{
+				_ = janitor_handle.await;
+			}
+		}
+
+		debug!("Waiting for sliding sync expiry...");
+		if let Some(expiry_handle) = self.users.sliding_sync_expiry_handle.lock().await.take() {
+			expiry_handle.abort();
+
+			#[allow(clippy::let_underscore_must_use)]
+			{
+				_ = expiry_handle.await;
+			}
+		}
+
 		debug!("Waiting for admin worker...");
 		self.admin.close().await;
 
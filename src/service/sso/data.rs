@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use ruma::{OwnedUserId, UserId};
+
+pub(super) struct Data {
+	oidcsubject_userid: Arc<Map>,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			oidcsubject_userid: db["oidcsubject_userid"].clone(),
+		}
+	}
+
+	/// Links the subject of an identity provider to a local user.
+	pub(super) fn link_subject(&self, idp_id: &str, subject: &str, user_id: &UserId) -> Result<()> {
+		self.oidcsubject_userid
+			.insert(&subject_key(idp_id, subject), user_id.as_bytes())
+	}
+
+	/// Returns the local user the subject of an identity provider is linked
+	/// to.
+	pub(super) fn subject_user(&self, idp_id: &str, subject: &str) -> Result<Option<OwnedUserId>> {
+		self.oidcsubject_userid
+			.get(&subject_key(idp_id, subject))?
+			.map(|bytes| {
+				UserId::parse(
+					utils::string_from_bytes(&bytes)
+						.map_err(|_| Error::bad_database("User ID in oidcsubject_userid is invalid unicode."))?,
+				)
+				.map_err(|_| Error::bad_database("User ID in oidcsubject_userid is invalid."))
+			})
+			.transpose()
+	}
+}
+
+fn subject_key(idp_id: &str, subject: &str) -> Vec<u8> {
+	let mut key = idp_id.as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(subject.as_bytes());
+	key
+}
@@ -0,0 +1,580 @@
+mod data;
+mod tests;
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use conduit::{
+	config::{OidcProviderConfig, SsoConfig},
+	debug_info, utils, warn, Error, Result, Server,
+};
+use data::Data;
+use database::Database;
+use jsonwebtoken::{
+	jwk::{JwkSet, KeyAlgorithm},
+	Algorithm, DecodingKey, Validation,
+};
+use ruma::{api::client::error::ErrorKind, thirdparty::Medium, OwnedUserId, UserId};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::{services, user_is_local};
+
+/// Path of the endpoint identity providers redirect back to after the user
+/// logged in.
+pub const CALLBACK_PATH: &str = "/_conduwuit/client/oidc/callback";
+
+/// Path of the login fallback page, which is always trusted as redirect URL.
+const LOGIN_FALLBACK_PATH: &str = "/_matrix/static/client/login/";
+
+/// How long users have to log in at the identity provider.
+const SESSION_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// How long clients have to exchange a login token for an access token.
+const LOGIN_TOKEN_LIFETIME: Duration = Duration::from_secs(2 * 60);
+
+/// Shortest time between fetches of an identity provider's configuration and
+/// keys, so ID tokens with unknown keys can't make us flood it with requests.
+const PROVIDER_FETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+const STATE_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 32;
+const CODE_VERIFIER_LENGTH: usize = 64;
+const LOGIN_TOKEN_LENGTH: usize = 32;
+
+pub struct Service {
+	db: Data,
+	config: SsoConfig,
+	providers: RwLock<HashMap<String, Arc<Provider>>>,
+	/// When each identity provider's configuration was last fetched
+	provider_fetches: Mutex<HashMap<String, Instant>>,
+	sessions: Mutex<HashMap<String, Session>>,
+	login_tokens: Mutex<HashMap<String, LoginToken>>,
+}
+
+/// A user who completed login at an identity provider.
+pub struct SsoLogin {
+	pub user_id: OwnedUserId,
+	/// Whether the account was created by this login
+	pub created: bool,
+	/// Where to send the user's browser to, with the login token for the
+	/// client attached
+	pub redirect_url: Url,
+	/// Whether the redirect URL is trusted, else users have to confirm they
+	/// want to log in to it before being sent there
+	pub trusted: bool,
+}
+
+/// Discovered configuration and signing keys of an identity provider.
+struct Provider {
+	metadata: Metadata,
+	jwks: JwkSet,
+}
+
+/// The parts of the OpenID provider metadata we use.
+#[derive(Deserialize)]
+struct Metadata {
+	issuer: String,
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	jwks_uri: Url,
+	#[serde(default)]
+	id_token_signing_alg_values_supported: Vec<String>,
+}
+
+/// A login waiting for the user to come back from the identity provider,
+/// keyed by the `state` parameter.
+struct Session {
+	idp_id: String,
+	redirect_url: Url,
+	code_verifier: String,
+	nonce: String,
+	created: Instant,
+}
+
+struct LoginToken {
+	user_id: OwnedUserId,
+	created: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	id_token: String,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		Ok(Self {
+			db: Data::new(db),
+			config: server.config.sso.clone(),
+			providers: RwLock::new(HashMap::new()),
+			provider_fetches: Mutex::new(HashMap::new()),
+			sessions: Mutex::new(HashMap::new()),
+			login_tokens: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// The configured identity providers, in the order they're offered to
+	/// users.
+	pub fn providers(&self) -> &[OidcProviderConfig] { &self.config.providers }
+
+	/// Starts a login at an identity provider, the first configured one if
+	/// none is given. Returns the URL of the provider's authorization endpoint
+	/// to redirect the user to.
+	pub async fn authorization_url(&self, idp_id: Option<&str>, redirect_url: &str) -> Result<String> {
+		let config = match idp_id {
+			Some(idp_id) => self.provider_config(idp_id)?,
+			None => self
+				.config
+				.providers
+				.first()
+				.ok_or(Error::BadRequest(ErrorKind::Unrecognized, "SSO login is not enabled."))?,
+		};
+
+		let redirect_url = Url::parse(redirect_url)
+			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "redirectUrl is not a valid URL."))?;
+
+		let provider = self.provider(config, false).await?;
+
+		let state = utils::random_string(STATE_LENGTH);
+		let session = Session {
+			idp_id: config.id.clone(),
+			redirect_url,
+			code_verifier: utils::random_string(CODE_VERIFIER_LENGTH),
+			nonce: utils::random_string(NONCE_LENGTH),
+			created: Instant::now(),
+		};
+
+		let mut url = provider.metadata.authorization_endpoint.clone();
+		url.query_pairs_mut()
+			.append_pair("response_type", "code")
+			.append_pair("client_id", &config.client_id)
+			.append_pair("redirect_uri", &self.callback_url())
+			.append_pair("scope", &config.scopes.join(" "))
+			.append_pair("state", &state)
+			.append_pair("nonce", &session.nonce)
+			.append_pair("code_challenge", &code_challenge(&session.code_verifier))
+			.append_pair("code_challenge_method", "S256");
+
+		let mut sessions = self.sessions.lock().expect("locked");
+		sessions.retain(|_, session| session.created.elapsed() < SESSION_LIFETIME);
+		sessions.insert(state, session);
+
+		Ok(url.into())
+	}
+
+	/// Finishes a login when the identity provider redirects back with an
+	/// authorization code: exchanges the code for an ID token, validates it
+	/// and maps it to a local user, creating the account if needed.
+	pub async fn complete_login(&self, state: &str, code: &str) -> Result<SsoLogin> {
+		let session = self
+			.sessions
+			.lock()
+			.expect("locked")
+			.remove(state)
+			.filter(|session| session.created.elapsed() < SESSION_LIFETIME)
+			.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Unknown or expired SSO session."))?;
+
+		let config = self.provider_config(&session.idp_id)?;
+		let id_token = self
+			.exchange_code(config, code, &session.code_verifier)
+			.await?;
+		let claims = self
+			.validate_id_token(config, &id_token, &session.nonce)
+			.await?;
+		let (user_id, created) = self.user_for_claims(config, &claims).await?;
+
+		let trusted = self.is_trusted_redirect(&session.redirect_url);
+		let token = utils::random_string(LOGIN_TOKEN_LENGTH);
+		let mut redirect_url = session.redirect_url;
+		redirect_url
+			.query_pairs_mut()
+			.append_pair("loginToken", &token);
+
+		let mut login_tokens = self.login_tokens.lock().expect("locked");
+		login_tokens.retain(|_, token| token.created.elapsed() < LOGIN_TOKEN_LIFETIME);
+		login_tokens.insert(
+			token,
+			LoginToken {
+				user_id: user_id.clone(),
+				created: Instant::now(),
+			},
+		);
+
+		Ok(SsoLogin {
+			user_id,
+			created,
+			redirect_url,
+			trusted,
+		})
+	}
+
+	/// Redeems a login token handed to the client after SSO login. Tokens can
+	/// only be used once.
+	pub fn take_login_token(&self, token: &str) -> Option<OwnedUserId> {
+		self.login_tokens
+			.lock()
+			.expect("locked")
+			.remove(token)
+			.filter(|token| token.created.elapsed() < LOGIN_TOKEN_LIFETIME)
+			.map(|token| token.user_id)
+	}
+
+	/// Links the subject of an identity provider to an existing local user, so
+	/// they can log in to it through SSO. Returns the user it was linked to
+	/// before, if any.
+	pub fn link(&self, idp_id: &str, subject: &str, user_id: &UserId) -> Result<Option<OwnedUserId>> {
+		self.provider_config(idp_id)?;
+		let previous = self.db.subject_user(idp_id, subject)?;
+		self.db.link_subject(idp_id, subject, user_id)?;
+
+		Ok(previous)
+	}
+
+	fn provider_config(&self, idp_id: &str) -> Result<&OidcProviderConfig> {
+		self.config
+			.providers
+			.iter()
+			.find(|provider| provider.id == idp_id)
+			.ok_or(Error::BadRequest(ErrorKind::NotFound, "Unknown identity provider."))
+	}
+
+	/// Whether users can be sent to a client redirect URL without confirming
+	/// it: the login fallback page or a configured client.
+	fn is_trusted_redirect(&self, redirect_url: &Url) -> bool {
+		let fallback_url = Url::parse(&format!("{}{LOGIN_FALLBACK_PATH}", self.public_base_url()));
+
+		fallback_url
+			.iter()
+			.chain(&self.config.client_redirect_urls)
+			.any(|trusted| redirect_url_matches(redirect_url, trusted))
+	}
+
+	fn callback_url(&self) -> String { format!("{}{CALLBACK_PATH}", self.public_base_url()) }
+
+	fn public_base_url(&self) -> &str {
+		self.config
+			.public_base_url
+			.as_ref()
+			.or(services().globals.config.well_known.client.as_ref())
+			.expect("checked on startup")
+			.as_str()
+			.trim_end_matches('/')
+	}
+
+	/// Returns the discovered configuration of an identity provider, fetching
+	/// it on first use or when `refresh` is set (e.g. after a key rotation).
+	/// Fetches are at most once per [`PROVIDER_FETCH_INTERVAL`]; in between the
+	/// cached configuration is returned, or an error if there is none.
+	async fn provider(&self, config: &OidcProviderConfig, refresh: bool) -> Result<Arc<Provider>> {
+		let cached = self.providers.read().await.get(&config.id).cloned();
+		if let Some(provider) = cached.as_ref().filter(|_| !refresh) {
+			return Ok(provider.clone());
+		}
+
+		{
+			let mut fetches = self.provider_fetches.lock().expect("locked");
+			if fetches
+				.get(&config.id)
+				.is_some_and(|fetched| fetched.elapsed() < PROVIDER_FETCH_INTERVAL)
+			{
+				return cached.ok_or(Error::BadServerResponse("Identity provider is unavailable, try again later."));
+			}
+
+			fetches.insert(config.id.clone(), Instant::now());
+		}
+
+		let discovery_url = format!(
+			"{}/.well-known/openid-configuration",
+			config.issuer.as_str().trim_end_matches('/')
+		);
+		let client = &services().globals.client.default;
+		let metadata: Metadata = fetch_json(client.get(discovery_url)).await?;
+		if metadata.issuer.trim_end_matches('/') != config.issuer.as_str().trim_end_matches('/') {
+			return Err(Error::Err(format!(
+				"Identity provider {} reported issuer {}, expected {}",
+				config.id, metadata.issuer, config.issuer
+			)));
+		}
+
+		let jwks = fetch_json(client.get(metadata.jwks_uri.clone())).await?;
+		let provider = Arc::new(Provider {
+			metadata,
+			jwks,
+		});
+
+		debug_info!(idp_id = %config.id, "Discovered identity provider configuration");
+		self.providers
+			.write()
+			.await
+			.insert(config.id.clone(), provider.clone());
+
+		Ok(provider)
+	}
+
+	async fn exchange_code(&self, config: &OidcProviderConfig, code: &str, code_verifier: &str) -> Result<String> {
+		let provider = self.provider(config, false).await?;
+		let callback_url = self.callback_url();
+		let mut form = vec![
+			("grant_type", "authorization_code"),
+			("code", code),
+			("redirect_uri", &callback_url),
+			("client_id", &config.client_id),
+			("code_verifier", code_verifier),
+		];
+		if let Some(client_secret) = &config.client_secret {
+			form.push(("client_secret", client_secret));
+		}
+
+		let request = services()
+			.globals
+			.client
+			.default
+			.post(provider.metadata.token_endpoint.clone())
+			.form(&form);
+
+		let response: TokenResponse = fetch_json(request).await.map_err(|e| {
+			warn!(idp_id = %config.id, "Failed to exchange authorization code: {e}");
+			Error::BadServerResponse("Identity provider did not accept the login.")
+		})?;
+
+		Ok(response.id_token)
+	}
+
+	/// Validates the signature, issuer, audience, expiry and nonce of an ID
+	/// token and returns its claims.
+	async fn validate_id_token(
+		&self, config: &OidcProviderConfig, id_token: &str, nonce: &str,
+	) -> Result<Map<String, Value>> {
+		let invalid = |e: &dyn std::fmt::Display| {
+			warn!(idp_id = %config.id, "Invalid ID token: {e}");
+			Error::BadServerResponse("Identity provider returned an invalid ID token.")
+		};
+
+		let header = jsonwebtoken::decode_header(id_token).map_err(|e| invalid(&e))?;
+
+		let mut provider = self.provider(config, false).await?;
+		if find_key(&provider.jwks, header.kid.as_deref()).is_none() {
+			provider = self.provider(config, true).await?;
+		}
+
+		let jwk = find_key(&provider.jwks, header.kid.as_deref()).ok_or_else(|| invalid(&"no matching key"))?;
+		let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(&e))?;
+
+		let algorithms = signing_algorithms(
+			jwk.common.key_algorithm,
+			&provider.metadata.id_token_signing_alg_values_supported,
+		);
+		if !algorithms.contains(&header.alg) {
+			return Err(invalid(&format!("unexpected signing algorithm {:?}", header.alg)));
+		}
+
+		let mut validation = Validation::new(header.alg);
+		validation.algorithms = algorithms;
+		validation.set_issuer(&[&provider.metadata.issuer]);
+		validation.set_audience(&[&config.client_id]);
+		validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+		let claims = jsonwebtoken::decode::<Map<String, Value>>(id_token, &key, &validation)
+			.map_err(|e| invalid(&e))?
+			.claims;
+
+		if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+			return Err(invalid(&"nonce mismatch"));
+		}
+
+		Ok(claims)
+	}
+
+	/// Finds the local user for the subject of an ID token. Subjects seen for
+	/// the first time get a new account with the localpart from
+	/// `localpart_claim`. They are only linked to an existing account by an
+	/// admin, or with `allow_existing_users` if the token has a verified email
+	/// address bound to the account.
+	async fn user_for_claims(
+		&self, config: &OidcProviderConfig, claims: &Map<String, Value>,
+	) -> Result<(OwnedUserId, bool)> {
+		let subject = claims
+			.get("sub")
+			.and_then(Value::as_str)
+			.ok_or(Error::BadServerResponse("ID token has no subject."))?;
+
+		if let Some(user_id) = self.db.subject_user(&config.id, subject)? {
+			if services().users.is_deactivated(&user_id)? {
+				return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+			}
+
+			return Ok((user_id, false));
+		}
+
+		if config.allow_existing_users {
+			if let Some(user_id) = verified_email_user(claims)? {
+				if services().users.is_deactivated(&user_id)? {
+					return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+				}
+
+				self.db.link_subject(&config.id, subject, &user_id)?;
+				debug_info!(%user_id, idp_id = %config.id, "Linked existing user to identity provider by email");
+
+				return Ok((user_id, false));
+			}
+		}
+
+		let user_id = claims
+			.get(&config.localpart_claim)
+			.and_then(Value::as_str)
+			.and_then(localpart_from_claim)
+			.and_then(|localpart| UserId::parse_with_server_name(localpart, services().globals.server_name()).ok())
+			.filter(|user_id| !user_id.is_historical() && user_is_local(user_id))
+			.ok_or_else(|| {
+				warn!(idp_id = %config.id, "ID token has no usable {} claim", config.localpart_claim);
+				Error::BadRequest(ErrorKind::InvalidUsername, "Could not derive a username from the login.")
+			})?;
+
+		if services().users.exists(&user_id)? {
+			return Err(Error::BadRequest(
+				ErrorKind::UserInUse,
+				"An account with this username already exists.",
+			));
+		}
+
+		if !config.allow_registration {
+			return Err(Error::BadRequest(ErrorKind::forbidden(), "Registration has been disabled."));
+		}
+
+		if services()
+			.globals
+			.forbidden_usernames()
+			.is_match(user_id.localpart())
+		{
+			return Err(Error::BadRequest(ErrorKind::Unknown, "Username is forbidden."));
+		}
+
+		if services().appservice.is_exclusive_user_id(&user_id).await {
+			return Err(Error::BadRequest(ErrorKind::Exclusive, "User ID reserved by appservice."));
+		}
+
+		let displayname = config
+			.displayname_claim
+			.as_ref()
+			.and_then(|claim| claims.get(claim))
+			.and_then(Value::as_str)
+			.filter(|displayname| !displayname.is_empty())
+			.map(ToOwned::to_owned);
+
+		services()
+			.users
+			.create_external(&user_id, displayname, "SSO")
+			.await?;
+		self.db.link_subject(&config.id, subject, &user_id)?;
+
+		Ok((user_id, true))
+	}
+}
+
+/// The local user an ID token's `email` claim is bound to as 3PID, if the
+/// identity provider verified the address.
+fn verified_email_user(claims: &Map<String, Value>) -> Result<Option<OwnedUserId>> {
+	if claims.get("email_verified").and_then(Value::as_bool) != Some(true) {
+		return Ok(None);
+	}
+
+	let Some(email) = claims.get("email").and_then(Value::as_str) else {
+		return Ok(None);
+	};
+
+	services().threepid.user(&Medium::Email, email)
+}
+
+/// The PKCE code challenge for a code verifier, using the `S256` method.
+fn code_challenge(code_verifier: &str) -> String {
+	general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Turns a claim into a localpart: lowercased, with characters not allowed in
+/// user IDs replaced by underscores.
+fn localpart_from_claim(claim: &str) -> Option<String> {
+	let localpart: String = claim
+		.trim()
+		.to_lowercase()
+		.chars()
+		.map(|c| match c {
+			'a'..='z' | '0'..='9' | '.' | '_' | '=' | '-' | '/' => c,
+			_ => '_',
+		})
+		.collect();
+
+	(!localpart.is_empty()).then_some(localpart)
+}
+
+/// Whether a client redirect URL starts with a trusted one: same scheme, host
+/// and port, below its path.
+fn redirect_url_matches(redirect_url: &Url, trusted: &Url) -> bool {
+	redirect_url.scheme() == trusted.scheme()
+		&& redirect_url.host() == trusted.host()
+		&& redirect_url.port_or_known_default() == trusted.port_or_known_default()
+		&& redirect_url.path().starts_with(trusted.path())
+}
+
+/// The algorithms ID tokens signed with a key are accepted with: the one the
+/// key is published for, else those the provider announces, RS256 if it
+/// doesn't. Symmetric algorithms are never accepted, as they would verify with
+/// a secret derived from public key material, and neither is `none`.
+fn signing_algorithms(key_algorithm: Option<KeyAlgorithm>, supported: &[String]) -> Vec<Algorithm> {
+	let algorithms = match key_algorithm {
+		Some(key_algorithm) => match key_algorithm {
+			KeyAlgorithm::ES256 => vec![Algorithm::ES256],
+			KeyAlgorithm::ES384 => vec![Algorithm::ES384],
+			KeyAlgorithm::RS256 => vec![Algorithm::RS256],
+			KeyAlgorithm::RS384 => vec![Algorithm::RS384],
+			KeyAlgorithm::RS512 => vec![Algorithm::RS512],
+			KeyAlgorithm::PS256 => vec![Algorithm::PS256],
+			KeyAlgorithm::PS384 => vec![Algorithm::PS384],
+			KeyAlgorithm::PS512 => vec![Algorithm::PS512],
+			KeyAlgorithm::EdDSA => vec![Algorithm::EdDSA],
+			// Symmetric or encryption keys
+			_ => Vec::new(),
+		},
+		None if supported.is_empty() => vec![Algorithm::RS256],
+		None => supported
+			.iter()
+			.filter_map(|algorithm| algorithm.parse().ok())
+			.collect(),
+	};
+
+	algorithms
+		.into_iter()
+		.filter(|algorithm| !matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512))
+		.collect()
+}
+
+/// Finds the signing key with the given key ID, or the only key if the token
+/// doesn't name one.
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a jsonwebtoken::jwk::Jwk> {
+	match kid {
+		Some(kid) => jwks.find(kid),
+		None if jwks.keys.len() == 1 => jwks.keys.first(),
+		None => None,
+	}
+}
+
+async fn fetch_json<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+	let response = request.send().await?;
+	let status = response.status();
+	let body = response.bytes().await?;
+	if !status.is_success() {
+		return Err(Error::Err(format!(
+			"Identity provider responded with {status}: {}",
+			String::from_utf8_lossy(&body)
+		)));
+	}
+
+	serde_json::from_slice(&body).map_err(|e| Error::Err(format!("Invalid response from identity provider: {e}")))
+}
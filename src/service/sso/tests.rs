@@ -0,0 +1,111 @@
+#![cfg(test)]
+
+use conduit::{config::OidcProviderConfig, Error};
+use jsonwebtoken::{jwk::KeyAlgorithm, Algorithm};
+use ruma::{thirdparty::Medium, user_id};
+use serde_json::{json, Map, Value};
+use url::Url;
+
+use super::{code_challenge, localpart_from_claim, redirect_url_matches, signing_algorithms, verified_email_user};
+use crate::{tests::services, threepid::Validated};
+
+#[test]
+fn pkce_code_challenge() {
+	// RFC 7636, Appendix B
+	assert_eq!(
+		code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+		"E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbjiGzQzdx4M"
+	);
+}
+
+#[test]
+fn localpart_mapping() {
+	assert_eq!(localpart_from_claim("alice").as_deref(), Some("alice"));
+	assert_eq!(localpart_from_claim(" Alice.Smith ").as_deref(), Some("alice.smith"));
+	assert_eq!(localpart_from_claim("alice@example.com").as_deref(), Some("alice_example.com"));
+	assert_eq!(localpart_from_claim("Jörg Müller").as_deref(), Some("j_rg_m_ller"));
+	assert_eq!(localpart_from_claim("  "), None);
+}
+
+#[test]
+fn trusted_redirect_urls() {
+	let trusted = Url::parse("https://app.example.com/client/").unwrap();
+	let matches = |url: &str| redirect_url_matches(&Url::parse(url).unwrap(), &trusted);
+
+	assert!(matches("https://app.example.com/client/"));
+	assert!(matches("https://app.example.com:443/client/#/login?next=1"));
+	assert!(!matches("http://app.example.com/client/"));
+	assert!(!matches("https://app.example.com.evil.com/client/"));
+	assert!(!matches("https://evil.com/?https://app.example.com/client/"));
+	assert!(!matches("https://app.example.com/other/"));
+}
+
+#[test]
+fn id_token_signing_algorithms() {
+	let supported = |algorithms: &[&str]| {
+		algorithms
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+	};
+
+	assert_eq!(
+		signing_algorithms(Some(KeyAlgorithm::ES256), &supported(&["RS256"])),
+		[Algorithm::ES256]
+	);
+	assert!(signing_algorithms(Some(KeyAlgorithm::HS256), &[]).is_empty());
+	assert_eq!(signing_algorithms(None, &[]), [Algorithm::RS256]);
+	assert_eq!(
+		signing_algorithms(None, &supported(&["none", "HS256", "RS256", "PS512"])),
+		[Algorithm::RS256, Algorithm::PS512]
+	);
+}
+
+#[tokio::test]
+async fn existing_users_by_verified_email() {
+	let services = services().await;
+	let user_id = user_id!("@sso-email:example.com");
+	services.users.create(user_id, Some("password")).unwrap();
+	services
+		.threepid
+		.add(
+			user_id,
+			&Validated {
+				medium: Medium::Email,
+				address: "sso-email@example.com".to_owned(),
+				validated_at: 0,
+			},
+		)
+		.unwrap();
+
+	let claims = |claims: Value| -> Map<String, Value> { serde_json::from_value(claims).unwrap() };
+
+	let verified = claims(json!({ "email": "SSO-Email@example.com", "email_verified": true }));
+	assert_eq!(verified_email_user(&verified).unwrap().as_deref(), Some(user_id));
+
+	let unverified = claims(json!({ "email": "sso-email@example.com", "email_verified": false }));
+	assert_eq!(verified_email_user(&unverified).unwrap(), None, "unverified addresses");
+
+	let unknown = claims(json!({ "email": "other@example.com", "email_verified": true }));
+	assert_eq!(verified_email_user(&unknown).unwrap(), None);
+}
+
+#[tokio::test]
+async fn provider_fetches_are_rate_limited() {
+	let services = services().await;
+	let config: OidcProviderConfig = serde_json::from_value(json!({
+		"id": "unreachable",
+		"issuer": "http://127.0.0.1:1/",
+		"client_id": "conduwuit",
+	}))
+	.unwrap();
+
+	let error = services.sso.provider(&config, false).await.err().unwrap();
+	assert!(matches!(error, Error::Reqwest(_)), "the identity provider was asked");
+
+	let error = services.sso.provider(&config, true).await.err().unwrap();
+	assert!(
+		matches!(error, Error::BadServerResponse(_)),
+		"failed without asking the identity provider again"
+	);
+}
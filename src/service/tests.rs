@@ -0,0 +1,95 @@
+//! Shared setup of the tests which need a database or go through
+//! `services()`, also used by the crates built on the services through the
+//! `testing` feature.
+
+use std::{
+	env, fs,
+	path::PathBuf,
+	process,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Once, OnceLock,
+	},
+};
+
+use conduit::{
+	log::{capture, Log, LogLevelReloadHandles},
+	Config, Server,
+};
+use database::Database;
+use tokio::{runtime::Runtime, sync::OnceCell};
+
+use crate::Services;
+
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+/// Opens an empty database in a new temporary directory.
+pub async fn database() -> Arc<Database> {
+	Arc::new(
+		Database::open(&Arc::new(server(None, "")))
+			.await
+			.expect("opened test database"),
+	)
+}
+
+/// Starts the services on an empty database once for all tests of the crate,
+/// for code going through `services()`. Tests share it, so each one uses rooms
+/// and users of its own.
+pub async fn services() -> &'static Services {
+	// Outlives the runtimes of single tests, for work spawned by the services
+	static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+	static STARTED: OnceCell<()> = OnceCell::const_new();
+
+	STARTED
+		.get_or_init(|| async {
+			let runtime = RUNTIME.get_or_init(|| Runtime::new().expect("built test runtime"));
+			crate::init(&Arc::new(server(Some(runtime.handle().clone()), "")))
+				.await
+				.expect("started test services");
+		})
+		.await;
+
+	crate::services()
+}
+
+/// A server with extra settings of the `[global]` section, in a new temporary
+/// directory, for services built on their own rather than by `services()`.
+pub fn server_with_config(config: &str) -> Arc<Server> { Arc::new(server(None, config)) }
+
+fn server(runtime: Option<tokio::runtime::Handle>, config: &str) -> Server {
+	let dir = test_dir().join(DATABASES.fetch_add(1, Ordering::Relaxed).to_string());
+	fs::create_dir_all(&dir).expect("created test directory");
+
+	let config_path = dir.join("conduwuit.toml");
+	fs::write(
+		&config_path,
+		format!(
+			"[global]\nserver_name = \"example.com\"\ndatabase_path = {:?}\n{config}",
+			dir.join("database")
+		),
+	)
+	.expect("wrote test config");
+
+	let config = Config::new(Some(config_path)).expect("valid test config");
+	let log = Log {
+		reload: LogLevelReloadHandles::new(Vec::new()),
+		capture: Arc::new(capture::State::new()),
+	};
+
+	Server::new(config, runtime, log)
+}
+
+/// The temporary directory holding the databases of this test process. The
+/// shared services are never dropped, so it's removed when the process exits.
+fn test_dir() -> PathBuf {
+	static REMOVE_ON_EXIT: Once = Once::new();
+
+	extern "C" fn remove_test_dir() { _ = fs::remove_dir_all(test_dir()); }
+
+	REMOVE_ON_EXIT.call_once(|| {
+		// SAFETY: the handler doesn't unwind and only touches the filesystem
+		unsafe { libc::atexit(remove_test_dir) };
+	});
+
+	env::temp_dir().join(format!("conduwuit-test-{}", process::id()))
+}
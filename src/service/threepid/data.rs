@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier, ThirdPartyIdentifierInit},
+	MilliSecondsSinceUnixEpoch, OwnedSessionId, OwnedUserId, SessionId, UInt, UserId,
+};
+
+use super::Session;
+
+pub(super) struct Data {
+	threepid_userid: Arc<Map>,
+	threepidsessionid_session: Arc<Map>,
+	userthreepid_data: Arc<Map>,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			threepid_userid: db["threepid_userid"].clone(),
+			threepidsessionid_session: db["threepidsessionid_session"].clone(),
+			userthreepid_data: db["userthreepid_data"].clone(),
+		}
+	}
+
+	/// Binds a 3PID to a user. The value holds when the 3PID was validated and
+	/// added.
+	pub(super) fn add(&self, user_id: &UserId, medium: &Medium, address: &str, validated_at: u64) -> Result<()> {
+		let threepid = threepid_key(medium, address);
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid);
+
+		let mut value = validated_at.to_be_bytes().to_vec();
+		value.extend_from_slice(&utils::millis_since_unix_epoch().to_be_bytes());
+
+		self.userthreepid_data.insert(&key, &value)?;
+		self.threepid_userid.insert(&threepid, user_id.as_bytes())
+	}
+
+	pub(super) fn remove(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<()> {
+		let threepid = threepid_key(medium, address);
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid);
+
+		self.userthreepid_data.remove(&key)?;
+		self.threepid_userid.remove(&threepid)
+	}
+
+	/// Returns the user a 3PID is bound to.
+	pub(super) fn user(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>> {
+		self.threepid_userid
+			.get(&threepid_key(medium, address))?
+			.map(|bytes| {
+				UserId::parse(
+					utils::string_from_bytes(&bytes)
+						.map_err(|_| Error::bad_database("User ID in threepid_userid is invalid unicode."))?,
+				)
+				.map_err(|_| Error::bad_database("User ID in threepid_userid is invalid."))
+			})
+			.transpose()
+	}
+
+	/// Returns the 3PIDs bound to a user.
+	pub(super) fn threepids<'a>(
+		&'a self, user_id: &UserId,
+	) -> Box<dyn Iterator<Item = Result<ThirdPartyIdentifier>> + 'a> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		let prefix_len = prefix.len();
+
+		Box::new(
+			self.userthreepid_data
+				.scan_prefix(prefix)
+				.map(move |(key, value)| {
+					let mut parts = key[prefix_len..].splitn(2, |&b| b == 0xFF);
+					let medium = parts
+						.next()
+						.and_then(|bytes| utils::string_from_bytes(bytes).ok())
+						.ok_or_else(|| Error::bad_database("Medium in userthreepid_data is invalid."))?;
+					let address = parts
+						.next()
+						.and_then(|bytes| utils::string_from_bytes(bytes).ok())
+						.ok_or_else(|| Error::bad_database("Address in userthreepid_data is invalid."))?;
+
+					if value.len() != 16 {
+						return Err(Error::bad_database("Timestamps in userthreepid_data are invalid."));
+					}
+
+					let timestamp = |bytes: &[u8]| {
+						utils::u64_from_bytes(bytes)
+							.ok()
+							.and_then(|millis| UInt::new(millis))
+							.map(MilliSecondsSinceUnixEpoch)
+							.ok_or_else(|| Error::bad_database("Timestamp in userthreepid_data is invalid."))
+					};
+
+					Ok(ThirdPartyIdentifierInit {
+						address,
+						medium: medium.into(),
+						validated_at: timestamp(&value[..8])?,
+						added_at: timestamp(&value[8..])?,
+					}
+					.into())
+				}),
+		)
+	}
+
+	pub(super) fn session(&self, sid: &SessionId) -> Result<Option<Session>> {
+		self.threepidsessionid_session
+			.get(sid.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid session in threepidsessionid_session."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set_session(&self, sid: &SessionId, session: &Session) -> Result<()> {
+		self.threepidsessionid_session.insert(
+			sid.as_bytes(),
+			&serde_json::to_vec(session).expect("Session serializes to json"),
+		)
+	}
+
+	pub(super) fn remove_session(&self, sid: &SessionId) -> Result<()> {
+		self.threepidsessionid_session.remove(sid.as_bytes())
+	}
+
+	/// Returns all validation sessions, skipping any which can't be read.
+	pub(super) fn sessions(&self) -> impl Iterator<Item = (OwnedSessionId, Session)> + '_ {
+		self.threepidsessionid_session
+			.iter()
+			.filter_map(|(key, value)| {
+				let sid = utils::string_from_bytes(&key)
+					.ok()
+					.and_then(|sid| SessionId::parse(sid).ok())?;
+				let session = serde_json::from_slice(&value).ok()?;
+				Some((sid, session))
+			})
+	}
+}
+
+fn threepid_key(medium: &Medium, address: &str) -> Vec<u8> {
+	let mut key = medium.as_str().as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(address.as_bytes());
+	key
+}
@@ -0,0 +1,485 @@
+mod data;
+mod tests;
+
+use std::sync::{Arc, Mutex};
+
+use conduit::{config::ThreepidConfig, debug_info, utils, warn, Error, Result, Server};
+use data::Data;
+use database::Database;
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use rand::Rng;
+use ruma::{
+	api::client::error::ErrorKind,
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, OwnedClientSecret, OwnedSessionId, OwnedUserId, SessionId, UInt, UserId,
+};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use url::Url;
+
+use crate::services;
+
+/// Path of the endpoint verification tokens are submitted to.
+pub const SUBMIT_TOKEN_PATH: &str = "/_conduwuit/client/3pid/submit_token";
+
+const SESSION_ID_LENGTH: usize = 32;
+const EMAIL_TOKEN_LENGTH: usize = 32;
+const SMS_CODE_LENGTH: usize = 6;
+
+/// Wrong tokens after which a session is dropped, so the short codes sent by
+/// SMS can't be guessed.
+const MAX_FAILED_ATTEMPTS: u8 = 5;
+
+pub struct Service {
+	db: Data,
+	config: ThreepidConfig,
+	mailer: Option<Mailer>,
+	/// Held while a session is read and written back, as the database can't
+	/// update it atomically
+	session_lock: Mutex<()>,
+}
+
+struct Mailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+/// What a validated 3PID is going to be used for.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Purpose {
+	/// Adding the 3PID to the user's account
+	Add,
+	/// Authenticating as the user the 3PID is bound to, to reset their
+	/// password or complete UIAA
+	Authenticate,
+}
+
+/// A 3PID validated through a session.
+pub struct Validated {
+	pub medium: Medium,
+	pub address: String,
+	pub validated_at: u64,
+}
+
+/// A validation session, stored so it survives restarts.
+#[derive(Deserialize, Serialize)]
+struct Session {
+	purpose: Purpose,
+	medium: Medium,
+	address: String,
+	client_secret: OwnedClientSecret,
+	send_attempt: UInt,
+	token: String,
+	next_link: Option<String>,
+	/// When the session was started, in milliseconds since the unix epoch
+	created: u64,
+	failed_attempts: u8,
+	validated_at: Option<u64>,
+}
+
+impl Service {
+	pub fn build(server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		let config = server.config.threepid.clone();
+		let mailer = config
+			.smtp_url
+			.as_deref()
+			.map(|smtp_url| {
+				let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)
+					.map_err(|e| Error::bad_config(&format!("Invalid threepid.smtp_url: {e}")))?
+					.build();
+				let from = config
+					.email_from
+					.as_deref()
+					.unwrap_or_default()
+					.parse()
+					.map_err(|e| Error::bad_config(&format!("Invalid threepid.email_from: {e}")))?;
+
+				Ok::<_, Error>(Mailer {
+					transport,
+					from,
+				})
+			})
+			.transpose()?;
+
+		Ok(Self {
+			db: Data::new(db),
+			config,
+			mailer,
+			session_lock: Mutex::new(()),
+		})
+	}
+
+	/// Whether 3PIDs of this medium can be validated.
+	pub fn supports(&self, medium: &Medium) -> bool {
+		match medium {
+			Medium::Email => self.mailer.is_some(),
+			Medium::Msisdn => self.config.sms_webhook_url.is_some(),
+			_ => false,
+		}
+	}
+
+	/// Whether users can reset their password with a verified email address.
+	pub fn password_reset_enabled(&self) -> bool { self.config.password_reset && self.supports(&Medium::Email) }
+
+	/// Returns the 3PIDs bound to a user.
+	pub fn threepids<'a>(&'a self, user_id: &UserId) -> impl Iterator<Item = Result<ThirdPartyIdentifier>> + 'a {
+		self.db.threepids(user_id)
+	}
+
+	/// Returns the local user a 3PID is bound to.
+	pub fn user(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>> {
+		self.db.user(medium, &normalize(medium, address))
+	}
+
+	/// Binds a validated 3PID to a user.
+	pub fn add(&self, user_id: &UserId, threepid: &Validated) -> Result<()> {
+		if self.db.user(&threepid.medium, &threepid.address)?.is_some() {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidInUse,
+				"Third party identifier is already in use.",
+			));
+		}
+
+		self.db
+			.add(user_id, &threepid.medium, &threepid.address, threepid.validated_at)
+	}
+
+	/// Unbinds a 3PID from a user. Returns whether it was bound to them.
+	pub fn remove(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<bool> {
+		let address = normalize(medium, address);
+		if self.db.user(medium, &address)?.as_deref() != Some(user_id) {
+			return Ok(false);
+		}
+
+		self.db.remove(user_id, medium, &address)?;
+		Ok(true)
+	}
+
+	/// Unbinds all 3PIDs of a user, e.g. when their account is deactivated.
+	pub fn remove_all(&self, user_id: &UserId) -> Result<()> {
+		let threepids: Vec<_> = self.threepids(user_id).filter_map(Result::ok).collect();
+		for threepid in threepids {
+			self.db
+				.remove(user_id, &threepid.medium, &threepid.address)?;
+		}
+
+		Ok(())
+	}
+
+	/// Starts validating a 3PID by sending a token to it, or returns the
+	/// existing session if the client retries with the same `send_attempt`.
+	/// A higher `send_attempt` sends a new token for the same session, which
+	/// keeps counting the wrong tokens submitted to it.
+	///
+	/// Asking to authenticate with a 3PID which isn't bound to any account
+	/// gets a session as well, but nothing is sent, so the response doesn't
+	/// tell whether the address is known.
+	pub async fn request_token(
+		&self, purpose: Purpose, medium: Medium, address: &str, client_secret: &ClientSecret, send_attempt: UInt,
+		next_link: Option<String>,
+	) -> Result<OwnedSessionId> {
+		if !self.supports(&medium) {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidMediumNotSupported,
+				"This third party identifier medium is not supported.",
+			));
+		}
+
+		let address = normalize(&medium, address);
+		if address.is_empty() {
+			return Err(Error::BadRequest(ErrorKind::InvalidParam, "Invalid third party identifier."));
+		}
+
+		let is_bound = self.db.user(&medium, &address)?.is_some();
+		if purpose == Purpose::Add && is_bound {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidInUse,
+				"Third party identifier is already in use.",
+			));
+		}
+
+		let existing = {
+			let _lock = self.session_lock.lock().expect("locked");
+			self.remove_expired_sessions()?;
+			self.db.sessions().find_map(|(sid, session)| {
+				(session.purpose == purpose
+					&& session.medium == medium
+					&& session.address == address
+					&& session.client_secret.as_str() == client_secret.as_str())
+				.then_some((sid, session.send_attempt))
+			})
+		};
+
+		if let Some((sid, last_send_attempt)) = &existing {
+			if send_attempt <= *last_send_attempt {
+				return Ok(sid.clone());
+			}
+		}
+
+		let is_resend = existing.is_some();
+		let sid = existing.map_or_else(
+			|| SessionId::parse(utils::random_string(SESSION_ID_LENGTH)).expect("random string is a valid session ID"),
+			|(sid, _)| sid,
+		);
+
+		let token = match medium {
+			Medium::Email => utils::random_string(EMAIL_TOKEN_LENGTH),
+			_ => random_code(),
+		};
+
+		match medium {
+			_ if !is_bound && purpose == Purpose::Authenticate => {
+				debug_info!(
+					medium = medium.as_str(),
+					"Not sending a token to an unknown third party identifier"
+				);
+			},
+			Medium::Email => {
+				self.send_email(purpose, &address, &sid, client_secret, &token)
+					.await?;
+			},
+			_ => self.send_sms(&address, &token).await?,
+		}
+
+		let _lock = self.session_lock.lock().expect("locked");
+		let failed_attempts = match self.db.session(&sid)? {
+			Some(session) => session.failed_attempts,
+			// dropped after too many wrong tokens while this one was sent
+			None if is_resend => {
+				return Err(Error::BadRequest(
+					ErrorKind::ThreepidAuthFailed,
+					"Unknown or expired validation session.",
+				));
+			},
+			None => 0,
+		};
+
+		self.db.set_session(
+			&sid,
+			&Session {
+				purpose,
+				medium,
+				address,
+				client_secret: client_secret.to_owned(),
+				send_attempt,
+				token,
+				next_link,
+				created: utils::millis_since_unix_epoch(),
+				failed_attempts,
+				validated_at: None,
+			},
+		)?;
+
+		Ok(sid)
+	}
+
+	/// Validates a session with the token sent to the 3PID. Returns where to
+	/// redirect the user to, if the client asked for it.
+	pub fn submit_token(&self, sid: &SessionId, client_secret: &ClientSecret, token: &str) -> Result<Option<String>> {
+		let _lock = self.session_lock.lock().expect("locked");
+		let mut session = self
+			.db
+			.session(sid)?
+			.filter(|session| session.client_secret.as_str() == client_secret.as_str())
+			.filter(|session| !self.is_expired(session))
+			.ok_or(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"Unknown or expired validation session.",
+			))?;
+
+		if !bool::from(session.token.as_bytes().ct_eq(token.as_bytes())) {
+			session.failed_attempts = session.failed_attempts.saturating_add(1);
+			if session.failed_attempts >= MAX_FAILED_ATTEMPTS {
+				self.db.remove_session(sid)?;
+			} else {
+				self.db.set_session(sid, &session)?;
+			}
+
+			return Err(Error::BadRequest(ErrorKind::ThreepidAuthFailed, "Invalid validation token."));
+		}
+
+		session.validated_at = Some(utils::millis_since_unix_epoch());
+		self.db.set_session(sid, &session)?;
+		debug_info!(medium = session.medium.as_str(), "Validated third party identifier");
+
+		Ok(session.next_link)
+	}
+
+	/// Takes a validated session for the given purpose. Sessions can only be
+	/// used once. A session for adding a 3PID which has been bound to another
+	/// account in the meantime is kept, as it wasn't used.
+	pub fn take_validated(&self, sid: &SessionId, client_secret: &ClientSecret, purpose: Purpose) -> Result<Validated> {
+		let _lock = self.session_lock.lock().expect("locked");
+		let session = self
+			.db
+			.session(sid)?
+			.filter(|session| {
+				session.purpose == purpose
+					&& session.client_secret.as_str() == client_secret.as_str()
+					&& session.validated_at.is_some()
+					&& !self.is_expired(session)
+			})
+			.ok_or(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"Third party identifier has not been validated.",
+			))?;
+
+		if purpose == Purpose::Add && self.db.user(&session.medium, &session.address)?.is_some() {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidInUse,
+				"Third party identifier is already in use.",
+			));
+		}
+
+		self.db.remove_session(sid)?;
+		Ok(Validated {
+			medium: session.medium,
+			address: session.address,
+			validated_at: session.validated_at.expect("checked above"),
+		})
+	}
+
+	/// Takes a validated session and returns the user its 3PID is bound to.
+	pub fn authenticate(&self, sid: &SessionId, client_secret: &ClientSecret) -> Result<OwnedUserId> {
+		let threepid = self.take_validated(sid, client_secret, Purpose::Authenticate)?;
+		self.db
+			.user(&threepid.medium, &threepid.address)?
+			.ok_or(Error::BadRequest(
+				ErrorKind::ThreepidNotFound,
+				"Third party identifier is not bound to any account.",
+			))
+	}
+
+	fn is_expired(&self, session: &Session) -> bool {
+		utils::millis_since_unix_epoch().saturating_sub(session.created)
+			>= self.config.token_lifetime_s.saturating_mul(1000)
+	}
+
+	/// Removes the sessions whose token can't be used anymore. Must be called
+	/// with the session lock held.
+	fn remove_expired_sessions(&self) -> Result<()> {
+		let expired: Vec<_> = self
+			.db
+			.sessions()
+			.filter(|(_, session)| self.is_expired(session))
+			.map(|(sid, _)| sid)
+			.collect();
+
+		for sid in expired {
+			self.db.remove_session(&sid)?;
+		}
+
+		Ok(())
+	}
+
+	fn submit_url(&self) -> String {
+		let base = self
+			.config
+			.public_base_url
+			.as_ref()
+			.or(services().globals.config.well_known.client.as_ref())
+			.expect("checked on startup");
+
+		format!("{}{SUBMIT_TOKEN_PATH}", base.as_str().trim_end_matches('/'))
+	}
+
+	/// The URL clients submit SMS codes to.
+	pub fn msisdn_submit_url(&self) -> String { self.submit_url() }
+
+	async fn send_email(
+		&self, purpose: Purpose, address: &str, sid: &SessionId, client_secret: &ClientSecret, token: &str,
+	) -> Result<()> {
+		let mailer = self.mailer.as_ref().expect("checked by supports()");
+		let to = address
+			.parse::<Address>()
+			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid email address."))?;
+
+		let mut link = Url::parse(&self.submit_url()).expect("valid URL");
+		link.query_pairs_mut()
+			.append_pair("sid", sid.as_str())
+			.append_pair("client_secret", client_secret.as_str())
+			.append_pair("token", token);
+
+		let server_name = services().globals.server_name();
+		let (subject, text) = match purpose {
+			Purpose::Add => (
+				format!("Verify your email address on {server_name}"),
+				format!(
+					"Someone asked to add this email address to an account on the Matrix server {server_name}.\n\nIf \
+					 that was you, open this link to verify it:\n\n{link}\n\nOtherwise you can ignore this email."
+				),
+			),
+			Purpose::Authenticate => (
+				format!("Confirm your identity on {server_name}"),
+				format!(
+					"Someone asked to reset the password of, or confirm their identity for, the account this email \
+					 address belongs to on the Matrix server {server_name}.\n\nIf that was you, open this link to \
+					 continue:\n\n{link}\n\nOtherwise you can ignore this email."
+				),
+			),
+		};
+
+		let message = Message::builder()
+			.from(mailer.from.clone())
+			.to(Mailbox::new(None, to))
+			.subject(subject)
+			.header(ContentType::TEXT_PLAIN)
+			.body(text)
+			.map_err(|e| Error::Err(format!("Failed to build email: {e}")))?;
+
+		mailer.transport.send(message).await.map_err(|e| {
+			warn!("Failed to send email: {e}");
+			Error::BadServerResponse("Failed to send the validation email.")
+		})?;
+
+		Ok(())
+	}
+
+	async fn send_sms(&self, msisdn: &str, code: &str) -> Result<()> {
+		let webhook_url = self
+			.config
+			.sms_webhook_url
+			.clone()
+			.expect("checked by supports()");
+		let body = serde_json::json!({
+			"to": format!("+{msisdn}"),
+			"body": format!("Your {} verification code is {code}", services().globals.server_name()),
+		});
+
+		let response = services()
+			.globals
+			.client
+			.default
+			.post(webhook_url)
+			.header(http::header::CONTENT_TYPE, "application/json")
+			.body(body.to_string())
+			.send()
+			.await?;
+
+		if !response.status().is_success() {
+			warn!("SMS webhook responded with {}", response.status());
+			return Err(Error::BadServerResponse("Failed to send the validation text message."));
+		}
+
+		Ok(())
+	}
+}
+
+/// Normalizes an address so the same 3PID is always stored the same way:
+/// email addresses are lowercased, phone numbers reduced to their digits.
+fn normalize(medium: &Medium, address: &str) -> String {
+	match medium {
+		Medium::Msisdn => address.chars().filter(char::is_ascii_digit).collect(),
+		_ => address.trim().to_lowercase(),
+	}
+}
+
+fn random_code() -> String {
+	let mut rng = rand::thread_rng();
+	(0..SMS_CODE_LENGTH)
+		.map(|_| char::from(b'0' + rng.gen_range(0..10)))
+		.collect()
+}
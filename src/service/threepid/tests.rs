@@ -0,0 +1,187 @@
+#![cfg(test)]
+
+use std::sync::{Arc, Mutex};
+
+use conduit::{config::ThreepidConfig, utils, Error};
+use database::Database;
+use lettre::{AsyncSmtpTransport, Tokio1Executor};
+use ruma::{api::client::error::ErrorKind, thirdparty::Medium, uint, user_id, ClientSecret, SessionId};
+
+use super::{data::Data, normalize, random_code, Mailer, Purpose, Service, Session, SMS_CODE_LENGTH};
+use crate::tests::database;
+
+/// A service which can't deliver anything, as no SMTP server is listening.
+fn service(db: &Arc<Database>) -> Service {
+	Service {
+		db: Data::new(db),
+		config: ThreepidConfig::default(),
+		mailer: Some(Mailer {
+			transport: AsyncSmtpTransport::<Tokio1Executor>::unencrypted_localhost(),
+			from: "noreply@example.com".parse().unwrap(),
+		}),
+		session_lock: Mutex::new(()),
+	}
+}
+
+#[test]
+fn normalize_addresses() {
+	assert_eq!(normalize(&Medium::Email, " Alice@Example.COM "), "alice@example.com");
+	assert_eq!(normalize(&Medium::Msisdn, "+44 (0)7700-900123"), "4407700900123");
+	assert_eq!(normalize(&Medium::Msisdn, "no digits"), "");
+}
+
+#[test]
+fn sms_codes_are_digits() {
+	let code = random_code();
+	assert_eq!(code.len(), SMS_CODE_LENGTH);
+	assert!(code.chars().all(|c| c.is_ascii_digit()));
+}
+
+#[tokio::test]
+async fn unknown_address_gets_a_session() {
+	let db = database().await;
+	let threepid = service(&db);
+	let client_secret = <&ClientSecret>::try_from("secret").unwrap();
+
+	// nothing is sent, which would fail without an SMTP server
+	let sid = threepid
+		.request_token(
+			Purpose::Authenticate,
+			Medium::Email,
+			"nobody@example.com",
+			client_secret,
+			uint!(1),
+			None,
+		)
+		.await
+		.unwrap();
+
+	let retried = threepid
+		.request_token(
+			Purpose::Authenticate,
+			Medium::Email,
+			"Nobody@example.com",
+			client_secret,
+			uint!(1),
+			None,
+		)
+		.await
+		.unwrap();
+	assert_eq!(sid, retried, "retrying returns the same session");
+
+	assert!(
+		threepid.authenticate(&sid, client_secret).is_err(),
+		"the session is never validated"
+	);
+}
+
+#[tokio::test]
+async fn sessions_survive_restarts() {
+	let db = database().await;
+	let client_secret = <&ClientSecret>::try_from("secret").unwrap();
+
+	let sid = service(&db)
+		.request_token(
+			Purpose::Authenticate,
+			Medium::Email,
+			"nobody@example.com",
+			client_secret,
+			uint!(1),
+			None,
+		)
+		.await
+		.unwrap();
+
+	let threepid = service(&db);
+	let token = threepid.db.session(&sid).unwrap().unwrap().token;
+	assert!(matches!(
+		threepid.submit_token(&sid, client_secret, "wrong"),
+		Err(Error::BadRequest(ErrorKind::ThreepidAuthFailed, _))
+	));
+	assert_eq!(threepid.db.session(&sid).unwrap().unwrap().failed_attempts, 1);
+
+	threepid.submit_token(&sid, client_secret, &token).unwrap();
+	assert!(threepid
+		.db
+		.session(&sid)
+		.unwrap()
+		.unwrap()
+		.validated_at
+		.is_some());
+}
+
+#[tokio::test]
+async fn resending_keeps_failed_attempts() {
+	let db = database().await;
+	let threepid = service(&db);
+	let client_secret = <&ClientSecret>::try_from("resend").unwrap();
+	let request = |send_attempt| {
+		threepid.request_token(
+			Purpose::Authenticate,
+			Medium::Email,
+			"resend@example.com",
+			client_secret,
+			send_attempt,
+			None,
+		)
+	};
+
+	let sid = request(uint!(1)).await.unwrap();
+	let token = threepid.db.session(&sid).unwrap().unwrap().token;
+	for _ in 0..2 {
+		assert!(threepid.submit_token(&sid, client_secret, "wrong").is_err());
+	}
+
+	assert_eq!(request(uint!(2)).await.unwrap(), sid, "same session");
+	let session = threepid.db.session(&sid).unwrap().unwrap();
+	assert_ne!(session.token, token, "a new token was sent");
+	assert_eq!(session.failed_attempts, 2);
+}
+
+#[tokio::test]
+async fn session_kept_when_threepid_in_use() {
+	let db = database().await;
+	let threepid = service(&db);
+	let client_secret = <&ClientSecret>::try_from("secret").unwrap();
+	let sid = <&SessionId>::try_from("session").unwrap();
+
+	threepid
+		.db
+		.set_session(
+			sid,
+			&Session {
+				purpose: Purpose::Add,
+				medium: Medium::Email,
+				address: "alice@example.com".to_owned(),
+				client_secret: client_secret.to_owned(),
+				send_attempt: uint!(1),
+				token: "token".to_owned(),
+				next_link: None,
+				created: utils::millis_since_unix_epoch(),
+				failed_attempts: 0,
+				validated_at: Some(utils::millis_since_unix_epoch()),
+			},
+		)
+		.unwrap();
+
+	let bob = user_id!("@bob:example.com");
+	threepid
+		.db
+		.add(bob, &Medium::Email, "alice@example.com", 0)
+		.unwrap();
+	assert!(matches!(
+		threepid.take_validated(sid, client_secret, Purpose::Add),
+		Err(Error::BadRequest(ErrorKind::ThreepidInUse, _))
+	));
+	assert!(threepid.db.session(sid).unwrap().is_some(), "session is kept");
+
+	threepid
+		.db
+		.remove(bob, &Medium::Email, "alice@example.com")
+		.unwrap();
+	let validated = threepid
+		.take_validated(sid, client_secret, Purpose::Add)
+		.unwrap();
+	assert_eq!(validated.address, "alice@example.com");
+	assert!(threepid.db.session(sid).unwrap().is_none(), "session is used up");
+}
@@ -8,7 +8,7 @@ use database::Database;
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{AuthData, AuthType, EmailIdentity, Password, UiaaInfo, UserIdentifier},
 	},
 	CanonicalJsonValue, DeviceId, UserId,
 };
@@ -48,7 +48,7 @@ impl Service {
 		)
 	}
 
-	pub fn try_auth(
+	pub async fn try_auth(
 		&self, user_id: &UserId, device_id: &DeviceId, auth: &AuthData, uiaainfo: &UiaaInfo,
 	) -> Result<(bool, UiaaInfo)> {
 		let mut uiaainfo = auth.session().map_or_else(
@@ -88,22 +88,31 @@ impl Service {
 					.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "User ID is invalid."))?;
 
 				// Check if password is correct
-				if let Some(hash) = services().users.password_hash(&user_id)? {
-					let hash_matches = hash::verify_password(password, &hash).is_ok();
-					if !hash_matches {
-						uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-							kind: ErrorKind::forbidden(),
-							message: "Invalid username or password.".to_owned(),
-						});
-						return Ok((false, uiaainfo));
-					}
+				let password_matches = if services().ldap.handles(&user_id) {
+					services().ldap.check_password(&user_id, password).await?
+				} else if let Some(hash) = services().users.password_hash(&user_id)? {
+					hash::verify_password(password, &hash).is_ok()
+				} else {
+					true
+				};
+
+				if !password_matches {
+					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+						kind: ErrorKind::forbidden(),
+						message: "Invalid username or password.".to_owned(),
+					});
+					return Ok((false, uiaainfo));
 				}
 
 				// Password was correct! Let's add it to `completed`
 				uiaainfo.completed.push(AuthType::Password);
 			},
 			AuthData::RegistrationToken(t) => {
-				if Some(t.token.trim()) == services().globals.config.registration_token.as_deref() {
+				let session = uiaainfo.session.as_ref().expect("session is set");
+				if services()
+					.registration_tokens
+					.reserve(t.token.trim(), session)?
+				{
 					uiaainfo.completed.push(AuthType::RegistrationToken);
 				} else {
 					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
@@ -113,6 +122,25 @@ impl Service {
 					return Ok((false, uiaainfo));
 				}
 			},
+			AuthData::EmailIdentity(EmailIdentity {
+				thirdparty_id_creds,
+				..
+			}) => {
+				// The session must have validated an email address bound to this user
+				let threepid_user = services()
+					.threepid
+					.authenticate(&thirdparty_id_creds.sid, &thirdparty_id_creds.client_secret);
+
+				if threepid_user.is_ok_and(|threepid_user| threepid_user == user_id) {
+					uiaainfo.completed.push(AuthType::EmailIdentity);
+				} else {
+					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+						kind: ErrorKind::ThreepidAuthFailed,
+						message: "Email address has not been validated for this account.".to_owned(),
+					});
+					return Ok((false, uiaainfo));
+				}
+			},
 			AuthData::Dummy(_) => {
 				uiaainfo.completed.push(AuthType::Dummy);
 			},
@@ -0,0 +1,77 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use conduit::{utils, Error, Result};
+use database::{Database, Map};
+use ruma::{OwnedUserId, UserId};
+
+use super::DirectoryEntry;
+
+pub(super) struct Data {
+	userdirectory_termuserid: Arc<Map>,
+	userid_userdirectory: Arc<Map>,
+}
+
+impl Data {
+	pub(super) fn new(db: &Arc<Database>) -> Self {
+		Self {
+			userdirectory_termuserid: db["userdirectory_termuserid"].clone(),
+			userid_userdirectory: db["userid_userdirectory"].clone(),
+		}
+	}
+
+	pub(super) fn get(&self, user_id: &UserId) -> Result<Option<DirectoryEntry>> {
+		self.userid_userdirectory
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid entry in userid_userdirectory."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set(&self, user_id: &UserId, entry: &DirectoryEntry, terms: &BTreeSet<String>) -> Result<()> {
+		for term in terms {
+			self.userdirectory_termuserid
+				.insert(&term_key(term, user_id), &[])?;
+		}
+
+		self.userid_userdirectory.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(entry).expect("DirectoryEntry::to_vec always works"),
+		)
+	}
+
+	pub(super) fn remove(&self, user_id: &UserId, terms: &BTreeSet<String>) -> Result<()> {
+		for term in terms {
+			self.userdirectory_termuserid
+				.remove(&term_key(term, user_id))?;
+		}
+
+		self.userid_userdirectory.remove(user_id.as_bytes())
+	}
+
+	/// Iterates over the users having a term starting with the prefix, in
+	/// order of the terms; a user is repeated for each of their matching terms
+	pub(super) fn users_with_term_prefix<'a>(
+		&'a self, prefix: &str,
+	) -> Box<dyn Iterator<Item = Result<OwnedUserId>> + 'a> {
+		Box::new(
+			self.userdirectory_termuserid
+				.scan_prefix(prefix.as_bytes().to_vec())
+				.map(|(key, _)| {
+					key.iter()
+						.position(|&b| b == 0xFF)
+						.and_then(|separator| utils::string_from_bytes(&key[separator.saturating_add(1)..]).ok())
+						.and_then(|user_id| UserId::parse(user_id).ok())
+						.ok_or_else(|| Error::bad_database("Invalid user ID in userdirectory_termuserid."))
+				}),
+		)
+	}
+}
+
+fn term_key(term: &str, user_id: &UserId) -> Vec<u8> {
+	let mut key = term.as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(user_id.as_bytes());
+	key
+}
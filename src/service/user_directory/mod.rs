@@ -0,0 +1,257 @@
+mod data;
+mod tests;
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+};
+
+use conduit::{Result, Server};
+use data::Data;
+use database::Database;
+use ruma::{
+	events::{
+		room::join_rules::{JoinRule, RoomJoinRulesEventContent},
+		StateEventType,
+	},
+	OwnedMxcUri, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{services, user_is_local};
+
+/// Most users whose terms are checked against a search, bounding the cost of
+/// short search terms matching many users
+const MAX_CANDIDATES: usize = 1000;
+
+/// Index of the users searchable in the user directory, by the words of their
+/// localpart and display name.
+///
+/// Local users are indexed from their profile, remote users from their
+/// membership events while they are joined to any room we know.
+pub struct Service {
+	db: Data,
+}
+
+/// What the user directory knows of a user.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DirectoryEntry {
+	pub display_name: Option<String>,
+	pub avatar_url: Option<OwnedMxcUri>,
+}
+
+/// Why a user is visible to the searching user, the best reason first.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Visibility {
+	SharedRoom,
+	PublicRoom,
+	LocalUser,
+}
+
+impl Service {
+	pub fn build(_server: &Arc<Server>, db: &Arc<Database>) -> Result<Self> {
+		Ok(Self {
+			db: Data::new(db),
+		})
+	}
+
+	/// Indexes the user under the terms of the entry, replacing the previous
+	/// ones
+	pub fn update(&self, user_id: &UserId, entry: DirectoryEntry) -> Result<()> {
+		let old = self.db.get(user_id)?;
+		if old.as_ref() == Some(&entry) {
+			return Ok(());
+		}
+
+		if let Some(old) = old {
+			self.db.remove(user_id, &terms(user_id, &old))?;
+		}
+
+		self.db.set(user_id, &entry, &terms(user_id, &entry))
+	}
+
+	/// Indexes a local user from their profile
+	pub fn update_local_user(&self, user_id: &UserId) -> Result<()> {
+		self.update(
+			user_id,
+			DirectoryEntry {
+				display_name: services().users.displayname(user_id)?,
+				avatar_url: services().users.avatar_url(user_id)?,
+			},
+		)
+	}
+
+	pub fn remove(&self, user_id: &UserId) -> Result<()> {
+		if let Some(entry) = self.db.get(user_id)? {
+			self.db.remove(user_id, &terms(user_id, &entry))?;
+		}
+
+		Ok(())
+	}
+
+	/// Users visible to the sender matching every word of the search term by
+	/// prefix, those sharing a room with the sender first, and whether there
+	/// were more than the limit
+	pub fn search(
+		&self, sender_user: &UserId, search_term: &str, limit: usize,
+	) -> Result<(Vec<(OwnedUserId, DirectoryEntry)>, bool)> {
+		let words = words(search_term);
+		let mut candidates = BTreeMap::new();
+		let mut limited = false;
+
+		// A full user ID, which the words of the localpart can't match
+		if let Ok(user_id) = UserId::parse(search_term.trim()) {
+			if let Some(entry) = self.db.get(&user_id)? {
+				candidates.insert(user_id, entry);
+			}
+		}
+
+		// Users sharing a room with the sender rank first, so all of them are checked
+		// rather than only those the capped scan of the index reaches
+		let mut checked = BTreeSet::new();
+		if !words.is_empty() {
+			for room_id in services().rooms.state_cache.rooms_joined(sender_user) {
+				for user_id in services().rooms.state_cache.room_members(&room_id?) {
+					let user_id = user_id?;
+					if checked.contains(&user_id) || candidates.contains_key(&user_id) {
+						continue;
+					}
+
+					let entry = self.db.get(&user_id)?;
+					checked.insert(user_id.clone());
+					if let Some(entry) = entry.filter(|entry| matches(&words, &user_id, entry)) {
+						candidates.insert(user_id, entry);
+					}
+				}
+			}
+		}
+
+		// Scan the index with the longest word, likely the most selective, and check
+		// the other words against the terms of the candidates
+		if let Some(longest) = words.iter().max_by_key(|word| word.len()) {
+			let mut scanned = 0_usize;
+			for user_id in self.db.users_with_term_prefix(longest) {
+				let user_id = user_id?;
+				if checked.contains(&user_id) || candidates.contains_key(&user_id) {
+					continue;
+				}
+
+				if scanned >= MAX_CANDIDATES {
+					limited = true;
+					break;
+				}
+
+				let Some(entry) = self.db.get(&user_id)? else {
+					continue;
+				};
+
+				scanned = scanned.saturating_add(1);
+				checked.insert(user_id.clone());
+				if matches(&words, &user_id, &entry) {
+					candidates.insert(user_id, entry);
+				}
+			}
+		}
+
+		let mut results = Vec::with_capacity(candidates.len());
+		for (user_id, entry) in candidates {
+			let Some(visibility) = self.visibility(sender_user, &user_id)? else {
+				continue;
+			};
+
+			let user_terms = terms(&user_id, &entry);
+			let exact = words.iter().any(|word| user_terms.contains(word));
+			results.push(((visibility, !exact), user_id, entry));
+		}
+
+		results.sort_by(|(a_rank, a_user, _), (b_rank, b_user, _)| (a_rank, a_user).cmp(&(b_rank, b_user)));
+		limited |= results.len() > limit;
+		results.truncate(limit);
+
+		Ok((
+			results
+				.into_iter()
+				.map(|(_, user_id, entry)| (user_id, entry))
+				.collect(),
+			limited,
+		))
+	}
+
+	/// Why the sender may find the user, if they may
+	fn visibility(&self, sender_user: &UserId, user_id: &UserId) -> Result<Option<Visibility>> {
+		if services()
+			.rooms
+			.state_cache
+			.user_sees_user(sender_user, user_id)?
+		{
+			return Ok(Some(Visibility::SharedRoom));
+		}
+
+		if is_in_public_room(user_id) {
+			return Ok(Some(Visibility::PublicRoom));
+		}
+
+		// Deactivated users left their rooms, but stay indexed under their localpart
+		if user_is_local(user_id)
+			&& user_is_local(sender_user)
+			&& services()
+				.globals
+				.config
+				.user_directory_search_all_local_users
+			&& !services().users.is_deactivated(user_id)?
+		{
+			return Ok(Some(Visibility::LocalUser));
+		}
+
+		Ok(None)
+	}
+}
+
+/// Whether the user is joined to a room anyone can join
+fn is_in_public_room(user_id: &UserId) -> bool {
+	services()
+		.rooms
+		.state_cache
+		.rooms_joined(user_id)
+		.filter_map(Result::ok)
+		.any(|room_id| {
+			services()
+				.rooms
+				.state_accessor
+				.room_state_get(&room_id, &StateEventType::RoomJoinRules, "")
+				.ok()
+				.flatten()
+				.and_then(|event| serde_json::from_str(event.content.get()).ok())
+				.map_or(false, |content: RoomJoinRulesEventContent| {
+					content.join_rule == JoinRule::Public
+				})
+		})
+}
+
+/// Whether every word is a prefix of a term of the user
+fn matches(words: &BTreeSet<String>, user_id: &UserId, entry: &DirectoryEntry) -> bool {
+	let user_terms = terms(user_id, entry);
+	words.iter().all(|word| {
+		user_terms
+			.iter()
+			.any(|term| term.starts_with(word.as_str()))
+	})
+}
+
+/// The terms indexing the user: the words of their localpart and display name
+fn terms(user_id: &UserId, entry: &DirectoryEntry) -> BTreeSet<String> {
+	let mut terms = words(user_id.localpart());
+	if let Some(display_name) = &entry.display_name {
+		terms.append(&mut words(display_name));
+	}
+
+	terms
+}
+
+/// Normalizes text into lowercase words, split on anything not alphanumeric
+fn words(text: &str) -> BTreeSet<String> {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(str::to_lowercase)
+		.collect()
+}
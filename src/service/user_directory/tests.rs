@@ -0,0 +1,87 @@
+#![cfg(test)]
+
+use ruma::{room_id, user_id, UserId};
+
+use super::{terms, words, DirectoryEntry, MAX_CANDIDATES};
+use crate::tests::services;
+
+#[test]
+fn words_are_normalized() {
+	assert_eq!(
+		words("Alice  O'Brien-Smith (she/her)")
+			.into_iter()
+			.collect::<Vec<_>>(),
+		["alice", "brien", "her", "o", "she", "smith"]
+	);
+	assert!(words("  -- ").is_empty());
+	assert_eq!(words("ÉLODIE").into_iter().collect::<Vec<_>>(), ["élodie"]);
+}
+
+#[test]
+fn terms_include_localpart_and_display_name() {
+	let entry = DirectoryEntry {
+		display_name: Some("Bob the Builder".to_owned()),
+		avatar_url: None,
+	};
+
+	assert_eq!(
+		terms(user_id!("@bob.b_42:example.com"), &entry)
+			.into_iter()
+			.collect::<Vec<_>>(),
+		["42", "b", "bob", "builder", "the"]
+	);
+	assert_eq!(
+		terms(user_id!("@carol:example.com"), &DirectoryEntry::default())
+			.into_iter()
+			.collect::<Vec<_>>(),
+		["carol"]
+	);
+}
+
+#[tokio::test]
+async fn shared_rooms_rank_first_past_the_candidate_cap() {
+	let services = services().await;
+	let directory = &services.user_directory;
+	let sender = user_id!("@searcher:example.com");
+	let friend = user_id!("@zanzibar-zz:remote.example");
+	let room_id = room_id!("!zanzibar:example.com");
+
+	// Strangers sorting before the friend in the index, more than a scan checks
+	for i in 0..=MAX_CANDIDATES {
+		let stranger = UserId::parse(format!("@zanzibar-a{i:04}:remote.example")).unwrap();
+		directory
+			.update(&stranger, DirectoryEntry::default())
+			.unwrap();
+	}
+	directory
+		.update(
+			friend,
+			DirectoryEntry {
+				display_name: Some("Zanzibar Friend".to_owned()),
+				avatar_url: None,
+			},
+		)
+		.unwrap();
+
+	for user_id in [sender, friend] {
+		services
+			.rooms
+			.state_cache
+			.mark_as_joined(user_id, room_id)
+			.unwrap();
+	}
+
+	let (results, limited) = directory.search(sender, "zanzibar", 10).unwrap();
+	assert_eq!(
+		results
+			.iter()
+			.map(|(user_id, _)| user_id.as_ref())
+			.collect::<Vec<_>>(),
+		[friend],
+		"strangers in no room we share or know are hidden"
+	);
+	assert!(limited, "the scan of the index stopped at the cap");
+
+	let (results, _) = directory.search(sender, "friend zan", 10).unwrap();
+	assert_eq!(results.len(), 1, "every word has to match");
+}
@@ -8,10 +8,13 @@ use ruma::{
 	events::{AnyToDeviceEvent, StateEventType},
 	serde::Raw,
 	uint, DeviceId, DeviceKeyAlgorithm, DeviceKeyId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedDeviceKeyId,
-	OwnedMxcUri, OwnedUserId, UInt, UserId,
+	OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
 };
 
-use crate::{services, users::clean_signatures};
+use crate::{
+	services,
+	users::{clean_signatures, AccountRestriction, DehydratedDevice, SlidingSyncConnection, SlidingSyncRoom},
+};
 
 pub struct Data {
 	userid_password: Arc<Map>,
@@ -31,6 +34,12 @@ pub struct Data {
 	keychangeid_userid: Arc<Map>,
 	todeviceid_events: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
+	userid_dehydrateddevice: Arc<Map>,
+	userdeviceconnid_slidingsync: Arc<Map>,
+	userdeviceconnroomid_slidingsyncroom: Arc<Map>,
+	userid_shadowbanned: Arc<Map>,
+	userid_suspended: Arc<Map>,
+	userid_locked: Arc<Map>,
 	_db: Arc<Database>,
 }
 
@@ -54,6 +63,12 @@ impl Data {
 			keychangeid_userid: db["keychangeid_userid"].clone(),
 			todeviceid_events: db["todeviceid_events"].clone(),
 			userfilterid_filter: db["userfilterid_filter"].clone(),
+			userid_dehydrateddevice: db["userid_dehydrateddevice"].clone(),
+			userdeviceconnid_slidingsync: db["userdeviceconnid_slidingsync"].clone(),
+			userdeviceconnroomid_slidingsyncroom: db["userdeviceconnroomid_slidingsyncroom"].clone(),
+			userid_shadowbanned: db["userid_shadowbanned"].clone(),
+			userid_suspended: db["userid_suspended"].clone(),
+			userid_locked: db["userid_locked"].clone(),
 			_db: db,
 		}
 	}
@@ -72,6 +87,46 @@ impl Data {
 			.is_empty())
 	}
 
+	pub(super) fn is_shadow_banned(&self, user_id: &UserId) -> Result<bool> {
+		Ok(self.userid_shadowbanned.get(user_id.as_bytes())?.is_some())
+	}
+
+	pub(super) fn set_shadow_banned(&self, user_id: &UserId, shadow_banned: bool) -> Result<()> {
+		if shadow_banned {
+			self.userid_shadowbanned.insert(user_id.as_bytes(), &[])
+		} else {
+			self.userid_shadowbanned.remove(user_id.as_bytes())
+		}
+	}
+
+	pub(super) fn suspension(&self, user_id: &UserId) -> Result<Option<AccountRestriction>> {
+		restriction(&self.userid_suspended, user_id)
+	}
+
+	pub(super) fn set_suspension(&self, user_id: &UserId, suspension: Option<&AccountRestriction>) -> Result<()> {
+		set_restriction(&self.userid_suspended, user_id, suspension)
+	}
+
+	pub(super) fn suspended_users<'a>(
+		&'a self,
+	) -> Box<dyn Iterator<Item = Result<(OwnedUserId, AccountRestriction)>> + 'a> {
+		restrictions(&self.userid_suspended)
+	}
+
+	pub(super) fn account_lock(&self, user_id: &UserId) -> Result<Option<AccountRestriction>> {
+		restriction(&self.userid_locked, user_id)
+	}
+
+	pub(super) fn set_account_lock(&self, user_id: &UserId, lock: Option<&AccountRestriction>) -> Result<()> {
+		set_restriction(&self.userid_locked, user_id, lock)
+	}
+
+	pub(super) fn locked_users<'a>(
+		&'a self,
+	) -> Box<dyn Iterator<Item = Result<(OwnedUserId, AccountRestriction)>> + 'a> {
+		restrictions(&self.userid_locked)
+	}
+
 	/// Returns the number of users registered on this server.
 	pub(super) fn count(&self) -> Result<usize> { Ok(self.userid_password.iter().count()) }
 
@@ -231,9 +286,10 @@ impl Data {
 		Ok(())
 	}
 
-	/// Adds a new device to a user.
+	/// Adds a new device to a user. Devices without a token can't be logged
+	/// in to.
 	pub(super) fn create_device(
-		&self, user_id: &UserId, device_id: &DeviceId, token: &str, initial_device_display_name: Option<String>,
+		&self, user_id: &UserId, device_id: &DeviceId, token: Option<&str>, initial_device_display_name: Option<String>,
 	) -> Result<()> {
 		// This method should never be called for nonexistent users. We shouldn't assert
 		// though...
@@ -260,7 +316,9 @@ impl Data {
 			.expect("Device::to_string never fails."),
 		)?;
 
-		self.set_token(user_id, device_id, token)?;
+		if let Some(token) = token {
+			self.set_token(user_id, device_id, token)?;
+		}
 
 		Ok(())
 	}
@@ -281,10 +339,24 @@ impl Data {
 		let mut prefix = userdeviceid.clone();
 		prefix.push(0xFF);
 
-		for (key, _) in self.todeviceid_events.scan_prefix(prefix) {
+		for (key, _) in self.todeviceid_events.scan_prefix(prefix.clone()) {
 			self.todeviceid_events.remove(&key)?;
 		}
 
+		// Remove sliding sync connections
+		for (key, _) in self
+			.userdeviceconnid_slidingsync
+			.scan_prefix(prefix.clone())
+		{
+			self.userdeviceconnid_slidingsync.remove(&key)?;
+		}
+		for (key, _) in self
+			.userdeviceconnroomid_slidingsyncroom
+			.scan_prefix(prefix)
+		{
+			self.userdeviceconnroomid_slidingsyncroom.remove(&key)?;
+		}
+
 		// TODO: Remove onetimekeys
 
 		self.userid_devicelistversion
@@ -794,6 +866,30 @@ impl Data {
 		Ok(events)
 	}
 
+	/// Returns up to `limit` to-device events of a device with their counts,
+	/// oldest first.
+	pub(super) fn get_to_device_events_with_count(
+		&self, user_id: &UserId, device_id: &DeviceId, limit: usize,
+	) -> Result<Vec<(u64, Raw<AnyToDeviceEvent>)>> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		prefix.extend_from_slice(device_id.as_bytes());
+		prefix.push(0xFF);
+
+		self.todeviceid_events
+			.scan_prefix(prefix)
+			.take(limit)
+			.map(|(key, value)| {
+				let count = utils::u64_from_bytes(&key[key.len() - size_of::<u64>()..key.len()])
+					.map_err(|_| Error::bad_database("ToDeviceId has invalid count bytes."))?;
+				let event = serde_json::from_slice(&value)
+					.map_err(|_| Error::bad_database("Event in todeviceid_events is invalid."))?;
+
+				Ok((count, event))
+			})
+			.collect()
+	}
+
 	pub(super) fn remove_to_device_events(&self, user_id: &UserId, device_id: &DeviceId, until: u64) -> Result<()> {
 		let mut prefix = user_id.as_bytes().to_vec();
 		prefix.push(0xFF);
@@ -920,6 +1016,178 @@ impl Data {
 			Ok(None)
 		}
 	}
+
+	pub(super) fn dehydrated_device(&self, user_id: &UserId) -> Result<Option<DehydratedDevice>> {
+		self.userid_dehydrateddevice
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid dehydrated device in userid_dehydrateddevice."))
+			})
+			.transpose()
+	}
+
+	pub(super) fn set_dehydrated_device(&self, user_id: &UserId, device: &DehydratedDevice) -> Result<()> {
+		self.userid_dehydrateddevice.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(device).expect("DehydratedDevice::to_vec always works"),
+		)
+	}
+
+	pub(super) fn remove_dehydrated_device(&self, user_id: &UserId) -> Result<()> {
+		self.userid_dehydrateddevice.remove(user_id.as_bytes())
+	}
+
+	/// Loads a sliding sync connection with the rooms sent on it, which are
+	/// stored one by one so a response only writes the rooms it changed.
+	pub(super) fn sliding_sync_connection(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str,
+	) -> Result<Option<SlidingSyncConnection>> {
+		let key = userdeviceconnid(user_id, device_id, conn_id);
+		let Some(bytes) = self.userdeviceconnid_slidingsync.get(&key)? else {
+			return Ok(None);
+		};
+
+		let mut connection: SlidingSyncConnection = serde_json::from_slice(&bytes)
+			.map_err(|_| Error::bad_database("Invalid connection in userdeviceconnid_slidingsync."))?;
+
+		let mut prefix = key;
+		prefix.push(0xFF);
+		connection.rooms = self
+			.userdeviceconnroomid_slidingsyncroom
+			.scan_prefix(prefix.clone())
+			.map(|(key, value)| {
+				let room_id = utils::string_from_bytes(&key[prefix.len()..])
+					.ok()
+					.and_then(|room_id| RoomId::parse(room_id).ok())
+					.ok_or_else(|| Error::bad_database("Invalid room ID in userdeviceconnroomid_slidingsyncroom."))?;
+				let room = serde_json::from_slice(&value)
+					.map_err(|_| Error::bad_database("Invalid room in userdeviceconnroomid_slidingsyncroom."))?;
+
+				Ok((room_id, room))
+			})
+			.collect::<Result<_>>()?;
+
+		Ok(Some(connection))
+	}
+
+	/// Stores a sliding sync connection without its rooms.
+	pub(super) fn set_sliding_sync_connection(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str, connection: &SlidingSyncConnection,
+	) -> Result<()> {
+		self.userdeviceconnid_slidingsync.insert(
+			&userdeviceconnid(user_id, device_id, conn_id),
+			&serde_json::to_vec(connection).expect("SlidingSyncConnection::to_vec always works"),
+		)
+	}
+
+	/// Stores the rooms of a sliding sync connection which changed, removing
+	/// those set to `None`.
+	pub(super) fn update_sliding_sync_rooms(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str,
+		rooms: &BTreeMap<OwnedRoomId, Option<SlidingSyncRoom>>,
+	) -> Result<()> {
+		let mut prefix = userdeviceconnid(user_id, device_id, conn_id);
+		prefix.push(0xFF);
+
+		for (room_id, room) in rooms {
+			let mut key = prefix.clone();
+			key.extend_from_slice(room_id.as_bytes());
+
+			match room {
+				Some(room) => self
+					.userdeviceconnroomid_slidingsyncroom
+					.insert(&key, &serde_json::to_vec(room).expect("SlidingSyncRoom::to_vec always works"))?,
+				None => self.userdeviceconnroomid_slidingsyncroom.remove(&key)?,
+			}
+		}
+
+		Ok(())
+	}
+
+	pub(super) fn remove_sliding_sync_connection(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str,
+	) -> Result<()> {
+		self.remove_sliding_sync_connection_key(userdeviceconnid(user_id, device_id, conn_id))
+	}
+
+	/// Removes the sliding sync connections of all devices last used before
+	/// `before`, in milliseconds since the unix epoch. Returns how many were
+	/// removed.
+	pub(super) fn expire_sliding_sync_connections(&self, before: u64) -> Result<usize> {
+		let expired: Vec<_> = self
+			.userdeviceconnid_slidingsync
+			.iter()
+			.filter(|(_, value)| {
+				serde_json::from_slice::<SlidingSyncConnection>(value)
+					.map_or(true, |connection| connection.last_used < before)
+			})
+			.map(|(key, _)| key)
+			.collect();
+
+		let removed = expired.len();
+		for key in expired {
+			self.remove_sliding_sync_connection_key(key)?;
+		}
+
+		Ok(removed)
+	}
+
+	fn remove_sliding_sync_connection_key(&self, key: Vec<u8>) -> Result<()> {
+		self.userdeviceconnid_slidingsync.remove(&key)?;
+
+		let mut prefix = key;
+		prefix.push(0xFF);
+		for (key, _) in self
+			.userdeviceconnroomid_slidingsyncroom
+			.scan_prefix(prefix)
+		{
+			self.userdeviceconnroomid_slidingsyncroom.remove(&key)?;
+		}
+
+		Ok(())
+	}
+}
+
+fn userdeviceconnid(user_id: &UserId, device_id: &DeviceId, conn_id: &str) -> Vec<u8> {
+	let mut key = user_id.as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(device_id.as_bytes());
+	key.push(0xFF);
+	key.extend_from_slice(conn_id.as_bytes());
+
+	key
+}
+
+fn restriction(map: &Map, user_id: &UserId) -> Result<Option<AccountRestriction>> {
+	map.get(user_id.as_bytes())?
+		.map(|bytes| {
+			serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid account restriction in db."))
+		})
+		.transpose()
+}
+
+fn set_restriction(map: &Map, user_id: &UserId, restriction: Option<&AccountRestriction>) -> Result<()> {
+	match restriction {
+		Some(restriction) => map.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(restriction).expect("AccountRestriction::to_vec always works"),
+		),
+		None => map.remove(user_id.as_bytes()),
+	}
+}
+
+fn restrictions(map: &Map) -> Box<dyn Iterator<Item = Result<(OwnedUserId, AccountRestriction)>> + '_> {
+	Box::new(map.iter().map(|(key, value)| {
+		let user_id = utils::string_from_bytes(&key)
+			.ok()
+			.and_then(|user_id| UserId::parse(user_id).ok())
+			.ok_or_else(|| Error::bad_database("Invalid user ID in account restrictions."))?;
+		let restriction =
+			serde_json::from_slice(&value).map_err(|_| Error::bad_database("Invalid account restriction in db."))?;
+
+		Ok((user_id, restriction))
+	}))
 }
 
 /// Will only return with Some(username) if the password was not empty and the
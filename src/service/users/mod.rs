@@ -1,17 +1,22 @@
 mod data;
+mod tests;
 
 use std::{
 	collections::{BTreeMap, BTreeSet},
+	fmt::Write as _,
 	mem,
 	sync::{Arc, Mutex, Mutex as StdMutex},
+	time::Duration,
 };
 
-use conduit::{Error, Result, Server};
+use conduit::{debug_info, info, utils, warn, Error, Result, Server};
 use data::Data;
 use database::Database;
 use ruma::{
 	api::client::{
+		dehydrated_device::DehydratedDeviceData,
 		device::Device,
+		error::ErrorKind,
 		filter::FilterDefinition,
 		sync::sync_events::{
 			self,
@@ -19,13 +24,24 @@ use ruma::{
 		},
 	},
 	encryption::{CrossSigningKey, DeviceKeys, OneTimeKey},
-	events::AnyToDeviceEvent,
+	events::{room::message::RoomMessageEventContent, AnyToDeviceEvent, GlobalAccountDataEventType, StateEventType},
+	push,
 	serde::Raw,
 	DeviceId, DeviceKeyAlgorithm, DeviceKeyId, OwnedDeviceId, OwnedDeviceKeyId, OwnedMxcUri, OwnedRoomId, OwnedUserId,
 	UInt, UserId,
 };
+use serde::{Deserialize, Serialize};
+use tokio::{task::JoinHandle, time::interval};
 
-use crate::services;
+use crate::{services, user_is_local};
+
+/// How often sliding sync connections unused for too long are removed
+const SLIDING_SYNC_EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Length of the password set for accounts created by external identity
+/// sources, which nobody knows. An empty password would mark the account as
+/// deactivated.
+const RANDOM_PASSWORD_LENGTH: usize = 64;
 
 pub struct SlidingSyncCache {
 	lists: BTreeMap<String, SyncRequestList>,
@@ -34,11 +50,73 @@ pub struct SlidingSyncCache {
 	extensions: ExtensionsConfig,
 }
 
+/// A device without access token which receives to-device events while the
+/// user is offline, to be rehydrated by their next client (MSC3814).
+#[derive(Deserialize, Serialize)]
+pub struct DehydratedDevice {
+	pub device_id: OwnedDeviceId,
+	/// Pickled device, encrypted by the client
+	pub device_data: Raw<DehydratedDeviceData>,
+}
+
+/// Why and since when an account is suspended or locked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountRestriction {
+	pub reason: Option<String>,
+	/// When the account was restricted, in milliseconds since the unix epoch
+	pub since: u64,
+}
+
+/// State of a simplified sliding sync (MSC4186) connection, persisted so
+/// clients can resume it after a restart.
+#[derive(Default, Deserialize, Serialize)]
+pub struct SlidingSyncConnection {
+	/// When the connection was last used, in milliseconds since the unix epoch
+	pub last_used: u64,
+	/// Position of the last response the client acknowledged, counting the
+	/// responses of the connection
+	pub pos: u64,
+	/// Count the acknowledged response was sent up to
+	pub since: u64,
+	/// Rooms the client received on the connection, stored apart
+	#[serde(skip)]
+	pub rooms: BTreeMap<OwnedRoomId, SlidingSyncRoom>,
+	/// The last response, which is applied to the above once the client
+	/// acknowledges receiving it by sending its position back
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub pending: Option<SlidingSyncResponse>,
+}
+
+/// What a sliding sync response changed on its connection.
+#[derive(Deserialize, Serialize)]
+pub struct SlidingSyncResponse {
+	pub pos: u64,
+	pub since: u64,
+	/// Rooms sent or sent differently, `None` for rooms removed
+	pub rooms: BTreeMap<OwnedRoomId, Option<SlidingSyncRoom>>,
+}
+
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SlidingSyncRoom {
+	/// Count the room was sent up to
+	pub since: u64,
+	/// Required state the room was sent with
+	pub required_state: BTreeSet<(StateEventType, String)>,
+	/// Whether the room was sent as an invite
+	#[serde(default)]
+	pub invited: bool,
+	/// Members whose membership event was sent, which `$LAZY` doesn't send
+	/// again
+	#[serde(default)]
+	pub lazy_members: BTreeSet<OwnedUserId>,
+}
+
 type DbConnections = Mutex<BTreeMap<(OwnedUserId, OwnedDeviceId, String), Arc<Mutex<SlidingSyncCache>>>>;
 
 pub struct Service {
 	pub db: Data,
 	pub connections: DbConnections,
+	pub sliding_sync_expiry_handle: tokio::sync::Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Service {
@@ -46,12 +124,74 @@ impl Service {
 		Ok(Self {
 			db: Data::new(db.clone()),
 			connections: StdMutex::new(BTreeMap::new()),
+			sliding_sync_expiry_handle: tokio::sync::Mutex::new(None),
 		})
 	}
 
 	/// Check if a user has an account on this homeserver.
 	pub fn exists(&self, user_id: &UserId) -> Result<bool> { self.db.exists(user_id) }
 
+	/// Loads the state of a simplified sliding sync connection at the
+	/// position the client sent, committing the last response if the client
+	/// acknowledged it. Requests without `pos` start the connection over.
+	/// Positions the connection doesn't know, or of connections unused for
+	/// `sliding_sync_connection_ttl`, are refused with `M_UNKNOWN_POS` so the
+	/// client starts over.
+	pub fn sliding_sync_connection(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str, pos: Option<u64>,
+	) -> Result<SlidingSyncConnection> {
+		let Some(pos) = pos else {
+			self.db
+				.remove_sliding_sync_connection(user_id, device_id, conn_id)?;
+
+			return Ok(SlidingSyncConnection::default());
+		};
+
+		let unknown_pos = || Error::BadRequest(ErrorKind::UnknownPos, "Unknown or expired pos, start over without it.");
+		let mut connection = self
+			.db
+			.sliding_sync_connection(user_id, device_id, conn_id)?
+			.filter(|connection| connection.last_used >= sliding_sync_expired_before())
+			.ok_or_else(unknown_pos)?;
+
+		match connection.pending.take() {
+			Some(response) if response.pos == pos => {
+				self.db
+					.update_sliding_sync_rooms(user_id, device_id, conn_id, &response.rooms)?;
+				for (room_id, room) in response.rooms {
+					match room {
+						Some(room) => connection.rooms.insert(room_id, room),
+						None => connection.rooms.remove(&room_id),
+					};
+				}
+				connection.pos = response.pos;
+				connection.since = response.since;
+			},
+			// The client retries without having received the last response
+			_ if connection.pos == pos => {},
+			_ => return Err(unknown_pos()),
+		}
+
+		Ok(connection)
+	}
+
+	/// Stores a sliding sync connection with the rooms of its last response,
+	/// to be applied once acknowledged.
+	pub fn save_sliding_sync_connection(
+		&self, user_id: &UserId, device_id: &DeviceId, conn_id: &str, connection: &mut SlidingSyncConnection,
+	) -> Result<()> {
+		connection.last_used = utils::millis_since_unix_epoch();
+		self.db
+			.set_sliding_sync_connection(user_id, device_id, conn_id, connection)
+	}
+
+	/// Removes the sliding sync connections unused for
+	/// `sliding_sync_connection_ttl`, returning how many were removed.
+	pub fn expire_sliding_sync_connections(&self) -> Result<usize> {
+		self.db
+			.expire_sliding_sync_connections(sliding_sync_expired_before())
+	}
+
 	pub fn forget_sync_request_connection(&self, user_id: OwnedUserId, device_id: OwnedDeviceId, conn_id: String) {
 		self.connections
 			.lock()
@@ -241,6 +381,54 @@ impl Service {
 	/// Check if account is deactivated
 	pub fn is_deactivated(&self, user_id: &UserId) -> Result<bool> { self.db.is_deactivated(user_id) }
 
+	/// Whether the user is shadow-banned: their events and profile changes are
+	/// accepted but silently dropped.
+	pub fn is_shadow_banned(&self, user_id: &UserId) -> Result<bool> { self.db.is_shadow_banned(user_id) }
+
+	pub fn set_shadow_banned(&self, user_id: &UserId, shadow_banned: bool) -> Result<()> {
+		self.db.set_shadow_banned(user_id, shadow_banned)
+	}
+
+	/// The suspension of the user, who may read but not send, join or upload
+	/// (MSC3823)
+	pub fn suspension(&self, user_id: &UserId) -> Result<Option<AccountRestriction>> { self.db.suspension(user_id) }
+
+	pub fn suspend(&self, user_id: &UserId, reason: Option<String>) -> Result<()> {
+		self.db.set_suspension(
+			user_id,
+			Some(&AccountRestriction {
+				reason,
+				since: utils::millis_since_unix_epoch(),
+			}),
+		)
+	}
+
+	pub fn unsuspend(&self, user_id: &UserId) -> Result<()> { self.db.set_suspension(user_id, None) }
+
+	pub fn suspended_users(&self) -> impl Iterator<Item = Result<(OwnedUserId, AccountRestriction)>> + '_ {
+		self.db.suspended_users()
+	}
+
+	/// The lock of the user's account, whose requests are all refused until
+	/// they log out (MSC3939)
+	pub fn account_lock(&self, user_id: &UserId) -> Result<Option<AccountRestriction>> { self.db.account_lock(user_id) }
+
+	pub fn lock_account(&self, user_id: &UserId, reason: Option<String>) -> Result<()> {
+		self.db.set_account_lock(
+			user_id,
+			Some(&AccountRestriction {
+				reason,
+				since: utils::millis_since_unix_epoch(),
+			}),
+		)
+	}
+
+	pub fn unlock_account(&self, user_id: &UserId) -> Result<()> { self.db.set_account_lock(user_id, None) }
+
+	pub fn locked_users(&self) -> impl Iterator<Item = Result<(OwnedUserId, AccountRestriction)>> + '_ {
+		self.db.locked_users()
+	}
+
 	/// Check if a user is an admin
 	pub fn is_admin(&self, user_id: &UserId) -> Result<bool> {
 		if let Some(admin_room_id) = crate::admin::Service::get_admin_room()? {
@@ -259,6 +447,79 @@ impl Service {
 		Ok(())
 	}
 
+	/// Sets up a new account the way registration does: creates it with the
+	/// password, sets the display name, defaulting to the localpart, with
+	/// `new_user_displayname_suffix` appended, and the default push rules.
+	/// Unless it's a guest, the first user after the server user is made an
+	/// admin.
+	pub async fn register(
+		&self, user_id: &UserId, password: Option<&str>, displayname: Option<String>, is_guest: bool,
+	) -> Result<()> {
+		self.create(user_id, password)?;
+
+		// Default to pretty displayname
+		let mut displayname = displayname.unwrap_or_else(|| user_id.localpart().to_owned());
+
+		// If `new_user_displayname_suffix` is set, registration will push whatever
+		// content is set to the user's display name with a space before it
+		if !services().globals.new_user_displayname_suffix().is_empty() {
+			write!(displayname, " {}", services().globals.config.new_user_displayname_suffix)
+				.expect("should be able to write to string buffer");
+		}
+
+		self.set_displayname(user_id, Some(displayname.clone()))
+			.await?;
+
+		// Initial account data
+		services().account_data.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+				content: ruma::events::push_rules::PushRulesEventContent {
+					global: push::Ruleset::server_default(user_id),
+				},
+			})
+			.expect("to json always works"),
+		)?;
+
+		// If this is the first real user, grant them admin privileges except for guest
+		// users Note: the server user, @conduit:servername, is generated first
+		if !is_guest {
+			if let Some(admin_room) = crate::admin::Service::get_admin_room()? {
+				if services()
+					.rooms
+					.state_cache
+					.room_joined_count(&admin_room)?
+					== Some(1)
+				{
+					crate::admin::make_user_admin(user_id, displayname).await?;
+					warn!("Granting {user_id} admin privileges as the first user");
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Registers an account for a user authenticated by an external identity
+	/// source such as SSO or LDAP. The account gets a random password so it
+	/// can't be logged in to locally.
+	pub async fn create_external(&self, user_id: &UserId, displayname: Option<String>, source: &str) -> Result<()> {
+		self.register(user_id, Some(&utils::random_string(RANDOM_PASSWORD_LENGTH)), displayname, false)
+			.await?;
+
+		debug_info!(%user_id, "User account was created through {source}");
+		services()
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"New user \"{user_id}\" registered on this server through {source}."
+			)))
+			.await;
+
+		Ok(())
+	}
+
 	/// Returns the number of users registered on this server.
 	pub fn count(&self) -> Result<usize> { self.db.count() }
 
@@ -290,7 +551,13 @@ impl Service {
 	/// Sets a new displayname or removes it if displayname is None. You still
 	/// need to nofify all rooms of this change.
 	pub async fn set_displayname(&self, user_id: &UserId, displayname: Option<String>) -> Result<()> {
-		self.db.set_displayname(user_id, displayname)
+		self.db.set_displayname(user_id, displayname)?;
+
+		if user_is_local(user_id) {
+			services().user_directory.update_local_user(user_id)?;
+		}
+
+		Ok(())
 	}
 
 	/// Get the avatar_url of a user.
@@ -298,7 +565,17 @@ impl Service {
 
 	/// Sets a new avatar_url or removes it if avatar_url is None.
 	pub async fn set_avatar_url(&self, user_id: &UserId, avatar_url: Option<OwnedMxcUri>) -> Result<()> {
-		self.db.set_avatar_url(user_id, avatar_url)
+		if let Some(avatar_url) = &avatar_url {
+			services().media.reference_media(avatar_url.as_str())?;
+		}
+
+		self.db.set_avatar_url(user_id, avatar_url)?;
+
+		if user_is_local(user_id) {
+			services().user_directory.update_local_user(user_id)?;
+		}
+
+		Ok(())
 	}
 
 	/// Get the blurhash of a user.
@@ -314,14 +591,56 @@ impl Service {
 		&self, user_id: &UserId, device_id: &DeviceId, token: &str, initial_device_display_name: Option<String>,
 	) -> Result<()> {
 		self.db
-			.create_device(user_id, device_id, token, initial_device_display_name)
+			.create_device(user_id, device_id, Some(token), initial_device_display_name)
 	}
 
 	/// Removes a device from a user.
 	pub fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) -> Result<()> {
+		if self.is_dehydrated_device(user_id, device_id)? {
+			self.db.remove_dehydrated_device(user_id)?;
+		}
+
 		self.db.remove_device(user_id, device_id)
 	}
 
+	/// Replaces the dehydrated device of a user, removing the previous one
+	/// with its pending to-device events.
+	pub fn set_dehydrated_device(
+		&self, user_id: &UserId, device: &DehydratedDevice, initial_device_display_name: Option<String>,
+	) -> Result<()> {
+		if let Some(previous) = self.db.dehydrated_device(user_id)? {
+			self.db.remove_device(user_id, &previous.device_id)?;
+		}
+
+		self.db
+			.create_device(user_id, &device.device_id, None, initial_device_display_name)?;
+		self.db.set_dehydrated_device(user_id, device)
+	}
+
+	pub fn dehydrated_device(&self, user_id: &UserId) -> Result<Option<DehydratedDevice>> {
+		self.db.dehydrated_device(user_id)
+	}
+
+	/// Removes the dehydrated device of a user, returning its ID if there was
+	/// one.
+	pub fn remove_dehydrated_device(&self, user_id: &UserId) -> Result<Option<OwnedDeviceId>> {
+		let Some(device) = self.db.dehydrated_device(user_id)? else {
+			return Ok(None);
+		};
+
+		self.db.remove_dehydrated_device(user_id)?;
+		self.db.remove_device(user_id, &device.device_id)?;
+
+		Ok(Some(device.device_id))
+	}
+
+	pub fn is_dehydrated_device(&self, user_id: &UserId, device_id: &DeviceId) -> Result<bool> {
+		Ok(self
+			.db
+			.dehydrated_device(user_id)?
+			.is_some_and(|device| device.device_id == device_id))
+	}
+
 	/// Returns an iterator over all device ids of this user.
 	pub fn all_device_ids<'a>(&'a self, user_id: &UserId) -> impl Iterator<Item = Result<OwnedDeviceId>> + 'a {
 		self.db.all_device_ids(user_id)
@@ -431,6 +750,15 @@ impl Service {
 		self.db.get_to_device_events(user_id, device_id)
 	}
 
+	/// Returns up to `limit` to-device events of a device with the counts to
+	/// remove them up to, oldest first.
+	pub fn get_to_device_events_with_count(
+		&self, user_id: &UserId, device_id: &DeviceId, limit: usize,
+	) -> Result<Vec<(u64, Raw<AnyToDeviceEvent>)>> {
+		self.db
+			.get_to_device_events_with_count(user_id, device_id, limit)
+	}
+
 	pub fn remove_to_device_events(&self, user_id: &UserId, device_id: &DeviceId, until: u64) -> Result<()> {
 		self.db.remove_to_device_events(user_id, device_id, until)
 	}
@@ -479,6 +807,37 @@ impl Service {
 	}
 }
 
+/// Starts the task which periodically removes the sliding sync connections of
+/// all devices unused for `sliding_sync_connection_ttl`.
+#[tracing::instrument]
+pub fn start_sliding_sync_expiry_task() -> JoinHandle<()> {
+	services().server.runtime().spawn(async move {
+		let mut i = interval(SLIDING_SYNC_EXPIRY_INTERVAL);
+
+		loop {
+			i.tick().await;
+
+			match services().users.expire_sliding_sync_connections() {
+				Ok(expired) if expired > 0 => info!("Removed {expired} expired sliding sync connections"),
+				Ok(_) => {},
+				Err(e) => warn!("Failed to remove expired sliding sync connections: {e}"),
+			}
+		}
+	})
+}
+
+/// Connections last used before this, in milliseconds since the unix epoch,
+/// are expired.
+fn sliding_sync_expired_before() -> u64 {
+	let ttl = services()
+		.globals
+		.config
+		.sliding_sync_connection_ttl
+		.saturating_mul(1000);
+
+	utils::millis_since_unix_epoch().saturating_sub(ttl)
+}
+
 /// Ensure that a user only sees signatures from themselves and the target user
 pub fn clean_signatures<F: Fn(&UserId) -> bool>(
 	cross_signing_key: &mut serde_json::Value, sender_user: Option<&UserId>, user_id: &UserId, allowed_signatures: F,
@@ -0,0 +1,212 @@
+#![cfg(test)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use conduit::Error;
+use ruma::{api::client::error::ErrorKind, device_id, owned_room_id, user_id, DeviceId};
+use serde_json::json;
+
+use super::{DehydratedDevice, SlidingSyncConnection, SlidingSyncResponse, SlidingSyncRoom};
+use crate::tests::services;
+
+fn dehydrated_device(device_id: &DeviceId) -> DehydratedDevice {
+	DehydratedDevice {
+		device_id: device_id.to_owned(),
+		device_data: serde_json::from_value(json!({
+			"algorithm": "m.dehydration.v1.olm",
+			"device_pickle": device_id.as_str(),
+		}))
+		.unwrap(),
+	}
+}
+
+#[tokio::test]
+async fn dehydrated_device_is_replaced() {
+	let users = &services().await.users;
+	let user_id = user_id!("@dehydrated:example.com");
+	let (first, second) = (device_id!("FIRST"), device_id!("SECOND"));
+	users.create(user_id, Some("password")).unwrap();
+
+	users
+		.set_dehydrated_device(user_id, &dehydrated_device(first), None)
+		.unwrap();
+	users
+		.add_to_device_event(user_id!("@sender:example.com"), user_id, first, "m.room.encrypted", json!({}))
+		.unwrap();
+	assert!(users.is_dehydrated_device(user_id, first).unwrap());
+	assert!(users.get_device_metadata(user_id, first).unwrap().is_some());
+
+	users
+		.set_dehydrated_device(user_id, &dehydrated_device(second), Some("Dehydrated".to_owned()))
+		.unwrap();
+	assert!(!users.is_dehydrated_device(user_id, first).unwrap());
+	assert!(
+		users.get_device_metadata(user_id, first).unwrap().is_none(),
+		"the previous device is removed"
+	);
+	assert!(
+		users
+			.get_to_device_events(user_id, first)
+			.unwrap()
+			.is_empty(),
+		"with its pending events"
+	);
+
+	let stored = users.dehydrated_device(user_id).unwrap().unwrap();
+	assert_eq!(stored.device_id, second);
+	assert!(stored.device_data.json().get().contains("SECOND"));
+	assert_eq!(
+		users
+			.get_device_metadata(user_id, second)
+			.unwrap()
+			.unwrap()
+			.display_name
+			.as_deref(),
+		Some("Dehydrated")
+	);
+}
+
+#[tokio::test]
+async fn dehydrated_device_is_removed() {
+	let users = &services().await.users;
+	let user_id = user_id!("@rehydrated:example.com");
+	let device_id = device_id!("DEHYDRATED");
+	users.create(user_id, Some("password")).unwrap();
+
+	users
+		.set_dehydrated_device(user_id, &dehydrated_device(device_id), None)
+		.unwrap();
+	assert_eq!(users.remove_dehydrated_device(user_id).unwrap().as_deref(), Some(device_id));
+	assert!(users.dehydrated_device(user_id).unwrap().is_none());
+	assert!(users
+		.get_device_metadata(user_id, device_id)
+		.unwrap()
+		.is_none());
+	assert!(users.remove_dehydrated_device(user_id).unwrap().is_none());
+
+	// logging the device out removes it as well
+	users
+		.set_dehydrated_device(user_id, &dehydrated_device(device_id), None)
+		.unwrap();
+	users.remove_device(user_id, device_id).unwrap();
+	assert!(users.dehydrated_device(user_id).unwrap().is_none());
+}
+
+fn sliding_sync_room(since: u64) -> SlidingSyncRoom {
+	SlidingSyncRoom {
+		since,
+		required_state: BTreeSet::new(),
+		invited: false,
+		lazy_members: BTreeSet::new(),
+	}
+}
+
+#[tokio::test]
+async fn sliding_sync_pos() {
+	let users = &services().await.users;
+	let (user_id, device_id) = (user_id!("@slider:example.com"), device_id!("SLIDER"));
+	let room_id = owned_room_id!("!room:example.com");
+	let is_unknown_pos = |result| matches!(result, Err(Error::BadRequest(ErrorKind::UnknownPos, _)));
+
+	let mut connection = users
+		.sliding_sync_connection(user_id, device_id, "conn", None)
+		.unwrap();
+	assert_eq!((connection.pos, connection.since), (0, 0));
+	connection.pending = Some(SlidingSyncResponse {
+		pos: 1,
+		since: 10,
+		rooms: BTreeMap::from([(room_id.clone(), Some(sliding_sync_room(10)))]),
+	});
+	users
+		.save_sliding_sync_connection(user_id, device_id, "conn", &mut connection)
+		.unwrap();
+
+	// the client retries without having received the response
+	let mut connection = users
+		.sliding_sync_connection(user_id, device_id, "conn", Some(0))
+		.unwrap();
+	assert!(connection.rooms.is_empty());
+	connection.pending = Some(SlidingSyncResponse {
+		pos: 1,
+		since: 12,
+		rooms: BTreeMap::from([(room_id.clone(), Some(sliding_sync_room(12)))]),
+	});
+	users
+		.save_sliding_sync_connection(user_id, device_id, "conn", &mut connection)
+		.unwrap();
+
+	let connection = users
+		.sliding_sync_connection(user_id, device_id, "conn", Some(1))
+		.unwrap();
+	assert_eq!((connection.pos, connection.since), (1, 12), "the response is acknowledged");
+	assert_eq!(connection.rooms[&room_id].since, 12);
+
+	assert!(is_unknown_pos(users.sliding_sync_connection(
+		user_id,
+		device_id,
+		"conn",
+		Some(5)
+	)));
+	assert!(is_unknown_pos(users.sliding_sync_connection(
+		user_id,
+		device_id,
+		"other",
+		Some(1)
+	)));
+
+	let connection = users
+		.sliding_sync_connection(user_id, device_id, "conn", None)
+		.unwrap();
+	assert!(connection.rooms.is_empty(), "starting over forgets the rooms");
+	assert!(is_unknown_pos(users.sliding_sync_connection(
+		user_id,
+		device_id,
+		"conn",
+		Some(1)
+	)));
+}
+
+#[tokio::test]
+async fn sliding_sync_expiry() {
+	let users = &services().await.users;
+	let user_id = user_id!("@idle:example.com");
+	let (idle, active) = (device_id!("IDLE"), device_id!("ACTIVE"));
+
+	users
+		.db
+		.set_sliding_sync_connection(
+			user_id,
+			idle,
+			"conn",
+			&SlidingSyncConnection {
+				last_used: 1,
+				..SlidingSyncConnection::default()
+			},
+		)
+		.unwrap();
+	users
+		.db
+		.update_sliding_sync_rooms(
+			user_id,
+			idle,
+			"conn",
+			&BTreeMap::from([(owned_room_id!("!idle:example.com"), Some(sliding_sync_room(1)))]),
+		)
+		.unwrap();
+	users
+		.save_sliding_sync_connection(user_id, active, "conn", &mut SlidingSyncConnection::default())
+		.unwrap();
+
+	assert!(users.expire_sliding_sync_connections().unwrap() >= 1);
+	assert!(
+		users
+			.db
+			.sliding_sync_connection(user_id, idle, "conn")
+			.unwrap()
+			.is_none(),
+		"connections of idle devices expire"
+	);
+	assert!(users
+		.sliding_sync_connection(user_id, active, "conn", Some(0))
+		.is_ok());
+}